@@ -1,7 +1,7 @@
 use std::env;
-use std::path::PathBuf;
+use std::sync::Arc;
 use vivotk::codec::decoder::Tmc2rsDecoder;
-use vivotk::codec::Decoder;
+use vivotk::codec::{decode_segment, Bitstream};
 use vivotk::pcd::{write_pcd_file, PCDDataType, PointCloudData};
 
 fn main() {
@@ -15,19 +15,15 @@ fn main() {
     let top = env::args().nth(6).expect("input file");
     let front = env::args().nth(7).expect("input file");
 
-    let mut decoder = Tmc2rsDecoder::new(&[
-        PathBuf::from(left),
-        PathBuf::from(bottom),
-        PathBuf::from(back),
-        PathBuf::from(right),
-        PathBuf::from(top),
-        PathBuf::from(front),
-    ]);
+    let paths = [left, bottom, back, right, top, front];
     let now = std::time::Instant::now();
-    decoder.start().unwrap();
+    let mut frames = decode_segment(
+        Arc::new(Tmc2rsDecoder),
+        paths.into_iter().map(Bitstream::from_file).collect(),
+    );
     let mut file_counter = 1;
-    while let Some(pc) = decoder.poll() {
-        let pcd = PointCloudData::from(&pc);
+    while let Some(frame) = frames.blocking_recv() {
+        let pcd = PointCloudData::from(&frame.unwrap().pc);
         dbg!(pcd.header().points());
         let filename = format!("{}/{}.pcd", output_folder, file_counter);
         file_counter += 1;
@@ -1,3 +1,6 @@
+use std::cell::Cell;
+use std::time::Duration;
+
 pub mod quetra;
 
 pub trait RateAdapter: Send {
@@ -18,6 +21,10 @@ pub trait RateAdapter: Send {
         available_bitrates: &[Vec<u64>],
         cosines: &[f32],
     ) -> Vec<usize>;
+
+    /// Reports how long a frame of a segment downloaded at `quality` took to decode.
+    /// Algorithms that do not account for decoding can ignore it.
+    fn report_decode_time(&mut self, _quality: &[usize], _decode_time: Duration) {}
 }
 
 /// Multiple-Choice Knapsack Problem
@@ -96,6 +103,91 @@ impl RateAdapter for MCKP {
     }
 }
 
+/// Wraps another rate adapter and lowers its choices to representations that can be decoded in real time.
+///
+/// Views are decoded concurrently, so the decode time of a frame is attributed to the highest representation
+/// of its segment. Representations without any report yet are assumed to be fast enough.
+///
+/// A representation that is too slow is not chosen, so its decode time is not measured anymore. Its estimate
+/// therefore expires after a number of segments, and the representation is tried again, e.g. in case
+/// the decode time was only high while the machine was busy.
+pub struct DecodeTimeAware {
+    inner: Box<dyn RateAdapter>,
+    /// time between two frames in seconds
+    frame_interval: f64,
+    /// moving average of the decode time of a frame, in seconds, for each representation,
+    /// with the number of segments selected when it was last updated
+    decode_times: Vec<Option<(f64, usize)>>,
+    /// number of segments selected so far
+    selections: Cell<usize>,
+}
+
+impl DecodeTimeAware {
+    /// weight of the latest report in the moving average
+    const ALPHA: f64 = 0.2;
+    /// number of segments after which the estimate of a representation that was not decoded expires
+    const EXPIRY: usize = 20;
+
+    pub fn new(inner: Box<dyn RateAdapter>, fps: f32) -> Self {
+        DecodeTimeAware {
+            inner,
+            frame_interval: 1.0 / fps as f64,
+            decode_times: vec![],
+            selections: Cell::new(0),
+        }
+    }
+
+    /// The decode time of a representation, unless it has expired
+    fn decode_time(&self, quality: usize) -> Option<f64> {
+        let (average, updated) = (*self.decode_times.get(quality)?)?;
+        (self.selections.get() - updated < Self::EXPIRY).then_some(average)
+    }
+
+    fn is_too_slow(&self, quality: usize) -> bool {
+        matches!(self.decode_time(quality), Some(t) if t > self.frame_interval)
+    }
+}
+
+impl RateAdapter for DecodeTimeAware {
+    fn select_quality(
+        &self,
+        buffer_occupancy: u64,
+        network_throughput: f64,
+        available_bitrates: &[Vec<u64>],
+        cosines: &[f32],
+    ) -> Vec<usize> {
+        let mut qualities = self.inner.select_quality(
+            buffer_occupancy,
+            network_throughput,
+            available_bitrates,
+            cosines,
+        );
+        for quality in qualities.iter_mut() {
+            while *quality > 0 && self.is_too_slow(*quality) {
+                *quality -= 1;
+            }
+        }
+        self.selections.set(self.selections.get() + 1);
+        qualities
+    }
+
+    fn report_decode_time(&mut self, quality: &[usize], decode_time: Duration) {
+        self.inner.report_decode_time(quality, decode_time);
+        let Some(&highest) = quality.iter().max() else {
+            return;
+        };
+        if self.decode_times.len() <= highest {
+            self.decode_times.resize(highest + 1, None);
+        }
+        let t = decode_time.as_secs_f64();
+        // an expired estimate starts over
+        let average = self
+            .decode_time(highest)
+            .map_or(t, |a| a + Self::ALPHA * (t - a));
+        self.decode_times[highest] = Some((average, self.selections.get()));
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -173,4 +265,68 @@ mod tests {
             vec![3, 0, 0, 0, 4, 2]
         );
     }
+
+    #[test]
+    fn test_decode_time_aware() {
+        struct Highest;
+        impl RateAdapter for Highest {
+            fn select_quality(
+                &self,
+                _buffer_occupancy: u64,
+                _network_throughput: f64,
+                available_bitrates: &[Vec<u64>],
+                _cosines: &[f32],
+            ) -> Vec<usize> {
+                available_bitrates.iter().map(|b| b.len() - 1).collect()
+            }
+        }
+
+        let mut abr = DecodeTimeAware::new(Box::new(Highest), 30.0);
+        let bitrates = vec![vec![100, 200, 300, 400]; 2];
+        assert_eq!(abr.select_quality(0, 0.0, &bitrates, &[]), vec![3, 3]);
+
+        // 60 ms per frame is too slow for 30 fps, 10 ms is fine
+        abr.report_decode_time(&[3, 1], Duration::from_millis(60));
+        abr.report_decode_time(&[2, 2], Duration::from_millis(50));
+        abr.report_decode_time(&[1], Duration::from_millis(10));
+        assert_eq!(abr.select_quality(0, 0.0, &bitrates, &[]), vec![1, 1]);
+
+        // the average recovers once decoding is fast again
+        for _ in 0..20 {
+            abr.report_decode_time(&[3, 3], Duration::from_millis(5));
+        }
+        assert_eq!(abr.select_quality(0, 0.0, &bitrates, &[]), vec![3, 3]);
+    }
+
+    #[test]
+    fn test_decode_time_aware_probes_again() {
+        struct Highest;
+        impl RateAdapter for Highest {
+            fn select_quality(
+                &self,
+                _buffer_occupancy: u64,
+                _network_throughput: f64,
+                available_bitrates: &[Vec<u64>],
+                _cosines: &[f32],
+            ) -> Vec<usize> {
+                available_bitrates.iter().map(|b| b.len() - 1).collect()
+            }
+        }
+
+        let mut abr = DecodeTimeAware::new(Box::new(Highest), 30.0);
+        let bitrates = vec![vec![100, 200]];
+        // a single slow frame, e.g. while the decoder warms up
+        abr.report_decode_time(&[1], Duration::from_millis(100));
+        let selected: Vec<Vec<usize>> = (0..DecodeTimeAware::EXPIRY + 1)
+            .map(|_| abr.select_quality(0, 0.0, &bitrates, &[]))
+            .collect();
+        assert!(selected[..DecodeTimeAware::EXPIRY]
+            .iter()
+            .all(|quality| quality == &[0]));
+        assert_eq!(selected[DecodeTimeAware::EXPIRY], vec![1]);
+
+        // still too slow when probed again, so it is avoided for a while longer
+        abr.report_decode_time(&[1], Duration::from_millis(100));
+        assert_eq!(abr.select_quality(0, 0.0, &bitrates, &[]), vec![0]);
+    }
 }
@@ -27,15 +27,19 @@
 //! cargo run --bin exporter --release -- --ply_folder [ply_folder] [camera_trace.csv] [output_folder]
 //! ```
 //!
+//! Add `--backend cpu` to render without a graphics adapter.
+//!
 
 use clap::Parser;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 use std::path::PathBuf;
-use vivotk::codec::{decoder::Tmc2rsDecoder, Decoder};
+use std::sync::Arc;
+use vivotk::codec::{decode_segment, decoder::Tmc2rsDecoder};
 use vivotk::dash::fetcher::Fetcher;
-use vivotk::render::wgpu::png::{PngWriter, RenderFormat};
+use vivotk::render::wgpu::png::{PngWriter, RenderBackend, RenderFormat};
+use vivotk::render::wgpu::splat::SplatArgs;
 use vivotk::simulation::CameraTrace;
 use vivotk::utils::read_file_to_point_cloud;
 
@@ -70,6 +74,11 @@ struct Args {
     /// To enable rendering at full screen, compile with `--features fullscreen` (depends on device gpu support)
     #[clap(short, long, default_value_t = 900)]
     height: u32,
+    /// Renderer to draw the pngs with, `cpu` needs no graphics adapter and is deterministic
+    #[clap(long, value_enum, default_value_t = RenderBackend::Gpu)]
+    backend: RenderBackend,
+    #[clap(flatten)]
+    splat: SplatArgs,
 }
 
 struct QualityTrace {
@@ -110,8 +119,10 @@ async fn main() {
         args.height,
         "#000000",
         RenderFormat::Png,
+        args.backend,
     );
     png_writer.set_background_color(wgpu::Color::WHITE);
+    png_writer.set_splat(args.splat.splat());
 
     let camera_trace = CameraTrace::new(&args.camera_trace, false);
 
@@ -122,7 +133,8 @@ async fn main() {
             &args.output_folder,
             true,
         )
-        .await;
+        .await
+        .expect("failed to load MPD");
         let mut frame_number = 0;
         for quality in quality_trace.data.iter() {
             let res = fetcher
@@ -135,13 +147,11 @@ async fn main() {
                 )
                 .await
                 .unwrap();
-            let paths = res.paths.into_iter().flatten().collect::<Vec<_>>();
-            let mut decoder = Tmc2rsDecoder::new(&paths);
-            decoder.start().unwrap();
+            let mut frames = decode_segment(Arc::new(Tmc2rsDecoder), res.bitstreams());
             for i in 0..args.segment_size {
                 let cam_pos = camera_trace.next();
                 png_writer.update_camera_pos(cam_pos);
-                let pc = decoder.poll().unwrap();
+                let pc = frames.recv().await.unwrap().unwrap().pc;
                 png_writer.write_to_png(&pc, &(i + frame_number as usize).to_string());
             }
             frame_number += args.segment_size as u64;
@@ -3,12 +3,14 @@ use clap::Parser;
 use log::{debug, info, trace, warn};
 use std::ffi::OsString;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tempfile::tempdir;
 use vivotk::abr::{
     quetra::{Quetra, QuetraMultiview},
     RateAdapter, MCKP,
 };
 use vivotk::codec::{
+    decode_segment,
     decoder::{DracoDecoder, NoopDecoder, Tmc2rsDecoder},
     Decoder,
 };
@@ -18,18 +20,17 @@ use vivotk::dash::{
     ThroughputPrediction, ViewportPrediction,
 };
 use vivotk::formats::{pointxyzrgba::PointXyzRgba, PointCloud};
-use vivotk::render::wgpu::camera::{Camera, CameraPosition};
-use vivotk::render::wgpu::reader::PcdAsyncReader;
 use vivotk::player::{
     builder::{EventType, RenderBuilder, RenderEvent},
     controls::Controller,
     metrics_reader::MetricsReader,
     renderer::Renderer,
 };
+use vivotk::render::wgpu::camera::{Camera, CameraPosition};
+use vivotk::render::wgpu::reader::PcdAsyncReader;
 use vivotk::simulation::{CameraTrace, NetworkTrace};
 use vivotk::utils::{
-    get_cosines, predict_quality, ExponentialMovingAverage, LastValue, SimpleRunningAverage, GAEMA,
-    LPEMA,
+    predict_quality, ExponentialMovingAverage, LastValue, SimpleRunningAverage, GAEMA, LPEMA,
 };
 use vivotk::{BufMsg, PCMetadata};
 
@@ -104,7 +105,6 @@ struct Args {
     enable_fetcher_optimizations: bool,
     #[clap(long, default_value = "rgb(255,255,255)")]
     bg_color: OsString,
-
 }
 
 #[derive(clap::ValueEnum, Clone, Copy)]
@@ -362,6 +362,9 @@ impl BufferManager {
                                 is_desired_buffer_level_reached = true;
                             }
                         }
+                        BufMsg::FetchFailed(req) => {
+                            warn!("Gave up fetching frame {}, playback stalls", req.frame_offset);
+                        }
                         BufMsg::PointCloud((mut metadata, mut rx)) => {
                             trace!("[buffer mgr] received a point cloud result {:?}", &metadata);
                             let orig_metadata = metadata.into();
@@ -499,17 +502,18 @@ fn main() {
                 let path = tmpdir.path();
                 trace!("[fetcher] Downloading files to {}", path.to_str().unwrap());
 
-                let mut fetcher = Fetcher::new(&src, path, args.enable_fetcher_optimizations).await;
+                let mut fetcher = Fetcher::new(&src, path, args.enable_fetcher_optimizations).await.expect("failed to load MPD");
                 total_frames_tx
                     .send((
                         fetcher.mpd_parser.total_frames(),
-                        fetcher.mpd_parser.segment_duration(),
+                        fetcher.mpd_parser.segment_duration().expect("invalid MPD"),
                     ))
                     .expect("sent total frames");
 
                 let qualities = fetcher
                     .mpd_parser
                     .get_qp()
+                    .unwrap_or_default()
                     .into_iter()
                     .map(|x| -> f32 {
                         if let (Some(geo_qp), Some(attr_qp)) = x {
@@ -545,24 +549,24 @@ fn main() {
                                 simulated_network_trace.as_ref().unwrap().next() * 1024.0
                             };
 
-                            let mut available_bitrates = vec![];
-                            if args.multiview {
-                                for i in 0..6 {
-                                    available_bitrates.push(fetcher.available_bitrates(
-                                        req.object_id,
-                                        req.frame_offset,
-                                        Some(i),
-                                    ));
-                                }
+                            let available_bitrates = if args.multiview {
+                                (0..6)
+                                    .map(|i| fetcher.available_bitrates(req.object_id, req.frame_offset, Some(i)))
+                                    .collect::<anyhow::Result<Vec<_>>>()
                             } else {
-                                available_bitrates.push(fetcher.available_bitrates(
-                                    req.object_id,
-                                    req.frame_offset,
-                                    None,
-                                ));
-                            }
+                                fetcher
+                                    .available_bitrates(req.object_id, req.frame_offset, None)
+                                    .map(|bitrates| vec![bitrates])
+                            };
+                            let available_bitrates = match available_bitrates {
+                                Ok(available_bitrates) => available_bitrates,
+                                Err(e) => {
+                                    warn!("Cannot fetch frame {}: {:#}", req.frame_offset, e);
+                                    continue;
+                                }
+                            };
 
-                            let cosines = get_cosines(camera_pos);
+                            let cosines = fetcher.view_cosines(req.object_id, req.frame_offset, camera_pos);
 
                             let quality = abr.select_quality(
                                 req.buffer_occupancy as u64,
@@ -628,8 +632,8 @@ fn main() {
                             trace!("[fetcher] got fetch request {:?}", req);
                             // let buffer know that we are done fetching
                             _ = to_buf_sx.send(BufMsg::FetchDone((req.into(), FetchResult {
-                                paths: [ply_files.get(req.frame_offset as usize).map(|p| p.to_path_buf()), None, None, None, None, None],
-                                throughput: 0.0,
+                                paths: vec![ply_files.get(req.frame_offset as usize).map(|p| p.to_path_buf())],
+                                ..Default::default()
                             })));
                         }
                         else => break,
@@ -651,47 +655,36 @@ fn main() {
                         trace!("[decoder] shutdown signal received");
                         break;
                     },
-                    Some((req, FetchResult {
-                        mut paths,
-                        throughput: _,
-                    })) = buf_dec_rx.recv() => {
+                    Some((req, res)) = buf_dec_rx.recv() => {
                         debug!("got fetch result {:?}", req);
-                        let decoder_path = decoder_path.clone();
-                        let to_buf_sx = to_buf_sx.clone();
-                        tokio::task::spawn_blocking(move || {
-                            let mut decoder: Box<dyn Decoder> = match decoder_type {
-                                DecoderType::Draco => Box::new(DracoDecoder::new(
-                                    decoder_path
-                                        .as_ref()
-                                        .expect("must provide decoder path for Draco")
-                                        .as_os_str(),
-                                    paths[0].take().unwrap().as_os_str(),
-                                )),
-                                DecoderType::Tmc2rs => {
-                                    let paths = paths.into_iter().flatten().collect::<Vec<_>>();
-                                    Box::new(Tmc2rsDecoder::new(&paths))
-                                }
-                                _ => Box::new(NoopDecoder::new(paths[0].take().unwrap().as_os_str())),
-                            };
-                            let now = std::time::Instant::now();
-                            decoder.start().unwrap();
-                            let (output_sx, output_rx) = tokio::sync::mpsc::unbounded_channel();
-                            _ = to_buf_sx
-                                .send(BufMsg::PointCloud((
-                                    PCMetadata {
-                                        frame_offset: req.frame_offset,
-                                        object_id: req.object_id,
-                                    },
-                                    output_rx,
-                                )));
-                            while let Some(pcd) = decoder.poll() {
-                                _ = output_sx.send(pcd);
+                        let decoder: Arc<dyn Decoder> = match decoder_type {
+                            DecoderType::Draco => Arc::new(DracoDecoder::new(
+                                decoder_path
+                                    .clone()
+                                    .expect("must provide decoder path for Draco"),
+                            )),
+                            DecoderType::Tmc2rs => Arc::new(Tmc2rsDecoder),
+                            _ => Arc::new(NoopDecoder),
+                        };
+                        let now = std::time::Instant::now();
+                        let mut frames = decode_segment(decoder, res.bitstreams());
+                        let (output_sx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+                        _ = to_buf_sx
+                            .send(BufMsg::PointCloud((
+                                PCMetadata {
+                                    frame_offset: req.frame_offset,
+                                    object_id: req.object_id,
+                                },
+                                output_rx,
+                            )));
+                        while let Some(frame) = frames.recv().await {
+                            match frame {
+                                Ok(frame) => _ = output_sx.send(frame.pc),
+                                Err(e) => warn!("{:#}", e),
                             }
-                            let elapsed = now.elapsed();
-                            debug!("Decoding took {:?}", elapsed);
-                        })
-                        .await
-                        .unwrap();
+                        }
+                        let elapsed = now.elapsed();
+                        debug!("Decoding took {:?}", elapsed);
                     }
                     else => break,
                 }
@@ -723,7 +716,7 @@ fn main() {
                     position: Point3::new(args.camera_x, args.camera_y, args.camera_z),
                     yaw: cgmath::Deg(args.camera_yaw).into(),
                     pitch: cgmath::Deg(args.camera_pitch).into(),
-                    up: Vector3::new(0.0, 0.0, 0.0)
+                    up: Vector3::new(0.0, 0.0, 0.0),
                 },
                 simulated_camera_trace,
                 record_camera_trace,
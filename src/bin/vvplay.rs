@@ -1,12 +1,19 @@
 use clap::Parser;
 use std::ffi::OsString;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use vivotk::formats::pointxyzrgba::PointXyzRgba;
+use vivotk::formats::PointCloud;
 use vivotk::player::builder::RenderBuilder;
 use vivotk::player::controls::Controller;
 use vivotk::player::metrics_reader::MetricsReader;
-use vivotk::player::render_manager::AdaptiveUpsamplingManager;
+use vivotk::player::render_manager::{
+    AdaptiveUpsamplingManager, RenderManager, StreamingLODManager,
+};
 use vivotk::player::renderer::Renderer;
+use vivotk::player::scene::{Scene, SceneManager};
 use vivotk::render::wgpu::camera::Camera;
+use vivotk::render::wgpu::splat::SplatArgs;
+use vivotk::upsample::view_adaptive::UpsampleThresholdArgs;
 
 /// Plays a folder of pcd files in lexicographical order
 #[derive(Parser)]
@@ -14,7 +21,13 @@ struct Args {
     /// src can be:
     /// 1. Directory with all the pcd files in lexicographical order
     /// 2. location of the mpd file
-    src: String,
+    /// 3. url of a progressive LOD mpd, with `--lod`
+    #[clap(required_unless_present = "scene")]
+    src: Option<String>,
+
+    /// Plays the objects of a scene file together instead of src, see the README
+    #[clap(long, conflicts_with = "src")]
+    scene: Option<PathBuf>,
 
     #[clap(short, long, default_value_t = 0)]
     quality: u8,
@@ -75,8 +88,19 @@ struct Args {
     #[clap(long, default_value = "rgb(255,255,255)")]
     bg_color: OsString,
 
+    /// Upsamples the regions of the visible points that would show holes from the current camera
     #[clap(long, default_value = "false")]
     adaptive_upsampling: bool,
+
+    #[clap(flatten)]
+    upsample_thresholds: UpsampleThresholdArgs,
+
+    /// Streams src, the mpd written by `vv lodify --progressive`, fetching only the levels the camera needs
+    #[clap(long, default_value = "false")]
+    lod: bool,
+
+    #[clap(flatten)]
+    splat: SplatArgs,
 }
 
 #[derive(clap::ValueEnum, Clone, Copy)]
@@ -87,8 +111,51 @@ enum DecoderType {
 
 fn main() {
     let args: Args = Args::parse();
-    let adaptive_manager = AdaptiveUpsamplingManager::new(&args.src, args.adaptive_upsampling);
+    if let Some(path) = &args.scene {
+        let scene = Scene::from_file(path).unwrap_or_else(|e| {
+            eprintln!("Failed to open the scene: {e:#}");
+            std::process::exit(1);
+        });
+        let mut manager = SceneManager::default();
+        for object in scene.objects {
+            // remote objects are progressive LOD streams, local ones folders of point clouds
+            if object.is_remote() {
+                manager.add_object(open_lod(&object.src, object.object_id), object.transform);
+            } else {
+                manager.add_object(
+                    AdaptiveUpsamplingManager::new(&object.src, args.adaptive_upsampling)
+                        .with_upsample_thresholds(args.upsample_thresholds.thresholds()),
+                    object.transform,
+                );
+            }
+        }
+        run(manager, args);
+        return;
+    }
+    let src = args.src.clone().unwrap();
+    if args.lod {
+        run(open_lod(&src, 0), args);
+    } else {
+        let manager = AdaptiveUpsamplingManager::new(&src, args.adaptive_upsampling)
+            .with_upsample_thresholds(args.upsample_thresholds.thresholds());
+        run(manager, args);
+    }
+}
+
+fn open_lod(src: &str, object_id: u8) -> StreamingLODManager {
+    match StreamingLODManager::new(src, object_id) {
+        Ok(manager) => manager,
+        Err(e) => {
+            eprintln!("Failed to open {src}: {e:#}");
+            std::process::exit(1);
+        }
+    }
+}
 
+fn run<M>(manager: M, args: Args)
+where
+    M: RenderManager<PointCloud<PointXyzRgba>> + 'static,
+{
     let camera = Camera::new(
         (args.camera_x, args.camera_y, args.camera_z),
         cgmath::Deg(args.camera_yaw),
@@ -98,15 +165,18 @@ fn main() {
         .metrics
         .map(|os_str| MetricsReader::from_directory(Path::new(&os_str)));
     let mut builder = RenderBuilder::default();
-    let slider_end = adaptive_manager.len() - 1;
-    let render = builder.add_window(Renderer::new(
-        adaptive_manager,
-        args.fps,
-        camera,
-        (args.width, args.height),
-        metrics,
-        args.bg_color.to_str().unwrap(),
-    ));
+    let slider_end = manager.len() - 1;
+    let render = builder.add_window(
+        Renderer::new(
+            manager,
+            args.fps,
+            camera,
+            (args.width, args.height),
+            metrics,
+            args.bg_color.to_str().unwrap(),
+        )
+        .with_splat(args.splat.splat()),
+    );
 
     if !args.hide_controls {
         let controls = builder.add_window(Controller { slider_end });
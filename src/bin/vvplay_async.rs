@@ -1,50 +1,42 @@
 use cgmath::Point3;
 use clap::Parser;
-use log::{debug, info, trace, warn};
+use log::{debug, trace, warn};
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 use tempfile::tempdir;
-use vivotk::abr::quetra::{Quetra, QuetraMultiview};
-use vivotk::abr::{RateAdapter, MCKP};
-use vivotk::codec::decoder::{DracoDecoder, NoopDecoder};
-use vivotk::codec::Decoder;
-use vivotk::dash::fetcher::{FetchResult, Fetcher};
+use vivotk::dash::fetcher::{FetchResult, Fetcher, RetryPolicy};
+use vivotk::dash::live::{LiveEdge, LivePlayhead};
 use vivotk::dash::{ThroughputPrediction, ViewportPrediction};
+use vivotk::formats::{pointxyzrgba::PointXyzRgba, PointCloud};
 use vivotk::player::{
     builder::{EventType, RenderBuilder, RenderEvent},
     controls::Controller,
     metrics_reader::MetricsReader,
     render_manager::{RenderManager, RenderReaderWrapper},
     renderer::Renderer,
+    scene::{Scene, SceneManager, SceneObject},
+    timeline::{PlaybackState, Timeline},
 };
 use vivotk::render::wgpu::{
-    camera::{Camera, CameraPosition},
+    camera::{Camera, CameraPosition, CameraState},
+    png::{PngWriter, RenderBackend, RenderFormat},
     reader::PcdAsyncReader,
 };
-use vivotk::utils::{
-    get_cosines, predict_quality, ExponentialMovingAverage, LastValue, SimpleRunningAverage, GAEMA,
-    LPEMA,
-};
+use vivotk::utils::{predict_quality, LastValue};
 use vivotk::vvplay_async_prefetch::args::Args;
 use vivotk::vvplay_async_prefetch::buffer_manager::BufferManager;
 use vivotk::vvplay_async_prefetch::camera_trace::CameraTrace;
-use vivotk::vvplay_async_prefetch::enums::AbrType;
+use vivotk::vvplay_async_prefetch::enums::Clock;
 use vivotk::vvplay_async_prefetch::enums::DecoderType;
-use vivotk::vvplay_async_prefetch::enums::ThroughputPredictionType;
 use vivotk::vvplay_async_prefetch::enums::ViewportPredictionType;
+use vivotk::vvplay_async_prefetch::fetch_loop::{decode_loop, FetchLoop};
 use vivotk::vvplay_async_prefetch::fetch_request::FetchRequest;
 use vivotk::vvplay_async_prefetch::network_trace::NetworkTrace;
-use vivotk::{BufMsg, PCMetadata};
-
-#[cfg(feature = "with-tmc2-rs-decoder")]
-use vivotk::codec::decoder::Tmc2rsDecoder;
+use vivotk::vvplay_async_prefetch::session_log::SessionLog;
+use vivotk::BufMsg;
 
 /// Plays a folder of pcd files in lexicographical order
 
-/// Returns if the source file is remote
-fn is_remote_src(src: &str) -> bool {
-    src.starts_with("http://") || src.starts_with("https://")
-}
-
 fn infer_format(src: &String) -> String {
     let choices = ["pcd", "ply", "bin", "http"];
     const PCD: usize = 0;
@@ -91,7 +83,17 @@ fn main() {
     // initialize logger for trace!()
     env_logger::init();
     let args: Args = Args::parse();
-    let play_format = infer_format(&args.src);
+    let objects = match &args.scene {
+        Some(path) => {
+            Scene::from_file(path)
+                .unwrap_or_else(|e| {
+                    eprintln!("Failed to open the scene: {e:#}");
+                    std::process::exit(1);
+                })
+                .objects
+        }
+        None => vec![SceneObject::new(args.src.clone().unwrap())],
+    };
 
     let rt = tokio::runtime::Builder::new_multi_thread()
         .worker_threads(8)
@@ -99,11 +101,246 @@ fn main() {
         .build()
         .unwrap();
     let (shutdown_send, shutdown_recv) = tokio::sync::watch::channel(false);
+    let session_log = args.session_log.as_ref().map(|path| {
+        let session_log = SessionLog::create(path).unwrap_or_else(|e| {
+            eprintln!("{e:#}");
+            std::process::exit(1);
+        });
+        // the headless player moves the virtual clock of the session, one frame interval per frame
+        // and the time it waits for frames that are not ready
+        if args.headless && args.clock == Clock::Virtual {
+            session_log.with_virtual_clock()
+        } else {
+            session_log
+        }
+    });
+
+    // every object is fetched, decoded, buffered and adapted on its own, the scene draws them together
+    let mut pcd_manager = SceneManager::default();
+    for (index, object) in objects.into_iter().enumerate() {
+        let reader = play_object(
+            &rt,
+            &args,
+            &object,
+            index == 0,
+            session_log.clone(),
+            shutdown_recv.clone(),
+        );
+        pcd_manager.add_object(reader, object.transform);
+    }
+
+    if args.headless {
+        rt.spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                _ = shutdown_send.send(true);
+            }
+        });
+        play_headless(&args, pcd_manager, session_log, shutdown_recv);
+        return;
+    }
+
+    let camera = Camera::new(
+        (args.camera_x, args.camera_y, args.camera_z),
+        cgmath::Deg(args.camera_yaw),
+        cgmath::Deg(args.camera_pitch),
+    );
+    let metrics = args
+        .metrics
+        .map(|os_str| MetricsReader::from_directory(Path::new(&os_str)));
+
+    let mut builder = RenderBuilder::default();
+    let slider_end = pcd_manager.len() - 1;
+
+    // This is the main window that renders the point cloud
+    let render_window_id =
+    // if args.buffer_size > 1 {
+    //     builder.add_window(Renderer::new(
+    //         BufRenderReader::new(args.buffer_size, reader),
+    //         args.fps,
+    //         camera,
+    //         (args.width, args.height),
+    //         metrics,
+    //     ))
+    // } else {
+        builder.add_window(Renderer::new(
+            pcd_manager,
+            args.fps,
+            camera,
+            (args.width, args.height),
+            metrics,
+            args.bg_color.to_str().unwrap()
+        ).with_splat(args.splat.splat()));
+    // };
+    if args.show_controls {
+        let controls_window_id = builder.add_window(Controller { slider_end });
+        builder
+            .get_windowed_mut(render_window_id)
+            .unwrap()
+            .add_output(controls_window_id);
+        builder
+            .get_windowed_mut(controls_window_id)
+            .unwrap()
+            .add_output(render_window_id);
+    }
+
+    {
+        // We run the shutdown signal listener as a separate tokio task.
+        let event_proxy = builder.get_proxy();
+        let window_ids = builder.get_window_ids();
+        rt.spawn(async move {
+            match tokio::signal::ctrl_c().await {
+                Ok(()) => {
+                    for window_id in window_ids {
+                        event_proxy
+                            .send_event(RenderEvent::new(window_id, EventType::Shutdown))
+                            .unwrap();
+                    }
+                    shutdown_send.send(true).unwrap();
+                }
+                Err(err) => {
+                    eprintln!("Unable to listen for shutdown signal: {err}");
+                    // we also shut down in case of error
+                }
+            }
+        });
+    }
+
+    // In MacOS, renderer must run in main thread.
+    builder.run();
+}
+
+/// Plays the scene without any window: frames are requested like the renderer does, by the wall clock or a virtual one.
+/// The camera follows `--camera-trace` and snapshots are rendered on the cpu.
+/// With the virtual clock, the session log is stamped with the virtual time of the frames, plus the time spent waiting
+/// for frames that were not ready.
+fn play_headless(
+    args: &Args,
+    mut pcd_manager: SceneManager,
+    session_log: Option<SessionLog>,
+    shutdown_recv: tokio::sync::watch::Receiver<bool>,
+) {
+    let frames = args.frames.unwrap_or_else(|| pcd_manager.len());
+    let mut camera_state = CameraState::new(
+        Camera::new(
+            (args.camera_x, args.camera_y, args.camera_z),
+            cgmath::Deg(args.camera_yaw),
+            cgmath::Deg(args.camera_pitch),
+        ),
+        args.width,
+        args.height,
+    );
+    let camera_trace = args
+        .camera_trace
+        .as_ref()
+        .map(|path| CameraTrace::new(path, false));
+    let mut snapshots = args.snapshot_dir.as_ref().map(|dir| {
+        let mut writer = PngWriter::new(
+            dir.clone().into_os_string(),
+            args.camera_x,
+            args.camera_y,
+            args.camera_z,
+            cgmath::Deg(args.camera_yaw).into(),
+            cgmath::Deg(args.camera_pitch).into(),
+            args.width,
+            args.height,
+            args.bg_color.to_str().unwrap(),
+            RenderFormat::Png,
+            RenderBackend::Cpu,
+        );
+        writer.set_splat(args.splat.splat());
+        writer
+    });
+
+    let mut shown = 0;
+    let mut show = |frame: usize| {
+        if let Some(trace) = &camera_trace {
+            // the trace does not record the up vector
+            let pos = CameraPosition {
+                up: cgmath::Vector3::unit_y(),
+                ..trace.next()
+            };
+            camera_state.update_camera_pos(pos);
+            pcd_manager.set_camera_state(Some(camera_state.clone()));
+        }
+        // on the virtual clock, the time spent waiting for a frame that is not ready counts as a stall
+        let pc = match &session_log {
+            Some(session_log) => session_log.run_while(|| pcd_manager.get_at(frame)),
+            None => pcd_manager.get_at(frame),
+        };
+        let Some(pc) = pc else {
+            warn!("Frame {} is not available", frame);
+            return;
+        };
+        shown += 1;
+        if let Some(writer) = snapshots.as_mut() {
+            if frame % args.snapshot_every.max(1) == 0 {
+                writer.update_camera_pos(*camera_state.camera);
+                writer.write_to_png(&pc, &format!("{frame:05}.png"));
+            }
+        }
+    };
+
+    let start = Instant::now();
+    match args.clock {
+        Clock::Virtual => {
+            let interval = Timeline::new(frames, args.fps).frame_interval();
+            for frame in 0..frames {
+                if *shutdown_recv.borrow() {
+                    break;
+                }
+                show(frame);
+                if let Some(session_log) = &session_log {
+                    session_log.advance(interval);
+                }
+            }
+        }
+        Clock::Wall => {
+            let mut timeline = Timeline::new(frames, args.fps);
+            timeline.set_looping(false);
+            timeline.set_state(PlaybackState::Play);
+            show(0);
+            let mut last_tick = Instant::now();
+            while timeline.state() == PlaybackState::Play && !*shutdown_recv.borrow() {
+                let interval = timeline.frame_interval();
+                if let Some(wait) = interval.checked_sub(last_tick.elapsed()) {
+                    std::thread::sleep(wait);
+                }
+                let now = Instant::now();
+                // show blocks until the frame is ready, the frames whose time passed meanwhile are skipped
+                if let Some(frame) = timeline.advance(now - last_tick) {
+                    show(frame);
+                }
+                last_tick = now;
+            }
+        }
+    }
+    println!(
+        "Showed {} of {} frames in {:.2}s",
+        shown,
+        frames,
+        start.elapsed().as_secs_f64()
+    );
+}
+
+/// Spawns the fetcher, decoder and buffer manager of one object of the scene, returning the reader of its frames.
+/// Only the first object records the camera trace, they all see the same camera.
+/// All of them write to the same session log.
+fn play_object(
+    rt: &tokio::runtime::Runtime,
+    args: &Args,
+    object: &SceneObject,
+    record_camera: bool,
+    session_log: Option<SessionLog>,
+    shutdown_recv: tokio::sync::watch::Receiver<bool>,
+) -> RenderReaderWrapper<PcdAsyncReader, PointCloud<PointXyzRgba>> {
+    let args = args.clone();
     // important to use tokio::mpsc here instead of std because it is bridging from sync -> async
     // the content is produced by the renderer and consumed by the fetcher
     let (buf_in_sx, mut buf_in_rx) = tokio::sync::mpsc::unbounded_channel::<FetchRequest>();
-    let (in_dec_sx, mut in_dec_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (in_dec_sx, in_dec_rx) = tokio::sync::mpsc::unbounded_channel();
     let (to_buf_sx, to_buf_rx) = tokio::sync::mpsc::unbounded_channel();
+    // the decoder reports the decode time of every frame, with the quality of its segment, to the ABR logic
+    let (decode_time_sx, decode_time_rx) = tokio::sync::mpsc::unbounded_channel();
     // this buffer is used to store the fetched data. It is a bounded buffer. It will store the data in segments.
     // the content is produced by the fetcher and consumed by the decoder thread.
     // let (dec_to_buf, decoder_rx) = Buffer::new(args.buffer_size.unwrap_or(10) as usize);
@@ -120,47 +357,71 @@ fn main() {
     let simulated_camera_trace = args.camera_trace.map(|path| CameraTrace::new(&path, false));
     let record_camera_trace = args
         .record_camera_trace
+        .filter(|_| record_camera)
         .map(|path| CameraTrace::new(&path, true));
 
     // copy variables to be moved into the async block
-    let src = args.src.clone();
-    let decoder_type = args.decoder_type;
-    let decoder_path = args.decoder_path.clone();
+    let src = object.src.clone();
+    let remote = object.is_remote();
+    let decoder = args.decoder_type.build(args.decoder_path.clone());
 
     // We run the fetcher as a separate tokio task. Although it is an infinite loop, it has a lot of await breakpoints.
     // Fetcher will fetch data and send it over to the buffer.
     {
         let to_buf_sx = to_buf_sx.clone();
         let mut shutdown_recv = shutdown_recv.clone();
-        let mut throughput_predictor: Box<dyn ThroughputPrediction> =
-            match args.throughput_prediction_type {
-                ThroughputPredictionType::Last => Box::new(LastValue::new()),
-                ThroughputPredictionType::Avg => Box::new(SimpleRunningAverage::<f64, 3>::new()),
-                ThroughputPredictionType::Ema => {
-                    Box::new(ExponentialMovingAverage::new(args.throughput_alpha))
-                }
-                ThroughputPredictionType::Gaema => Box::new(GAEMA::new(args.throughput_alpha)),
-                ThroughputPredictionType::Lpema => Box::new(LPEMA::new(args.throughput_alpha)),
-            };
+        let session_log = session_log.clone();
+        let throughput_predictor: Box<dyn ThroughputPrediction> =
+            args.throughput_prediction_type.build(args.throughput_alpha);
 
         rt.spawn(async move {
-            if is_remote_src(&args.src) {
+            if remote {
                 let tmpdir = tempdir().expect("created temp dir to store files");
                 let path = tmpdir.path();
                 trace!("[fetcher] Downloading files to {}", path.to_str().unwrap());
 
-                let mut fetcher = Fetcher::new(&src, path, args.enable_fetcher_optimizations).await;
+                let mut fetcher = Fetcher::new(&src, path, args.enable_fetcher_optimizations)
+                    .await
+                    .unwrap_or_else(|e| {
+                        eprintln!("Failed to load MPD: {e:#}");
+                        std::process::exit(1);
+                    });
+                fetcher.set_retry_policy(RetryPolicy {
+                    max_retries: args.retries,
+                    ..Default::default()
+                });
+                if let Some(cache_size) = args.cache_size {
+                    if let Err(e) = fetcher.set_cache_capacity(cache_size * 1024 * 1024) {
+                        warn!("Failed to create segment cache: {:#}", e);
+                    }
+                }
+                let abandon_after = args.abandon_after.map(std::time::Duration::from_secs_f32);
+                let (segment_duration, segment_frames) = fetcher
+                    .mpd_parser
+                    .segment_duration()
+                    .and_then(|duration| Ok((duration, fetcher.mpd_parser.segment_frames()?)))
+                    .unwrap_or_else(|e| {
+                        eprintln!("Invalid MPD: {e:#}");
+                        std::process::exit(1);
+                    });
+                // for dynamic MPDs, the fetcher keeps the manifest and the live edge up to date
+                let live_edge = fetcher.mpd_parser.is_dynamic().then(LiveEdge::new);
+                if let Some(live_edge) = live_edge.as_ref() {
+                    live_edge.set(fetcher.live_edge());
+                }
                 total_frames_tx
                     .send((
                         fetcher.mpd_parser.total_frames(),
-                        fetcher.mpd_parser.segment_duration(),
+                        segment_duration,
+                        segment_frames,
+                        live_edge.clone(),
                     ))
                     .expect("sent total frames");
 
-
                 let qualities = fetcher
                     .mpd_parser
                     .get_qp()
+                    .unwrap_or_default()
                     .into_iter()
                     .map(|x| -> f32 {
                         if let (Some(geo_qp), Some(attr_qp)) = x {
@@ -171,92 +432,28 @@ fn main() {
                     })
                     .collect();
 
-                let abr: Box<dyn RateAdapter> = match args.abr_type {
-                    AbrType::Quetra => Box::new(Quetra::new(buffer_capacity, args.fps)),
-                    AbrType::Mckp => Box::new(MCKP::new(6, qualities)),
-                    AbrType::QuetraMultiview => {
-                        Box::new(QuetraMultiview::new(buffer_capacity, args.fps, 6, qualities))
-                    }
+                let views = if args.multiview {
+                    fetcher.views(0, 0)
+                } else {
+                    1
                 };
-
-                loop {
-                    tokio::select! {
-                        _ = shutdown_recv.changed() => {
-                            trace!("[fetcher] shutdown signal received");
-                             _ = tmpdir.close();
-                            break;
-                        },
-                        //if there is a fetch request for remote source, do something with the camera_pos and network throughput
-                        Some(req) = buf_in_rx.recv() => {
-                            let camera_pos = req.camera_pos.expect("camera position is always provided");
-
-                            // We start with a guess of 1Mbps network throughput.
-                            let network_throughput = if simulated_network_trace.is_none() {
-                                throughput_predictor.predict().unwrap_or(1_000_000.0)
-                            } else {
-                                simulated_network_trace.as_ref().unwrap().next() * 1024.0
-                            };
-
-                            let mut available_bitrates = vec![];
-                            if args.multiview {
-                                for i in 0..6 {
-                                    available_bitrates.push(fetcher.available_bitrates(
-                                        req.object_id,
-                                        req.frame_offset,
-                                        Some(i),
-                                    ));
-                                }
-                            } else {
-                                available_bitrates.push(fetcher.available_bitrates(
-                                    req.object_id,
-                                    req.frame_offset,
-                                    None,
-                                ));
-                            }
-
-                            let cosines = get_cosines(camera_pos);
-
-                            let quality = abr.select_quality(
-                                req.buffer_occupancy as u64,
-                                network_throughput,
-                                &available_bitrates,
-                                &cosines,
-                            );
-                            info!("buffer_occupancy: {}, network: {}, cosines: {:?}", req.buffer_occupancy, network_throughput, &cosines);
-
-                            // This is a retry loop, we should probably do *bounded* retry here instead of looping indefinitely.
-                            loop {
-                                trace!("[fetcher] trying request {:?}", &req);
-
-                                let p = fetcher
-                                    .download(req.object_id, req.frame_offset, &quality, args.multiview, if simulated_network_trace.is_some() { Some(network_throughput) } else { None })
-                                    .await;
-
-                                match p {
-                                    Ok(res) => {
-                                        // update throughput prediction
-                                        throughput_predictor.add(res.throughput);
-                                        // send the response to the decoder
-                                        _ = in_dec_sx.send((req.into(), res.clone()));
-                                        // let buffer know that we are done fetching
-                                        _ = to_buf_sx.send(BufMsg::FetchDone((req.into(), res.clone())));
-                                        break;
-                                    }
-                                    Err(e) => {
-                                        warn!("Error downloading file: {}", e)
-                                    }
-                                }
-                            }
-                        }
-                        else => {
-                            _ = tmpdir.close();
-                            break;
-                        }
-                    }
-                }
+                let abr = args
+                    .abr_type
+                    .build(buffer_capacity, args.fps, views, qualities);
+
+                FetchLoop::new(fetcher, abr, throughput_predictor)
+                    .with_multiview(args.multiview)
+                    .with_abandon_after(abandon_after)
+                    .with_network_trace(simulated_network_trace)
+                    .with_session_log(session_log)
+                    .with_live_edge(live_edge)
+                    .run(buf_in_rx, to_buf_sx, in_dec_sx, decode_time_rx, shutdown_recv)
+                    .await;
+                _ = tmpdir.close();
             } else {
                 //if the source is not remote, load the file and update the status as fetchdone
-                let path = Path::new(&args.src);
+                let play_format = infer_format(&src);
+                let path = Path::new(&src);
                 // This vector contains all the ply_files in one directory
                 let mut ply_files: Vec<PathBuf> = vec![];
                 debug!("1. Finished downloading to / reading from {:?}", path);
@@ -270,7 +467,7 @@ fn main() {
                     ply_files.push(f);
                 }
                 total_frames_tx
-                    .send((ply_files.len(), (1, 30)))
+                    .send((ply_files.len(), (1, 30), 1, None))
                     .expect("sent total frames");
                 ply_files.sort();
                 loop {
@@ -281,15 +478,12 @@ fn main() {
                         },
                         Some(req) = buf_in_rx.recv() => {
                             trace!("[fetcher] got fetch request {:?}", req);
-                            _ = in_dec_sx.send((req, FetchResult {
-                                paths: [ply_files.get(req.frame_offset as usize).map(|p| p.to_path_buf()), None, None, None, None, None],
-                                throughput: 0.0,
+                            _ = in_dec_sx.send((req.clone(), FetchResult {
+                                paths: vec![ply_files.get(req.frame_offset as usize).map(|p| p.to_path_buf())],
+                                ..Default::default()
                             })); // Use the cloned 'res' value
                             // let buffer know that we are done fetching
-                            _ = to_buf_sx.send(BufMsg::FetchDone((req.into(), FetchResult {
-                                paths: [None, None, None, None, None, None], // todo
-                                throughput: 0.0,
-                            }))); // Use the original 'res' value
+                            _ = to_buf_sx.send(BufMsg::FetchDone((req.into(), FetchResult::default()))); // Use the original 'res' value
                         }
                         else => break,
                     }
@@ -300,70 +494,16 @@ fn main() {
 
     // We run the decoder as a separate tokio task.
     // Decoder will read the buffer and send it over to the renderer.
-    {
-        let to_buf_sx = to_buf_sx.clone();
-        let mut shutdown_recv = shutdown_recv.clone();
-        rt.spawn(async move {
-            loop {
-                tokio::select! {
-                    _ = shutdown_recv.changed() => {
-                        trace!("[decoder] shutdown signal received");
-                        break;
-                    },
-                    Some((req, FetchResult {
-                        mut paths,
-                        throughput: _,
-                    })) = in_dec_rx.recv() => {
-                        debug!("got fetch result {:?}", req);
-                        let decoder_path = decoder_path.clone();
-                        let to_buf_sx = to_buf_sx.clone();
-                        tokio::task::spawn_blocking(move || {
-                            let mut decoder: Box<dyn Decoder> = match decoder_type {
-                                DecoderType::Draco => {
-                                    Box::new(DracoDecoder::new(
-                                    decoder_path
-                                        .as_ref()
-                                        .expect("must provide decoder path for Draco")
-                                        .as_os_str(),
-                                    paths[0].take().unwrap().as_os_str(),
-                                )) },
-                                #[cfg(feature = "with-tmc2-rs-decoder")]
-                                DecoderType::Tmc2rs => {
-                                    let paths = paths.into_iter().flatten().collect::<Vec<_>>();
-                                    Box::new(Tmc2rsDecoder::new(&paths))
-                                }
-                                _ =>{
-                                    Box::new(NoopDecoder::new(paths[0].take().unwrap().as_os_str()))
-                                },
-                            };
-                            decoder.start().unwrap();
-                            //t: the unbound receiver for PointCloud is here, trace this down
-                            // Everytime a PointCloud is ready, an unbounded channel is created
-                            // t: For case of Noop, only one PointCloud will be produced each time, not sure about other decoder
-                            let (output_sx, output_rx) = tokio::sync::mpsc::unbounded_channel();
-                            // Send BufMsg to inform the buffer that the PointCloud is ready
-                            _ = to_buf_sx
-                                .send(BufMsg::PointCloud((
-                                    PCMetadata {
-                                        frame_offset: req.frame_offset,
-                                        object_id: req.object_id,
-                                    },
-                                    output_rx,
-                                )));
-                            while let Some(pcd) = decoder.poll() {
-                                _ = output_sx.send(pcd);
-                            }
-                        })
-                        .await
-                        .unwrap();
-                    }
-                    else => break,
-                }
-            }
-        });
-    }
+    rt.spawn(decode_loop(
+        decoder,
+        in_dec_rx,
+        to_buf_sx.clone(),
+        decode_time_sx,
+        shutdown_recv.clone(),
+    ));
 
-    let (total_frames, segment_size) = total_frames_rx.blocking_recv().unwrap();
+    let (total_frames, segment_size, segment_frames, live_edge) =
+        total_frames_rx.blocking_recv().unwrap();
 
     let mut buffer = BufferManager::new(
         to_buf_rx,
@@ -375,6 +515,14 @@ fn main() {
         segment_size,
         shutdown_recv,
     );
+    if let Some(session_log) = session_log.clone() {
+        buffer.set_session_log(session_log);
+    }
+    if let Some(live_edge) = live_edge.clone() {
+        let latency = (args.live_latency * args.fps) as u64;
+        // segment_size is in timescale units, the playhead counts frames
+        buffer.set_live(LivePlayhead::new(live_edge, latency, segment_frames));
+    }
     let viewport_predictor: Box<dyn ViewportPrediction> = match args.viewport_prediction_type {
         ViewportPredictionType::Last => Box::new(LastValue::new()),
     };
@@ -394,77 +542,15 @@ fn main() {
             .await
     });
     // let mut pcd_reader = PcdAsyncReader::new(buf_out_rx, out_buf_sx, args.buffer_size);
-    let pcd_reader = PcdAsyncReader::new(buf_out_rx, to_buf_sx);
+    let pcd_reader = PcdAsyncReader::new(buf_out_rx, to_buf_sx)
+        .with_object_id(object.object_id)
+        .with_session_log(session_log);
     let mut pcd_manager = RenderReaderWrapper::new(pcd_reader);
-    // set the reader max length
-    pcd_manager.set_len(total_frames);
-
-    let camera = Camera::new(
-        (args.camera_x, args.camera_y, args.camera_z),
-        cgmath::Deg(args.camera_yaw),
-        cgmath::Deg(args.camera_pitch),
-    );
-    let metrics = args
-        .metrics
-        .map(|os_str| MetricsReader::from_directory(Path::new(&os_str)));
-
-    let mut builder = RenderBuilder::default();
-    let slider_end = pcd_manager.len() - 1;
-
-    // This is the main window that renders the point cloud
-    let render_window_id =
-    // if args.buffer_size > 1 {
-    //     builder.add_window(Renderer::new(
-    //         BufRenderReader::new(args.buffer_size, reader),
-    //         args.fps,
-    //         camera,
-    //         (args.width, args.height),
-    //         metrics,
-    //     ))
-    // } else {
-        builder.add_window(Renderer::new(
-            pcd_manager,
-            args.fps,
-            camera,
-            (args.width, args.height),
-            metrics,
-            args.bg_color.to_str().unwrap()
-        ));
-    // };
-    if args.show_controls {
-        let controls_window_id = builder.add_window(Controller { slider_end });
-        builder
-            .get_windowed_mut(render_window_id)
-            .unwrap()
-            .add_output(controls_window_id);
-        builder
-            .get_windowed_mut(controls_window_id)
-            .unwrap()
-            .add_output(render_window_id);
-    }
-
-    {
-        // We run the shutdown signal listener as a separate tokio task.
-        let event_proxy = builder.get_proxy();
-        let window_ids = builder.get_window_ids();
-        rt.spawn(async move {
-            match tokio::signal::ctrl_c().await {
-                Ok(()) => {
-                    for window_id in window_ids {
-                        event_proxy
-                            .send_event(RenderEvent::new(window_id, EventType::Shutdown))
-                            .unwrap();
-                    }
-                    shutdown_send.send(true).unwrap();
-                }
-                Err(err) => {
-                    eprintln!("Unable to listen for shutdown signal: {err}");
-                    // we also shut down in case of error
-                }
-            }
-        });
-    }
-
-    // In MacOS, renderer must run in main thread.
-    builder.run();
+    // set the reader max length. A live stream has no end, so the renderer just keeps counting.
+    pcd_manager.set_len(if live_edge.is_some() {
+        u32::MAX as usize
+    } else {
+        total_frames
+    });
+    pcd_manager
 }
@@ -1,8 +1,7 @@
-use crate::codec::Decoder;
+use crate::codec::{command_from_template, octree, sequence, Bitstream, Decoder};
 use crate::formats::pointxyzrgba::PointXyzRgba;
 use crate::formats::PointCloud;
 use crate::utils::read_file_to_point_cloud;
-use std::ffi::OsStr;
 use std::path::PathBuf;
 use std::process::Command;
 
@@ -11,147 +10,157 @@ use anyhow::{Error, Result};
 #[cfg(feature = "with-tmc2-rs-decoder")]
 use log::debug;
 
-pub struct NoopDecoder {
-    to_decode: PathBuf,
-    pcd: Option<PointCloud<PointXyzRgba>>,
-}
-
-impl NoopDecoder {
-    pub fn new(filename: &OsStr) -> Self {
-        NoopDecoder {
-            to_decode: PathBuf::from(filename),
-            pcd: None,
-        }
-    }
-}
+/// Reads uncoded point clouds, i.e. any file `read_file_to_point_cloud` understands.
+pub struct NoopDecoder;
 
 impl Decoder for NoopDecoder {
-    fn start(&mut self) -> Result<()> {
-        self.pcd = read_file_to_point_cloud(&self.to_decode);
-        self.pcd
-            .as_ref()
-            .map(|_| ())
-            .ok_or(Error::msg("Fail to read point cloud"))
-    }
-
-    fn poll(&mut self) -> Option<PointCloud<PointXyzRgba>> {
-        self.pcd.take()
+    fn decode_view(
+        &self,
+        bitstream: &Bitstream,
+        emit: &mut dyn FnMut(PointCloud<PointXyzRgba>),
+    ) -> Result<()> {
+        let file = bitstream.file()?;
+        let pcd =
+            read_file_to_point_cloud(file.path()).ok_or(Error::msg("Fail to read point cloud"))?;
+        emit(pcd);
+        Ok(())
     }
-
-    // fn decode_folder(&self, directory: &Path) -> Result<()> {
-    //     let path = Path::new(directory);
-    //     for file_entry in path.read_dir().unwrap() {
-    //         match file_entry {
-    //             Ok(_entry) => {}
-    //             Err(e) => return Err(Error::from(e)),
-    //         }
-    //     }
-    //     Ok(())
-    // }
 }
 
+/// Decodes by invoking the `draco_decoder` binary. It only works on files, so the bitstream and the
+/// decoded ply go through a temporary directory.
 pub struct DracoDecoder {
     path: PathBuf,
-    filename: PathBuf,
-    pcd: Option<PointCloud<PointXyzRgba>>,
 }
 
 impl DracoDecoder {
-    pub fn new<P, Q>(path: P, filename: Q) -> Self
+    pub fn new<P>(path: P) -> Self
     where
         P: Into<PathBuf>,
-        Q: Into<PathBuf>,
     {
-        DracoDecoder {
-            path: path.into(),
-            filename: filename.into(),
-            pcd: None,
-        }
+        DracoDecoder { path: path.into() }
     }
 }
 
 impl Decoder for DracoDecoder {
-    fn start(&mut self) -> Result<()> {
-        // filename must be full path
-        let mut output_filename = self.filename.clone();
-        output_filename.set_extension("ply");
+    fn decode_view(
+        &self,
+        bitstream: &Bitstream,
+        emit: &mut dyn FnMut(PointCloud<PointXyzRgba>),
+    ) -> Result<()> {
+        let input = bitstream.file()?;
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("output.ply");
         let status = Command::new(&self.path)
             .arg("-i")
-            .arg(&self.filename)
+            .arg(input.path())
             .arg("-o")
-            .arg(output_filename.to_str().unwrap())
-            .status();
-        if status.is_ok() {
-            self.pcd = read_file_to_point_cloud(&output_filename);
-            Ok(())
-        } else {
-            Err(Error::from(status.err().unwrap()))
+            .arg(&output)
+            .status()?;
+        if !status.success() {
+            return Err(Error::msg(format!(
+                "{:?} failed to decode {:?} ({status})",
+                self.path,
+                bitstream.path()
+            )));
         }
+        let pcd =
+            read_file_to_point_cloud(&output).ok_or(Error::msg("Fail to read point cloud"))?;
+        emit(pcd);
+        Ok(())
     }
+}
 
-    fn poll(&mut self) -> Option<PointCloud<PointXyzRgba>> {
-        self.pcd.take()
+/// Decodes frames coded with the native octree codec, see [`crate::codec::octree`], straight from
+/// the bytes. A bitstream can also be a whole sequence of frames, see [`crate::codec::sequence`],
+/// whose frames are emitted one by one.
+pub struct OctreeDecoder;
+
+impl Decoder for OctreeDecoder {
+    fn decode_view(
+        &self,
+        bitstream: &Bitstream,
+        emit: &mut dyn FnMut(PointCloud<PointXyzRgba>),
+    ) -> Result<()> {
+        let data = bitstream.bytes()?;
+        if sequence::is_sequence(&data) {
+            sequence::decode_frames(&data, emit)
+        } else {
+            emit(octree::decode(&data)?);
+            Ok(())
+        }
     }
+}
 
-    // fn decode_folder(&self, directory: &Path) -> Result<()> {
-    //     let path = Path::new(directory);
-    //     for file_entry in path.read_dir().unwrap() {
-    //         match file_entry {
-    //             Ok(entry) => {
-    //                 let path = entry.path();
-    //                 if path.is_file() {
-    //                     self.decode(path.into_os_string().as_os_str());
-    //                 }
-    //             }
-    //             Err(e) => return Err(Error::from(e)),
-    //         }
-    //     }
-    //     Ok(())
-    // }
+/// Decodes by running any command built from a template, see [`crate::codec::command_from_template`].
+///
+/// `{input}` is replaced by the bitstream and `{output}` by the ply file the command must write.
+pub struct ExternalDecoder {
+    template: String,
 }
 
-#[cfg(feature = "with-tmc2-rs-decoder")]
-pub struct Tmc2rsDecoder {
-    decoders: Vec<tmc2rs::Decoder>,
+impl ExternalDecoder {
+    pub fn new(template: &str) -> Self {
+        ExternalDecoder {
+            template: template.to_string(),
+        }
+    }
 }
 
-#[cfg(feature = "with-tmc2-rs-decoder")]
-impl Tmc2rsDecoder {
-    pub fn new(paths: &[PathBuf]) -> Self {
-        let decoders = paths
-            .iter()
-            .map(|path| tmc2rs::Decoder::new(tmc2rs::Params::new(path.to_owned())))
-            .collect::<Vec<_>>();
-        Tmc2rsDecoder { decoders }
+impl Decoder for ExternalDecoder {
+    fn decode_view(
+        &self,
+        bitstream: &Bitstream,
+        emit: &mut dyn FnMut(PointCloud<PointXyzRgba>),
+    ) -> Result<()> {
+        let input = bitstream.file()?;
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("output.ply");
+        let input_str = input.path().to_string_lossy();
+        let output_str = output.to_string_lossy();
+        let mut command = command_from_template(
+            &self.template,
+            &[
+                ("input", input_str.as_ref()),
+                ("output", output_str.as_ref()),
+            ],
+        )?;
+        let status = command.status()?;
+        if !status.success() || !output.exists() {
+            return Err(Error::msg(format!(
+                "{:?} failed to decode {:?} ({status})",
+                command.get_program(),
+                bitstream.path()
+            )));
+        }
+        let pcd =
+            read_file_to_point_cloud(&output).ok_or(Error::msg("Fail to read point cloud"))?;
+        emit(pcd);
+        Ok(())
     }
 }
 
+/// Decodes V-PCC bitstreams with tmc2-rs. It only reads files, so bitstreams in memory are written
+/// to a temporary file first.
+#[cfg(feature = "with-tmc2-rs-decoder")]
+pub struct Tmc2rsDecoder;
+
 #[cfg(feature = "with-tmc2-rs-decoder")]
 impl Decoder for Tmc2rsDecoder {
-    fn start(&mut self) -> Result<()> {
-        // start all decoders. This will run in parallel
-        for decoder in self.decoders.iter_mut() {
-            decoder.start();
+    fn decode_view(
+        &self,
+        bitstream: &Bitstream,
+        emit: &mut dyn FnMut(PointCloud<PointXyzRgba>),
+    ) -> Result<()> {
+        let file = bitstream.file()?;
+        let mut decoder = tmc2rs::Decoder::new(tmc2rs::Params::new(file.path().to_owned()));
+        decoder.start();
+        let mut now = std::time::Instant::now();
+        while let Some(frame) = decoder.recv_frame() {
+            debug!("Decoder took {} micros", now.elapsed().as_micros());
+            emit(PointCloud::from(frame));
+            now = std::time::Instant::now();
         }
         Ok(())
     }
-
-    fn poll(&mut self) -> Option<PointCloud<PointXyzRgba>> {
-        // assume all decoders have the same number of frames
-        let now = std::time::Instant::now();
-        let frame = self
-            .decoders
-            .iter()
-            .map(|decoder| decoder.recv_frame())
-            .map(|frame| frame.map(PointCloud::from))
-            .reduce(|mut acc, frame| {
-                acc.as_ref()?;
-                acc.as_mut().unwrap().combine(&frame.unwrap());
-                acc
-            })
-            .unwrap();
-        let elapsed = now.elapsed();
-        debug!("Decoder took {} micros", elapsed.as_micros());
-        frame
-    }
 }
@@ -0,0 +1,203 @@
+use crate::codec::octree::{self, OctreeParams};
+use crate::codec::{command_from_template, Encoder};
+use crate::formats::pointxyzrgba::PointXyzRgba;
+use crate::formats::PointCloud;
+use crate::pcd::{create_pcd, PCDDataType};
+use crate::utils::pcd_to_ply_from_data;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Error, Result};
+
+/// Writes the point cloud as a binary ply, the input format of the external encoders.
+fn write_ply(pc: &PointCloud<PointXyzRgba>, path: &Path) -> Result<()> {
+    pcd_to_ply_from_data(path, PCDDataType::Binary, create_pcd(pc))
+        .map_err(|e| Error::msg(format!("Fail to write {}: {e}", path.display())))
+}
+
+fn run(mut command: Command) -> Result<()> {
+    let status = command.status()?;
+    if !status.success() {
+        bail!("{:?} exited with {status}", command.get_program());
+    }
+    Ok(())
+}
+
+/// Encodes with the native octree codec, see [`crate::codec::octree`].
+pub struct OctreeEncoder {
+    params: OctreeParams,
+}
+
+impl OctreeEncoder {
+    pub fn new(params: OctreeParams) -> Self {
+        OctreeEncoder { params }
+    }
+}
+
+impl Encoder for OctreeEncoder {
+    fn encode(&self, pc: &PointCloud<PointXyzRgba>) -> Result<Vec<u8>> {
+        octree::encode(pc, &self.params)
+    }
+
+    fn extension(&self) -> &str {
+        octree::EXTENSION
+    }
+}
+
+/// Encodes by invoking the `draco_encoder` binary.
+pub struct DracoEncoder {
+    path: PathBuf,
+    quantization_bits: Option<u8>,
+    compression_level: Option<u8>,
+}
+
+impl DracoEncoder {
+    pub fn new<P>(path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        DracoEncoder {
+            path: path.into(),
+            quantization_bits: None,
+            compression_level: None,
+        }
+    }
+
+    /// Quantisation bits of the positions (`-qp`). Draco uses 11 by default.
+    pub fn set_quantization_bits(&mut self, bits: u8) {
+        self.quantization_bits = Some(bits);
+    }
+
+    /// Compression level between 0 and 10 (`-cl`). Draco uses 7 by default.
+    pub fn set_compression_level(&mut self, level: u8) {
+        self.compression_level = Some(level);
+    }
+}
+
+impl Encoder for DracoEncoder {
+    fn encode(&self, pc: &PointCloud<PointXyzRgba>) -> Result<Vec<u8>> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("input.ply");
+        let output = dir.path().join("output.drc");
+        write_ply(pc, &input)?;
+
+        let mut command = Command::new(&self.path);
+        command
+            .arg("-point_cloud")
+            .arg("-i")
+            .arg(&input)
+            .arg("-o")
+            .arg(&output);
+        if let Some(bits) = self.quantization_bits {
+            command.arg("-qp").arg(bits.to_string());
+        }
+        if let Some(level) = self.compression_level {
+            command.arg("-cl").arg(level.to_string());
+        }
+        run(command)?;
+        Ok(std::fs::read(output)?)
+    }
+
+    fn extension(&self) -> &str {
+        "drc"
+    }
+}
+
+/// Encodes by running any command built from a template, see [`crate::codec::command_from_template`].
+///
+/// `{input}` is replaced by a binary ply with the frame and `{output}` by the path the command
+/// must write the bitstream to. Extra variables, e.g. QPs, can be added with `set_var`.
+pub struct ExternalEncoder {
+    template: String,
+    extension: String,
+    vars: Vec<(String, String)>,
+}
+
+impl ExternalEncoder {
+    pub fn new(template: &str, extension: &str) -> Self {
+        ExternalEncoder {
+            template: template.to_string(),
+            extension: extension.to_string(),
+            vars: vec![],
+        }
+    }
+
+    pub fn set_var(&mut self, name: &str, value: &str) {
+        self.vars.push((name.to_string(), value.to_string()));
+    }
+}
+
+impl Encoder for ExternalEncoder {
+    fn encode(&self, pc: &PointCloud<PointXyzRgba>) -> Result<Vec<u8>> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("input.ply");
+        let output = dir.path().join(format!("output.{}", self.extension));
+        write_ply(pc, &input)?;
+
+        let input = input.to_string_lossy();
+        let output_str = output.to_string_lossy();
+        let mut vars = vec![("input", input.as_ref()), ("output", output_str.as_ref())];
+        vars.extend(
+            self.vars
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        );
+        run(command_from_template(&self.template, &vars)?)?;
+        Ok(std::fs::read(&output)?)
+    }
+
+    fn extension(&self) -> &str {
+        &self.extension
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::decoder::ExternalDecoder;
+    use crate::codec::{decode_blocking, Bitstream};
+    use std::sync::Arc;
+
+    fn sample_cloud() -> PointCloud<PointXyzRgba> {
+        let points: Vec<PointXyzRgba> = (0..100)
+            .map(|i| PointXyzRgba {
+                x: i as f32,
+                y: 0.5,
+                z: -1.0,
+                r: i as u8,
+                g: 0,
+                b: 255,
+                a: 255,
+            })
+            .collect();
+        PointCloud::new(points.len(), points)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_external_roundtrip() {
+        // `cp` is the identity codec: the bitstream is the ply file itself
+        let encoder = ExternalEncoder::new("cp {input} {output}", "ply");
+        let pc = sample_cloud();
+        let bitstream = encoder.encode(&pc).unwrap();
+
+        let decoder = ExternalDecoder::new("cp {input} {output}");
+        let name = format!("00000.{}", encoder.extension());
+        let frames = decode_blocking(
+            Arc::new(decoder),
+            vec![Bitstream::from_bytes(name, bitstream)],
+        )
+        .unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].points, pc.points);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_external_failure() {
+        let encoder = ExternalEncoder::new("false {input} {output}", "bin");
+        assert!(encoder.encode(&sample_cloud()).is_err());
+        let encoder = DracoEncoder::new("/nonexistent/draco_encoder");
+        assert!(encoder.encode(&sample_cloud()).is_err());
+    }
+}
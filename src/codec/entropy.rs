@@ -0,0 +1,288 @@
+//! Adaptive binary range coder shared by the native codecs.
+//!
+//! This is the LZMA flavour of range coding: 32-bit range, byte-wise renormalisation with carry
+//! propagation, and 11-bit adaptive probabilities for every binary decision.
+
+const PROB_BITS: u32 = 11;
+const PROB_ONE: u32 = 1 << PROB_BITS;
+const MOVE_BITS: u32 = 5;
+const TOP: u32 = 1 << 24;
+
+/// Adaptive probability that the next bit is a zero.
+#[derive(Debug, Clone, Copy)]
+pub struct BitModel(u16);
+
+impl Default for BitModel {
+    fn default() -> Self {
+        BitModel((PROB_ONE / 2) as u16)
+    }
+}
+
+impl BitModel {
+    fn update(&mut self, bit: bool) {
+        if bit {
+            self.0 -= self.0 >> MOVE_BITS;
+        } else {
+            self.0 += ((PROB_ONE - self.0 as u32) >> MOVE_BITS) as u16;
+        }
+    }
+}
+
+pub struct RangeEncoder {
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: u64,
+    out: Vec<u8>,
+}
+
+impl Default for RangeEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RangeEncoder {
+    pub fn new() -> Self {
+        RangeEncoder {
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            cache_size: 1,
+            out: vec![],
+        }
+    }
+
+    pub fn encode_bit(&mut self, model: &mut BitModel, bit: bool) {
+        let bound = (self.range >> PROB_BITS) * model.0 as u32;
+        if bit {
+            self.low += bound as u64;
+            self.range -= bound;
+        } else {
+            self.range = bound;
+        }
+        model.update(bit);
+        self.normalize();
+    }
+
+    /// Writes the lowest `bits` bits of `value` with a fixed probability of one half.
+    pub fn encode_direct(&mut self, value: u32, bits: u32) {
+        for i in (0..bits).rev() {
+            self.range >>= 1;
+            if (value >> i) & 1 == 1 {
+                self.low += self.range as u64;
+            }
+            self.normalize();
+        }
+    }
+
+    /// Flushes the coder and returns the coded bytes.
+    pub fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+        self.out
+    }
+
+    fn normalize(&mut self) {
+        while self.range < TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    fn shift_low(&mut self) {
+        if (self.low as u32) < 0xFF00_0000 || (self.low >> 32) != 0 {
+            let carry = (self.low >> 32) as u8;
+            let mut temp = self.cache;
+            loop {
+                self.out.push(temp.wrapping_add(carry));
+                temp = 0xFF;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                }
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00FF_FFFF) << 8;
+    }
+}
+
+pub struct RangeDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    range: u32,
+    code: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        let mut decoder = RangeDecoder {
+            data,
+            pos: 0,
+            range: u32::MAX,
+            code: 0,
+        };
+        for _ in 0..5 {
+            decoder.code = (decoder.code << 8) | decoder.next_byte() as u32;
+        }
+        decoder
+    }
+
+    pub fn decode_bit(&mut self, model: &mut BitModel) -> bool {
+        let bound = (self.range >> PROB_BITS) * model.0 as u32;
+        let bit = if self.code < bound {
+            self.range = bound;
+            false
+        } else {
+            self.code -= bound;
+            self.range -= bound;
+            true
+        };
+        model.update(bit);
+        self.normalize();
+        bit
+    }
+
+    pub fn decode_direct(&mut self, bits: u32) -> u32 {
+        let mut value = 0;
+        for _ in 0..bits {
+            self.range >>= 1;
+            let bit = self.code >= self.range;
+            if bit {
+                self.code -= self.range;
+            }
+            value = (value << 1) | bit as u32;
+            self.normalize();
+        }
+        value
+    }
+
+    /// Whether the decoder has read past the end of its input, which means the stream is truncated.
+    pub fn is_exhausted(&self) -> bool {
+        self.pos > self.data.len() + 4
+    }
+
+    fn normalize(&mut self) {
+        while self.range < TOP {
+            self.range <<= 8;
+            self.code = (self.code << 8) | self.next_byte() as u32;
+        }
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.data.get(self.pos).copied().unwrap_or(0);
+        self.pos += 1;
+        byte
+    }
+}
+
+/// Adaptive Exp-Golomb model for unsigned integers.
+///
+/// The length prefix is coded with one context per bit, and the mantissa goes in bypass mode.
+#[derive(Debug, Clone, Default)]
+pub struct UIntModel {
+    prefix: [BitModel; 32],
+}
+
+impl UIntModel {
+    pub fn encode(&mut self, encoder: &mut RangeEncoder, value: u32) {
+        let value = value as u64 + 1;
+        let bits = 64 - value.leading_zeros() - 1;
+        for i in 0..bits {
+            encoder.encode_bit(&mut self.prefix[i as usize], true);
+        }
+        if bits < 32 {
+            encoder.encode_bit(&mut self.prefix[bits as usize], false);
+        }
+        encoder.encode_direct((value & ((1 << bits) - 1)) as u32, bits);
+    }
+
+    pub fn decode(&mut self, decoder: &mut RangeDecoder) -> u32 {
+        let mut bits = 0;
+        while bits < 32 && decoder.decode_bit(&mut self.prefix[bits as usize]) {
+            bits += 1;
+        }
+        let value = (1u64 << bits) | decoder.decode_direct(bits) as u64;
+        (value - 1) as u32
+    }
+}
+
+/// Adaptive model for signed integers: a zero flag, a sign and the magnitude.
+#[derive(Debug, Clone, Default)]
+pub struct SIntModel {
+    zero: BitModel,
+    sign: BitModel,
+    magnitude: UIntModel,
+}
+
+impl SIntModel {
+    pub fn encode(&mut self, encoder: &mut RangeEncoder, value: i32) {
+        encoder.encode_bit(&mut self.zero, value == 0);
+        if value != 0 {
+            encoder.encode_bit(&mut self.sign, value < 0);
+            self.magnitude.encode(encoder, value.unsigned_abs() - 1);
+        }
+    }
+
+    pub fn decode(&mut self, decoder: &mut RangeDecoder) -> i32 {
+        if decoder.decode_bit(&mut self.zero) {
+            return 0;
+        }
+        let negative = decoder.decode_bit(&mut self.sign);
+        let magnitude = self.magnitude.decode(decoder) as i64 + 1;
+        (if negative { -magnitude } else { magnitude }) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits_roundtrip() {
+        let bits: Vec<bool> = (0..10_000u32).map(|i| (i * 7919) % 13 < 3).collect();
+        let mut models = [BitModel::default(); 4];
+        let mut encoder = RangeEncoder::new();
+        for (i, bit) in bits.iter().enumerate() {
+            encoder.encode_bit(&mut models[i % 4], *bit);
+            encoder.encode_direct(i as u32, 3);
+        }
+        let data = encoder.finish();
+        // the skewed bits should compress well below one bit each
+        assert!(data.len() < 10_000 * 4 / 8);
+
+        let mut models = [BitModel::default(); 4];
+        let mut decoder = RangeDecoder::new(&data);
+        for (i, bit) in bits.iter().enumerate() {
+            assert_eq!(decoder.decode_bit(&mut models[i % 4]), *bit);
+            assert_eq!(decoder.decode_direct(3), i as u32 & 7);
+        }
+        assert!(!decoder.is_exhausted());
+    }
+
+    #[test]
+    fn test_integers_roundtrip() {
+        let values = [0, 1, -1, 2, -300, 65_535, i32::MAX, i32::MIN + 1, 7, 0, 0];
+        let mut unsigned = UIntModel::default();
+        let mut signed = SIntModel::default();
+        let mut encoder = RangeEncoder::new();
+        for value in values {
+            unsigned.encode(&mut encoder, value.unsigned_abs());
+            signed.encode(&mut encoder, value);
+        }
+        unsigned.encode(&mut encoder, u32::MAX);
+        let data = encoder.finish();
+
+        let mut unsigned = UIntModel::default();
+        let mut signed = SIntModel::default();
+        let mut decoder = RangeDecoder::new(&data);
+        for value in values {
+            assert_eq!(unsigned.decode(&mut decoder), value.unsigned_abs());
+            assert_eq!(signed.decode(&mut decoder), value);
+        }
+        assert_eq!(unsigned.decode(&mut decoder), u32::MAX);
+    }
+}
@@ -1,12 +1,292 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::formats::pointxyzrgba::PointXyzRgba;
 use crate::formats::PointCloud;
 
 pub mod decoder;
+pub mod encoder;
+pub mod entropy;
+pub mod octree;
+pub mod sequence;
 
-pub trait Decoder {
-    fn start(&mut self) -> Result<()>;
-    fn poll(&mut self) -> Option<PointCloud<PointXyzRgba>>;
-    // fn decode_folder(&self, foldername: &Path) -> Result<()>;
+/// The coded data of one view of a segment, either in memory or in a file.
+#[derive(Clone)]
+pub struct Bitstream {
+    /// where the bitstream is, or would be, stored. The extension tells its format.
+    path: PathBuf,
+    /// the bytes, if they are already in memory, e.g. straight from the network
+    data: Option<Arc<[u8]>>,
+}
+
+impl Bitstream {
+    pub fn from_file<P: Into<PathBuf>>(path: P) -> Self {
+        Bitstream {
+            path: path.into(),
+            data: None,
+        }
+    }
+
+    /// `name` gives the format by its extension, and the file name if the bytes ever need to be
+    /// written to a file. Nothing is read from it.
+    pub fn from_bytes<P: Into<PathBuf>, D: Into<Arc<[u8]>>>(name: P, data: D) -> Self {
+        Bitstream {
+            path: name.into(),
+            data: Some(data.into()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn extension(&self) -> Option<&str> {
+        self.path.extension().and_then(|ext| ext.to_str())
+    }
+
+    /// The bytes of the bitstream, read from the file if they are not in memory.
+    pub fn bytes(&self) -> Result<Arc<[u8]>> {
+        match &self.data {
+            Some(data) => Ok(data.clone()),
+            None => Ok(std::fs::read(&self.path)
+                .with_context(|| format!("failed to read {}", self.path.display()))?
+                .into()),
+        }
+    }
+
+    /// A file with the bitstream, for decoders that can only read files.
+    /// Bytes in memory are written to a temporary file, which lives as long as the returned value.
+    pub fn file(&self) -> Result<BitstreamFile> {
+        let Some(data) = &self.data else {
+            return Ok(BitstreamFile {
+                path: self.path.clone(),
+                _dir: None,
+            });
+        };
+        let dir = tempfile::tempdir()?;
+        let path = dir
+            .path()
+            .join(self.path.file_name().unwrap_or("bitstream".as_ref()));
+        std::fs::write(&path, data)?;
+        Ok(BitstreamFile {
+            path,
+            _dir: Some(dir),
+        })
+    }
+}
+
+/// See [`Bitstream::file`].
+pub struct BitstreamFile {
+    path: PathBuf,
+    _dir: Option<tempfile::TempDir>,
+}
+
+impl BitstreamFile {
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+/// A decoded frame and how long it took to decode.
+pub struct DecodedFrame {
+    pub pc: PointCloud<PointXyzRgba>,
+    /// decoding time of the slowest view, since the views are decoded concurrently
+    pub decode_time: Duration,
+}
+
+/// The frames of a segment in presentation order, as soon as they are decoded.
+/// A view that fails to decode yields an error, and the remaining views carry on.
+pub type FrameStream = tokio::sync::mpsc::UnboundedReceiver<Result<DecodedFrame>>;
+
+pub trait Decoder: Send + Sync {
+    /// Decodes one view of a segment, passing every frame to `emit` as soon as it is decoded.
+    fn decode_view(
+        &self,
+        bitstream: &Bitstream,
+        emit: &mut dyn FnMut(PointCloud<PointXyzRgba>),
+    ) -> Result<()>;
+}
+
+/// Decodes the views of a segment concurrently and merges them into one point cloud per frame.
+///
+/// Decoding runs on threads of its own, so the stream can be consumed from async code with `recv`
+/// as well as from synchronous code with `blocking_recv`.
+pub fn decode_segment(decoder: Arc<dyn Decoder>, views: Vec<Bitstream>) -> FrameStream {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let receivers: Vec<_> = views
+        .into_iter()
+        .map(|bitstream| {
+            let decoder = decoder.clone();
+            let (view_tx, view_rx) = std::sync::mpsc::channel();
+            std::thread::spawn(move || {
+                let mut last = Instant::now();
+                let result = decoder.decode_view(&bitstream, &mut |pc| {
+                    _ = view_tx.send(Ok((pc, last.elapsed())));
+                    last = Instant::now();
+                });
+                if let Err(e) = result {
+                    let path = bitstream.path().display();
+                    _ = view_tx.send(Err(e.context(format!("failed to decode {path}"))));
+                }
+            });
+            Some(view_rx)
+        })
+        .collect();
+
+    std::thread::spawn(move || {
+        let mut receivers = receivers;
+        loop {
+            let mut points = vec![];
+            let mut decode_time = Duration::ZERO;
+            let mut decoded = false;
+            for receiver in receivers.iter_mut() {
+                let Some(view_rx) = receiver else {
+                    continue;
+                };
+                match view_rx.recv() {
+                    Ok(Ok((pc, time))) => {
+                        points.extend(pc.points);
+                        decode_time = decode_time.max(time);
+                        decoded = true;
+                    }
+                    Ok(Err(e)) => {
+                        _ = tx.send(Err(e));
+                        *receiver = None;
+                    }
+                    // the view has no more frames
+                    Err(_) => *receiver = None,
+                }
+            }
+            if !decoded {
+                break;
+            }
+            let frame = DecodedFrame {
+                pc: PointCloud::new(points.len(), points),
+                decode_time,
+            };
+            if tx.send(Ok(frame)).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Decodes every frame of a segment from synchronous code, e.g. a pipeline stage.
+/// Must not be called from within an async runtime.
+pub fn decode_blocking(
+    decoder: Arc<dyn Decoder>,
+    views: Vec<Bitstream>,
+) -> Result<Vec<PointCloud<PointXyzRgba>>> {
+    let mut stream = decode_segment(decoder, views);
+    let mut frames = vec![];
+    while let Some(frame) = stream.blocking_recv() {
+        frames.push(frame?.pc);
+    }
+    Ok(frames)
+}
+
+pub trait Encoder: Send + Sync {
+    /// Encodes one frame into a bitstream.
+    fn encode(&self, pc: &PointCloud<PointXyzRgba>) -> Result<Vec<u8>>;
+    /// File extension of the bitstreams, without the dot.
+    fn extension(&self) -> &str;
+}
+
+/// Builds a command from a template such as `my_encoder -i {input} -o {output}`.
+///
+/// The template is split on whitespace first, so substituted paths may contain spaces.
+/// Every `{name}` of `vars` is replaced by its value.
+pub(crate) fn command_from_template(template: &str, vars: &[(&str, &str)]) -> Result<Command> {
+    let mut tokens = template.split_whitespace().map(|token| {
+        vars.iter().fold(token.to_string(), |token, (name, value)| {
+            token.replace(&format!("{{{name}}}"), value)
+        })
+    });
+    let Some(program) = tokens.next() else {
+        bail!("empty command template");
+    };
+    let mut command = Command::new(program);
+    command.args(tokens);
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::pointxyzrgba::fixtures::{cloud, point};
+
+    #[test]
+    fn test_command_from_template() {
+        let command = command_from_template(
+            "enc  -i {input} -o={output} -q {qp}",
+            &[
+                ("input", "/tmp/a b.ply"),
+                ("output", "out.bin"),
+                ("qp", "3"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(command.get_program(), "enc");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["-i", "/tmp/a b.ply", "-o=out.bin", "-q", "3"]);
+        assert!(command_from_template("  ", &[]).is_err());
+    }
+
+    /// `n` points stacked along y at `x`
+    fn column(x: f32, n: usize) -> PointCloud<PointXyzRgba> {
+        cloud((0..n).map(|i| point(x, i as f32, 0.0)))
+    }
+
+    #[test]
+    fn test_decode_segment() {
+        let params = octree::OctreeParams::default();
+        // one view with a single frame, one with a sequence of three frames and a broken one
+        let single = octree::encode(&column(0.0, 10), &params).unwrap();
+        let frames: Vec<_> = (1..4).map(|n| column(1.0, n * 5)).collect();
+        let seq = sequence::encode(&frames, &params).unwrap();
+        let views = vec![
+            Bitstream::from_bytes("0.vvo", single),
+            Bitstream::from_bytes("1.vvs", seq),
+            Bitstream::from_bytes("2.vvo", b"broken".to_vec()),
+        ];
+        let mut stream = decode_segment(Arc::new(decoder::OctreeDecoder), views);
+
+        let mut sizes = vec![];
+        let mut errors = 0;
+        while let Some(frame) = stream.blocking_recv() {
+            match frame {
+                Ok(frame) => sizes.push(frame.pc.number_of_points),
+                Err(_) => errors += 1,
+            }
+        }
+        // the views are merged frame by frame
+        assert_eq!(sizes, vec![15, 10, 15]);
+        assert_eq!(errors, 1);
+    }
+
+    #[test]
+    fn test_bitstream() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.vvo");
+        std::fs::write(&path, b"data").unwrap();
+        let on_disk = Bitstream::from_file(&path);
+        assert_eq!(&*on_disk.bytes().unwrap(), b"data");
+        assert_eq!(on_disk.file().unwrap().path(), &path);
+
+        let in_memory = Bitstream::from_bytes("segments/b.drc", b"bytes".to_vec());
+        assert_eq!(in_memory.extension(), Some("drc"));
+        let file = in_memory.file().unwrap();
+        assert!(file.path().ends_with("b.drc"));
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"bytes");
+        let temporary = file.path().clone();
+        drop(file);
+        assert!(!temporary.exists());
+        assert!(Bitstream::from_file(dir.path().join("missing"))
+            .bytes()
+            .is_err());
+    }
 }
@@ -0,0 +1,640 @@
+//! Native point cloud codec: octree occupancy coding for geometry and DPCM for colours.
+//!
+//! Positions are voxelised on a `2^depth` grid over the bounding box, coarsened by the geometry QP,
+//! and the occupied voxels are coded breadth-first as one occupancy byte per octree node.
+//! Points falling into the same voxel are merged and their colours averaged.
+//! Colours are then visited in Morton order, predicted from the previous reconstructed colour and
+//! the residuals are quantised with a uniform step derived from the attribute QP.
+//!
+//! A stream starts with a fixed 28 byte header:
+//!
+//! | bytes | content |
+//! |-------|---------|
+//! | 4     | magic `VVOC` |
+//! | 1     | version |
+//! | 1     | octree depth |
+//! | 1     | geometry QP |
+//! | 1     | attribute QP |
+//! | 4     | number of points (u32, little endian) |
+//! | 12    | origin (3 x f32) |
+//! | 4     | voxel size (f32) |
+//!
+//! followed by the range coded payload. Sequences of frames use the same header with their own
+//! magic, see [`super::sequence`].
+
+use anyhow::{bail, Result};
+
+use super::entropy::{BitModel, RangeDecoder, RangeEncoder, SIntModel};
+use crate::formats::pointxyzrgba::PointXyzRgba;
+use crate::formats::PointCloud;
+
+pub(crate) const MAGIC: &[u8; 4] = b"VVOC";
+const VERSION: u8 = 1;
+pub(crate) const HEADER_SIZE: usize = 28;
+/// Morton codes are stored in a u64, so every axis can use at most 21 bits.
+pub const MAX_DEPTH: u8 = 21;
+pub const MAX_QP: u8 = 51;
+/// File extension used for encoded frames.
+pub const EXTENSION: &str = "vvo";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OctreeParams {
+    /// Precision of the voxel grid, in bits per axis.
+    pub depth: u8,
+    /// Geometry quantisation. 4 keeps the `2^depth` grid, every +6 doubles the voxel size.
+    pub geometry_qp: u8,
+    /// Colour quantisation. 4 and below is lossless, every +6 doubles the quantisation step.
+    pub attribute_qp: u8,
+}
+
+impl Default for OctreeParams {
+    fn default() -> Self {
+        OctreeParams {
+            depth: 10,
+            geometry_qp: 4,
+            attribute_qp: 4,
+        }
+    }
+}
+
+/// Quantisation step of a QP, following the video codec convention of doubling every 6 steps.
+pub fn qp_to_step(qp: u8) -> f32 {
+    2f32.powf((qp as f32 - 4.0) / 6.0)
+}
+
+/// An occupied voxel: its Morton code on the grid and its colour.
+pub(crate) type Leaf = (u64, [u8; 4]);
+
+/// Voxel grid the positions are quantised on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Grid {
+    pub origin: [f32; 3],
+    /// size of a voxel
+    pub step: f32,
+}
+
+impl Grid {
+    /// Grid over the bounding box of `points`, at the precision of `params`.
+    pub fn fit<'a>(
+        points: impl IntoIterator<Item = &'a PointXyzRgba>,
+        params: &OctreeParams,
+    ) -> Grid {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        let mut empty = true;
+        for p in points {
+            for (axis, v) in [p.x, p.y, p.z].into_iter().enumerate() {
+                min[axis] = min[axis].min(v);
+                max[axis] = max[axis].max(v);
+            }
+            empty = false;
+        }
+        if empty {
+            min = [0.0; 3];
+            max = [0.0; 3];
+        }
+        let extent = (0..3)
+            .map(|axis| max[axis] - min[axis])
+            .fold(0f32, f32::max);
+        let voxel = if extent > 0.0 {
+            extent / ((1u32 << params.depth) - 1) as f32
+        } else {
+            1.0
+        };
+        Grid {
+            origin: min,
+            step: voxel * qp_to_step(params.geometry_qp),
+        }
+    }
+
+    /// Leaves sorted by Morton code. Points that share a voxel are merged and their colours averaged.
+    pub fn voxelise(&self, points: &[PointXyzRgba]) -> Vec<Leaf> {
+        let mut voxels: Vec<(u64, PointXyzRgba)> = points
+            .iter()
+            .map(|p| {
+                let q = |v: f32, axis: usize| ((v - self.origin[axis]) / self.step).round() as u32;
+                (morton_encode(q(p.x, 0), q(p.y, 1), q(p.z, 2)), *p)
+            })
+            .collect();
+        voxels.sort_unstable_by_key(|(code, _)| *code);
+        let mut leaves: Vec<Leaf> = Vec::with_capacity(voxels.len());
+        for group in voxels.chunk_by(|a, b| a.0 == b.0) {
+            let n = group.len() as u32;
+            let mut sum = [0u32; 4];
+            for (_, p) in group {
+                for (s, c) in sum.iter_mut().zip([p.r, p.g, p.b, p.a]) {
+                    *s += c as u32;
+                }
+            }
+            leaves.push((group[0].0, sum.map(|s| ((s + n / 2) / n) as u8)));
+        }
+        leaves
+    }
+
+    pub fn point(&self, code: u64, colour: [u8; 4]) -> PointXyzRgba {
+        let (x, y, z) = morton_decode(code);
+        PointXyzRgba {
+            x: self.origin[0] + x as f32 * self.step,
+            y: self.origin[1] + y as f32 * self.step,
+            z: self.origin[2] + z as f32 * self.step,
+            r: colour[0],
+            g: colour[1],
+            b: colour[2],
+            a: colour[3],
+        }
+    }
+}
+
+/// The fixed size header of frames and sequences, see the module documentation.
+pub(crate) struct Header {
+    pub depth: u8,
+    pub geometry_qp: u8,
+    pub attribute_qp: u8,
+    /// number of points of a frame, or of frames of a sequence
+    pub count: u32,
+    pub grid: Grid,
+}
+
+impl Header {
+    pub fn write(&self, magic: &[u8; 4], out: &mut Vec<u8>) {
+        out.extend_from_slice(magic);
+        out.extend_from_slice(&[VERSION, self.depth, self.geometry_qp, self.attribute_qp]);
+        out.extend_from_slice(&self.count.to_le_bytes());
+        for v in self.grid.origin {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        out.extend_from_slice(&self.grid.step.to_le_bytes());
+    }
+
+    /// Parses the header of `data`, which must start with `magic`.
+    pub fn read(magic: &[u8; 4], data: &[u8]) -> Result<Header> {
+        if data.len() < HEADER_SIZE || &data[..4] != magic {
+            bail!(
+                "not an octree coded stream, expected {}",
+                String::from_utf8_lossy(magic)
+            );
+        }
+        if data[4] != VERSION {
+            bail!("unsupported octree codec version {}", data[4]);
+        }
+        let depth = data[5];
+        if depth > MAX_DEPTH {
+            bail!("invalid octree depth {depth}");
+        }
+        let read_f32 =
+            |offset: usize| f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        Ok(Header {
+            depth,
+            geometry_qp: data[6],
+            attribute_qp: data[7],
+            count: u32::from_le_bytes(data[8..12].try_into().unwrap()),
+            grid: Grid {
+                origin: [read_f32(12), read_f32(16), read_f32(20)],
+                step: read_f32(24),
+            },
+        })
+    }
+}
+
+pub(crate) fn check_params(params: &OctreeParams) -> Result<()> {
+    if params.depth == 0 || params.depth > MAX_DEPTH {
+        bail!("octree depth must be between 1 and {MAX_DEPTH}");
+    }
+    if params.geometry_qp > MAX_QP || params.attribute_qp > MAX_QP {
+        bail!("QPs must be at most {MAX_QP}");
+    }
+    // below QP 4 voxels are smaller than the depth alone gives, the grid must still fit in a Morton code
+    let max_coord = ((1u32 << params.depth) - 1) as f32 / qp_to_step(params.geometry_qp);
+    if max_coord.round() > ((1u32 << MAX_DEPTH) - 1) as f32 {
+        bail!(
+            "geometry QP {} is too fine for depth {}",
+            params.geometry_qp,
+            params.depth
+        );
+    }
+    Ok(())
+}
+
+/// Number of octree levels needed to reach the voxel with the largest Morton code.
+pub(crate) fn tree_depth(max_code: u64) -> u8 {
+    (64 - max_code.leading_zeros()).div_ceil(3) as u8
+}
+
+pub fn encode(pc: &PointCloud<PointXyzRgba>, params: &OctreeParams) -> Result<Vec<u8>> {
+    check_params(params)?;
+    let grid = Grid::fit(&pc.points, params);
+    let leaves = grid.voxelise(&pc.points);
+    let depth = tree_depth(leaves.last().map(|(code, _)| *code).unwrap_or(0));
+
+    let mut out = Vec::with_capacity(HEADER_SIZE);
+    Header {
+        depth,
+        geometry_qp: params.geometry_qp,
+        attribute_qp: params.attribute_qp,
+        count: leaves.len() as u32,
+        grid,
+    }
+    .write(MAGIC, &mut out);
+
+    let mut encoder = RangeEncoder::new();
+    encode_geometry(&mut encoder, &leaves, depth);
+    let mut colours = ColourCoder::new(params.attribute_qp);
+    for (_, colour) in &leaves {
+        colours.encode(&mut encoder, *colour, None);
+    }
+    out.extend(encoder.finish());
+    Ok(out)
+}
+
+pub fn decode(data: &[u8]) -> Result<PointCloud<PointXyzRgba>> {
+    let header = Header::read(MAGIC, data)?;
+    let num_points = header.count as usize;
+    let mut decoder = RangeDecoder::new(&data[HEADER_SIZE..]);
+    let codes = decode_geometry(&mut decoder, header.depth, num_points)?;
+    let mut colours = ColourCoder::new(header.attribute_qp);
+    let points: Vec<PointXyzRgba> = codes
+        .into_iter()
+        .map(|code| header.grid.point(code, colours.decode(&mut decoder, None)))
+        .collect();
+    if decoder.is_exhausted() {
+        bail!("truncated octree coded point cloud");
+    }
+    Ok(PointCloud::new(points.len(), points))
+}
+
+/// Codes the occupancy bytes of the octree over the sorted `leaves`, breadth-first.
+pub(crate) fn encode_geometry(encoder: &mut RangeEncoder, leaves: &[Leaf], depth: u8) {
+    let mut occupancy_models = OccupancyModels::default();
+    // popcount of the parent occupancy of every node on the current level, used as context
+    let mut contexts = vec![0u8];
+    for level in (0..depth as u32).rev() {
+        let mut next_contexts = vec![];
+        let mut node_iter = leaves
+            .iter()
+            .map(|(code, _)| code >> (3 * level))
+            .peekable();
+        let mut node = 0;
+        while let Some(child) = node_iter.next() {
+            let mut occupancy = 1u8 << (child & 7);
+            while let Some(next) = node_iter.peek() {
+                if next >> 3 != child >> 3 {
+                    break;
+                }
+                occupancy |= 1 << (next & 7);
+                node_iter.next();
+            }
+            occupancy_models.encode(encoder, contexts[node], occupancy);
+            let count = occupancy.count_ones();
+            next_contexts.extend(std::iter::repeat_n(count as u8, count as usize));
+            node += 1;
+        }
+        contexts = next_contexts;
+    }
+}
+
+/// Decodes the Morton codes of the `num_points` leaves, in increasing order.
+pub(crate) fn decode_geometry(
+    decoder: &mut RangeDecoder,
+    depth: u8,
+    num_points: usize,
+) -> Result<Vec<u64>> {
+    let mut occupancy_models = OccupancyModels::default();
+    let mut nodes = vec![0u64];
+    let mut contexts = vec![0u8];
+    if num_points == 0 {
+        nodes.clear();
+    }
+    for _ in 0..depth {
+        let mut next_nodes = vec![];
+        let mut next_contexts = vec![];
+        for (node, context) in nodes.iter().zip(&contexts) {
+            let occupancy = occupancy_models.decode(decoder, *context);
+            let count = occupancy.count_ones() as u8;
+            for child in 0..8 {
+                if occupancy & (1 << child) != 0 {
+                    next_nodes.push(node << 3 | child);
+                    next_contexts.push(count);
+                }
+            }
+            if next_nodes.len() > num_points || decoder.is_exhausted() {
+                bail!("corrupted octree: more nodes than the {num_points} points in the header");
+            }
+        }
+        nodes = next_nodes;
+        contexts = next_contexts;
+    }
+    if nodes.len() != num_points {
+        bail!(
+            "corrupted octree: decoded {} points, expected {num_points}",
+            nodes.len()
+        );
+    }
+    Ok(nodes)
+}
+
+/// Colour coder of the leaves in Morton order.
+///
+/// Colours are predicted from the colour of the same voxel in a reference frame if there is one,
+/// and from the previous reconstructed colour otherwise. The residuals are quantised with a
+/// uniform step derived from the attribute QP. Colours predicted from the reference start with a
+/// flag telling whether they are unchanged.
+pub(crate) struct ColourCoder {
+    step: f32,
+    /// models of the residuals predicted within the frame and from the reference
+    models: [[SIntModel; 4]; 2],
+    unchanged: BitModel,
+    prev: [u8; 4],
+}
+
+impl ColourCoder {
+    pub fn new(attribute_qp: u8) -> Self {
+        ColourCoder {
+            step: qp_to_step(attribute_qp).max(1.0),
+            models: Default::default(),
+            unchanged: BitModel::default(),
+            prev: [0; 4],
+        }
+    }
+
+    /// Codes `colour` and returns its reconstruction.
+    pub fn encode(
+        &mut self,
+        encoder: &mut RangeEncoder,
+        colour: [u8; 4],
+        reference: Option<[u8; 4]>,
+    ) -> [u8; 4] {
+        let prediction = reference.unwrap_or(self.prev);
+        let q: [i32; 4] = std::array::from_fn(|channel| {
+            let residual = colour[channel] as f32 - prediction[channel] as f32;
+            // ties go towards zero, so that a static colour does not flip between two levels
+            ((residual.abs() / self.step - 0.5).ceil().max(0.0) * residual.signum()) as i32
+        });
+        if reference.is_some() {
+            let unchanged = q == [0; 4];
+            encoder.encode_bit(&mut self.unchanged, unchanged);
+            if unchanged {
+                self.prev = prediction;
+                return prediction;
+            }
+        }
+        let models = &mut self.models[reference.is_some() as usize];
+        for channel in 0..4 {
+            models[channel].encode(encoder, q[channel]);
+            self.prev[channel] = dequantise(prediction[channel], q[channel], self.step);
+        }
+        self.prev
+    }
+
+    pub fn decode(&mut self, decoder: &mut RangeDecoder, reference: Option<[u8; 4]>) -> [u8; 4] {
+        let prediction = reference.unwrap_or(self.prev);
+        if reference.is_some() && decoder.decode_bit(&mut self.unchanged) {
+            self.prev = prediction;
+            return prediction;
+        }
+        let models = &mut self.models[reference.is_some() as usize];
+        for channel in 0..4 {
+            let q = models[channel].decode(decoder);
+            self.prev[channel] = dequantise(prediction[channel], q, self.step);
+        }
+        self.prev
+    }
+}
+
+fn dequantise(prediction: u8, q: i32, step: f32) -> u8 {
+    (prediction as f32 + q as f32 * step)
+        .round()
+        .clamp(0.0, 255.0) as u8
+}
+
+/// Occupancy bytes are coded bit by bit with a binary tree of contexts,
+/// one tree per number of occupied siblings of the parent node.
+struct OccupancyModels {
+    models: Vec<[BitModel; 256]>,
+}
+
+impl Default for OccupancyModels {
+    fn default() -> Self {
+        OccupancyModels {
+            models: vec![[BitModel::default(); 256]; 9],
+        }
+    }
+}
+
+impl OccupancyModels {
+    fn encode(&mut self, encoder: &mut RangeEncoder, context: u8, occupancy: u8) {
+        let models = &mut self.models[context as usize];
+        let mut node = 1;
+        for i in (0..8).rev() {
+            let bit = (occupancy >> i) & 1 == 1;
+            encoder.encode_bit(&mut models[node], bit);
+            node = node * 2 + bit as usize;
+        }
+    }
+
+    fn decode(&mut self, decoder: &mut RangeDecoder, context: u8) -> u8 {
+        let models = &mut self.models[context as usize];
+        let mut node = 1;
+        for _ in 0..8 {
+            let bit = decoder.decode_bit(&mut models[node]);
+            node = node * 2 + bit as usize;
+        }
+        node as u8
+    }
+}
+
+fn spread_bits(v: u32) -> u64 {
+    debug_assert!(
+        v <= 0x1F_FFFF,
+        "coordinate {v} does not fit in a Morton code"
+    );
+    let mut v = v as u64 & 0x1F_FFFF;
+    v = (v | v << 32) & 0x001F_0000_0000_FFFF;
+    v = (v | v << 16) & 0x001F_0000_FF00_00FF;
+    v = (v | v << 8) & 0x100F_00F0_0F00_F00F;
+    v = (v | v << 4) & 0x10C3_0C30_C30C_30C3;
+    v = (v | v << 2) & 0x1249_2492_4924_9249;
+    v
+}
+
+fn compact_bits(v: u64) -> u32 {
+    let mut v = v & 0x1249_2492_4924_9249;
+    v = (v | v >> 2) & 0x10C3_0C30_C30C_30C3;
+    v = (v | v >> 4) & 0x100F_00F0_0F00_F00F;
+    v = (v | v >> 8) & 0x001F_0000_FF00_00FF;
+    v = (v | v >> 16) & 0x001F_0000_0000_FFFF;
+    v = (v | v >> 32) & 0x1F_FFFF;
+    v as u32
+}
+
+fn morton_encode(x: u32, y: u32, z: u32) -> u64 {
+    spread_bits(x) << 2 | spread_bits(y) << 1 | spread_bits(z)
+}
+
+fn morton_decode(code: u64) -> (u32, u32, u32) {
+    (
+        compact_bits(code >> 2),
+        compact_bits(code >> 1),
+        compact_bits(code),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_cloud() -> PointCloud<PointXyzRgba> {
+        let points: Vec<PointXyzRgba> = (0..2000)
+            .map(|i| {
+                let t = i as f32 / 2000.0 * std::f32::consts::TAU;
+                PointXyzRgba {
+                    x: t.cos(),
+                    y: (i % 50) as f32 / 25.0,
+                    z: t.sin() * 0.5,
+                    r: (i % 256) as u8,
+                    g: 128,
+                    b: (255 - i % 256) as u8,
+                    a: 255,
+                }
+            })
+            .collect();
+        PointCloud::new(points.len(), points)
+    }
+
+    #[test]
+    fn test_morton() {
+        for (x, y, z) in [
+            (0, 0, 0),
+            (1, 2, 3),
+            (0x1F_FFFF, 0, 0x12345),
+            (7, 0x1F_FFFF, 1),
+        ] {
+            assert_eq!(morton_decode(morton_encode(x, y, z)), (x, y, z));
+        }
+        assert!(morton_encode(1, 0, 0) > morton_encode(0, 1, 1));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let pc = sample_cloud();
+        let params = OctreeParams::default();
+        let data = encode(&pc, &params).unwrap();
+        assert!(data.len() < pc.points.len() * std::mem::size_of::<PointXyzRgba>());
+        let decoded = decode(&data).unwrap();
+        assert_eq!(decoded.number_of_points, decoded.points.len());
+        assert!(decoded.number_of_points <= pc.number_of_points);
+
+        // every original point has a decoded point within half a voxel on each axis
+        let voxel = 2.0 / 1023.0;
+        for p in &pc.points {
+            let nearest = decoded
+                .points
+                .iter()
+                .map(|q| {
+                    (p.x - q.x)
+                        .abs()
+                        .max((p.y - q.y).abs())
+                        .max((p.z - q.z).abs())
+                })
+                .fold(f32::MAX, f32::min);
+            assert!(nearest <= voxel * 0.5 + 1e-4, "{nearest}");
+        }
+    }
+
+    #[test]
+    fn test_lossless_colours() {
+        // points on distinct voxels keep their exact colours at attribute QP 4
+        let points: Vec<PointXyzRgba> = (0..64)
+            .map(|i| PointXyzRgba {
+                x: (i % 4) as f32,
+                y: (i / 4 % 4) as f32,
+                z: (i / 16) as f32,
+                r: (i * 37 % 256) as u8,
+                g: (i * 11 % 256) as u8,
+                b: (i * 3) as u8,
+                a: 255,
+            })
+            .collect();
+        let pc = PointCloud::new(points.len(), points);
+        let mut decoded = decode(&encode(&pc, &OctreeParams::default()).unwrap()).unwrap();
+        let mut expected = pc.points.clone();
+        decoded.points.sort();
+        expected.sort();
+        for (a, b) in decoded.points.iter().zip(&expected) {
+            assert!(
+                (a.x - b.x).abs() < 1e-3 && (a.y - b.y).abs() < 1e-3 && (a.z - b.z).abs() < 1e-3
+            );
+            assert_eq!((a.r, a.g, a.b, a.a), (b.r, b.g, b.b, b.a));
+        }
+    }
+
+    #[test]
+    fn test_qps_reduce_size() {
+        let pc = sample_cloud();
+        let size = |geometry_qp, attribute_qp| {
+            let params = OctreeParams {
+                depth: 10,
+                geometry_qp,
+                attribute_qp,
+            };
+            encode(&pc, &params).unwrap().len()
+        };
+        assert!(size(22, 4) < size(4, 4));
+        assert!(size(4, 28) < size(4, 4));
+
+        let coarse = decode(
+            &encode(
+                &pc,
+                &OctreeParams {
+                    depth: 10,
+                    geometry_qp: 40,
+                    attribute_qp: 40,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(coarse.number_of_points < pc.number_of_points);
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        let empty = PointCloud::new(0, vec![]);
+        let decoded = decode(&encode(&empty, &OctreeParams::default()).unwrap()).unwrap();
+        assert_eq!(decoded.number_of_points, 0);
+
+        let p = PointXyzRgba {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+            r: 1,
+            g: 2,
+            b: 3,
+            a: 4,
+        };
+        let single = PointCloud::new(2, vec![p, p]);
+        let decoded = decode(&encode(&single, &OctreeParams::default()).unwrap()).unwrap();
+        assert_eq!(decoded.points, vec![p]);
+
+        let data = encode(&sample_cloud(), &OctreeParams::default()).unwrap();
+        assert!(decode(&data[..data.len() / 2]).is_err());
+        assert!(decode(b"not a point cloud").is_err());
+        assert!(encode(
+            &single,
+            &OctreeParams {
+                depth: 22,
+                ..Default::default()
+            }
+        )
+        .is_err());
+        // voxels finer than the depth at QPs below 4 overflow the 21 bits of an axis
+        let fine = |depth, geometry_qp| OctreeParams {
+            depth,
+            geometry_qp,
+            ..Default::default()
+        };
+        assert!(encode(&sample_cloud(), &fine(21, 0)).is_err());
+        assert!(encode(&sample_cloud(), &fine(21, 4)).is_ok());
+        let decoded = decode(&encode(&sample_cloud(), &fine(20, 0)).unwrap()).unwrap();
+        assert!(decoded.number_of_points > 0);
+    }
+}
@@ -0,0 +1,482 @@
+//! Sequence mode of the octree codec: groups of pictures with inter-frame prediction.
+//!
+//! A sequence is a self-contained group of pictures (GOP) that decodes without any other file,
+//! so that one sequence can be one DASH segment. All the frames of a sequence share one voxel
+//! grid, fitted to the bounding box of the whole GOP.
+//!
+//! The first frame is an I-frame, coded exactly like a single frame of [`super::octree`].
+//! The following frames are P-frames predicted from the previous decoded frame: every occupancy
+//! byte is coded against the occupancy of the same node in the reference, and the colour of a
+//! voxel that was already occupied in the reference is coded as the residual against it.
+//! A frame is coded as an I-frame instead whenever that is smaller, e.g. after a scene cut.
+//!
+//! A sequence starts with the header of [`super::octree`], with the magic `VVOS` and the number of
+//! frames in place of the number of points. Every frame then has a 9 byte header:
+//!
+//! | bytes | content |
+//! |-------|---------|
+//! | 1     | frame type, 0 for I and 1 for P |
+//! | 4     | number of points (u32, little endian) |
+//! | 4     | length of the payload (u32, little endian) |
+//!
+//! followed by its range coded payload.
+
+use anyhow::{bail, Result};
+
+use super::entropy::{BitModel, RangeDecoder, RangeEncoder};
+use super::octree::{
+    check_params, decode_geometry, encode_geometry, tree_depth, ColourCoder, Grid, Header, Leaf,
+    OctreeParams, HEADER_SIZE,
+};
+use crate::formats::pointxyzrgba::PointXyzRgba;
+use crate::formats::PointCloud;
+
+const MAGIC: &[u8; 4] = b"VVOS";
+const FRAME_HEADER_SIZE: usize = 9;
+const I_FRAME: u8 = 0;
+const P_FRAME: u8 = 1;
+/// File extension used for encoded sequences.
+pub const EXTENSION: &str = "vvs";
+
+/// Whether `data` is a sequence rather than a single frame.
+pub fn is_sequence(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encodes `frames` as one group of pictures.
+pub fn encode(frames: &[PointCloud<PointXyzRgba>], params: &OctreeParams) -> Result<Vec<u8>> {
+    check_params(params)?;
+    let grid = Grid::fit(frames.iter().flat_map(|pc| &pc.points), params);
+    let leaves: Vec<Vec<Leaf>> = frames.iter().map(|pc| grid.voxelise(&pc.points)).collect();
+    let max_code = leaves
+        .iter()
+        .filter_map(|leaves| leaves.last().map(|(code, _)| *code))
+        .max()
+        .unwrap_or(0);
+    let depth = tree_depth(max_code);
+
+    let mut out = vec![];
+    Header {
+        depth,
+        geometry_qp: params.geometry_qp,
+        attribute_qp: params.attribute_qp,
+        count: frames.len() as u32,
+        grid,
+    }
+    .write(MAGIC, &mut out);
+
+    let mut reference: Option<Vec<Leaf>> = None;
+    for current in &leaves {
+        let intra = encode_intra(current, depth, params.attribute_qp);
+        let (frame_type, (payload, reconstructed)) = match &reference {
+            Some(reference) => {
+                let inter = encode_inter(current, reference, depth, params.attribute_qp);
+                if inter.0.len() < intra.0.len() {
+                    (P_FRAME, inter)
+                } else {
+                    (I_FRAME, intra)
+                }
+            }
+            None => (I_FRAME, intra),
+        };
+        out.push(frame_type);
+        out.extend_from_slice(&(current.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        out.extend(payload);
+        reference = Some(reconstructed);
+    }
+    Ok(out)
+}
+
+/// Decodes every frame of a sequence.
+pub fn decode(data: &[u8]) -> Result<Vec<PointCloud<PointXyzRgba>>> {
+    let mut frames = vec![];
+    decode_frames(data, &mut |pc| frames.push(pc))?;
+    Ok(frames)
+}
+
+/// Decodes the frames of a sequence one by one, passing each to `emit` as soon as it is decoded.
+pub fn decode_frames(data: &[u8], emit: &mut dyn FnMut(PointCloud<PointXyzRgba>)) -> Result<()> {
+    let header = Header::read(MAGIC, data)?;
+    let mut reference: Option<Vec<Leaf>> = None;
+    let mut pos = HEADER_SIZE;
+    for i in 0..header.count {
+        let Some(frame_header) = data.get(pos..pos + FRAME_HEADER_SIZE) else {
+            bail!("truncated octree coded sequence: frame {i} is missing");
+        };
+        let frame_type = frame_header[0];
+        let num_points = u32::from_le_bytes(frame_header[1..5].try_into().unwrap()) as usize;
+        let length = u32::from_le_bytes(frame_header[5..9].try_into().unwrap()) as usize;
+        pos += FRAME_HEADER_SIZE;
+        let Some(payload) = data.get(pos..pos + length) else {
+            bail!("truncated octree coded sequence: frame {i} is incomplete");
+        };
+        pos += length;
+
+        let mut decoder = RangeDecoder::new(payload);
+        let leaves = match (frame_type, &reference) {
+            (I_FRAME, _) => decode_intra(&mut decoder, &header, num_points)?,
+            (P_FRAME, Some(reference)) => {
+                decode_inter(&mut decoder, &header, num_points, reference)?
+            }
+            (P_FRAME, None) => bail!("frame {i} is predicted, but there is no reference frame"),
+            _ => bail!("invalid frame type {frame_type} of frame {i}"),
+        };
+        if decoder.is_exhausted() {
+            bail!("truncated octree coded sequence: frame {i} is incomplete");
+        }
+        let points: Vec<PointXyzRgba> = leaves
+            .iter()
+            .map(|(code, colour)| header.grid.point(*code, *colour))
+            .collect();
+        emit(PointCloud::new(points.len(), points));
+        reference = Some(leaves);
+    }
+    Ok(())
+}
+
+/// Codes a frame on its own. Returns the payload and the reconstructed leaves.
+fn encode_intra(leaves: &[Leaf], depth: u8, attribute_qp: u8) -> (Vec<u8>, Vec<Leaf>) {
+    let mut encoder = RangeEncoder::new();
+    encode_geometry(&mut encoder, leaves, depth);
+    let mut colours = ColourCoder::new(attribute_qp);
+    let reconstructed = leaves
+        .iter()
+        .map(|(code, colour)| (*code, colours.encode(&mut encoder, *colour, None)))
+        .collect();
+    (encoder.finish(), reconstructed)
+}
+
+fn decode_intra(
+    decoder: &mut RangeDecoder,
+    header: &Header,
+    num_points: usize,
+) -> Result<Vec<Leaf>> {
+    let codes = decode_geometry(decoder, header.depth, num_points)?;
+    let mut colours = ColourCoder::new(header.attribute_qp);
+    Ok(codes
+        .into_iter()
+        .map(|code| (code, colours.decode(decoder, None)))
+        .collect())
+}
+
+/// Codes a frame predicted from the reconstructed `reference`.
+/// Returns the payload and the reconstructed leaves.
+fn encode_inter(
+    leaves: &[Leaf],
+    reference: &[Leaf],
+    depth: u8,
+    attribute_qp: u8,
+) -> (Vec<u8>, Vec<Leaf>) {
+    let mut encoder = RangeEncoder::new();
+    let mut models = InterOccupancyModels::default();
+    for level in (0..depth as u32).rev() {
+        let mut cursor = ReferenceCursor::new(reference, level);
+        for (node, occupancy) in occupancies(leaves, level) {
+            models.encode(&mut encoder, cursor.find(node), occupancy);
+        }
+    }
+    let mut colours = ColourCoder::new(attribute_qp);
+    let reconstructed = leaves
+        .iter()
+        .map(|(code, colour)| {
+            let predicted = colours.encode(&mut encoder, *colour, colour_at(reference, *code));
+            (*code, predicted)
+        })
+        .collect();
+    (encoder.finish(), reconstructed)
+}
+
+fn decode_inter(
+    decoder: &mut RangeDecoder,
+    header: &Header,
+    num_points: usize,
+    reference: &[Leaf],
+) -> Result<Vec<Leaf>> {
+    let mut models = InterOccupancyModels::default();
+    let mut nodes = if num_points == 0 { vec![] } else { vec![0u64] };
+    for level in (0..header.depth as u32).rev() {
+        let mut cursor = ReferenceCursor::new(reference, level);
+        let mut next_nodes = vec![];
+        for node in &nodes {
+            let occupancy = models.decode(decoder, cursor.find(*node));
+            for child in 0..8 {
+                if occupancy & (1 << child) != 0 {
+                    next_nodes.push(node << 3 | child);
+                }
+            }
+            if next_nodes.len() > num_points || decoder.is_exhausted() {
+                bail!("corrupted octree: more nodes than the {num_points} points in the header");
+            }
+        }
+        nodes = next_nodes;
+    }
+    if nodes.len() != num_points {
+        bail!(
+            "corrupted octree: decoded {} points, expected {num_points}",
+            nodes.len()
+        );
+    }
+    let mut colours = ColourCoder::new(header.attribute_qp);
+    Ok(nodes
+        .into_iter()
+        .map(|code| (code, colours.decode(decoder, colour_at(reference, code))))
+        .collect())
+}
+
+fn colour_at(reference: &[Leaf], code: u64) -> Option<[u8; 4]> {
+    reference
+        .binary_search_by_key(&code, |(code, _)| *code)
+        .ok()
+        .map(|i| reference[i].1)
+}
+
+/// Occupancy bytes of the nodes whose children are `level` levels above the leaves,
+/// as `(node, occupancy)` sorted by node.
+fn occupancies(leaves: &[Leaf], level: u32) -> Vec<(u64, u8)> {
+    let mut out: Vec<(u64, u8)> = vec![];
+    for (code, _) in leaves {
+        let child = code >> (3 * level);
+        let (node, bit) = (child >> 3, 1u8 << (child & 7));
+        match out.last_mut() {
+            Some((last, occupancy)) if *last == node => *occupancy |= bit,
+            _ => out.push((node, bit)),
+        }
+    }
+    out
+}
+
+/// Looks up the reference occupancy of the nodes of one level, which are visited in increasing order.
+struct ReferenceCursor {
+    occupancies: Vec<(u64, u8)>,
+    pos: usize,
+}
+
+impl ReferenceCursor {
+    fn new(reference: &[Leaf], level: u32) -> Self {
+        ReferenceCursor {
+            occupancies: occupancies(reference, level),
+            pos: 0,
+        }
+    }
+
+    fn find(&mut self, node: u64) -> Option<u8> {
+        while self.pos < self.occupancies.len() && self.occupancies[self.pos].0 < node {
+            self.pos += 1;
+        }
+        self.occupancies
+            .get(self.pos)
+            .filter(|(n, _)| *n == node)
+            .map(|(_, occupancy)| *occupancy)
+    }
+}
+
+/// Occupancy bytes of nodes that exist in the reference start with a flag telling whether they
+/// are unchanged. Otherwise they are coded bit by bit with a binary tree of contexts, like intra
+/// frames. The tree is picked by the same child in the reference: absent node, empty child or
+/// occupied child.
+struct InterOccupancyModels {
+    unchanged: BitModel,
+    models: Vec<[BitModel; 256]>,
+}
+
+impl Default for InterOccupancyModels {
+    fn default() -> Self {
+        InterOccupancyModels {
+            unchanged: BitModel::default(),
+            models: vec![[BitModel::default(); 256]; 3],
+        }
+    }
+}
+
+impl InterOccupancyModels {
+    fn context(reference: Option<u8>, child: u32) -> usize {
+        match reference {
+            None => 0,
+            Some(occupancy) => 1 + ((occupancy >> child) & 1) as usize,
+        }
+    }
+
+    fn encode(&mut self, encoder: &mut RangeEncoder, reference: Option<u8>, occupancy: u8) {
+        if let Some(reference) = reference {
+            encoder.encode_bit(&mut self.unchanged, occupancy == reference);
+            if occupancy == reference {
+                return;
+            }
+        }
+        let mut node = 1;
+        for i in (0..8).rev() {
+            let bit = (occupancy >> i) & 1 == 1;
+            encoder.encode_bit(&mut self.models[Self::context(reference, i)][node], bit);
+            node = node * 2 + bit as usize;
+        }
+    }
+
+    fn decode(&mut self, decoder: &mut RangeDecoder, reference: Option<u8>) -> u8 {
+        if let Some(reference) = reference {
+            if decoder.decode_bit(&mut self.unchanged) {
+                return reference;
+            }
+        }
+        let mut node = 1;
+        for i in (0..8).rev() {
+            let bit = decoder.decode_bit(&mut self.models[Self::context(reference, i)][node]);
+            node = node * 2 + bit as usize;
+        }
+        node as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::octree;
+
+    /// A helix that rotates a little every frame, with a static floor.
+    fn sequence(frames: usize) -> Vec<PointCloud<PointXyzRgba>> {
+        (0..frames)
+            .map(|f| {
+                let points: Vec<PointXyzRgba> = (0..3000)
+                    .map(|i| {
+                        if i % 2 == 0 {
+                            PointXyzRgba {
+                                x: (i % 60) as f32 / 30.0 - 1.0,
+                                y: -1.0,
+                                z: (i / 60) as f32 / 25.0 - 1.0,
+                                r: 90,
+                                g: 60,
+                                b: 30,
+                                a: 255,
+                            }
+                        } else {
+                            let t = i as f32 / 3000.0 * std::f32::consts::TAU + f as f32 * 0.02;
+                            PointXyzRgba {
+                                x: t.cos() * 0.5,
+                                y: i as f32 / 3000.0,
+                                z: t.sin() * 0.5,
+                                r: (i % 256) as u8,
+                                g: 128,
+                                b: (f * 10) as u8,
+                                a: 255,
+                            }
+                        }
+                    })
+                    .collect();
+                PointCloud::new(points.len(), points)
+            })
+            .collect()
+    }
+
+    /// Type and payload length of every frame.
+    fn frame_headers(data: &[u8]) -> Vec<(u8, usize)> {
+        let mut headers = vec![];
+        let mut pos = HEADER_SIZE;
+        while pos < data.len() {
+            let length = u32::from_le_bytes(data[pos + 5..pos + 9].try_into().unwrap()) as usize;
+            headers.push((data[pos], length));
+            pos += FRAME_HEADER_SIZE + length;
+        }
+        headers
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let frames = sequence(8);
+        let params = OctreeParams::default();
+        let data = encode(&frames, &params).unwrap();
+        assert!(is_sequence(&data));
+        let headers = frame_headers(&data);
+        assert_eq!(headers[0].0, I_FRAME);
+        assert!(headers[1..].iter().all(|(t, _)| *t == P_FRAME));
+
+        // P-frames reconstruct what intra coding on the same grid would
+        let grid = Grid::fit(frames.iter().flat_map(|pc| &pc.points), &params);
+        let decoded = decode(&data).unwrap();
+        assert_eq!(decoded.len(), frames.len());
+        for (pc, original) in decoded.iter().zip(&frames) {
+            let leaves = grid.voxelise(&original.points);
+            assert_eq!(pc.number_of_points, leaves.len());
+            for (p, (code, colour)) in pc.points.iter().zip(&leaves) {
+                assert_eq!(*p, grid.point(*code, *colour));
+            }
+        }
+    }
+
+    #[test]
+    fn test_prediction_reduces_size() {
+        let params = OctreeParams {
+            attribute_qp: 16,
+            ..Default::default()
+        };
+        let intra = |frames: &[PointCloud<PointXyzRgba>]| -> usize {
+            frames
+                .iter()
+                .map(|pc| octree::encode(pc, &params).unwrap().len())
+                .sum()
+        };
+
+        // half of the points move
+        let frames = sequence(8);
+        let inter = encode(&frames, &params).unwrap().len();
+        assert!(
+            inter * 4 < intra(&frames) * 3,
+            "{inter} vs {}",
+            intra(&frames)
+        );
+
+        // nothing moves: the P-frames are almost free
+        let frames = vec![sequence(1).remove(0); 8];
+        let inter = encode(&frames, &params).unwrap().len();
+        assert!(
+            inter * 10 < intra(&frames[..1]) * 11,
+            "{inter} vs {}",
+            intra(&frames[..1])
+        );
+    }
+
+    #[test]
+    fn test_scene_cut() {
+        let mut frames = sequence(3);
+        let other: Vec<PointXyzRgba> = (0..3000)
+            .map(|i| PointXyzRgba {
+                x: (i * 7919 % 1000) as f32 / 500.0 - 1.0,
+                y: (i * 6151 % 1000) as f32 / 1000.0,
+                z: (i * 3571 % 1000) as f32 / 2000.0,
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255,
+            })
+            .collect();
+        frames.push(PointCloud::new(other.len(), other));
+        let params = OctreeParams::default();
+        let data = encode(&frames, &params).unwrap();
+        assert_eq!(decode(&data).unwrap().len(), 4);
+
+        // the unrelated frame costs at most as much as coding it on its own
+        let grid = Grid::fit(frames.iter().flat_map(|pc| &pc.points), &params);
+        let leaves = grid.voxelise(&frames[3].points);
+        let (intra, _) = encode_intra(&leaves, data[5], params.attribute_qp);
+        assert!(frame_headers(&data)[3].1 <= intra.len());
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        let empty = PointCloud::new(0, vec![]);
+        let frames = vec![empty.clone(), sequence(1).remove(0), empty];
+        let decoded = decode(&encode(&frames, &OctreeParams::default()).unwrap()).unwrap();
+        assert_eq!(decoded[0].number_of_points, 0);
+        assert!(decoded[1].number_of_points > 0);
+        assert_eq!(decoded[2].number_of_points, 0);
+        assert!(decode(&encode(&[], &OctreeParams::default()).unwrap())
+            .unwrap()
+            .is_empty());
+
+        let data = encode(&sequence(4), &OctreeParams::default()).unwrap();
+        assert!(decode(&data[..data.len() - 10]).is_err());
+        assert!(decode(&data[..HEADER_SIZE + 4]).is_err());
+        let single = octree::encode(&sequence(1)[0], &OctreeParams::default()).unwrap();
+        assert!(!is_sequence(&single));
+        assert!(decode(&single).is_err());
+    }
+}
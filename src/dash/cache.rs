@@ -0,0 +1,235 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A size-bounded cache of downloaded segments on disk, evicting the least recently used segments first.
+///
+/// Every entry remembers the length and modification time of the segment file, which are checked before a cached
+/// segment is handed out again. Entries that fail the check (e.g. truncated by a crash, or modified by someone else)
+/// are dropped, so the segment is downloaded again. Checking the metadata keeps a cache hit from reading the whole file.
+///
+/// Note that a segment may be evicted as soon as it is no longer among the most recently used ones.
+/// The capacity should therefore comfortably exceed the size of the segments that are buffered at any time.
+#[derive(Debug)]
+pub struct SegmentCache {
+    dir: PathBuf,
+    /// capacity in bytes
+    capacity: u64,
+    /// total size of the cached segments in bytes
+    size: u64,
+    entries: HashMap<String, CacheEntry>,
+    /// logical clock, incremented on every access, used to find the least recently used segment
+    clock: u64,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    len: u64,
+    modified: Option<SystemTime>,
+    last_used: u64,
+}
+
+/// Key of the bytes of an interrupted download of the segment `key`.
+pub fn partial_key(key: &str) -> String {
+    format!("{key}.part")
+}
+
+/// Length and modification time of a file
+fn stat(path: &Path) -> Option<(u64, Option<SystemTime>)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()))
+}
+
+impl SegmentCache {
+    /// Creates a cache that stores segments in `dir`, using at most `capacity` bytes.
+    ///
+    /// Segments already present in `dir` are adopted into the cache.
+    pub fn new<P: Into<PathBuf>>(dir: P, capacity: u64) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("creating cache directory {}", dir.display()))?;
+        let mut cache = SegmentCache {
+            dir,
+            capacity,
+            size: 0,
+            entries: HashMap::new(),
+            clock: 0,
+        };
+        for entry in std::fs::read_dir(&cache.dir)?.flatten() {
+            let path = entry.path();
+            let key = match path.file_name().and_then(|f| f.to_str()) {
+                Some(key) if path.is_file() => key.to_string(),
+                _ => continue,
+            };
+            cache.track(&key);
+        }
+        cache.evict(0);
+        Ok(cache)
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Total size of the cached segments in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+
+    /// Starts tracking the segment file of `key` as it is on disk now.
+    fn track(&mut self, key: &str) {
+        let Some((len, modified)) = stat(&self.path(key)) else {
+            return;
+        };
+        self.clock += 1;
+        let entry = CacheEntry {
+            len,
+            modified,
+            last_used: self.clock,
+        };
+        self.size += entry.len;
+        if let Some(old) = self.entries.insert(key.to_string(), entry) {
+            self.size -= old.len;
+        }
+    }
+
+    /// Returns the path of a cached segment if it is present and intact.
+    pub fn get(&mut self, key: &str) -> Option<PathBuf> {
+        let path = self.path(key);
+        let entry = self.entries.get(key)?;
+        let intact = stat(&path) == Some((entry.len, entry.modified));
+        if !intact {
+            warn!("Cached segment {} is corrupted, dropping it", key);
+            self.remove(key);
+            return None;
+        }
+        self.clock += 1;
+        self.entries.get_mut(key).unwrap().last_used = self.clock;
+        Some(path)
+    }
+
+    /// Stores a segment, evicting the least recently used segments to make space for it.
+    pub fn insert(&mut self, key: &str, data: &[u8]) -> Result<PathBuf> {
+        if data.len() as u64 > self.capacity {
+            bail!(
+                "segment {} ({} bytes) is larger than the cache ({} bytes)",
+                key,
+                data.len(),
+                self.capacity
+            );
+        }
+        self.remove(key);
+        self.evict(data.len() as u64);
+        let path = self.path(key);
+        std::fs::write(&path, data)
+            .with_context(|| format!("writing segment to {}", path.display()))?;
+        self.track(key);
+        Ok(path)
+    }
+
+    /// Stores the first bytes of a segment whose download was interrupted, so that it can be resumed.
+    /// They count towards the capacity and are evicted like segments.
+    pub fn insert_partial(&mut self, key: &str, data: &[u8]) -> Result<()> {
+        self.insert(&partial_key(key), data).map(|_| ())
+    }
+
+    /// Takes the bytes of an interrupted download of `key` out of the cache, if they are present and intact.
+    pub fn take_partial(&mut self, key: &str) -> Option<Vec<u8>> {
+        let key = partial_key(key);
+        let data = std::fs::read(self.get(&key)?).ok();
+        self.remove(&key);
+        data
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.size -= entry.len;
+            _ = std::fs::remove_file(self.path(key));
+        }
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Evicts segments until `incoming` more bytes fit into the cache.
+    fn evict(&mut self, incoming: u64) {
+        while self.size + incoming > self.capacity {
+            let lru = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match lru {
+                Some(key) => {
+                    debug!("Evicting segment {} from the cache", key);
+                    self.remove(&key);
+                }
+                None => break,
+            }
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = SegmentCache::new(dir.path(), 10).unwrap();
+
+        cache.insert("a", &[1, 2, 3, 4]).unwrap();
+        cache.insert("b", &[5, 6, 7, 8]).unwrap();
+        assert_eq!(cache.size(), 8);
+        // touch `a`, so that `b` becomes the least recently used
+        assert_eq!(cache.get("a"), Some(dir.path().join("a")));
+
+        cache.insert("c", &[9, 10, 11]).unwrap();
+        assert!(cache.contains("a"));
+        assert!(!cache.contains("b"));
+        assert!(!dir.path().join("b").exists());
+        assert_eq!(cache.size(), 7);
+
+        assert!(cache.insert("d", &[0; 11]).is_err());
+
+        // a truncated segment is dropped
+        std::fs::write(dir.path().join("c"), [9, 10]).unwrap();
+        assert_eq!(cache.get("c"), None);
+        assert!(!cache.contains("c"));
+        assert_eq!(cache.size(), 4);
+
+        // segments left on disk are picked up again
+        let mut cache = SegmentCache::new(dir.path(), 10).unwrap();
+        assert!(cache.contains("a"));
+        assert_eq!(cache.size(), 4);
+
+        // the bytes of an interrupted download are kept next to the segments, and handed out once
+        cache.insert_partial("e", &[1, 2]).unwrap();
+        assert!(dir.path().join("e.part").exists());
+        assert_eq!(cache.size(), 6);
+        assert!(!cache.contains("e"));
+        assert_eq!(cache.take_partial("e"), Some(vec![1, 2]));
+        assert_eq!(cache.take_partial("e"), None);
+        assert_eq!(cache.size(), 4);
+    }
+}
@@ -1,62 +1,315 @@
-use super::parser::MPDParser;
-use anyhow::{Context, Result};
+use super::cache::{partial_key, SegmentCache};
+use super::parser::{FrameRateConversion, MPDParser};
+use crate::codec::Bitstream;
+use crate::render::wgpu::camera::CameraPosition;
+use crate::utils::{get_cosines, get_tile_cosines};
+use anyhow::{anyhow, bail, Context, Result};
 use futures::future;
-use log::info;
+use log::{info, warn};
 use std::path::PathBuf;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use thiserror::Error;
 use tokio::fs::File;
+use tokio::sync::Notify;
 
 pub type HttpClient = reqwest::Client;
 
+/// How often and how patiently a segment download is retried before giving up.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// number of retries after the first attempt. Every attempt tries all BaseURLs.
+    pub max_retries: u32,
+    /// delay before the first retry. It doubles after every retry.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the given retry (starting from 0).
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+/// Cancels an ongoing fetch, e.g. because the buffer no longer needs the frame.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<(AtomicBool, Notify)>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.0.store(true, Ordering::SeqCst);
+        self.inner.1.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.0.load(Ordering::SeqCst)
+    }
+
+    /// Completes once the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            // `notified` must be created before checking the flag so that we cannot miss a wakeup
+            let notified = self.inner.1.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Reasons for a fetch to end early. They are returned wrapped in an `anyhow::Error`, use `downcast_ref` to check for them.
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("fetch was cancelled")]
+    Cancelled,
+    /// The download took longer than its deadline and was given up on, e.g. so that a lower quality can be fetched instead.
+    #[error("fetch was abandoned after {elapsed:?} ({received_bits} bits received)")]
+    Abandoned {
+        received_bits: usize,
+        elapsed: Duration,
+    },
+}
+
 #[derive(Clone)]
 pub struct Fetcher {
     http_client: HttpClient,
     pub mpd_parser: MPDParser,
+    mpd_url: String,
+    /// when the MPD was last (re)fetched. Only relevant for dynamic MPDs.
+    last_mpd_refresh: Instant,
     download_dir: PathBuf,
     enable_optimizations: bool,
+    retry_policy: RetryPolicy,
+    /// if set, segments are stored in (and served from) the cache instead of plainly written to `download_dir`.
+    cache: Option<Arc<Mutex<SegmentCache>>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone, Default)]
 pub struct FetchResult {
-    pub paths: [Option<PathBuf>; 6],
+    /// the file of every view, `None` for the views that could not be downloaded
+    pub paths: Vec<Option<PathBuf>>,
+    /// the bytes of the views that were downloaded. Views served from disk only have a path.
+    pub contents: Vec<Option<Arc<[u8]>>>,
+    /// the representation requested for each view
+    pub quality: Vec<usize>,
     pub throughput: f64,
+    /// set if the frame rate of the representation differs from the presentation frame rate
+    pub frame_rate: Option<FrameRateConversion>,
+}
+
+impl FetchResult {
+    /// The views of the segment, in memory where possible so decoding does not touch the disk.
+    pub fn bitstreams(&self) -> Vec<Bitstream> {
+        self.paths
+            .iter()
+            .zip(&self.contents)
+            .filter_map(|(path, content)| match (path, content) {
+                (Some(path), Some(content)) => Some(Bitstream::from_bytes(path, content.clone())),
+                (Some(path), None) => Some(Bitstream::from_file(path)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for FetchResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FetchResult")
+            .field("paths", &self.paths)
+            .field("quality", &self.quality)
+            .field("throughput", &self.throughput)
+            .field("frame_rate", &self.frame_rate)
+            .finish_non_exhaustive()
+    }
 }
 
+/// Downloads a segment, trying each of `urls` in turn. `data` holds the bytes received so far, so an interrupted
+/// download is resumed with a range request instead of starting over.
+async fn fetch_segment(
+    client: &HttpClient,
+    urls: &[String],
+    data: &mut Vec<u8>,
+    received: &AtomicUsize,
+) -> Result<()> {
+    let mut last_err = anyhow!("no url to download from");
+    for url in urls {
+        let mut req = client.get(url);
+        if !data.is_empty() {
+            req = req.header(reqwest::header::RANGE, format!("bytes={}-", data.len()));
+        }
+        let result: Result<()> = async {
+            let mut resp = req.send().await?.error_for_status()?;
+            if resp.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+                // the server sent the whole segment
+                data.clear();
+            }
+            let expected = resp.content_length().map(|len| data.len() as u64 + len);
+            while let Some(chunk) = resp.chunk().await? {
+                data.extend_from_slice(&chunk);
+                received.fetch_add(chunk.len(), Ordering::Relaxed);
+            }
+            match expected {
+                Some(expected) if expected != data.len() as u64 => {
+                    bail!("expected {} bytes but received {}", expected, data.len())
+                }
+                _ => Ok(()),
+            }
+        }
+        .await;
+        match result {
+            Ok(()) => return Ok(()),
+            Err(e) => last_err = e,
+        }
+        warn!("Failed to download {url}: {last_err}, trying next BaseURL");
+    }
+    Err(last_err)
+}
+
+/// Fetches the MPD. Anything that is not an http(s) URL is read from the local filesystem.
 async fn fetch_mpd(mpd_url: &str, http_client: &HttpClient) -> Result<String> {
-    let resp = http_client.get(mpd_url).send().await?;
+    if !(mpd_url.starts_with("http://") || mpd_url.starts_with("https://")) {
+        let path = mpd_url.strip_prefix("file://").unwrap_or(mpd_url);
+        return Ok(tokio::fs::read_to_string(path).await?);
+    }
+    let resp = http_client.get(mpd_url).send().await?.error_for_status()?;
     let content = resp.text().await?;
 
     Ok(content)
 }
 
 impl Fetcher {
-    // number of views
-    const VIEWS: usize = 6;
-
     pub async fn new<P: Into<PathBuf>>(
         mpd_url: &str,
         download_dir: P,
         enable_optimizations: bool,
-    ) -> Fetcher {
+    ) -> Result<Fetcher> {
         let client = reqwest::Client::builder()
             .timeout(Duration::new(30, 0))
             .gzip(true)
             .build()
-            .context("building reqwest HTTP client")
-            .unwrap();
+            .context("building reqwest HTTP client")?;
 
         let mpd = fetch_mpd(mpd_url, &client)
             .await
-            .expect("failed to fetch mpd");
+            .context("failed to fetch mpd")?;
+        let mut mpd_parser = MPDParser::new(&mpd)?;
+        mpd_parser.set_manifest_url(mpd_url);
 
-        Fetcher {
+        Ok(Fetcher {
             http_client: client,
-            mpd_parser: MPDParser::new(&mpd),
+            mpd_parser,
+            mpd_url: mpd_url.to_string(),
+            last_mpd_refresh: Instant::now(),
             download_dir: download_dir.into(),
             enable_optimizations,
+            retry_policy: RetryPolicy::default(),
+            cache: None,
+        })
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Keep downloaded segments in a LRU cache of at most `capacity` bytes in the download directory.
+    /// This supersedes `enable_optimizations`, which keeps every segment forever.
+    pub fn set_cache_capacity(&mut self, capacity: u64) -> Result<()> {
+        let cache = SegmentCache::new(&self.download_dir, capacity)?;
+        self.cache = Some(Arc::new(Mutex::new(cache)));
+        Ok(())
+    }
+
+    /// Refetches the MPD and merges it into the current one.
+    pub async fn refresh_mpd(&mut self) -> Result<()> {
+        let mpd = fetch_mpd(&self.mpd_url, &self.http_client)
+            .await
+            .context("failed to refresh mpd")?;
+        self.mpd_parser.merge(MPDParser::new(&mpd)?)?;
+        self.last_mpd_refresh = Instant::now();
+        Ok(())
+    }
+
+    /// Refreshes a dynamic MPD once its `minimumUpdatePeriod` has passed. Returns whether the MPD was refreshed.
+    pub async fn refresh_mpd_if_needed(&mut self) -> Result<bool> {
+        match self.mpd_parser.minimum_update_period() {
+            Some(period) if self.last_mpd_refresh.elapsed() >= period => {
+                self.refresh_mpd().await?;
+                Ok(true)
+            }
+            _ => Ok(false),
         }
     }
 
+    /// Get the frame offset of the newest available segment. Always `None` for static MPDs.
+    pub fn live_edge(&self) -> Option<u64> {
+        self.mpd_parser.live_edge(SystemTime::now())
+    }
+
+    fn is_cached(&self, filename: &str) -> bool {
+        self.cache
+            .as_ref()
+            .map(|cache| cache.lock().unwrap().get(filename).is_some())
+            .unwrap_or(false)
+    }
+
+    fn cache_segment(&self, filename: &str, data: &[u8]) -> Result<()> {
+        if let Some(cache) = self.cache.as_ref() {
+            cache.lock().unwrap().insert(filename, data)?;
+        }
+        Ok(())
+    }
+
+    /// Keeps the bytes received before a download was interrupted as a `.part` file, in the cache if there is one,
+    /// so that the next download of the segment resumes after them.
+    fn store_partial(&self, filename: &str, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let result = match self.cache.as_ref() {
+            Some(cache) => cache.lock().unwrap().insert_partial(filename, data),
+            None => std::fs::write(self.download_dir.join(partial_key(filename)), data)
+                .map_err(Into::into),
+        };
+        if let Err(e) = result {
+            warn!("Cannot keep the partial download of {filename}: {e:#}");
+        }
+    }
+
+    /// Takes the bytes of an interrupted download of a segment, see [`Fetcher::store_partial`].
+    fn take_partial(&self, filename: &str) -> Vec<u8> {
+        match self.cache.as_ref() {
+            Some(cache) => cache.lock().unwrap().take_partial(filename),
+            None => {
+                let path = self.download_dir.join(partial_key(filename));
+                let data = std::fs::read(&path).ok();
+                _ = std::fs::remove_file(path);
+                data
+            }
+        }
+        .unwrap_or_default()
+    }
+
     // object_id is adaptation set id
     pub async fn download(
         &mut self,
@@ -66,84 +319,153 @@ impl Fetcher {
         is_multiview: bool,
         simulated_network_throughput: Option<f64>,
     ) -> Result<FetchResult> {
-        let mut paths = core::array::from_fn(|_| None);
+        self.download_cancellable(
+            object_id,
+            frame,
+            quality,
+            is_multiview,
+            simulated_network_throughput,
+            &CancellationToken::new(),
+            None,
+        )
+        .await
+    }
 
-        // quality is representation id (0 is lowest quality)
-        let mut urls: [Option<String>; 6] = core::array::from_fn(|_| None);
-        let mut bandwidths = [None; 6];
-
-        if is_multiview {
-            for view_id in 0..Fetcher::VIEWS {
-                let (url, bandwidth) = self.mpd_parser.get_info(
-                    object_id,
-                    quality[std::cmp::min(view_id, quality.len() - 1)] as u8,
-                    frame,
-                    Some(view_id as u8),
-                );
-                let output_path = self
-                    .download_dir
-                    .join(generate_filename_from_url(url.as_str()));
-                urls[view_id] = Some(url);
-                bandwidths[view_id] = bandwidth;
-                paths[view_id] = Some(output_path);
-            }
+    /// Same as [`Fetcher::download`], but gives up with a [`FetchError`] once `cancel` is cancelled or,
+    /// if given, once `deadline` has passed since the start of the download.
+    ///
+    /// For multiview requests, views that cannot be downloaded are left out of the result. The request only fails
+    /// if none of the views could be downloaded. The bytes received by an interrupted download are kept, and the
+    /// next download of the segment resumes after them.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn download_cancellable(
+        &mut self,
+        object_id: u8,
+        frame: u64,
+        quality: &[usize],
+        is_multiview: bool,
+        simulated_network_throughput: Option<f64>,
+        cancel: &CancellationToken,
+        deadline: Option<Duration>,
+    ) -> Result<FetchResult> {
+        if cancel.is_cancelled() {
+            return Err(FetchError::Cancelled.into());
+        }
+        let views = if is_multiview {
+            self.views(object_id, frame)
         } else {
-            let (url, bandwidth) = self.mpd_parser.get_info(
+            1
+        };
+        let mut paths: Vec<Option<PathBuf>> = vec![None; views];
+        let mut segments: Vec<Option<Arc<[u8]>>> = vec![None; views];
+
+        // quality is representation id (0 is lowest quality)
+        // each view has a list of candidate urls, in order of preference
+        let mut urls: Vec<Option<Vec<String>>> = vec![None; views];
+        let mut bandwidths = vec![None; views];
+        let mut filenames = vec![String::new(); views];
+        // bytes received so far, resumed from an interrupted download
+        let mut data: Vec<Vec<u8>> = vec![vec![]; views];
+
+        for view_id in 0..views {
+            let (url, bandwidth) = self.mpd_parser.get_urls(
                 object_id,
-                quality[std::cmp::min(0, quality.len() - 1)] as u8,
+                quality[std::cmp::min(view_id, quality.len() - 1)] as u8,
                 frame,
-                None,
-            );
-            let output_path = self
-                .download_dir
-                .join(generate_filename_from_url(url.as_str()));
-            urls[0] = Some(url);
-            bandwidths[0] = bandwidth;
-            paths[0] = Some(output_path);
+                is_multiview.then_some(view_id as u8),
+            )?;
+            let filename = generate_filename_from_url(url[0].as_str());
+            let output_path = self.download_dir.join(&filename);
+            bandwidths[view_id] = bandwidth;
+            paths[view_id] = Some(output_path);
+            let cached = if self.cache.is_some() {
+                self.is_cached(&filename)
+            } else {
+                // If file exists, then there is no need to download again.
+                self.enable_optimizations
+                    && File::open(paths[view_id].as_ref().unwrap()).await.is_ok()
+            };
+            if !cached {
+                urls[view_id] = Some(url);
+                data[view_id] = self.take_partial(&filename);
+            }
+            filenames[view_id] = filename;
         }
         let download_start = tokio::time::Instant::now();
+        let received = AtomicUsize::new(0);
 
-        // If file exists, then there is no need to download again.
-        let contents = future::join_all(urls.into_iter().filter(|url| url.is_some()).map(|url| {
-            url.map(|url| {
+        let downloads = future::join_all(urls.iter().zip(data.iter_mut()).enumerate().filter_map(
+            |(view_id, (urls, data))| {
+                let urls = urls.as_ref()?;
                 let client = &self.http_client;
-                let filename = generate_filename_from_url(url.as_str());
-                let local_file_path = self.download_dir.join(filename);
-                let enable_optimizations = self.enable_optimizations;
-                async move {
-                    let f = File::open(local_file_path).await;
-                    if enable_optimizations && f.is_ok() {
-                        // File exists so we should skip downloading
-                        Ok(None)
-                    } else {
-                        let resp = client.get(url).send().await?;
-                        match resp.error_for_status() {
-                            Ok(resp) => Ok(resp.bytes().await.ok()),
-                            Err(e) => Err(e),
+                let retry_policy = &self.retry_policy;
+                let received = &received;
+                Some(async move {
+                    let mut result = fetch_segment(client, urls, data, received).await;
+                    for retry in 0..retry_policy.max_retries {
+                        if result.is_ok() {
+                            break;
                         }
+                        tokio::time::sleep(retry_policy.backoff(retry)).await;
+                        result = fetch_segment(client, urls, data, received).await;
+                    }
+                    (view_id, result)
+                })
+            },
+        ));
+        let outcome = tokio::select! {
+            results = downloads => Ok(results),
+            _ = cancel.cancelled() => Err(FetchError::Cancelled),
+            _ = tokio::time::sleep(deadline.unwrap_or_default()), if deadline.is_some() => {
+                Err(FetchError::Abandoned {
+                    received_bits: received.load(Ordering::Relaxed) * 8,
+                    elapsed: download_start.elapsed(),
+                })
+            }
+        };
+        let results = match outcome {
+            Ok(results) => results,
+            Err(e) => {
+                for (view_id, data) in data.iter().enumerate() {
+                    if urls[view_id].is_some() {
+                        self.store_partial(&filenames[view_id], data);
                     }
                 }
-            })
-            .unwrap()
-        }))
-        .await;
-
-        let total_bits = contents
-            .iter()
-            .filter_map(|c| c.as_ref().ok())
-            .filter(|c| c.is_some())
-            .map(|c| c.as_ref().unwrap().len())
-            .sum::<usize>()
-            * 8;
-
-        for (i, content) in contents.into_iter().enumerate() {
-            if let Ok(Some(content)) = content {
-                let mut file = File::create(&paths[i].clone().unwrap()).await?;
-                tokio::io::copy(&mut content.as_ref(), &mut file).await?;
-            } else if let Err(e) = content {
-                eprintln!("Error downloading file: {e}");
                 return Err(e.into());
             }
+        };
+
+        // only the bytes received now, not the ones resumed from an earlier download
+        let total_bits = received.load(Ordering::Relaxed) * 8;
+
+        let mut last_err = None;
+        for (view_id, result) in results {
+            let content = std::mem::take(&mut data[view_id]);
+            match result {
+                Ok(()) => {
+                    let path = paths[view_id].as_ref().unwrap();
+                    // decoders take the bytes from memory, the disk is only needed to reuse segments
+                    if self.cache.is_some() {
+                        let filename = path.file_name().unwrap().to_str().unwrap();
+                        self.cache_segment(filename, &content)?;
+                    } else if self.enable_optimizations {
+                        let mut file = File::create(path).await?;
+                        tokio::io::copy(&mut content.as_slice(), &mut file).await?;
+                    }
+                    segments[view_id] = Some(content.into());
+                }
+                Err(e) => {
+                    eprintln!("Error downloading file: {e}");
+                    self.store_partial(&filenames[view_id], &content);
+                    paths[view_id] = None;
+                    last_err = Some(e);
+                }
+            }
+        }
+        if let Some(e) = last_err {
+            if paths.iter().all(|p| p.is_none()) {
+                return Err(e);
+            }
         }
 
         // simulate sleep
@@ -168,12 +490,88 @@ impl Fetcher {
             );
         }
 
+        let frame_rate = self.mpd_parser.frame_rate_conversion(
+            object_id,
+            quality[0] as u8,
+            frame,
+            is_multiview.then_some(0),
+        )?;
         Ok(FetchResult {
             paths,
+            contents: segments,
+            quality: quality.to_vec(),
             throughput: avg_bitrate_in_bps,
+            frame_rate,
         })
     }
 
+    /// Downloads refinement levels of the tiles of a progressive LOD stream, see [`crate::lodify::progressive`].
+    ///
+    /// `chunks` lists the (tile, level) pairs to fetch for the segment containing `frame`. They are downloaded
+    /// concurrently and returned in the same order, straight from memory. The segment cache is used if set.
+    pub async fn download_lod(
+        &mut self,
+        object_id: u8,
+        frame: u64,
+        chunks: &[(u8, u8)],
+    ) -> Result<Vec<Arc<[u8]>>> {
+        let mut requests = vec![];
+        for &(tile, level) in chunks {
+            let (urls, _) = self
+                .mpd_parser
+                .get_tile_urls(object_id, tile, level, frame)?;
+            let filename = generate_filename_from_url(&urls[0]);
+            let cached = self
+                .cache
+                .as_ref()
+                .and_then(|cache| cache.lock().unwrap().get(&filename));
+            requests.push((urls, filename, cached));
+        }
+
+        let client = &self.http_client;
+        let retry_policy = &self.retry_policy;
+        let received = AtomicUsize::new(0);
+        let download_start = Instant::now();
+        let contents = future::join_all(requests.iter().map(|(urls, _, cached)| {
+            let received = &received;
+            async move {
+                if let Some(path) = cached {
+                    return tokio::fs::read(path).await.map_err(anyhow::Error::from);
+                }
+                let mut data = vec![];
+                let mut result = fetch_segment(client, urls, &mut data, received).await;
+                for retry in 0..retry_policy.max_retries {
+                    if result.is_ok() {
+                        break;
+                    }
+                    tokio::time::sleep(retry_policy.backoff(retry)).await;
+                    result = fetch_segment(client, urls, &mut data, received).await;
+                }
+                result.map(|_| data)
+            }
+        }))
+        .await;
+
+        let mut result = vec![];
+        for ((_, filename, cached), content) in requests.iter().zip(contents) {
+            let content = content.with_context(|| format!("failed to download {filename}"))?;
+            if cached.is_none() {
+                self.cache_segment(filename, &content)?;
+            }
+            result.push(content.into());
+        }
+        let bits = received.load(Ordering::Relaxed) * 8;
+        if bits > 0 {
+            info!(
+                "Downloaded {} LOD chunks: {} bits in {} s",
+                chunks.len(),
+                bits,
+                download_start.elapsed().as_secs_f32()
+            );
+        }
+        Ok(result)
+    }
+
     /// Get available representation bitrates for a view.
     ///
     /// If view is None, it will get the bitrate for the first representation
@@ -182,26 +580,68 @@ impl Fetcher {
         object_id: u8,
         frame_offset: u64,
         view_id: Option<u8>,
-    ) -> Vec<u64> {
+    ) -> Result<Vec<u64>> {
         self.mpd_parser
             .available_bitrates(object_id, frame_offset, view_id)
     }
 
+    /// Get the cosines from the camera to every view, per tile if the MPD gives the bounds of the views
+    /// and per face of a cube otherwise.
+    pub fn view_cosines(
+        &self,
+        object_id: u8,
+        frame_offset: u64,
+        camera_pos: CameraPosition,
+    ) -> Vec<f32> {
+        let views = self.views(object_id, frame_offset);
+        match self
+            .mpd_parser
+            .view_bounds(object_id, frame_offset, views as u8)
+        {
+            Ok(Some(tiles)) => get_tile_cosines(camera_pos, &tiles),
+            _ if views == 6 => get_cosines(camera_pos),
+            _ => vec![1.0; views],
+        }
+    }
+
+    /// Get the number of views of an object, see [`MPDParser::views`]. 1 if the MPD does not give views.
+    pub fn views(&self, object_id: u8, frame_offset: u64) -> usize {
+        self.mpd_parser
+            .views(object_id, frame_offset)
+            .map_or(1, |views| views.max(1) as usize)
+    }
+
     /// Get available representation bitrates for all views
-    pub fn all_available_bitrates(&self, object_id: u8, frame_offset: u64) -> Vec<Vec<u64>> {
-        vec![
-            self.available_bitrates(object_id, frame_offset, Some(0)),
-            self.available_bitrates(object_id, frame_offset, Some(1)),
-            self.available_bitrates(object_id, frame_offset, Some(2)),
-            self.available_bitrates(object_id, frame_offset, Some(3)),
-            self.available_bitrates(object_id, frame_offset, Some(4)),
-            self.available_bitrates(object_id, frame_offset, Some(5)),
-        ]
+    pub fn all_available_bitrates(
+        &self,
+        object_id: u8,
+        frame_offset: u64,
+    ) -> Result<Vec<Vec<u64>>> {
+        (0..self.views(object_id, frame_offset) as u8)
+            .map(|view_id| self.available_bitrates(object_id, frame_offset, Some(view_id)))
+            .collect()
     }
 }
 
-fn generate_filename_from_url(url: &str) -> &str {
-    url.rsplit_terminator('/').next().unwrap()
+/// Name of the file a segment is stored in, and its key in the segment cache.
+///
+/// It is the whole path of the URL, as segments of different representations or views often share the last part
+/// of their path. Characters that are not safe in a file name, including `/`, are percent-encoded.
+fn generate_filename_from_url(url: &str) -> String {
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').map_or("", |(_, path)| path),
+        None => url,
+    };
+    let mut filename = String::with_capacity(path.len());
+    for (i, byte) in path.bytes().enumerate() {
+        // a leading dot would make a hidden file, or "." and ".."
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' || (byte == b'.' && i > 0) {
+            filename.push(byte as char);
+        } else {
+            filename += &format!("%{byte:02X}");
+        }
+    }
+    filename
 }
 
 #[cfg(test)]
@@ -209,6 +649,79 @@ mod tests {
 
     use super::*;
 
+    fn live_mpd(start: u64, repeat: u64) -> String {
+        // one segment per second, numbered by its start time
+        let start_number = start / 30;
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <MPD type="dynamic" availabilityStartTime="2023-06-01T10:00:00Z" minimumUpdatePeriod="PT0S">
+                <Period id="0" start="PT0S">
+                    <AdaptationSet frameRate="30">
+                        <Representation id="0" bandwidth="1000">
+                            <SegmentTemplate media="$Number$.bin" timescale="30" startNumber="{start_number}">
+                                <SegmentTimeline>
+                                    <S t="{start}" d="30" r="{repeat}"/>
+                                </SegmentTimeline>
+                            </SegmentTemplate>
+                        </Representation>
+                    </AdaptationSet>
+                </Period>
+            </MPD>"#
+        )
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(800));
+        assert_eq!(policy.backoff(10), Duration::from_secs(2));
+        assert_eq!(policy.backoff(100), Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let child = token.clone();
+        let waiter = tokio::spawn(async move { child.cancelled().await });
+        token.cancel();
+        waiter.await.unwrap();
+        assert!(token.is_cancelled());
+        // completes immediately once cancelled
+        token.cancelled().await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_live_mpd() {
+        let dir = tempfile::tempdir().unwrap();
+        let mpd_path = dir.path().join("live.mpd");
+        std::fs::write(&mpd_path, live_mpd(0, 1)).unwrap();
+
+        let mut fetcher = Fetcher::new(mpd_path.to_str().unwrap(), dir.path(), false)
+            .await
+            .unwrap();
+        assert_eq!(fetcher.mpd_parser.total_frames(), 60);
+        assert_eq!(fetcher.live_edge(), Some(30));
+
+        // the packager publishes 2 more segments and drops the first one
+        std::fs::write(&mpd_path, live_mpd(30, 2)).unwrap();
+        assert!(fetcher.refresh_mpd_if_needed().await.unwrap());
+        assert_eq!(fetcher.mpd_parser.total_frames(), 120);
+        assert_eq!(fetcher.live_edge(), Some(90));
+        assert!(fetcher
+            .mpd_parser
+            .get_info(0, 0, 0, None)
+            .unwrap()
+            .0
+            .ends_with("/0.bin"));
+        assert!(fetcher
+            .mpd_parser
+            .get_info(0, 0, 90, None)
+            .unwrap()
+            .0
+            .ends_with("/3.bin"));
+    }
+
     #[test]
     pub fn test_generate_filename_from_url() {
         assert_eq!(
@@ -217,7 +730,116 @@ mod tests {
         );
         assert_eq!(
             generate_filename_from_url("https://www.example.com/p/a/paya.ply"),
-            "paya.ply"
+            "p%2Fa%2Fpaya.ply"
+        );
+        assert_eq!(
+            generate_filename_from_url("https://www.example.com/../a b.ply?t=1"),
+            "%2E.%2Fa%20b.ply%3Ft%3D1"
         );
+        // representations sharing the last part of their path
+        assert_ne!(
+            generate_filename_from_url("http://localhost/longdress/0/longdress_vox10_1080.ply"),
+            generate_filename_from_url("http://localhost/longdress/1/longdress_vox10_1080.ply")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_representations_sharing_file_name() {
+        let dir = tempfile::tempdir().unwrap();
+        for (repr, content) in [(0, b"low"), (1, b"top")] {
+            std::fs::create_dir(dir.path().join(repr.to_string())).unwrap();
+            std::fs::write(dir.path().join(format!("{repr}/seg_0.bin")), content).unwrap();
+        }
+        std::fs::write(
+            dir.path().join("test.mpd"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <MPD type="static">
+                <Period id="0" duration="PT1S">
+                    <AdaptationSet id="0" frameRate="30">
+                        <SegmentTemplate media="$RepresentationID$/seg_$Number$.bin" duration="30" timescale="30" startNumber="0"></SegmentTemplate>
+                        <Representation id="0" bandwidth="1000"></Representation>
+                        <Representation id="1" bandwidth="2000"></Representation>
+                    </AdaptationSet>
+                </Period>
+            </MPD>"#,
+        )
+        .unwrap();
+        let server = crate::dash::server::DashServer::new(dir.path())
+            .bind("127.0.0.1:0")
+            .await
+            .unwrap();
+
+        let download_dir = tempfile::tempdir().unwrap();
+        let mut fetcher = Fetcher::new(&server.url("test.mpd"), download_dir.path(), false)
+            .await
+            .unwrap();
+        fetcher.set_cache_capacity(1024).unwrap();
+        // twice each, so that the second download of each representation is served from the cache
+        for _ in 0..2 {
+            for (repr, content) in [(0, b"low"), (1, b"top")] {
+                let res = fetcher.download(0, 0, &[repr], false, None).await.unwrap();
+                let path = res.paths[0].as_ref().unwrap();
+                assert_eq!(std::fs::read(path).unwrap(), content);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume_abandoned_download() {
+        let dir = tempfile::tempdir().unwrap();
+        let segment: Vec<u8> = (0..1024).map(|i| i as u8).collect();
+        std::fs::write(dir.path().join("seg_0.bin"), &segment).unwrap();
+        std::fs::write(
+            dir.path().join("test.mpd"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <MPD type="static">
+                <Period id="0" duration="PT1S">
+                    <AdaptationSet id="0" frameRate="30">
+                        <SegmentTemplate media="seg_$Number$.bin" duration="30" timescale="30" startNumber="0"></SegmentTemplate>
+                        <Representation id="0" bandwidth="1000"></Representation>
+                    </AdaptationSet>
+                </Period>
+            </MPD>"#,
+        )
+        .unwrap();
+        let mut server = crate::dash::server::DashServer::new(dir.path());
+        // about a second for the segment
+        server.set_network_trace(crate::simulation::NetworkTrace::from_samples(vec![8.0]));
+        let server = server.bind("127.0.0.1:0").await.unwrap();
+
+        let download_dir = tempfile::tempdir().unwrap();
+        let mut fetcher = Fetcher::new(&server.url("test.mpd"), download_dir.path(), false)
+            .await
+            .unwrap();
+        fetcher.set_cache_capacity(1 << 20).unwrap();
+        let err = fetcher
+            .download_cancellable(
+                0,
+                0,
+                &[0],
+                false,
+                None,
+                &CancellationToken::new(),
+                Some(Duration::from_millis(300)),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(FetchError::Abandoned { .. })
+        ));
+        let part = download_dir
+            .path()
+            .join(partial_key(&generate_filename_from_url(
+                &server.url("seg_0.bin"),
+            )));
+        let kept = std::fs::read(&part).unwrap();
+        assert!(!kept.is_empty() && kept.len() < segment.len());
+        assert_eq!(kept, segment[..kept.len()]);
+
+        // the next download only fetches the rest
+        let res = fetcher.download(0, 0, &[0], false, None).await.unwrap();
+        assert_eq!(res.contents[0].as_deref(), Some(segment.as_slice()));
+        assert!(!part.exists());
     }
 }
@@ -0,0 +1,123 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// The live edge of a dynamic MPD, as last computed by the fetcher.
+///
+/// It is shared between the fetcher, which updates it whenever the manifest is refreshed, and the buffer manager,
+/// which uses it to decide which frames to request.
+#[derive(Debug, Clone)]
+pub struct LiveEdge(Arc<AtomicU64>);
+
+impl LiveEdge {
+    const UNKNOWN: u64 = u64::MAX;
+
+    pub fn new() -> Self {
+        LiveEdge(Arc::new(AtomicU64::new(Self::UNKNOWN)))
+    }
+
+    pub fn set(&self, frame_offset: Option<u64>) {
+        self.0
+            .store(frame_offset.unwrap_or(Self::UNKNOWN), Ordering::Relaxed);
+    }
+
+    /// Frame offset of the newest fully available segment, if known.
+    pub fn get(&self) -> Option<u64> {
+        match self.0.load(Ordering::Relaxed) {
+            Self::UNKNOWN => None,
+            frame_offset => Some(frame_offset),
+        }
+    }
+}
+
+impl Default for LiveEdge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Maps the frame indices requested by the renderer onto the frames of a live stream,
+/// so that playback stays `target_latency` frames behind the live edge.
+#[derive(Debug, Clone)]
+pub struct LivePlayhead {
+    edge: LiveEdge,
+    /// in frames, rounded up to a whole number of segments
+    target_latency: u64,
+    /// once playback falls this many frames behind the live edge (e.g. after stalls), we jump back to the target latency
+    max_latency: u64,
+    segment_frames: u64,
+    /// absolute frame offset = renderer index + offset
+    offset: Option<i64>,
+}
+
+impl LivePlayhead {
+    /// # Arguments
+    ///
+    /// * `edge` - live edge shared with the fetcher
+    /// * `target_latency` - how many frames behind the live edge playback should be
+    /// * `segment_frames` - number of frames per segment. Playback always starts at a segment boundary.
+    pub fn new(edge: LiveEdge, target_latency: u64, segment_frames: u64) -> Self {
+        let segment_frames = segment_frames.max(1);
+        let target_latency =
+            (target_latency + segment_frames - 1) / segment_frames * segment_frames;
+        LivePlayhead {
+            edge,
+            target_latency,
+            max_latency: 2 * target_latency + segment_frames,
+            segment_frames,
+            offset: None,
+        }
+    }
+
+    /// Translates the renderer's frame index into a frame offset of the live stream.
+    /// Returns `None` while the live edge is unknown.
+    pub fn map(&mut self, index: u64) -> Option<u64> {
+        let edge = self.edge.get()?;
+        let frame_offset = self
+            .offset
+            .map(|offset| (index as i64 + offset).max(0) as u64);
+        match frame_offset {
+            Some(frame_offset) if edge.saturating_sub(frame_offset) <= self.max_latency => {
+                Some(frame_offset)
+            }
+            _ => {
+                // (re)start playback at the target latency, aligned to a segment boundary
+                let target = edge.saturating_sub(self.target_latency) / self.segment_frames
+                    * self.segment_frames;
+                self.offset = Some(target as i64 - index as i64);
+                Some(target)
+            }
+        }
+    }
+
+    /// How many frames `frame_offset` is behind the live edge.
+    pub fn latency(&self, frame_offset: u64) -> Option<u64> {
+        self.edge
+            .get()
+            .map(|edge| edge.saturating_sub(frame_offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_live_playhead() {
+        let edge = LiveEdge::new();
+        let mut playhead = LivePlayhead::new(edge.clone(), 50, 30);
+        assert_eq!(playhead.map(0), None);
+
+        // target latency is rounded up to 2 segments
+        edge.set(Some(300));
+        assert_eq!(playhead.map(0), Some(240));
+        assert_eq!(playhead.map(1), Some(241));
+        assert_eq!(playhead.latency(241), Some(59));
+
+        // falling behind by more than the max latency jumps back to the target latency
+        edge.set(Some(390));
+        assert_eq!(playhead.map(30), Some(270));
+        edge.set(Some(600));
+        assert_eq!(playhead.map(31), Some(540));
+        assert_eq!(playhead.map(32), Some(541));
+    }
+}
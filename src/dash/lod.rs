@@ -0,0 +1,128 @@
+//! Client side of progressive LOD streaming, see [`crate::lodify::progressive`].
+
+use anyhow::{bail, Result};
+
+use super::fetcher::Fetcher;
+use crate::lodify::progressive::{Chunk, LodSegment};
+use crate::player::resolution_controller::ResolutionController;
+use crate::render::wgpu::camera::CameraState;
+use crate::render::wgpu::renderable::Renderable;
+
+/// Fetches a progressive LOD stream segment by segment: the base level of every tile first, then as many
+/// refinement levels per tile as the camera-dependent point budgets of a [`ResolutionController`] ask for.
+pub struct LodStream {
+    fetcher: Fetcher,
+    object_id: u8,
+    /// representation id of every level of every tile, from the base up
+    levels: Vec<Vec<u8>>,
+    /// created from the base of the first segment, which sets the reference point spacing
+    resolution_controller: Option<ResolutionController>,
+}
+
+impl LodStream {
+    pub fn new(fetcher: Fetcher, object_id: u8) -> Result<Self> {
+        let tiles = fetcher.mpd_parser.tiles(object_id, 0)?;
+        if tiles.is_empty() {
+            bail!("object {object_id} is not a progressive LOD stream, it has no tiles");
+        }
+        let mut levels = vec![];
+        for (i, tile) in tiles.into_iter().enumerate() {
+            if tile as usize != i {
+                bail!("the tiles must be numbered from 0 without gaps, found tile {tile}");
+            }
+            let tile_levels = fetcher.mpd_parser.lod_levels(object_id, tile, 0)?;
+            levels.push(tile_levels.into_iter().map(|(id, _)| id).collect());
+        }
+        Ok(LodStream {
+            fetcher,
+            object_id,
+            levels,
+            resolution_controller: None,
+        })
+    }
+
+    pub fn fetcher(&self) -> &Fetcher {
+        &self.fetcher
+    }
+
+    pub fn tiles(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Fetches the base of every tile of the segment containing `frame_offset`, refined for `camera_state` if given.
+    pub async fn fetch(
+        &mut self,
+        frame_offset: u64,
+        camera_state: Option<&CameraState>,
+    ) -> Result<LodSegment> {
+        let bases: Vec<(u8, u8)> = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(tile, levels)| (tile as u8, levels[0]))
+            .collect();
+        let data = self
+            .fetcher
+            .download_lod(self.object_id, frame_offset, &bases)
+            .await?;
+        let chunks = data
+            .iter()
+            .map(|data| Chunk::from_bytes(data))
+            .collect::<Result<Vec<_>>>()?;
+        let mut segment = LodSegment::new(chunks)?;
+        if let Some(camera_state) = camera_state {
+            self.refine(&mut segment, frame_offset, camera_state)
+                .await?;
+        }
+        Ok(segment)
+    }
+
+    /// Number of levels every tile of `segment` needs to be shown at the point density the camera asks for.
+    pub fn wanted_levels(
+        &mut self,
+        segment: &LodSegment,
+        camera_state: &CameraState,
+    ) -> Vec<usize> {
+        let controller = self.resolution_controller.get_or_insert_with(|| {
+            let anchor = segment.frame(0).unwrap();
+            ResolutionController::new(&anchor.points, None, anchor.antialias())
+        });
+        controller.set_metadata(Some(segment.metadata()));
+        let desired: Vec<Vec<usize>> = (0..segment.frames())
+            .map(|frame| controller.get_desired_num_points(frame, camera_state))
+            .collect();
+        segment.levels_for(&desired)
+    }
+
+    /// Fetches the levels `segment` is missing for `camera_state`. Returns whether anything was fetched.
+    ///
+    /// `frame_offset` is any frame of the segment.
+    pub async fn refine(
+        &mut self,
+        segment: &mut LodSegment,
+        frame_offset: u64,
+        camera_state: &CameraState,
+    ) -> Result<bool> {
+        let wanted = self.wanted_levels(segment, camera_state);
+        let missing: Vec<(u8, u8)> = wanted
+            .iter()
+            .enumerate()
+            .flat_map(|(tile, &wanted)| {
+                let loaded = segment.loaded_levels(tile);
+                let levels = &self.levels[tile];
+                (loaded..wanted.min(levels.len())).map(move |level| (tile as u8, levels[level]))
+            })
+            .collect();
+        if missing.is_empty() {
+            return Ok(false);
+        }
+        let data = self
+            .fetcher
+            .download_lod(self.object_id, frame_offset, &missing)
+            .await?;
+        for data in data {
+            segment.add(Chunk::from_bytes(&data)?)?;
+        }
+        Ok(true)
+    }
+}
@@ -1,8 +1,12 @@
 use crate::render::wgpu::camera::CameraPosition;
 
 pub mod buffer;
+pub mod cache;
 pub mod fetcher;
+pub mod live;
+pub mod lod;
 pub mod parser;
+pub mod server;
 
 pub trait ViewportPrediction: Send {
     fn add(&mut self, pos: CameraPosition);
@@ -3,59 +3,160 @@
 
 #![allow(non_snake_case)]
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use regex::Regex;
 use serde::de;
 use serde::{Deserialize, Serialize, Serializer};
 use serde_with::skip_serializing_none;
 use std::collections::HashMap;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-const FPS: u64 = 30;
+use crate::formats::bounds::Bounds;
+
+/// Frame rate (numerator, denominator) used when the MPD does not declare any `frameRate`.
+const DEFAULT_FPS: (u64, u64) = (30, 1);
+
+/// Maps the frames of a representation whose frame rate differs from the presentation frame rate onto
+/// presentation frames, repeating or dropping frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRateConversion {
+    /// frame rate of the representation as (numerator, denominator)
+    representation: (u64, u64),
+    /// presentation frame rate as (numerator, denominator)
+    presentation: (u64, u64),
+}
+
+impl FrameRateConversion {
+    /// The frame of the representation shown at presentation frame `frame`, both counted from the start of a segment.
+    pub fn representation_frame(&self, frame: u64) -> u64 {
+        frame * self.representation.0 * self.presentation.1
+            / (self.representation.1 * self.presentation.0)
+    }
+}
 
 #[derive(Clone)]
 pub struct MPDParser {
     mpd: Mpd,
     /// contains the first frame offsets for all `Period` in the MPD and the total number of frames.
     period_markers: Vec<u64>,
+    /// presentation frame rate as (numerator, denominator). All frame offsets are counted at this rate.
+    fps: (u64, u64),
+    /// URL the MPD was fetched from. Used to resolve relative BaseURLs.
+    manifest_url: Option<String>,
+    /// `MPD@availabilityStartTime`, only present for dynamic MPDs.
+    availability_start: Option<SystemTime>,
 }
 
 impl MPDParser {
-    pub fn new(xml: &str) -> MPDParser {
-        let mpd = Mpd::from_xml(xml).unwrap();
+    pub fn new(xml: &str) -> Result<MPDParser> {
+        let mpd = Mpd::from_xml(xml).context("failed to parse MPD")?;
+        MPDParser::from_mpd(mpd, SystemTime::now())
+    }
 
-        let mut framestamps: Vec<u64> = vec![];
-        let mut curr_frame = 0;
-        framestamps.push(curr_frame);
-        for period in mpd.periods.iter() {
-            if let Some(duration) = period.duration {
-                curr_frame += (duration.as_secs_f64() * FPS as f64) as u64;
-                framestamps.push(curr_frame);
-            }
+    /// `now` is only used to find the end of the open-ended last period of a dynamic MPD.
+    fn from_mpd(mpd: Mpd, now: SystemTime) -> Result<MPDParser> {
+        if mpd.periods.is_empty() {
+            bail!("MPD does not contain any Period");
         }
+        let fps = presentation_fps(&mpd)?;
+        let availability_start = mpd
+            .availabilityStartTime
+            .as_deref()
+            .map(parse_xs_datetime)
+            .transpose()
+            .context("invalid availabilityStartTime")?;
+        let is_dynamic = mpd.mpdtype.as_deref() == Some("dynamic");
+        if is_dynamic && availability_start.is_none() {
+            bail!("dynamic MPD must have an availabilityStartTime");
+        }
+        let elapsed = availability_start
+            .filter(|_| is_dynamic)
+            .map(|ast| now.duration_since(ast).unwrap_or_default());
+        let period_markers = compute_period_markers(&mpd, fps, elapsed)?;
 
-        MPDParser {
+        Ok(MPDParser {
             mpd,
-            period_markers: framestamps,
+            period_markers,
+            fps,
+            manifest_url: None,
+            availability_start,
+        })
+    }
+
+    /// Returns true if the MPD is `type="dynamic"`, i.e. a live stream that changes over time.
+    pub fn is_dynamic(&self) -> bool {
+        self.mpd.mpdtype.as_deref() == Some("dynamic")
+    }
+
+    /// How often a dynamic MPD should be refetched. `None` if the MPD never changes.
+    pub fn minimum_update_period(&self) -> Option<Duration> {
+        self.mpd.minimumUpdatePeriod.filter(|_| self.is_dynamic())
+    }
+
+    /// Get the frame offset of the first frame of the newest segment that is fully available at `now`.
+    /// Always `None` for static MPDs.
+    pub fn live_edge(&self, now: SystemTime) -> Option<u64> {
+        if !self.is_dynamic() {
+            return None;
+        }
+        let elapsed = now.duration_since(self.availability_start?).ok()?;
+        let mut available = (elapsed.as_secs_f64() * self.fps()) as u64;
+        // a SegmentTimeline only lists the segments that have been published
+        let last = self.mpd.periods.len() - 1;
+        if let Some(d) = timeline_duration(&self.mpd.periods[last]) {
+            let timeline_end = self.period_markers[last] + self.duration_to_frames(d);
+            available = available.min(timeline_end);
         }
+        let segment_frames = self.segment_frames().ok()?;
+        Some((available / segment_frames).saturating_sub(1) * segment_frames)
     }
 
-    /// gets MPD's top-level BaseURL
-    pub fn get_base_url(&self) -> String {
-        let url = self
-            .mpd
-            .base_urls
-            .as_ref()
-            .unwrap()
-            .first()
-            .expect("no base url found")
-            .base
-            .clone();
-        if url.ends_with('/') {
-            url
-        } else {
-            url + "/"
+    /// Merges a refreshed copy of a dynamic MPD into this one.
+    ///
+    /// Periods and SegmentTimeline entries that have scrolled out of the refreshed MPD are kept,
+    /// so frame offsets handed out before the refresh stay valid.
+    pub fn merge(&mut self, newer: MPDParser) -> Result<()> {
+        let mut newer_mpd = newer.mpd;
+        let mut periods = vec![];
+        for old in &self.mpd.periods {
+            match newer_mpd
+                .periods
+                .iter_mut()
+                .find(|p| p.id.is_some() && p.id == old.id)
+            {
+                Some(new) => merge_period(new, old),
+                None => periods.push(old.clone()),
+            }
         }
+        periods.append(&mut newer_mpd.periods);
+        newer_mpd.periods = periods;
+
+        let mut merged = MPDParser::from_mpd(newer_mpd, SystemTime::now())?;
+        merged.manifest_url = self.manifest_url.take();
+        *self = merged;
+        Ok(())
+    }
+
+    fn duration_to_frames(&self, d: Duration) -> u64 {
+        (d.as_secs_f64() * self.fps()).round() as u64
+    }
+
+    /// Sets the URL the MPD was fetched from, so that relative (or missing) BaseURLs can be resolved against it.
+    pub fn set_manifest_url(&mut self, url: &str) {
+        self.manifest_url = Some(url.to_string());
+    }
+
+    /// gets MPD's top-level BaseURL
+    pub fn get_base_url(&self) -> Result<String> {
+        self.base_url_candidates(&[self.mpd.base_urls.as_deref()])
+            .into_iter()
+            .find(|url| !url.is_empty())
+            .ok_or_else(|| anyhow!("no base url found"))
+    }
+
+    /// Get the presentation frame rate in frames per second.
+    pub fn fps(&self) -> f64 {
+        self.fps.0 as f64 / self.fps.1 as f64
     }
 
     /// Get the number of frames in the whole MPD.
@@ -64,16 +165,38 @@ impl MPDParser {
     }
 
     /// Get the segment template's duration and timescale. To get the time in seconds, need to divide duration by timescale.
-    /// It is assumed that all representations (in all periods) have the same segment template duration.
-    pub fn segment_duration(&self) -> (u64, u64) {
-        let st = self.mpd.periods[0].adaptations.as_ref().unwrap()[0]
+    /// The first representation of the first period is used. If it is described by a `SegmentTimeline`,
+    /// the duration of its first segment is returned.
+    pub fn segment_duration(&self) -> Result<(u64, u64)> {
+        let adaptation_set = self.mpd.periods[0]
+            .adaptations
+            .as_ref()
+            .and_then(|a| a.first())
+            .ok_or_else(|| anyhow!("first period has no AdaptationSet"))?;
+        let representation = adaptation_set
             .representations
             .as_ref()
-            .unwrap()[0]
-            .segment_template
+            .and_then(|r| r.first())
+            .ok_or_else(|| anyhow!("first AdaptationSet has no Representation"))?;
+        let st = SegmentTemplate::merge(
+            representation.segment_template.as_ref(),
+            adaptation_set.segment_template.as_ref(),
+        );
+        let timescale = st.timescale.unwrap_or(1);
+        if let Some(duration) = st.duration {
+            return Ok((duration, timescale));
+        }
+        st.segment_timeline
             .as_ref()
-            .unwrap();
-        (st.duration.unwrap(), st.timescale.unwrap())
+            .and_then(|tl| tl.segments.first())
+            .map(|s| (s.d, timescale))
+            .ok_or_else(|| anyhow!("SegmentTemplate has neither @duration nor SegmentTimeline"))
+    }
+
+    /// Get the number of frames in a segment, from [`Self::segment_duration`] and the frame rate.
+    pub fn segment_frames(&self) -> Result<u64> {
+        let (duration, timescale) = self.segment_duration()?;
+        Ok(((duration as f64 / timescale as f64 * self.fps()).round() as u64).max(1))
     }
 
     // From https://dashif.org/docs/DASH-IF-IOP-v4.3.pdf:
@@ -109,7 +232,79 @@ impl MPDParser {
         result
     }
 
-    /// gets the URL and the bandwidth information for the requested segment.
+    /// Resolves the BaseURLs from the outermost to the innermost level. Each level may list several
+    /// BaseURLs for redundancy, so the result contains every combination in order of preference.
+    fn base_url_candidates(&self, levels: &[Option<&[BaseURL]>]) -> Vec<String> {
+        let mut candidates = vec![self
+            .manifest_url
+            .as_deref()
+            .map(|url| match url.rfind('/') {
+                Some(idx) => url[..=idx].to_string(),
+                None => String::new(),
+            })
+            .unwrap_or_default()];
+        for base_urls in levels.iter().flatten() {
+            if base_urls.is_empty() {
+                continue;
+            }
+            candidates = candidates
+                .iter()
+                .flat_map(|parent| base_urls.iter().map(|b| join_url(parent, b.base.trim())))
+                .collect();
+        }
+        candidates
+            .into_iter()
+            .map(|url| {
+                if url.is_empty() || url.ends_with('/') {
+                    url
+                } else {
+                    url + "/"
+                }
+            })
+            .collect()
+    }
+
+    fn period_index(&self, frame_offset: u64) -> Result<usize> {
+        let period_idx =
+            match self.period_markers[..].binary_search_by(|probe| probe.cmp(&frame_offset)) {
+                Ok(idx) => idx,
+                Err(idx) => idx - 1,
+            };
+        // the last period of a dynamic MPD keeps growing
+        if self.is_dynamic() && period_idx >= self.mpd.periods.len() {
+            return Ok(self.mpd.periods.len() - 1);
+        }
+        if period_idx >= self.mpd.periods.len() {
+            bail!(
+                "frame {} is beyond the end of the MPD ({} frames)",
+                frame_offset,
+                self.total_frames()
+            );
+        }
+        Ok(period_idx)
+    }
+
+    fn find_adaptation_set(
+        period: &Period,
+        object_id: u8,
+        view_id: Option<u8>,
+    ) -> Result<&AdaptationSet> {
+        period
+            .adaptations
+            .as_ref()
+            .and_then(|adaptations| {
+                adaptations.iter().find(|as_| {
+                    (view_id.is_none() || view_id.unwrap() as u64 == as_.viewId.unwrap_or_default())
+                        && as_.srcObjectId.unwrap_or_default() == object_id as u64
+                })
+            })
+            .ok_or_else(|| {
+                anyhow!("no AdaptationSet found for object {object_id} and view {view_id:?}")
+            })
+    }
+
+    /// gets the candidate URLs and the bandwidth information for the requested segment.
+    /// The URLs are ordered by preference. Later ones should only be tried if the earlier ones fail.
     ///
     /// # Arguments
     ///
@@ -117,64 +312,247 @@ impl MPDParser {
     /// * `representation_id` - quality of the requested segment
     /// * `frame offset` - Frame offset as calculated from the beginning of the video / MPD
     /// * `view_id` - View ID of the requested segment. If `None`, the parser assumes the pointclouds are not segmented into different planes, and will return the info for the first matching segment.
-    pub fn get_info(
+    pub fn get_urls(
         &self,
         object_id: u8,
         representation_id: u8,
         frame_offset: u64,
         view_id: Option<u8>,
-    ) -> (String, Option<u64>) {
-        let period_idx =
-            match self.period_markers[..].binary_search_by(|probe| probe.cmp(&frame_offset)) {
-                Ok(idx) => idx,
-                Err(idx) => idx - 1,
-            };
+    ) -> Result<(Vec<String>, Option<u64>)> {
+        let period_idx = self.period_index(frame_offset)?;
+        let period = &self.mpd.periods[period_idx];
+        let adaptation_set = Self::find_adaptation_set(period, object_id, view_id)?;
+        self.segment_urls(period_idx, adaptation_set, representation_id, frame_offset)
+    }
 
-        let base_url = self.get_base_url();
-        let period = self.mpd.periods.get(period_idx).unwrap();
-        let adaptation_set = period
-            .adaptations
+    /// Get how the frames of a representation map onto presentation frames.
+    /// `None` if the representation has the presentation frame rate.
+    pub fn frame_rate_conversion(
+        &self,
+        object_id: u8,
+        representation_id: u8,
+        frame_offset: u64,
+        view_id: Option<u8>,
+    ) -> Result<Option<FrameRateConversion>> {
+        let period = &self.mpd.periods[self.period_index(frame_offset)?];
+        let adaptation_set = Self::find_adaptation_set(period, object_id, view_id)?;
+        let rate = adaptation_set
+            .representations
+            .iter()
+            .flatten()
+            .find(|r| r.id.as_ref().and_then(|id| id.parse::<u8>().ok()) == Some(representation_id))
+            .and_then(|r| r.frameRate.as_ref())
+            .or(adaptation_set.frameRate.as_ref())
+            .map(|rate| parse_frame_rate(rate))
+            .transpose()?
+            .unwrap_or(self.fps);
+        // compare the fractions, "30" and "60/2" are the same rate
+        let same = rate.0 * self.fps.1 == self.fps.0 * rate.1;
+        Ok((!same).then_some(FrameRateConversion {
+            representation: rate,
+            presentation: self.fps,
+        }))
+    }
+
+    /// gets the candidate URLs and the bandwidth of a segment of `representation_id` in `adaptation_set`,
+    /// which belongs to the period `period_idx`.
+    fn segment_urls(
+        &self,
+        period_idx: usize,
+        adaptation_set: &AdaptationSet,
+        representation_id: u8,
+        frame_offset: u64,
+    ) -> Result<(Vec<String>, Option<u64>)> {
+        let period = &self.mpd.periods[period_idx];
+        let representation = adaptation_set
+            .representations
             .as_ref()
-            .unwrap()
+            .and_then(|reprs| {
+                reprs.iter().find(|r| {
+                    r.id.as_ref().and_then(|id| id.parse::<u8>().ok()) == Some(representation_id)
+                })
+            })
+            .ok_or_else(|| anyhow!("representation {representation_id} not found"))?;
+        let st = SegmentTemplate::merge(
+            representation.segment_template.as_ref(),
+            adaptation_set.segment_template.as_ref(),
+        );
+        let media = st
+            .media
+            .as_ref()
+            .ok_or_else(|| anyhow!("representation {representation_id} has no media template"))?;
+        let timescale = st.timescale.unwrap_or(1);
+        let start_number = st.startNumber.unwrap_or(1);
+
+        // position of the requested frame in the timescale of the segment template
+        let frame_in_period = frame_offset - self.period_markers[period_idx];
+        let time = frame_in_period * timescale * self.fps.1 / self.fps.0;
+        let (number, segment_time) = match (&st.segment_timeline, st.duration) {
+            (Some(timeline), _) => {
+                let period_frames =
+                    self.period_markers[period_idx + 1] - self.period_markers[period_idx];
+                let period_end = period_frames * timescale * self.fps.1 / self.fps.0;
+                let (idx, t) = timeline.locate(time, period_end)?;
+                (start_number + idx, t)
+            }
+            (None, Some(0)) => bail!("SegmentTemplate@duration must not be 0"),
+            (None, Some(duration)) => {
+                // NOTE: our own packager numbers segments by the timestamp of their first frame,
+                // i.e. consecutive segment numbers are `duration` apart.
+                let idx = time / duration;
+                (idx * duration + start_number, idx * duration)
+            }
+            (None, None) => {
+                bail!("SegmentTemplate has neither @duration nor SegmentTimeline")
+            }
+        };
+
+        let id = representation.id.clone().unwrap_or_default();
+        let mut params = HashMap::from_iter(vec![
+            ("RepresentationID", id),
+            ("Number", number.to_string()),
+            ("Time", segment_time.to_string()),
+        ]);
+        if let Some(bandwidth) = representation.bandwidth {
+            params.insert("Bandwidth", bandwidth.to_string());
+        }
+        let path = self.resolve_url_template(media, &params);
+
+        let urls = self
+            .base_url_candidates(&[
+                self.mpd.base_urls.as_deref(),
+                period.base_urls.as_deref(),
+                adaptation_set.base_urls.as_deref(),
+                representation.base_urls.as_deref(),
+            ])
+            .into_iter()
+            .map(|base| join_url(&base, &path))
+            .collect();
+        Ok((urls, representation.bandwidth))
+    }
+
+    fn find_tile(period: &Period, object_id: u8, tile_id: u8) -> Result<&AdaptationSet> {
+        period
+            .adaptations
             .iter()
+            .flatten()
             .find(|as_| {
-                (view_id.is_none() || view_id.unwrap() as u64 == as_.viewId.unwrap_or_default())
+                as_.tileId == Some(tile_id as u64)
                     && as_.srcObjectId.unwrap_or_default() == object_id as u64
             })
-            .unwrap();
-        let representation = adaptation_set
+            .ok_or_else(|| anyhow!("no tile {tile_id} found for object {object_id}"))
+    }
+
+    /// Get the tiles of a progressive LOD stream, i.e. the `tileId` of every AdaptationSet of the object.
+    /// Empty if the object is not tiled.
+    pub fn tiles(&self, object_id: u8, frame_offset: u64) -> Result<Vec<u8>> {
+        let period = &self.mpd.periods[self.period_index(frame_offset)?];
+        let mut tiles: Vec<u8> = period
+            .adaptations
+            .iter()
+            .flatten()
+            .filter(|as_| as_.srcObjectId.unwrap_or_default() == object_id as u64)
+            .filter_map(|as_| as_.tileId.map(|id| id as u8))
+            .collect();
+        tiles.sort_unstable();
+        Ok(tiles)
+    }
+
+    /// Get the number of views of an object, i.e. of its AdaptationSets with a `viewId`. 0 if it has a single view.
+    pub fn views(&self, object_id: u8, frame_offset: u64) -> Result<u8> {
+        let period = &self.mpd.periods[self.period_index(frame_offset)?];
+        Ok(period
+            .adaptations
+            .iter()
+            .flatten()
+            .filter(|as_| {
+                as_.viewId.is_some() && as_.srcObjectId.unwrap_or_default() == object_id as u64
+            })
+            .count() as u8)
+    }
+
+    /// Get the bounds of the views `0..views` of an object, from the `tileBounds` of their AdaptationSets,
+    /// so that the views can be weighted per tile. `None` if a view has no bounds, e.g. the faces of a cube.
+    pub fn view_bounds(
+        &self,
+        object_id: u8,
+        frame_offset: u64,
+        views: u8,
+    ) -> Result<Option<Vec<Bounds>>> {
+        let period = &self.mpd.periods[self.period_index(frame_offset)?];
+        let mut bounds = vec![];
+        for view_id in 0..views {
+            let adaptation_set = Self::find_adaptation_set(period, object_id, Some(view_id))?;
+            let Some(tile_bounds) = adaptation_set.tileBounds.as_deref() else {
+                return Ok(None);
+            };
+            bounds.push(parse_bounds(tile_bounds)?);
+        }
+        Ok(Some(bounds))
+    }
+
+    /// Get the refinement levels of a tile, from the base up, as representation ids and bitrates.
+    ///
+    /// The base level is the Representation without `dependencyId`, and every further level depends on the one below.
+    pub fn lod_levels(
+        &self,
+        object_id: u8,
+        tile_id: u8,
+        frame_offset: u64,
+    ) -> Result<Vec<(u8, Option<u64>)>> {
+        let period = &self.mpd.periods[self.period_index(frame_offset)?];
+        let representations = Self::find_tile(period, object_id, tile_id)?
             .representations
-            .as_ref()
-            .unwrap()
+            .as_deref()
+            .unwrap_or_default();
+        let mut levels = vec![];
+        let mut below: Option<&str> = None;
+        while let Some(r) = representations
             .iter()
-            .find(|r| r.id.as_ref().unwrap().parse::<u8>().unwrap() == representation_id)
-            .expect("representation not found");
-        let st = representation.segment_template.as_ref().unwrap();
-        let media = st.media.as_ref().unwrap();
-        (
-            base_url
-                + self
-                    .resolve_url_template(
-                        media,
-                        &HashMap::from_iter(vec![
-                            (
-                                "RepresentationID",
-                                representation.id.as_ref().unwrap().clone(),
-                            ),
-                            (
-                                "Number",
-                                (((frame_offset - self.period_markers.get(period_idx).unwrap())
-                                    * st.timescale.unwrap()
-                                    / (st.duration.unwrap() * FPS))
-                                    * st.duration.unwrap()
-                                    + st.startNumber.expect("start number not provided"))
-                                .to_string(),
-                            ),
-                        ]),
-                    )
-                    .as_str(),
-            representation.bandwidth,
-        )
+            .find(|r| r.dependencyId.as_deref() == below)
+        {
+            let id = r.id.as_deref().unwrap_or_default();
+            let level = id
+                .parse::<u8>()
+                .with_context(|| format!("invalid representation id {id:?}"))?;
+            levels.push((level, r.bandwidth));
+            if levels.len() > representations.len() {
+                bail!("the levels of tile {tile_id} depend on each other in a cycle");
+            }
+            below = Some(id);
+        }
+        if levels.is_empty() {
+            bail!("tile {tile_id} has no base level");
+        }
+        Ok(levels)
+    }
+
+    /// gets the candidate URLs and the bandwidth of the segment of a refinement level of a tile,
+    /// see [`MPDParser::lod_levels`].
+    pub fn get_tile_urls(
+        &self,
+        object_id: u8,
+        tile_id: u8,
+        level: u8,
+        frame_offset: u64,
+    ) -> Result<(Vec<String>, Option<u64>)> {
+        let period_idx = self.period_index(frame_offset)?;
+        let adaptation_set = Self::find_tile(&self.mpd.periods[period_idx], object_id, tile_id)?;
+        self.segment_urls(period_idx, adaptation_set, level, frame_offset)
+    }
+
+    /// gets the URL and the bandwidth information for the requested segment.
+    /// Only the most preferred URL is returned, see [`MPDParser::get_urls`] for the fallbacks.
+    pub fn get_info(
+        &self,
+        object_id: u8,
+        representation_id: u8,
+        frame_offset: u64,
+        view_id: Option<u8>,
+    ) -> Result<(String, Option<u64>)> {
+        let (mut urls, bandwidth) =
+            self.get_urls(object_id, representation_id, frame_offset, view_id)?;
+        Ok((urls.swap_remove(0), bandwidth))
     }
 
     /// Get available bitrates in bits per second
@@ -183,48 +561,264 @@ impl MPDParser {
         object_id: u8,
         frame_offset: u64,
         view_id: Option<u8>,
-    ) -> Vec<u64> {
-        let period_idx =
-            match self.period_markers[..].binary_search_by(|probe| probe.cmp(&frame_offset)) {
-                Ok(idx) => idx,
-                Err(idx) => idx - 1,
-            };
-
-        let period = self.mpd.periods.get(period_idx).unwrap();
-        let adaptation_set = period
-            .adaptations
-            .as_ref()
-            .unwrap()
-            .iter()
-            .find(|as_| {
-                (view_id.is_none() || view_id.unwrap() as u64 == as_.viewId.unwrap_or_default())
-                    && as_.srcObjectId.unwrap_or_default() == object_id as u64
-            })
-            .unwrap();
+    ) -> Result<Vec<u64>> {
+        let period = &self.mpd.periods[self.period_index(frame_offset)?];
+        let adaptation_set = Self::find_adaptation_set(period, object_id, view_id)?;
         adaptation_set
             .representations
             .as_ref()
-            .unwrap()
+            .ok_or_else(|| anyhow!("AdaptationSet has no Representation"))?
             .iter()
-            .map(|r| r.bandwidth.unwrap())
+            .map(|r| {
+                r.bandwidth.ok_or_else(|| {
+                    anyhow!("representation {:?} has no @bandwidth", r.id.as_deref())
+                })
+            })
             .collect()
     }
 
     /// Get a vector of (geometry_qp, attribute_qp) tuples for all representations in the MPD.
     /// It is assumed that the data is the same for all representations and periods.
-    pub fn get_qp(&self) -> Vec<(Option<u64>, Option<u64>)> {
-        let period = self.mpd.periods.first().unwrap();
-        let adaptation_set = &period.adaptations.as_ref().unwrap()[0];
-        adaptation_set
-            .representations
+    pub fn get_qp(&self) -> Result<Vec<(Option<u64>, Option<u64>)>> {
+        let period = &self.mpd.periods[0];
+        let adaptation_set = period
+            .adaptations
             .as_ref()
-            .unwrap()
+            .and_then(|a| a.first())
+            .ok_or_else(|| anyhow!("first period has no AdaptationSet"))?;
+        Ok(adaptation_set
+            .representations
+            .as_deref()
+            .unwrap_or_default()
             .iter()
             .map(|r| (r.geometry_qp, r.attribute_qp))
-            .collect()
+            .collect())
     }
 }
 
+/// Joins a (possibly relative) URL to its parent. Absolute URLs replace the parent.
+fn join_url(parent: &str, url: &str) -> String {
+    if url.contains("://") || parent.is_empty() {
+        url.to_string()
+    } else if parent.ends_with('/') {
+        format!("{parent}{url}")
+    } else {
+        format!("{parent}/{url}")
+    }
+}
+
+/// Parses a `frameRate` attribute, which is either an integer or a fraction, e.g. "30" or "30000/1001".
+fn parse_frame_rate(s: &str) -> Result<(u64, u64)> {
+    let (num, den) = match s.split_once('/') {
+        Some((num, den)) => (num.trim(), den.trim()),
+        None => (s.trim(), "1"),
+    };
+    let num = num
+        .parse::<u64>()
+        .with_context(|| format!("invalid frameRate {s:?}"))?;
+    let den = den
+        .parse::<u64>()
+        .with_context(|| format!("invalid frameRate {s:?}"))?;
+    if num == 0 || den == 0 {
+        bail!("invalid frameRate {s:?}");
+    }
+    Ok((num, den))
+}
+
+/// Parses `tileBounds`, six numbers separated by whitespace or commas.
+fn parse_bounds(s: &str) -> Result<Bounds> {
+    let values = s
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid tileBounds {s:?}"))?;
+    let [min_x, min_y, min_z, max_x, max_y, max_z] = values[..] else {
+        bail!("invalid tileBounds {s:?}, expected 6 numbers");
+    };
+    Ok(Bounds {
+        min_x,
+        max_x,
+        min_y,
+        max_y,
+        min_z,
+        max_z,
+    })
+}
+
+/// The presentation frame rate, the first one declared on the representations or their adaptation sets.
+///
+/// Frame offsets, segment lengths and the live edge are all counted at this rate. Representations may declare
+/// other rates, their frames are mapped onto the presentation frames with a [`FrameRateConversion`].
+fn presentation_fps(mpd: &Mpd) -> Result<(u64, u64)> {
+    let mut fps: Option<(u64, u64)> = None;
+    for adaptation_set in mpd
+        .periods
+        .iter()
+        .flat_map(|p| p.adaptations.iter().flatten())
+    {
+        let rates = adaptation_set
+            .representations
+            .iter()
+            .flatten()
+            .filter_map(|r| r.frameRate.as_ref())
+            .chain(adaptation_set.frameRate.as_ref());
+        for rate in rates {
+            // every rate is checked, so that they can be parsed later on
+            let rate = parse_frame_rate(rate)?;
+            fps.get_or_insert(rate);
+        }
+    }
+    Ok(fps.unwrap_or(DEFAULT_FPS))
+}
+
+fn timeline_duration(period: &Period) -> Option<Duration> {
+    period
+        .adaptations
+        .iter()
+        .flatten()
+        .find_map(|adaptation_set| {
+            adaptation_set
+                .representations
+                .iter()
+                .flatten()
+                .find_map(|r| {
+                    let st = SegmentTemplate::merge(
+                        r.segment_template.as_ref(),
+                        adaptation_set.segment_template.as_ref(),
+                    );
+                    let total = st.segment_timeline.as_ref()?.total_duration()?;
+                    Some(Duration::from_secs_f64(
+                        total as f64 / st.timescale.unwrap_or(1) as f64,
+                    ))
+                })
+        })
+}
+
+/// `elapsed` is the time since `availabilityStartTime` for dynamic MPDs, it bounds the last period
+/// when its duration is not known yet.
+fn compute_period_markers(
+    mpd: &Mpd,
+    fps: (u64, u64),
+    elapsed: Option<Duration>,
+) -> Result<Vec<u64>> {
+    let mut framestamps: Vec<u64> = vec![];
+    let mut curr_frame = 0;
+    let mut curr_start = Duration::ZERO;
+    framestamps.push(curr_frame);
+    for (idx, period) in mpd.periods.iter().enumerate() {
+        let is_last = idx + 1 == mpd.periods.len();
+        // the first period starts at 0 unless stated otherwise
+        let start = period.start.unwrap_or(curr_start);
+        let duration = period
+            .duration
+            .or_else(|| {
+                let end = match mpd.periods.get(idx + 1) {
+                    Some(next) => next.start?,
+                    None => mpd.mediaPresentationDuration?,
+                };
+                end.checked_sub(start)
+            })
+            .or_else(|| timeline_duration(period))
+            .or_else(|| {
+                elapsed
+                    .filter(|_| is_last)
+                    .map(|elapsed| elapsed.saturating_sub(start))
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "cannot determine the duration of period {}",
+                    period.id.as_deref().unwrap_or("<unnamed>")
+                )
+            })?;
+        curr_start = start + duration;
+        curr_frame += (duration.as_secs_f64() * fps.0 as f64 / fps.1 as f64).round() as u64;
+        framestamps.push(curr_frame);
+    }
+    Ok(framestamps)
+}
+
+/// Carries the SegmentTimeline entries of `older` that are no longer listed in `newer` over.
+fn merge_period(newer: &mut Period, older: &Period) {
+    for new_as in newer.adaptations.iter_mut().flatten() {
+        let old_as = older.adaptations.iter().flatten().find(|a| {
+            a.id == new_as.id && a.viewId == new_as.viewId && a.srcObjectId == new_as.srcObjectId
+        });
+        if let Some(old_as) = old_as {
+            merge_segment_template(&mut new_as.segment_template, &old_as.segment_template);
+            for new_r in new_as.representations.iter_mut().flatten() {
+                if let Some(old_r) = old_as
+                    .representations
+                    .iter()
+                    .flatten()
+                    .find(|r| r.id == new_r.id)
+                {
+                    merge_segment_template(&mut new_r.segment_template, &old_r.segment_template);
+                }
+            }
+        }
+    }
+}
+
+fn merge_segment_template(newer: &mut Option<SegmentTemplate>, older: &Option<SegmentTemplate>) {
+    let old_timeline = older.as_ref().and_then(|st| st.segment_timeline.as_ref());
+    if let (Some(st), Some(old_timeline)) = (newer.as_mut(), old_timeline) {
+        if let Some(timeline) = st.segment_timeline.as_mut() {
+            let prepended = timeline.prepend(old_timeline);
+            // $Number$ of the segments already listed must not change
+            st.startNumber = Some(st.startNumber.unwrap_or(1).saturating_sub(prepended));
+        }
+    }
+}
+
+// Parse an XML dateTime string, as per https://www.w3.org/TR/xmlschema-2/#dateTime
+//
+// Examples: "2023-06-01T10:00:00Z", "2023-06-01T18:00:00.250+08:00"
+//
+// Limitations: a missing timezone is interpreted as UTC, and dates before 1970 are rejected.
+fn parse_xs_datetime(s: &str) -> Result<SystemTime> {
+    let re = Regex::new(concat!(
+        r"^(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})",
+        r"T(?P<hour>\d{2}):(?P<minute>\d{2}):(?P<second>\d{2})(?P<nanoseconds>[.,]\d+)?",
+        r"(?P<tz>Z|[+-]\d{2}:\d{2})?$"
+    ))
+    .unwrap();
+    let m = re
+        .captures(s.trim())
+        .ok_or_else(|| anyhow!("couldn't parse XS dateTime {s:?}"))?;
+    let field = |name: &str| m[name].parse::<i64>().unwrap();
+
+    let days = days_from_civil(field("year"), field("month"), field("day"));
+    let mut secs = days * 86400 + field("hour") * 3600 + field("minute") * 60 + field("second");
+    if let Some(tz) = m.name("tz").map(|tz| tz.as_str()).filter(|tz| *tz != "Z") {
+        let offset =
+            tz[1..3].parse::<i64>().unwrap() * 3600 + tz[4..6].parse::<i64>().unwrap() * 60;
+        // local time is ahead of UTC for positive offsets
+        secs -= if tz.starts_with('+') { offset } else { -offset };
+    }
+    let mut nsecs: u32 = 0;
+    if let Some(s) = m.name("nanoseconds") {
+        let mut s = &s.as_str()[1..]; // drop initial "."
+        if s.len() > 9 {
+            s = &s[..9];
+        }
+        nsecs = format!("{s:0<9}").parse::<u32>().unwrap();
+    }
+    let secs = u64::try_from(secs).map_err(|_| anyhow!("dateTime {s:?} is before 1970"))?;
+    Ok(UNIX_EPOCH + Duration::new(secs, nsecs))
+}
+
+// Number of days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+// From http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
 // Modified from https://github.com/emarsden/dash-mpd-rs
 //
 // Parse an XML duration string, as per https://www.w3.org/TR/xmlschema-2/#duration
@@ -370,6 +964,130 @@ pub(super) struct SegmentTemplate {
     // https://dash.akamaized.net/akamai/bbb_30fps/bbb_with_multiple_tiled_thumbnails.mpd)
     pub duration: Option<u64>,
     pub timescale: Option<u64>,
+    #[serde(rename = "SegmentTimeline")]
+    pub segment_timeline: Option<SegmentTimeline>,
+}
+
+impl SegmentTemplate {
+    /// A SegmentTemplate on a Representation inherits the attributes it does not set from the
+    /// SegmentTemplate of its AdaptationSet.
+    fn merge(own: Option<&SegmentTemplate>, parent: Option<&SegmentTemplate>) -> SegmentTemplate {
+        let own = own.cloned().unwrap_or_default();
+        let parent = parent.cloned().unwrap_or_default();
+        SegmentTemplate {
+            media: own.media.or(parent.media),
+            startNumber: own.startNumber.or(parent.startNumber),
+            duration: own.duration.or(parent.duration),
+            timescale: own.timescale.or(parent.timescale),
+            segment_timeline: own.segment_timeline.or(parent.segment_timeline),
+        }
+    }
+}
+
+/// An explicit list of segment start times and durations, used instead of a constant `@duration`.
+#[skip_serializing_none]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub(super) struct SegmentTimeline {
+    #[serde(rename = "S")]
+    pub segments: Vec<S>,
+}
+
+/// One entry of a SegmentTimeline: `r + 1` consecutive segments of duration `d`, the first starting at `t`.
+#[skip_serializing_none]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub(super) struct S {
+    pub t: Option<u64>,
+    pub d: u64,
+    /// number of repetitions. A negative value repeats the segment until the end of the period.
+    pub r: Option<i64>,
+}
+
+impl SegmentTimeline {
+    /// Prepends the entries of `older` that end before the first entry of this timeline.
+    /// Returns the number of segments prepended.
+    fn prepend(&mut self, older: &SegmentTimeline) -> u64 {
+        let first = match self.segments.first() {
+            Some(s) => s.t.unwrap_or(0),
+            None => return 0,
+        };
+        let mut kept = vec![];
+        let mut count = 0;
+        let mut start = 0;
+        for s in &older.segments {
+            start = s.t.unwrap_or(start);
+            if s.d == 0 || start >= first {
+                break;
+            }
+            let repeats = match s.r.unwrap_or(0) {
+                r if r >= 0 => r as u64 + 1,
+                _ => (first - start) / s.d,
+            };
+            let n = repeats.min((first - start) / s.d);
+            if n > 0 {
+                kept.push(S {
+                    t: Some(start),
+                    d: s.d,
+                    r: Some(n as i64 - 1),
+                });
+                count += n;
+            }
+            start += s.d * repeats;
+        }
+        if count > 0 {
+            self.segments[0].t = Some(first);
+            kept.append(&mut self.segments);
+            self.segments = kept;
+        }
+        count
+    }
+
+    /// Sum of the durations of all segments, or `None` if an entry repeats until the end of the period.
+    fn total_duration(&self) -> Option<u64> {
+        let mut end = 0;
+        for s in &self.segments {
+            let repeat = u64::try_from(s.r.unwrap_or(0)).ok()?;
+            end = s.t.unwrap_or(end) + s.d * (repeat + 1);
+        }
+        Some(end)
+    }
+
+    /// Finds the segment containing `time`. Returns the index of the segment in the timeline and its start time.
+    ///
+    /// `period_end` is the end of the period in the same timescale, used to expand negative repeat counts.
+    fn locate(&self, time: u64, period_end: u64) -> Result<(u64, u64)> {
+        let mut index = 0;
+        let mut start = 0;
+        for (i, s) in self.segments.iter().enumerate() {
+            if s.d == 0 {
+                bail!("SegmentTimeline entry with zero duration");
+            }
+            start = s.t.unwrap_or(start);
+            let count = match s.r.unwrap_or(0) {
+                r if r >= 0 => r as u64 + 1,
+                _ => {
+                    // repeat until the start of the next entry or the end of the period
+                    let end = self
+                        .segments
+                        .get(i + 1)
+                        .and_then(|next| next.t)
+                        .unwrap_or(period_end);
+                    (end.saturating_sub(start) + s.d - 1) / s.d
+                }
+            };
+            if time < start {
+                break;
+            }
+            if time < start + s.d * count {
+                let offset = (time - start) / s.d;
+                return Ok((index + offset, start + offset * s.d));
+            }
+            index += count;
+            start += s.d * count;
+        }
+        bail!("time {time} is not covered by the SegmentTimeline")
+    }
 }
 
 /// A representation describes a version of the content, using a specific encoding and bitrate.
@@ -389,9 +1107,12 @@ pub(super) struct Representation {
     pub contentType: Option<String>,
     pub frameRate: Option<String>, // can be something like "15/2"
     pub bandwidth: Option<u64>,
+    /// the Representation this one refines, e.g. the level below in a progressive LOD stream
+    pub dependencyId: Option<String>,
     // pub width: Option<u64>,
     // pub height: Option<u64>,
-    // pub BaseURL: Option<String>,
+    #[serde(rename = "BaseURL")]
+    pub base_urls: Option<Vec<BaseURL>>,
     #[serde(rename = "SegmentTemplate")]
     pub segment_template: Option<SegmentTemplate>,
     #[serde(rename = "GeometryQP")]
@@ -407,13 +1128,22 @@ pub(super) struct Representation {
 #[serde(default)]
 pub(super) struct AdaptationSet {
     pub id: Option<u64>,
-    pub BaseURL: Option<BaseURL>,
+    #[serde(rename = "BaseURL")]
+    pub base_urls: Option<Vec<BaseURL>>,
     // eg "video/mp4"
     pub mimeType: Option<String>,
+    pub frameRate: Option<String>,
+    /// Default SegmentTemplate for all Representations of this AdaptationSet.
+    #[serde(rename = "SegmentTemplate")]
+    pub segment_template: Option<SegmentTemplate>,
     #[serde(rename = "Representation")]
     pub representations: Option<Vec<Representation>>,
     pub viewId: Option<u64>,
     pub srcObjectId: Option<u64>,
+    /// tile of a progressive LOD stream
+    pub tileId: Option<u64>,
+    /// bounds of the tile a view covers, as `min_x min_y min_z max_x max_y max_z`
+    pub tileBounds: Option<String>,
 }
 
 /// Describes a chunk of the content with a start time and a duration. Content can be split up into
@@ -423,13 +1153,17 @@ pub(super) struct AdaptationSet {
 #[serde(default)]
 pub(super) struct Period {
     pub id: Option<String>,
-    pub start: Option<String>,
+    #[serde(deserialize_with = "deserialize_xs_duration", default)]
+    #[serde(serialize_with = "serialize_xs_duration")]
+    pub start: Option<Duration>,
     // note: the spec says that this is an xs:duration, not an unsigned int as for other "duration" fields
     #[serde(deserialize_with = "deserialize_xs_duration", default)]
     #[serde(serialize_with = "serialize_xs_duration")]
     pub duration: Option<Duration>,
     #[serde(rename = "AdaptationSet")]
     pub adaptations: Option<Vec<AdaptationSet>>,
+    #[serde(rename = "BaseURL")]
+    pub base_urls: Option<Vec<BaseURL>>,
 }
 
 /// The root node of a parsed DASH MPD manifest.
@@ -447,6 +1181,18 @@ pub(super) struct Mpd {
     #[serde(deserialize_with = "deserialize_xs_duration", default)]
     #[serde(serialize_with = "serialize_xs_duration")]
     pub suggestedPresentationDelay: Option<Duration>,
+    #[serde(deserialize_with = "deserialize_xs_duration", default)]
+    #[serde(serialize_with = "serialize_xs_duration")]
+    pub minimumUpdatePeriod: Option<Duration>,
+    #[serde(deserialize_with = "deserialize_xs_duration", default)]
+    #[serde(serialize_with = "serialize_xs_duration")]
+    pub timeShiftBufferDepth: Option<Duration>,
+    // xs:dateTime, see `parse_xs_datetime`
+    pub availabilityStartTime: Option<String>,
+    pub publishTime: Option<String>,
+    #[serde(deserialize_with = "deserialize_xs_duration", default)]
+    #[serde(serialize_with = "serialize_xs_duration")]
+    pub mediaPresentationDuration: Option<Duration>,
     #[serde(rename = "Period", default)]
     pub periods: Vec<Period>,
     /// There may be several BaseURLs, for redundancy (for example multiple CDNs)
@@ -553,7 +1299,8 @@ mod tests {
                     </AdaptationSet>
                 </Period>
             </MPD>"#,
-        );
+        )
+        .unwrap();
 
         let periods = &p.mpd.periods;
         let first_period = periods.get(0).unwrap();
@@ -565,33 +1312,316 @@ mod tests {
         let reprs = first_ad.representations.as_ref().unwrap();
         assert_eq!(reprs.len(), 3);
         assert_eq!(
-            p.get_info(0, 2, 29, None),
+            p.get_info(0, 2, 29, None).unwrap(),
             (
-                p.get_base_url() + "longdress/2/longdress_vox10_1080.ply",
+                p.get_base_url().unwrap() + "longdress/2/longdress_vox10_1080.ply",
                 Some(204800)
             )
         );
         assert_eq!(
-            p.get_info(0, 2, 29, Some(5)),
+            p.get_info(0, 2, 29, Some(5)).unwrap(),
             (
-                p.get_base_url() + "longdress/2/S26C2AIR02_F30_1051_5.bin",
+                p.get_base_url().unwrap() + "longdress/2/S26C2AIR02_F30_1051_5.bin",
                 Some(138240)
             )
         );
         assert_eq!(
-            p.get_info(0, 2, 30, Some(5)),
+            p.get_info(0, 2, 30, Some(5)).unwrap(),
             (
-                p.get_base_url() + "longdress/2/S26C2AIR02_F30_1081_5.bin",
+                p.get_base_url().unwrap() + "longdress/2/S26C2AIR02_F30_1081_5.bin",
                 Some(138240)
             )
         );
         assert_eq!(
-            p.available_bitrates(0, 30, None),
+            p.available_bitrates(0, 30, None).unwrap(),
             vec![13631488, 1536000, 204800]
         );
         assert_eq!(
-            p.available_bitrates(0, 30, Some(5)),
+            p.available_bitrates(0, 30, Some(5)).unwrap(),
             vec![100352, 138240, 196608]
         );
     }
+
+    #[test]
+    pub fn test_segment_timeline() {
+        let p = MPDParser::new(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <MPD type="static" mediaPresentationDuration="PT4S">
+                <BaseURL>http://primary.example.com/</BaseURL>
+                <BaseURL>http://backup.example.com/</BaseURL>
+                <Period id="1">
+                    <AdaptationSet frameRate="15">
+                        <SegmentTemplate media="$RepresentationID$/seg_$Time$.bin" timescale="1000" startNumber="0">
+                            <SegmentTimeline>
+                                <S t="0" d="1000" r="1"/>
+                                <S d="500" r="-1"/>
+                            </SegmentTimeline>
+                        </SegmentTemplate>
+                        <Representation id="0" bandwidth="1000">
+                            <BaseURL>low/</BaseURL>
+                        </Representation>
+                        <Representation id="1" bandwidth="2000">
+                            <SegmentTemplate media="$RepresentationID$/seg_$Number%03d$.bin"/>
+                        </Representation>
+                    </AdaptationSet>
+                </Period>
+            </MPD>"#,
+        )
+        .unwrap();
+
+        assert_eq!(p.fps(), 15.0);
+        assert_eq!(p.total_frames(), 60);
+        assert_eq!(p.segment_duration().unwrap(), (1000, 1000));
+        assert_eq!(p.segment_frames().unwrap(), 15);
+
+        // frame 16 at 15 fps is at t=1066ms, which is inside the second 1s segment
+        let (urls, bandwidth) = p.get_urls(0, 0, 16, None).unwrap();
+        assert_eq!(
+            urls,
+            vec![
+                "http://primary.example.com/low/0/seg_1000.bin",
+                "http://backup.example.com/low/0/seg_1000.bin"
+            ]
+        );
+        assert_eq!(bandwidth, Some(1000));
+
+        // frame 40 is at t=2666ms, inside the 2nd 500ms segment after the first two (index 3, starting at 2500)
+        assert_eq!(
+            p.get_info(0, 1, 40, None).unwrap().0,
+            "http://primary.example.com/1/seg_003.bin"
+        );
+        assert!(p.get_info(0, 1, 60, None).is_err());
+    }
+
+    #[test]
+    pub fn test_representation_frame_rates() {
+        let p = MPDParser::new(
+            r#"<MPD>
+                <Period duration="PT2S">
+                    <AdaptationSet frameRate="30">
+                        <SegmentTemplate media="$RepresentationID$_$Number$.bin" duration="1" timescale="1" startNumber="0"/>
+                        <Representation id="0" frameRate="15"/>
+                        <Representation id="1" frameRate="60/2"/>
+                        <Representation id="2" frameRate="60"/>
+                    </AdaptationSet>
+                    <AdaptationSet srcObjectId="1" frameRate="25">
+                        <SegmentTemplate media="o1_$Number$.bin" duration="1" timescale="1" startNumber="0"/>
+                        <Representation id="0"/>
+                    </AdaptationSet>
+                </Period>
+            </MPD>"#,
+        )
+        .unwrap();
+        assert_eq!(p.fps(), 30.0);
+        assert_eq!(p.total_frames(), 60);
+        // the segments are found by time, whatever the rate of the representation
+        assert_eq!(p.get_info(0, 0, 45, None).unwrap().0, "0_1.bin");
+        assert_eq!(p.get_info(1, 0, 45, None).unwrap().0, "o1_1.bin");
+
+        assert_eq!(p.frame_rate_conversion(0, 1, 0, None).unwrap(), None);
+        let half = p.frame_rate_conversion(0, 0, 0, None).unwrap().unwrap();
+        let frames: Vec<u64> = (0..4).map(|f| half.representation_frame(f)).collect();
+        assert_eq!(frames, vec![0, 0, 1, 1]);
+        let double = p.frame_rate_conversion(0, 2, 0, None).unwrap().unwrap();
+        assert_eq!(double.representation_frame(3), 6);
+        let object = p.frame_rate_conversion(1, 0, 0, None).unwrap().unwrap();
+        assert_eq!(object.representation_frame(29), 24);
+    }
+
+    #[test]
+    pub fn test_malformed_mpd() {
+        assert!(MPDParser::new("<MPD></MPD>").is_err());
+        assert!(MPDParser::new(
+            r#"<MPD><Period><AdaptationSet><Representation id="0"/></AdaptationSet></Period></MPD>"#
+        )
+        .is_err());
+        assert!(MPDParser::new(
+            r#"<MPD><Period duration="PT1S"><AdaptationSet frameRate="0"/></Period></MPD>"#
+        )
+        .is_err());
+        assert!(MPDParser::new(
+            r#"<MPD>
+                <Period duration="PT1S">
+                    <AdaptationSet frameRate="30">
+                        <Representation id="0" frameRate="15/0"/>
+                    </AdaptationSet>
+                </Period>
+            </MPD>"#
+        )
+        .is_err());
+
+        let p = MPDParser::new(
+            r#"<MPD>
+                <Period duration="PT1S">
+                    <AdaptationSet>
+                        <Representation id="0"/>
+                    </AdaptationSet>
+                </Period>
+            </MPD>"#,
+        )
+        .unwrap();
+        assert!(p.get_base_url().is_err());
+        assert!(p.get_info(0, 0, 0, None).is_err());
+        assert!(p.get_info(0, 1, 0, None).is_err());
+        assert!(p.get_info(1, 0, 0, None).is_err());
+        assert!(p.available_bitrates(0, 0, None).is_err());
+        assert!(p.segment_duration().is_err());
+    }
+
+    fn live_mpd(start: u64, start_number: u64, repeat: u64) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <MPD type="dynamic" availabilityStartTime="2023-06-01T10:00:00Z" minimumUpdatePeriod="PT2S">
+                <BaseURL>http://localhost:3000/</BaseURL>
+                <Period id="0" start="PT0S">
+                    <AdaptationSet frameRate="30">
+                        <Representation id="0" bandwidth="1000">
+                            <SegmentTemplate media="$Number$.bin" timescale="1000" startNumber="{start_number}">
+                                <SegmentTimeline>
+                                    <S t="{start}" d="1000" r="{repeat}"/>
+                                </SegmentTimeline>
+                            </SegmentTemplate>
+                        </Representation>
+                    </AdaptationSet>
+                </Period>
+            </MPD>"#
+        )
+    }
+
+    #[test]
+    pub fn test_parse_xs_datetime() {
+        assert_eq!(
+            parse_xs_datetime("1970-01-01T00:00:00Z").unwrap(),
+            UNIX_EPOCH
+        );
+        assert_eq!(
+            parse_xs_datetime("2023-06-01T18:00:00.5+08:00").unwrap(),
+            parse_xs_datetime("2023-06-01T10:00:00.500Z").unwrap()
+        );
+        assert_eq!(
+            parse_xs_datetime("2023-06-01T10:00:00Z").unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1685613600)
+        );
+        assert!(parse_xs_datetime("yesterday").is_err());
+    }
+
+    #[test]
+    pub fn test_live_edge() {
+        let p = MPDParser::new(&live_mpd(0, 1, 9)).unwrap();
+        let ast = parse_xs_datetime("2023-06-01T10:00:00Z").unwrap();
+
+        assert!(p.is_dynamic());
+        assert_eq!(p.minimum_update_period(), Some(Duration::from_secs(2)));
+        // 5.5s in, the segment [4s, 5s) is the newest complete one
+        assert_eq!(p.live_edge(ast + Duration::from_millis(5500)), Some(120));
+        // the timeline only lists 10s worth of segments
+        assert_eq!(p.live_edge(ast + Duration::from_secs(20)), Some(270));
+        assert_eq!(p.live_edge(ast - Duration::from_secs(1)), None);
+        // segments beyond the timeline are not available yet
+        assert!(p.get_info(0, 0, 310, None).is_err());
+
+        // half-second segments at 29.97 fps have 15 frames
+        let ntsc = live_mpd(0, 1, 9)
+            .replace(r#"frameRate="30""#, r#"frameRate="30000/1001""#)
+            .replace(r#"d="1000""#, r#"d="500""#);
+        let p = MPDParser::new(&ntsc).unwrap();
+        assert_eq!(p.segment_frames().unwrap(), 15);
+        assert_eq!(p.live_edge(ast + Duration::from_millis(3200)), Some(75));
+    }
+
+    #[test]
+    pub fn test_merge_dynamic_mpd() {
+        let mut p = MPDParser::new(&live_mpd(0, 1, 9)).unwrap();
+        // the refreshed MPD has dropped the first 5 segments out of its time shift buffer
+        p.merge(MPDParser::new(&live_mpd(5000, 6, 9)).unwrap())
+            .unwrap();
+
+        assert_eq!(p.total_frames(), 450);
+        assert_eq!(
+            p.get_info(0, 0, 0, None).unwrap().0,
+            "http://localhost:3000/1.bin"
+        );
+        assert_eq!(
+            p.get_info(0, 0, 400, None).unwrap().0,
+            "http://localhost:3000/14.bin"
+        );
+    }
+
+    #[test]
+    pub fn test_progressive_lod() {
+        let tile = |min_x: f32| Bounds {
+            min_x,
+            max_x: min_x + 1.0,
+            min_y: 0.0,
+            max_y: 1.0,
+            min_z: 0.0,
+            max_z: 1.0,
+        };
+        let xml = crate::lodify::progressive::manifest(
+            &[vec![100, 200, 300], vec![50, 80, 90]],
+            &[tile(0.0), tile(1.0)],
+            45,
+            15,
+            30,
+        );
+        let mut p = MPDParser::new(&xml).unwrap();
+        p.set_manifest_url("http://localhost:3000/lod/manifest.mpd");
+        assert_eq!(p.total_frames(), 45);
+        assert_eq!(p.segment_frames().unwrap(), 15);
+        assert_eq!(p.tiles(0, 0).unwrap(), vec![0, 1]);
+        let tile_bounds = MPDParser::find_tile(&p.mpd.periods[0], 0, 1)
+            .unwrap()
+            .tileBounds
+            .as_deref()
+            .unwrap();
+        assert_eq!(parse_bounds(tile_bounds).unwrap().max_x, 2.0);
+        assert!(p.tiles(1, 0).unwrap().is_empty());
+        assert_eq!(
+            p.lod_levels(0, 1, 0).unwrap(),
+            vec![(0, Some(50)), (1, Some(80)), (2, Some(90))]
+        );
+        assert_eq!(
+            p.get_tile_urls(0, 1, 2, 20).unwrap(),
+            (
+                vec!["http://localhost:3000/lod/tile1_level2_00015.vvl".to_string()],
+                Some(90)
+            )
+        );
+        assert!(p.get_tile_urls(0, 2, 0, 0).is_err());
+        assert!(p.lod_levels(0, 2, 0).is_err());
+    }
+
+    #[test]
+    pub fn test_view_bounds() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <MPD type="static" mediaPresentationDuration="PT1S">
+                <Period id="0" duration="PT1S">
+                    <AdaptationSet viewId="0" tileBounds="0 0 0 1 2 1">
+                        <SegmentTemplate media="v0_$Number$.bin" duration="30" timescale="30"/>
+                        <Representation id="0" bandwidth="100"/>
+                    </AdaptationSet>
+                    <AdaptationSet viewId="1" tileBounds="0,2,0,1,4,1">
+                        <SegmentTemplate media="v1_$Number$.bin" duration="30" timescale="30"/>
+                        <Representation id="0" bandwidth="100"/>
+                    </AdaptationSet>
+                    <AdaptationSet viewId="2" tileBounds="0 1 2">
+                        <SegmentTemplate media="v2_$Number$.bin" duration="30" timescale="30"/>
+                        <Representation id="0" bandwidth="100"/>
+                    </AdaptationSet>
+                    <AdaptationSet viewId="3">
+                        <SegmentTemplate media="v3_$Number$.bin" duration="30" timescale="30"/>
+                        <Representation id="0" bandwidth="100"/>
+                    </AdaptationSet>
+                </Period>
+            </MPD>"#;
+        let p = MPDParser::new(xml).unwrap();
+        assert_eq!(p.views(0, 0).unwrap(), 4);
+        assert_eq!(p.views(1, 0).unwrap(), 0);
+        let bounds = p.view_bounds(0, 0, 2).unwrap().unwrap();
+        assert_eq!(bounds.len(), 2);
+        assert_eq!((bounds[1].min_y, bounds[1].max_y), (2.0, 4.0));
+        assert!(p.view_bounds(0, 0, 3).is_err());
+        let tail = MPDParser::new(&xml.replace(r#" tileBounds="0 1 2""#, "")).unwrap();
+        assert!(tail.view_bounds(0, 0, 4).unwrap().is_none());
+    }
 }
@@ -0,0 +1,412 @@
+use crate::simulation::NetworkTrace;
+use anyhow::{bail, Context, Result};
+use log::{debug, info, warn};
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// A minimal HTTP/1.1 server for DASH content, i.e. a directory of segments and an MPD.
+///
+/// It understands just enough of HTTP for the fetcher: `GET` and `HEAD` requests, byte ranges and keep-alive.
+/// If a network trace is set, every response body is throttled to the next bandwidth sample of the trace,
+/// so that players can be tested against a shaped network without simulating it on the client side.
+pub struct DashServer {
+    root: PathBuf,
+    /// served at `/<file name>`, in addition to the files in `root`
+    mpd: Option<PathBuf>,
+    network_trace: Option<Arc<Mutex<NetworkTrace>>>,
+}
+
+/// Handle to a server running in the background. The server is stopped when the handle is dropped.
+pub struct ServerHandle {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    range: Option<String>,
+    keep_alive: bool,
+}
+
+impl DashServer {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        DashServer {
+            root: root.into(),
+            mpd: None,
+            network_trace: None,
+        }
+    }
+
+    /// Serve this MPD at `/<file name>`, even if it is not inside the served directory.
+    pub fn set_mpd<P: Into<PathBuf>>(&mut self, mpd: P) {
+        self.mpd = Some(mpd.into());
+    }
+
+    /// Throttle every response to the next sample of the trace (in Kbps).
+    pub fn set_network_trace(&mut self, network_trace: NetworkTrace) {
+        self.network_trace = Some(Arc::new(Mutex::new(network_trace)));
+    }
+
+    /// Starts serving in the background. Bind to port 0 to let the OS pick a free port.
+    pub async fn bind<A: ToSocketAddrs>(self, addr: A) -> Result<ServerHandle> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .context("binding server socket")?;
+        let addr = listener.local_addr()?;
+        let task = tokio::spawn(async move {
+            if let Err(e) = self.accept_loop(listener).await {
+                warn!("DASH server stopped: {:#}", e);
+            }
+        });
+        Ok(ServerHandle { addr, task })
+    }
+
+    /// Serves until an error occurs.
+    pub async fn serve<A: ToSocketAddrs>(self, addr: A) -> Result<()> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .context("binding server socket")?;
+        info!(
+            "Serving {} on {}",
+            self.root.display(),
+            listener.local_addr()?
+        );
+        self.accept_loop(listener).await
+    }
+
+    async fn accept_loop(self, listener: TcpListener) -> Result<()> {
+        let server = Arc::new(self);
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = server.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle_connection(stream).await {
+                    debug!("connection to {} closed: {:#}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn handle_connection(&self, stream: TcpStream) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        while let Some(req) = read_request(&mut reader).await? {
+            self.respond(&req, &mut writer).await?;
+            if !req.keep_alive {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps a request path onto a file, refusing anything outside the served directory.
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let path = percent_decode(path.split('?').next().unwrap_or_default());
+        let relative = Path::new(path.trim_start_matches('/'));
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return None;
+        }
+        if let Some(mpd) = self.mpd.as_ref() {
+            if mpd.file_name() == Some(relative.as_os_str()) {
+                return Some(mpd.clone());
+            }
+        }
+        let file = self.root.join(relative);
+        file.is_file().then_some(file)
+    }
+
+    async fn respond<W: AsyncWrite + Unpin>(&self, req: &Request, writer: &mut W) -> Result<()> {
+        debug!("{} {}", req.method, req.path);
+        if req.method != "GET" && req.method != "HEAD" {
+            return write_status(writer, "405 Method Not Allowed", req.keep_alive).await;
+        }
+        let data = match self.resolve(&req.path) {
+            Some(file) => tokio::fs::read(&file).await?,
+            None => return write_status(writer, "404 Not Found", req.keep_alive).await,
+        };
+        let len = data.len() as u64;
+        let (status, start, end) = match req.range.as_deref() {
+            None => ("200 OK", 0, len),
+            Some(range) => match parse_range(range, len) {
+                Some((start, end)) => ("206 Partial Content", start, end),
+                None => {
+                    let header = format!(
+                        "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{len}\r\nContent-Length: 0\r\n{}\r\n",
+                        connection_header(req.keep_alive)
+                    );
+                    writer.write_all(header.as_bytes()).await?;
+                    return Ok(());
+                }
+            },
+        };
+        let mut header = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\n",
+            content_type(&req.path),
+            end - start
+        );
+        if req.range.is_some() {
+            header += &format!("Content-Range: bytes {}-{}/{len}\r\n", start, end - 1);
+        }
+        header += &format!("{}\r\n", connection_header(req.keep_alive));
+        writer.write_all(header.as_bytes()).await?;
+        if req.method == "GET" {
+            let body = &data[start as usize..end as usize];
+            let rate = self.network_trace.as_ref().map(|trace| {
+                // a sample of 0 would stall the response forever
+                trace.lock().unwrap().next().max(1.0) * 1024.0
+            });
+            write_throttled(writer, body, rate).await?;
+        }
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+impl ServerHandle {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The URL of a file served by this server.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}/{}", self.addr, path.trim_start_matches('/'))
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Reads the request line and headers. Returns `None` once the client closes the connection.
+async fn read_request<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Result<Option<Request>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (method, path, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(path), Some(version)) => {
+            (method.to_string(), path.to_string(), version.to_string())
+        }
+        _ => bail!("malformed request line {:?}", line.trim()),
+    };
+    let mut req = Request {
+        method,
+        path,
+        range: None,
+        keep_alive: version != "HTTP/1.0",
+    };
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "range" => req.range = Some(value.to_string()),
+                "connection" => req.keep_alive = !value.eq_ignore_ascii_case("close"),
+                _ => {}
+            }
+        }
+    }
+    Ok(Some(req))
+}
+
+/// Parses a single `bytes=` range into a half-open interval. Returns `None` if it cannot be satisfied.
+fn parse_range(range: &str, len: u64) -> Option<(u64, u64)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => (len.saturating_sub(suffix.parse().ok()?), len),
+        (start, "") => (start.parse().ok()?, len),
+        (start, end) => (start.parse().ok()?, (end.parse::<u64>().ok()? + 1).min(len)),
+    };
+    (start < end).then_some((start, end))
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn content_type(path: &str) -> &'static str {
+    if path.split('?').next().unwrap_or_default().ends_with(".mpd") {
+        "application/dash+xml"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+fn connection_header(keep_alive: bool) -> &'static str {
+    if keep_alive {
+        "Connection: keep-alive\r\n"
+    } else {
+        "Connection: close\r\n"
+    }
+}
+
+async fn write_status<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    status: &str,
+    keep_alive: bool,
+) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Length: 0\r\n{}\r\n",
+        connection_header(keep_alive)
+    );
+    writer.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Writes the body, pacing it to `rate` bits per second if given.
+async fn write_throttled<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    body: &[u8],
+    rate: Option<f64>,
+) -> Result<()> {
+    let rate = match rate {
+        Some(rate) => rate,
+        None => {
+            writer.write_all(body).await?;
+            return Ok(());
+        }
+    };
+    // send about 50ms worth of data at a time
+    let chunk_size = ((rate / 8.0 * 0.05) as usize).clamp(512, 64 * 1024);
+    let start = Instant::now();
+    let mut sent = 0;
+    for chunk in body.chunks(chunk_size) {
+        writer.write_all(chunk).await?;
+        writer.flush().await?;
+        sent += chunk.len();
+        tokio::time::sleep_until(start + Duration::from_secs_f64(sent as f64 * 8.0 / rate)).await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 100)));
+        assert_eq!(parse_range("bytes=500-", 1000), Some((500, 1000)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 1000)));
+        assert_eq!(parse_range("bytes=900-2000", 1000), Some((900, 1000)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("seg.bin"), b"").unwrap();
+        let server = DashServer::new(dir.path().join("sub"));
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub/seg.bin"), b"").unwrap();
+        assert_eq!(
+            server.resolve("/seg.bin?t=1"),
+            Some(dir.path().join("sub/seg.bin"))
+        );
+        assert_eq!(server.resolve("/../seg.bin"), None);
+        assert_eq!(server.resolve("/%2e%2e/seg.bin"), None);
+        assert_eq!(server.resolve("//etc/passwd"), None);
+    }
+
+    #[tokio::test]
+    async fn test_serve_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("seg")).unwrap();
+        std::fs::write(dir.path().join("seg/a b.bin"), b"0123456789").unwrap();
+        let mpd = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(mpd.path(), "<MPD/>").unwrap();
+        let mut server = DashServer::new(dir.path());
+        server.set_mpd(mpd.path());
+        let server = server.bind("127.0.0.1:0").await.unwrap();
+        let client = reqwest::Client::new();
+
+        let res = client
+            .get(server.url("seg/a%20b.bin"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.bytes().await.unwrap().as_ref(), b"0123456789");
+
+        let res = client
+            .get(server.url("seg/a%20b.bin"))
+            .header(reqwest::header::RANGE, "bytes=4-")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 206);
+        assert_eq!(res.bytes().await.unwrap().as_ref(), b"456789");
+
+        let mpd_name = mpd.path().file_name().unwrap().to_str().unwrap();
+        let res = client.get(server.url(mpd_name)).send().await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "<MPD/>");
+
+        for path in ["missing.bin", "seg"] {
+            let res = client.get(server.url(path)).send().await.unwrap();
+            assert_eq!(res.status(), 404, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_throttle() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("seg.bin"), vec![0u8; 12800]).unwrap();
+        let mut server = DashServer::new(dir.path());
+        // 500 Kbps, i.e. 0.2s for the 12800 bytes, then unthrottled (effectively)
+        server.set_network_trace(NetworkTrace::from_samples(vec![500.0, 1_000_000.0]));
+        let server = server.bind("127.0.0.1:0").await.unwrap();
+        let client = reqwest::Client::new();
+
+        let start = std::time::Instant::now();
+        let body = client
+            .get(server.url("seg.bin"))
+            .send()
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(body.len(), 12800);
+        assert!(start.elapsed() >= Duration::from_millis(190));
+
+        let start = std::time::Instant::now();
+        client.get(server.url("seg.bin")).send().await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(190));
+    }
+}
@@ -126,4 +126,27 @@ impl Bounds {
             && point.z >= self.min_z
             && point.z <= self.max_z
     }
+
+    /// The smallest bounds containing both.
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds::new(
+            self.min_x.min(other.min_x),
+            self.max_x.max(other.max_x),
+            self.min_y.min(other.min_y),
+            self.max_y.max(other.max_y),
+            self.min_z.min(other.min_z),
+            self.max_z.max(other.max_z),
+        )
+    }
+
+    /// Squared distance from the point to the bounds, 0 if it is contained.
+    pub fn distance_squared(&self, point: &PointXyzRgba) -> f32 {
+        let d = |v: f32, min: f32, max: f32| (min - v).max(v - max).max(0.0);
+        let (dx, dy, dz) = (
+            d(point.x, self.min_x, self.max_x),
+            d(point.y, self.min_y, self.max_y),
+            d(point.z, self.min_z, self.max_z),
+        );
+        dx * dx + dy * dy + dz * dz
+    }
 }
@@ -6,21 +6,39 @@ use super::bounds::Bounds;
 pub struct MetaData {
     pub bounds: Vec<Bounds>,
     pub base_point_num: Vec<Vec<usize>>,
+    /// total number of additional points per frame and segment, over all the additional levels
     pub additional_point_num: Vec<Vec<usize>>,
+    /// number of points of every additional level per frame, level and segment.
+    /// Empty for metadata written before levels were introduced, which has a single additional level.
+    #[serde(default)]
+    pub level_point_num: Vec<Vec<Vec<usize>>>,
+    /// bounds of every tile of every frame.
+    /// Empty for metadata written before adaptive tiling, whose tiles are the `partitions` of `bounds`.
+    #[serde(default)]
+    pub tile_bounds: Vec<Vec<Bounds>>,
+    /// grid the tiles were cut with, `(0, 0, 0)` for adaptive tiles
     pub partitions: (usize, usize, usize),
 }
 
 impl MetaData {
     pub fn new(
         bounds: Vec<Bounds>,
+        tile_bounds: Vec<Vec<Bounds>>,
         base_point_num: Vec<Vec<usize>>,
-        additional_point_num: Vec<Vec<usize>>,
+        level_point_num: Vec<Vec<Vec<usize>>>,
         partitions: (usize, usize, usize),
     ) -> Self {
+        let additional_point_num = level_point_num
+            .iter()
+            .zip(&base_point_num)
+            .map(|(levels, base)| Self::total(levels, base.len()))
+            .collect();
         Self {
             bounds,
             base_point_num,
             additional_point_num,
+            level_point_num,
+            tile_bounds,
             partitions,
         }
     }
@@ -30,18 +48,73 @@ impl MetaData {
             bounds: vec![],
             base_point_num: vec![],
             additional_point_num: vec![],
+            level_point_num: vec![],
+            tile_bounds: vec![],
             partitions: (0, 0, 0),
         }
     }
 
+    /// Adds a frame with its tiles and the number of points of its base and of every additional level, by tile.
     pub fn next(
         &mut self,
         bound: Bounds,
+        tiles: Vec<Bounds>,
         base_point_num: Vec<usize>,
-        additional_point_num: Vec<usize>,
+        level_point_num: Vec<Vec<usize>>,
     ) {
         self.bounds.push(bound);
+        self.tile_bounds.push(tiles);
+        self.additional_point_num
+            .push(Self::total(&level_point_num, base_point_num.len()));
         self.base_point_num.push(base_point_num);
-        self.additional_point_num.push(additional_point_num);
+        self.level_point_num.push(level_point_num);
+    }
+
+    /// Bounds of the tiles of frame `index`.
+    pub fn tiles(&self, index: usize) -> Vec<Bounds> {
+        match self.tile_bounds.get(index) {
+            Some(tiles) => tiles.clone(),
+            None => self.bounds[index].partition(self.partitions),
+        }
+    }
+
+    /// Largest number of tiles of a frame.
+    pub fn max_tiles(&self) -> usize {
+        (0..self.bounds.len())
+            .map(|index| self.base_point_num[index].len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Number of additional levels, i.e. not counting the base.
+    pub fn levels(&self) -> usize {
+        if self.level_point_num.is_empty() {
+            1
+        } else {
+            self.level_point_num.iter().map(Vec::len).max().unwrap_or(0)
+        }
+    }
+
+    /// Number of points of every additional level of frame `index` by segment, `[level - 1][segment]`.
+    /// Always has [`MetaData::levels`] levels, empty ones added where a frame has fewer.
+    pub fn additional_levels(&self, index: usize) -> Vec<Vec<usize>> {
+        let segments = self.base_point_num[index].len();
+        let mut levels = match self.level_point_num.get(index) {
+            Some(levels) => levels.clone(),
+            None => vec![self.additional_point_num[index].clone()],
+        };
+        levels.resize(self.levels(), vec![0; segments]);
+        levels
+    }
+
+    fn total(levels: &[Vec<usize>], segments: usize) -> Vec<usize> {
+        (0..segments)
+            .map(|segment| {
+                levels
+                    .iter()
+                    .map(|level| level.get(segment).copied().unwrap_or(0))
+                    .sum()
+            })
+            .collect()
     }
 }
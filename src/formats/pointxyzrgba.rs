@@ -58,3 +58,30 @@ impl Serialize for PointXyzRgba {
         state.end()
     }
 }
+
+/// Points and point clouds for the tests of the crate
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::PointXyzRgba;
+    use crate::formats::PointCloud;
+
+    /// An opaque black point, e.g. `PointXyzRgba { r: 255, ..point(x, y, z) }` for a red one
+    pub(crate) fn point(x: f32, y: f32, z: f32) -> PointXyzRgba {
+        PointXyzRgba {
+            x,
+            y,
+            z,
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        }
+    }
+
+    pub(crate) fn cloud(
+        points: impl IntoIterator<Item = PointXyzRgba>,
+    ) -> PointCloud<PointXyzRgba> {
+        let points: Vec<_> = points.into_iter().collect();
+        PointCloud::new(points.len(), points)
+    }
+}
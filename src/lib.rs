@@ -36,6 +36,8 @@ pub enum BufMsg {
     ),
     /// Fetch result from the fetcher
     FetchDone((FrameRequest, FetchResult)),
+    /// The fetcher gave up on a frame after exhausting its retries
+    FetchFailed(FrameRequest),
     // FetchDone(FrameRequest),
     FrameRequest(FrameRequest),
 }
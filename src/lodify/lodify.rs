@@ -1,32 +1,141 @@
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 use std::iter::zip;
 
 use crate::formats::bounds::Bounds;
 use crate::formats::{pointxyzrgba::PointXyzRgba, PointCloud};
+use crate::lodify::tiling::Tiling;
 use crate::utils::get_pc_bound;
 
+/// How the points of a frame are split into levels of detail.
+/// Every level is a strict superset of the previous one and the last level has all the points.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LevelTargets {
+    /// percentage of the points in every level but the last, coarsest first, e.g. `[10, 25, 50]`
+    Proportions(Vec<usize>),
+    /// target spacing between the points of every level but the last, coarsest first.
+    /// A level has one point per occupied voxel of that size.
+    Spacings(Vec<f32>),
+}
+
+impl LevelTargets {
+    /// Number of levels, including the base and the last level.
+    pub fn levels(&self) -> usize {
+        match self {
+            LevelTargets::Proportions(proportions) => proportions.len() + 1,
+            LevelTargets::Spacings(spacings) => spacings.len() + 1,
+        }
+    }
+
+    /// Number of points of every level but the last, clamped so that they never decrease.
+    pub fn point_nums(&self, pc: &PointCloud<PointXyzRgba>) -> Vec<usize> {
+        let total = pc.points.len();
+        let nums: Vec<usize> = match self {
+            LevelTargets::Proportions(proportions) => proportions
+                .iter()
+                .map(|&proportion| (total as f32 * proportion as f32 / 100.0).ceil() as usize)
+                .collect(),
+            LevelTargets::Spacings(spacings) => spacings
+                .iter()
+                .map(|&spacing| occupied_voxels(&pc.points, spacing))
+                .collect(),
+        };
+        nums.into_iter()
+            .scan(0, |min, num| {
+                *min = num.clamp(*min, total);
+                Some(*min)
+            })
+            .collect()
+    }
+}
+
+/// Number of voxels of side `size` with at least one point.
+fn occupied_voxels(points: &[PointXyzRgba], size: f32) -> usize {
+    if size <= 0.0 {
+        return points.len();
+    }
+    points
+        .iter()
+        .map(|p| {
+            (
+                (p.x / size).floor() as i64,
+                (p.y / size).floor() as i64,
+                (p.z / size).floor() as i64,
+            )
+        })
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// Splits the points into levels. The first is the base and every other holds the points it adds to the previous one.
+///
+/// Each level is sampled from the next finer one, so every prefix of the levels is spread as evenly as possible.
+pub fn split_levels(
+    pc: &PointCloud<PointXyzRgba>,
+    targets: &LevelTargets,
+    points_per_voxel_threshold: usize,
+) -> Vec<PointCloud<PointXyzRgba>> {
+    let mut levels = vec![];
+    let mut current = pc.clone();
+    for num in targets.point_nums(pc).into_iter().rev() {
+        let (coarser, added) = sample(&current, num, points_per_voxel_threshold);
+        levels.push(added);
+        current = coarser;
+    }
+    levels.push(current);
+    levels.reverse();
+    levels
+}
+
+/// Name of the directory `vv lodify` writes the points `level` adds to `segment` to, relative to the output directory.
+/// The base is in `base`, and the first additional level keeps the name of its segment.
+pub fn additional_dir(segment: usize, level: usize) -> String {
+    if level <= 1 {
+        format!("{segment}")
+    } else {
+        format!("{segment}_{level}")
+    }
+}
+
+/// A frame split into levels of detail and tiles.
+pub struct Lodified {
+    /// the base points, segmented by tile
+    pub base: PointCloud<PointXyzRgba>,
+    /// the points of every additional level by tile, `[level - 1][tile]`
+    pub levels: Vec<Vec<PointCloud<PointXyzRgba>>>,
+    pub base_point_nums: Vec<usize>,
+    /// number of points of every additional level by tile, `[level - 1][tile]`
+    pub level_point_nums: Vec<Vec<usize>>,
+    /// bounds of every tile
+    pub tiles: Vec<Bounds>,
+}
+
+/// Splits a point cloud into levels of detail and tiles every level.
 pub fn lodify(
     points: &PointCloud<PointXyzRgba>,
-    partitions: (usize, usize, usize),
-    base_proportion: usize,
+    tiling: &Tiling,
+    targets: &LevelTargets,
     points_per_voxel_threshold: usize,
-) -> (
-    PointCloud<PointXyzRgba>,
-    Vec<PointCloud<PointXyzRgba>>,
-    Vec<usize>,
-    Vec<usize>,
-) {
+) -> Lodified {
     if points.points.is_empty() {
-        (points.clone(), vec![], vec![], vec![])
-    } else {
-        let factor = base_proportion as f32 / 100.0;
-        let base_point_num = (points.points.len() as f32 * factor).ceil() as usize;
-
-        let (base_pc, additional_pc) = sample(points, base_point_num, points_per_voxel_threshold);
+        return Lodified {
+            base: points.clone(),
+            levels: vec![],
+            base_point_nums: vec![],
+            level_point_nums: vec![],
+            tiles: vec![],
+        };
+    }
+    let tiles = tiling.tiles(points);
+    let mut levels = split_levels(points, targets, points_per_voxel_threshold)
+        .into_iter()
+        .map(|level| partition_with(&level, &tiles));
 
-        let partitioned_base_pc = partition(&base_pc, partitions);
-        let partitioned_add_pc = partition(&additional_pc, partitions);
+    let base = levels.next().unwrap();
+    let base_point_nums = segment_sizes(&base);
 
+    let mut pc_by_level = vec![];
+    let mut level_point_nums = vec![];
+    for partitioned_add_pc in levels {
         let add_segments = partitioned_add_pc.segments.as_ref().unwrap();
         let pc_by_segment = (0..add_segments.len())
             .map(|segment_id| {
@@ -34,33 +143,31 @@ pub fn lodify(
                 PointCloud::new(add_segments[segment_id].point_indices.len(), points)
             })
             .collect();
+        pc_by_level.push(pc_by_segment);
+        level_point_nums.push(segment_sizes(&partitioned_add_pc));
+    }
 
-        let base_point_nums = partitioned_base_pc
-            .segments
-            .as_ref()
-            .unwrap()
-            .iter()
-            .map(|segment| segment.point_indices.len())
-            .collect();
-
-        let additional_point_nums = partitioned_add_pc
-            .segments
-            .as_ref()
-            .unwrap()
-            .iter()
-            .map(|segment| segment.point_indices.len())
-            .collect();
-
-        (
-            partitioned_base_pc,
-            pc_by_segment,
-            base_point_nums,
-            additional_point_nums,
-        )
+    Lodified {
+        base,
+        levels: pc_by_level,
+        base_point_nums,
+        level_point_nums,
+        tiles,
     }
 }
 
-fn sample(
+fn segment_sizes(pc: &PointCloud<PointXyzRgba>) -> Vec<usize> {
+    pc.segments
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|segment| segment.point_indices.len())
+        .collect()
+}
+
+/// Splits the points into `base_point_num` base points, spread as evenly as possible, and the rest.
+/// The additional points come out in the same round-robin order over the voxels.
+pub(crate) fn sample(
     pc: &PointCloud<PointXyzRgba>,
     base_point_num: usize,
     points_per_voxel_threshold: usize,
@@ -137,8 +244,14 @@ fn partition(
     partitions: (usize, usize, usize),
 ) -> PointCloud<PointXyzRgba> {
     let pc_bound = get_pc_bound(pc);
-    let child_bounds = pc_bound.partition(partitions);
+    partition_with(pc, &pc_bound.partition(partitions))
+}
 
+/// Segments the points by the first of `child_bounds` that contains them.
+fn partition_with(
+    pc: &PointCloud<PointXyzRgba>,
+    child_bounds: &[Bounds],
+) -> PointCloud<PointXyzRgba> {
     let num_segments = child_bounds.len();
     let mut partitioned_points = vec![vec![]; num_segments];
 
@@ -160,13 +273,14 @@ fn partition(
     let points = partitioned_points.into_iter().flatten().collect();
     let mut new_pc = PointCloud::new(pc.number_of_points, points);
 
-    new_pc.self_segment(&base_point_nums, &child_bounds);
+    new_pc.self_segment(&base_point_nums, &child_bounds.to_vec());
     new_pc
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::formats::pointxyzrgba::fixtures::{cloud, point};
 
     #[test]
 
@@ -226,4 +340,63 @@ mod test {
         assert_eq!(segments[6].point_indices.len(), 0);
         assert_eq!(segments[7].point_indices.len(), 2);
     }
+
+    fn grid(n: usize) -> PointCloud<PointXyzRgba> {
+        let coordinate = |i: usize| (i % n) as f32;
+        cloud(
+            (0..n * n * n).map(|i| point(coordinate(i), coordinate(i / n), coordinate(i / n / n))),
+        )
+    }
+
+    #[test]
+    fn test_point_nums() {
+        let pc = grid(10);
+        let proportions = LevelTargets::Proportions(vec![10, 5, 50, 150]);
+        assert_eq!(proportions.levels(), 5);
+        assert_eq!(proportions.point_nums(&pc), vec![100, 100, 500, 1000]);
+
+        // one point per occupied voxel, coarsest first
+        let spacings = LevelTargets::Spacings(vec![5.0, 2.0, 0.0]);
+        assert_eq!(spacings.point_nums(&pc), vec![8, 125, 1000]);
+    }
+
+    #[test]
+    fn test_lodify_levels() {
+        let pc = grid(10);
+        let targets = LevelTargets::Proportions(vec![10, 25, 50]);
+        let levels = split_levels(&pc, &targets, 10);
+        let sizes: Vec<_> = levels.iter().map(|level| level.points.len()).collect();
+        assert_eq!(sizes, vec![100, 150, 250, 500]);
+        let mut all: Vec<_> = levels.into_iter().flat_map(|level| level.points).collect();
+        all.sort();
+        let mut original = pc.points.clone();
+        original.sort();
+        assert_eq!(all, original);
+
+        let lodified = lodify(&pc, &Tiling::Grid((2, 2, 2)), &targets, 10);
+        assert_eq!(lodified.base.points.len(), 100);
+        assert_eq!(lodified.base_point_nums.len(), 8);
+        assert_eq!(lodified.tiles.len(), 8);
+        assert_eq!(lodified.levels.len(), 3);
+        assert_eq!(lodified.level_point_nums.len(), 3);
+        for (level, nums) in lodified.levels.iter().zip(&lodified.level_point_nums) {
+            let sizes: Vec<_> = level.iter().map(|pc| pc.points.len()).collect();
+            assert_eq!(&sizes, nums);
+        }
+        assert_eq!(lodified.level_point_nums[2].iter().sum::<usize>(), 500);
+
+        // adaptive tiles hold at most the given number of points over all levels
+        let lodified = lodify(&pc, &Tiling::KdTree { max_points: 300 }, &targets, 10);
+        for tile in 0..lodified.tiles.len() {
+            let total = lodified.base_point_nums[tile]
+                + lodified
+                    .level_point_nums
+                    .iter()
+                    .map(|level| level[tile])
+                    .sum::<usize>();
+            assert!(total > 0 && total <= 300);
+        }
+        assert_eq!(additional_dir(3, 1), "3");
+        assert_eq!(additional_dir(3, 2), "3_2");
+    }
 }
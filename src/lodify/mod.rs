@@ -1 +1,3 @@
 pub mod lodify;
+pub mod progressive;
+pub mod tiling;
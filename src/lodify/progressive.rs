@@ -0,0 +1,799 @@
+//! Progressive, view-dependent container for the output of [`lodify`](crate::lodify::lodify::lodify),
+//! so that it can be streamed.
+//!
+//! A segment of frames is cut into one chunk per tile and refinement level. The tiles are either the
+//! partitions of the bounds of every frame or adaptive tiles cut once for the whole stream, see [`StreamTiles`].
+//! Level 0 of a tile holds its base points together with the index of the tile, i.e. the bounds of every
+//! frame and of the tile in it, and the number of points of every level.
+//! Each further level adds the points of the next level of detail, so a client fetches the base of every
+//! tile first and then, tile by tile, as many levels as its point budget asks for.
+//!
+//! Chunk layout, little endian:
+//! - magic `VVLP`, tile (u16), level (u8), number of levels (u8), number of frames (u32)
+//! - level 0 only: partitions (3 x u16, all 0 for adaptive tiles), then for every frame the bounds of the frame
+//!   and of the tile (min_x, max_x, min_y, max_y, min_z, max_z as f32 each) and the number of points of
+//!   every level (u32)
+//! - for every frame, the number of points (u32) followed by the points as x, y, z (f32) and r, g, b, a (u8)
+//!
+//! In the MPD, every tile is an `AdaptationSet` with a `tileId` and the `tileBounds` of the tile over the
+//! whole stream, and every level a `Representation` whose `dependencyId` is the level below, see [`manifest`].
+
+use anyhow::{bail, ensure, Result};
+use std::fmt::Write;
+
+use crate::formats::bounds::Bounds;
+use crate::formats::metadata::MetaData;
+use crate::formats::pointxyzrgba::PointXyzRgba;
+use crate::formats::PointCloud;
+use crate::lodify::lodify::{split_levels, LevelTargets};
+use crate::lodify::tiling::Tiling;
+use crate::utils::get_pc_bound;
+
+pub const EXTENSION: &str = "vvl";
+
+const MAGIC: &[u8; 4] = b"VVLP";
+const POINT_SIZE: usize = 16;
+
+/// How frames are cut into levels.
+#[derive(Debug, Clone)]
+pub struct LodParams {
+    /// the levels of detail, which are also the levels of every tile
+    pub targets: LevelTargets,
+    pub points_per_voxel_threshold: usize,
+}
+
+/// The tiles of a stream. Every tile is an `AdaptationSet` of the MPD, so all the segments have the same tiles.
+#[derive(Debug, Clone)]
+pub enum StreamTiles {
+    /// the `x × y × z` partitions of the bounds of every frame
+    Grid((usize, usize, usize)),
+    /// adaptive tiles cut from the first frame of the stream. In later frames, a tile is stretched over the
+    /// points that fall outside of every tile and are nearest to it.
+    Fixed(Vec<Bounds>),
+}
+
+impl StreamTiles {
+    /// The tiles of a stream starting with `first`.
+    pub fn new(tiling: &Tiling, first: &PointCloud<PointXyzRgba>) -> Self {
+        match tiling {
+            Tiling::Grid(partitions) => StreamTiles::Grid(*partitions),
+            Tiling::Octree { .. } | Tiling::KdTree { .. } => {
+                let tiles = tiling.tiles(first);
+                if tiles.is_empty() {
+                    StreamTiles::Fixed(vec![empty_bounds()])
+                } else {
+                    StreamTiles::Fixed(tiles)
+                }
+            }
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        match self {
+            StreamTiles::Grid((x, y, z)) => x * y * z,
+            StreamTiles::Fixed(tiles) => tiles.len(),
+        }
+    }
+
+    fn partitions(&self) -> (usize, usize, usize) {
+        match self {
+            StreamTiles::Grid(partitions) => *partitions,
+            StreamTiles::Fixed(_) => (0, 0, 0),
+        }
+    }
+
+    fn of_frame(&self, bounds: &Bounds) -> Vec<Bounds> {
+        match self {
+            StreamTiles::Grid(partitions) => bounds.partition(*partitions),
+            StreamTiles::Fixed(tiles) => tiles.clone(),
+        }
+    }
+}
+
+/// The index of a tile, carried by its base level.
+#[derive(Debug, Clone)]
+pub struct TileIndex {
+    /// grid the tiles were cut with, `(0, 0, 0)` for adaptive tiles
+    pub partitions: (usize, usize, usize),
+    /// bounds of every frame
+    pub bounds: Vec<Bounds>,
+    /// bounds of the tile in every frame
+    pub tile_bounds: Vec<Bounds>,
+    /// number of points of every level, for every frame
+    pub points: Vec<Vec<usize>>,
+}
+
+impl TileIndex {
+    fn frames(&self) -> usize {
+        self.points.len()
+    }
+}
+
+/// One level of one tile, for every frame of a segment.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub tile: usize,
+    pub level: usize,
+    pub levels: usize,
+    /// only present in the base level
+    pub index: Option<TileIndex>,
+    pub frames: Vec<Vec<PointXyzRgba>>,
+}
+
+impl Chunk {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let points = self.frames.iter().map(Vec::len).sum::<usize>();
+        let mut out = Vec::with_capacity(12 + self.frames.len() * 4 + points * POINT_SIZE);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&(self.tile as u16).to_le_bytes());
+        out.push(self.level as u8);
+        out.push(self.levels as u8);
+        out.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        if let Some(index) = &self.index {
+            let (x, y, z) = index.partitions;
+            for p in [x, y, z] {
+                out.extend_from_slice(&(p as u16).to_le_bytes());
+            }
+            for ((bounds, tile_bounds), points) in index
+                .bounds
+                .iter()
+                .zip(&index.tile_bounds)
+                .zip(&index.points)
+            {
+                for b in [bounds, tile_bounds] {
+                    for v in [b.min_x, b.max_x, b.min_y, b.max_y, b.min_z, b.max_z] {
+                        out.extend_from_slice(&v.to_le_bytes());
+                    }
+                }
+                for n in points {
+                    out.extend_from_slice(&(*n as u32).to_le_bytes());
+                }
+            }
+        }
+        for frame in &self.frames {
+            out.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            for p in frame {
+                for v in [p.x, p.y, p.z] {
+                    out.extend_from_slice(&v.to_le_bytes());
+                }
+                out.extend_from_slice(&[p.r, p.g, p.b, p.a]);
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Chunk> {
+        let mut reader = Reader { data, pos: 0 };
+        if reader.take(4)? != MAGIC {
+            bail!("not a progressive LOD chunk");
+        }
+        let tile = reader.u16()? as usize;
+        let level = reader.u8()? as usize;
+        let levels = reader.u8()? as usize;
+        let num_frames = reader.u32()? as usize;
+        ensure!(
+            level < levels,
+            "level {level} of a chunk with {levels} levels"
+        );
+
+        let index = if level == 0 {
+            let partitions = (
+                reader.u16()? as usize,
+                reader.u16()? as usize,
+                reader.u16()? as usize,
+            );
+            let mut bounds = vec![];
+            let mut tile_bounds = vec![];
+            let mut points = vec![];
+            for _ in 0..num_frames {
+                bounds.push(reader.bounds()?);
+                tile_bounds.push(reader.bounds()?);
+                points.push(
+                    (0..levels)
+                        .map(|_| reader.u32().map(|n| n as usize))
+                        .collect::<Result<_>>()?,
+                );
+            }
+            Some(TileIndex {
+                partitions,
+                bounds,
+                tile_bounds,
+                points,
+            })
+        } else {
+            None
+        };
+
+        // a frame takes at least the 4 bytes of its point count, so a corrupt frame count cannot over-allocate
+        let mut frames = Vec::with_capacity(num_frames.min(reader.remaining() / 4));
+        for _ in 0..num_frames {
+            let n = reader.u32()? as usize;
+            let bytes = reader.take(n.saturating_mul(POINT_SIZE))?;
+            frames.push(
+                bytes
+                    .chunks_exact(POINT_SIZE)
+                    .map(|b| {
+                        let f = |i: usize| f32::from_le_bytes(b[i..i + 4].try_into().unwrap());
+                        PointXyzRgba {
+                            x: f(0),
+                            y: f(4),
+                            z: f(8),
+                            r: b[12],
+                            g: b[13],
+                            b: b[14],
+                            a: b[15],
+                        }
+                    })
+                    .collect(),
+            );
+        }
+        Ok(Chunk {
+            tile,
+            level,
+            levels,
+            index,
+            frames,
+        })
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.remaining() < n {
+            bail!("truncated progressive LOD chunk");
+        }
+        let bytes = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn bounds(&mut self) -> Result<Bounds> {
+        Ok(Bounds {
+            min_x: self.f32()?,
+            max_x: self.f32()?,
+            min_y: self.f32()?,
+            max_y: self.f32()?,
+            min_z: self.f32()?,
+            max_z: self.f32()?,
+        })
+    }
+}
+
+/// Cuts a segment of frames into chunks, `chunks[tile][level]`.
+///
+/// The points of a frame are split into levels of detail as `lodify` does, then every level is assigned to
+/// the `tiles` of the stream.
+pub fn package(
+    frames: &[PointCloud<PointXyzRgba>],
+    tiles: &StreamTiles,
+    params: &LodParams,
+) -> Vec<Vec<Chunk>> {
+    let partitions = tiles.partitions();
+    let levels = params.targets.levels();
+    let mut chunks: Vec<Vec<Chunk>> = (0..tiles.count())
+        .map(|tile| {
+            (0..levels)
+                .map(|level| Chunk {
+                    tile,
+                    level,
+                    levels,
+                    index: (level == 0).then(|| TileIndex {
+                        partitions,
+                        bounds: vec![],
+                        tile_bounds: vec![],
+                        points: vec![],
+                    }),
+                    frames: vec![],
+                })
+                .collect()
+        })
+        .collect();
+
+    for frame in frames {
+        let bounds = if frame.points.is_empty() {
+            empty_bounds()
+        } else {
+            get_pc_bound(frame)
+        };
+        let mut tile_bounds = tiles.of_frame(&bounds);
+        let mut by_tile = vec![vec![]; tile_bounds.len()];
+        for level in split_levels(frame, &params.targets, params.points_per_voxel_threshold) {
+            for (tile, points) in split_by_tile(level.points, &tile_bounds)
+                .into_iter()
+                .enumerate()
+            {
+                by_tile[tile].push(points);
+            }
+        }
+
+        for (tile, frame_levels) in by_tile.into_iter().enumerate() {
+            let counts = frame_levels.iter().map(Vec::len).collect();
+            let tile_bounds = &mut tile_bounds[tile];
+            for point in frame_levels.iter().flatten() {
+                if !tile_bounds.contains(point) {
+                    *tile_bounds = tile_bounds.union(&point_bounds(point));
+                }
+            }
+            let index = chunks[tile][0].index.as_mut().unwrap();
+            index.bounds.push(bounds.clone());
+            index.tile_bounds.push(tile_bounds.clone());
+            index.points.push(counts);
+            for (chunk, points) in chunks[tile].iter_mut().zip(frame_levels) {
+                chunk.frames.push(points);
+            }
+        }
+    }
+    chunks
+}
+
+fn empty_bounds() -> Bounds {
+    Bounds {
+        min_x: 0.0,
+        max_x: 0.0,
+        min_y: 0.0,
+        max_y: 0.0,
+        min_z: 0.0,
+        max_z: 0.0,
+    }
+}
+
+fn point_bounds(point: &PointXyzRgba) -> Bounds {
+    Bounds {
+        min_x: point.x,
+        max_x: point.x,
+        min_y: point.y,
+        max_y: point.y,
+        min_z: point.z,
+        max_z: point.z,
+    }
+}
+
+/// Assigns every point to the first tile that contains it, or else to the nearest tile, keeping their order.
+fn split_by_tile(points: Vec<PointXyzRgba>, tile_bounds: &[Bounds]) -> Vec<Vec<PointXyzRgba>> {
+    let mut tiles = vec![vec![]; tile_bounds.len()];
+    for point in points {
+        let tile = tile_bounds
+            .iter()
+            .position(|b| b.contains(&point))
+            .unwrap_or_else(|| {
+                (0..tile_bounds.len())
+                    .min_by(|&a, &b| {
+                        tile_bounds[a]
+                            .distance_squared(&point)
+                            .total_cmp(&tile_bounds[b].distance_squared(&point))
+                    })
+                    .unwrap()
+            });
+        tiles[tile].push(point);
+    }
+    tiles
+}
+
+/// Name of the chunk of `tile` and `level` for the segment starting at frame `number`,
+/// matching the `media` template of [`manifest`].
+pub fn chunk_name(tile: usize, level: usize, number: u64) -> String {
+    format!("tile{tile}_level{level}_{number:05}.{EXTENSION}")
+}
+
+/// Writes the MPD of a progressive stream.
+///
+/// # Arguments
+///
+/// * `bandwidths` - average bitrate in bits per second of every level of every tile, `bandwidths[tile][level]`
+/// * `tile_bounds` - bounds of every tile over the whole stream
+/// * `total_frames` - number of frames of the stream
+/// * `segment_frames` - number of frames per segment. Segments are numbered by their first frame.
+/// * `fps` - frame rate
+pub fn manifest(
+    bandwidths: &[Vec<u64>],
+    tile_bounds: &[Bounds],
+    total_frames: u64,
+    segment_frames: u64,
+    fps: u32,
+) -> String {
+    let duration = total_frames as f64 / fps as f64;
+    let mut mpd = String::new();
+    _ = writeln!(mpd, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    _ = writeln!(
+        mpd,
+        r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" profiles="urn:mpeg:dash:profile:full:2011" type="static" mediaPresentationDuration="PT{duration}S">"#
+    );
+    _ = writeln!(mpd, r#"  <Period id="0" duration="PT{duration}S">"#);
+    for (tile, (levels, b)) in bandwidths.iter().zip(tile_bounds).enumerate() {
+        let bounds = format!(
+            "{} {} {} {} {} {}",
+            b.min_x, b.min_y, b.min_z, b.max_x, b.max_y, b.max_z
+        );
+        _ = writeln!(
+            mpd,
+            r#"    <AdaptationSet id="{tile}" srcObjectId="0" tileId="{tile}" tileBounds="{bounds}" mimeType="application/x-vvtk-lod" frameRate="{fps}">"#
+        );
+        _ = writeln!(
+            mpd,
+            r#"      <SegmentTemplate media="tile{tile}_level$RepresentationID$_$Number%05d$.{EXTENSION}" duration="{segment_frames}" timescale="{fps}" startNumber="0"/>"#
+        );
+        for (level, bandwidth) in levels.iter().enumerate() {
+            let dependency = match level {
+                0 => String::new(),
+                _ => format!(r#" dependencyId="{}""#, level - 1),
+            };
+            _ = writeln!(
+                mpd,
+                r#"      <Representation id="{level}"{dependency} bandwidth="{bandwidth}"/>"#
+            );
+        }
+        _ = writeln!(mpd, "    </AdaptationSet>");
+    }
+    _ = writeln!(mpd, "  </Period>");
+    _ = writeln!(mpd, "</MPD>");
+    mpd
+}
+
+/// The chunks of a segment received so far, from which frames are assembled.
+#[derive(Debug, Clone)]
+pub struct LodSegment {
+    /// index of every tile
+    index: Vec<TileIndex>,
+    /// `chunks[tile][level]`, the points of every frame
+    chunks: Vec<Vec<Option<Vec<Vec<PointXyzRgba>>>>>,
+}
+
+impl LodSegment {
+    /// Starts from the base level of every tile, in tile order.
+    pub fn new(bases: Vec<Chunk>) -> Result<Self> {
+        let mut index: Vec<TileIndex> = vec![];
+        let mut chunks = vec![];
+        for (tile, chunk) in bases.into_iter().enumerate() {
+            let Some(tile_index) = chunk.index else {
+                bail!("chunk of tile {tile} is not a base level");
+            };
+            ensure!(
+                chunk.tile == tile,
+                "expected tile {tile}, got {}",
+                chunk.tile
+            );
+            ensure!(
+                tile_index.frames() == chunk.frames.len(),
+                "the index of tile {tile} does not match its frames"
+            );
+            if let Some(first) = index.first() {
+                ensure!(
+                    first.frames() == tile_index.frames(),
+                    "the tiles have different numbers of frames"
+                );
+            }
+            let mut levels = vec![None; chunk.levels];
+            levels[0] = Some(chunk.frames);
+            index.push(tile_index);
+            chunks.push(levels);
+        }
+        ensure!(!index.is_empty(), "a segment needs at least one tile");
+        Ok(LodSegment { index, chunks })
+    }
+
+    /// Adds a refinement level.
+    pub fn add(&mut self, chunk: Chunk) -> Result<()> {
+        let levels = self
+            .chunks
+            .get_mut(chunk.tile)
+            .ok_or_else(|| anyhow::anyhow!("no tile {}", chunk.tile))?;
+        ensure!(
+            chunk.level < levels.len(),
+            "tile {} has no level {}",
+            chunk.tile,
+            chunk.level
+        );
+        ensure!(
+            chunk.frames.len() == self.index[chunk.tile].frames(),
+            "level {} of tile {} has a different number of frames",
+            chunk.level,
+            chunk.tile
+        );
+        levels[chunk.level] = Some(chunk.frames);
+        Ok(())
+    }
+
+    pub fn tiles(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn frames(&self) -> usize {
+        self.index[0].frames()
+    }
+
+    pub fn levels(&self, tile: usize) -> usize {
+        self.chunks[tile].len()
+    }
+
+    /// Number of levels of `tile` that are usable, i.e. loaded without any gap from the base up.
+    pub fn loaded_levels(&self, tile: usize) -> usize {
+        self.chunks[tile]
+            .iter()
+            .take_while(|level| level.is_some())
+            .count()
+    }
+
+    /// The layout of the segment as [`MetaData`], with one entry per frame, e.g. for a `ResolutionController`.
+    pub fn metadata(&self) -> MetaData {
+        let mut metadata = MetaData::new_with_default();
+        metadata.partitions = self.index[0].partitions;
+        for frame in 0..self.frames() {
+            let base = self.index.iter().map(|tile| tile.points[frame][0]);
+            let levels = (1..self.index[0].points[frame].len())
+                .map(|level| {
+                    self.index
+                        .iter()
+                        .map(|tile| tile.points[frame].get(level).copied().unwrap_or(0))
+                        .collect()
+                })
+                .collect();
+            let tiles = self
+                .index
+                .iter()
+                .map(|tile| tile.tile_bounds[frame].clone())
+                .collect();
+            metadata.next(
+                self.index[0].bounds[frame].clone(),
+                tiles,
+                base.collect(),
+                levels,
+            );
+        }
+        metadata
+    }
+
+    /// Number of levels of every tile needed to show `desired[frame][tile]` points in every frame.
+    pub fn levels_for(&self, desired: &[Vec<usize>]) -> Vec<usize> {
+        (0..self.tiles())
+            .map(|tile| {
+                let index = &self.index[tile];
+                desired
+                    .iter()
+                    .zip(&index.points)
+                    .map(|(desired, counts)| {
+                        let wanted = desired.get(tile).copied().unwrap_or(0);
+                        let mut total = 0;
+                        counts
+                            .iter()
+                            .position(|n| {
+                                total += n;
+                                total >= wanted
+                            })
+                            .map_or(counts.len(), |level| level + 1)
+                    })
+                    .max()
+                    .unwrap_or(1)
+            })
+            .collect()
+    }
+
+    /// The points of `frame` in the usable levels, segmented by tile.
+    pub fn frame(&self, frame: usize) -> Option<PointCloud<PointXyzRgba>> {
+        if frame >= self.frames() {
+            return None;
+        }
+        let mut points = vec![];
+        let mut offsets = vec![];
+        for (tile, levels) in self.chunks.iter().enumerate() {
+            let start = points.len();
+            for level in levels.iter().take(self.loaded_levels(tile)) {
+                points.extend_from_slice(&level.as_ref().unwrap()[frame]);
+            }
+            offsets.push(points.len() - start);
+        }
+        let tiles = self
+            .index
+            .iter()
+            .map(|tile| tile.tile_bounds[frame].clone())
+            .collect();
+        let mut pc = PointCloud::new(points.len(), points);
+        pc.self_segment(&offsets, &tiles);
+        Some(pc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::pointxyzrgba::fixtures::{self, point};
+
+    /// `n` points on a 10 x 10 grid per z, told apart by their colour
+    fn cloud(n: usize) -> PointCloud<PointXyzRgba> {
+        fixtures::cloud((0..n).map(|i| PointXyzRgba {
+            r: i as u8,
+            ..point((i % 10) as f32, (i / 10 % 10) as f32, (i / 100) as f32)
+        }))
+    }
+
+    const GRID: StreamTiles = StreamTiles::Grid((2, 1, 1));
+
+    fn params() -> LodParams {
+        LodParams {
+            targets: LevelTargets::Proportions(vec![20, 60]),
+            points_per_voxel_threshold: 4,
+        }
+    }
+
+    #[test]
+    fn test_package_roundtrip() {
+        let frames = vec![cloud(500), cloud(300)];
+        let chunks = package(&frames, &GRID, &params());
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|levels| levels.len() == 3));
+        let mut chunks: Vec<Vec<Option<Chunk>>> = chunks
+            .iter()
+            .map(|levels| {
+                levels
+                    .iter()
+                    .map(|c| Some(Chunk::from_bytes(&c.to_bytes()).unwrap()))
+                    .collect()
+            })
+            .collect();
+        let mut take = |tile: usize, level: usize| chunks[tile][level].take().unwrap();
+
+        let mut segment = LodSegment::new(vec![take(0, 0), take(1, 0)]).unwrap();
+        assert_eq!(segment.frames(), 2);
+        assert_eq!(segment.loaded_levels(0), 1);
+
+        // the base alone is about a fifth of the points, segmented by tile
+        let base = segment.frame(0).unwrap();
+        assert!(base.points.len() >= 100 && base.points.len() < 500);
+        assert_eq!(base.segments.as_ref().unwrap().len(), 2);
+        let metadata = segment.metadata();
+        assert_eq!(metadata.bounds.len(), 2);
+        assert_eq!(
+            metadata.base_point_num[0].iter().sum::<usize>()
+                + metadata.additional_point_num[0].iter().sum::<usize>(),
+            500
+        );
+
+        // a level with a gap below is not used
+        segment.add(take(0, 2)).unwrap();
+        assert_eq!(segment.loaded_levels(0), 1);
+        assert_eq!(segment.frame(0).unwrap().points.len(), base.points.len());
+
+        // all the levels give back every point
+        for (tile, level) in [(0, 1), (1, 1), (1, 2)] {
+            segment.add(take(tile, level)).unwrap();
+        }
+        assert_eq!(segment.loaded_levels(0), 3);
+        for (i, original) in frames.iter().enumerate() {
+            let mut frame = segment.frame(i).unwrap().points;
+            let mut original = original.points.clone();
+            frame.sort();
+            original.sort();
+            assert_eq!(frame, original);
+        }
+        assert!(segment.frame(2).is_none());
+    }
+
+    #[test]
+    fn test_levels_for() {
+        let chunks = package(&[cloud(500)], &GRID, &params());
+        let segment = LodSegment::new(
+            chunks
+                .into_iter()
+                .map(|mut levels| levels.swap_remove(0))
+                .collect(),
+        )
+        .unwrap();
+        let metadata = segment.metadata();
+        let base = &metadata.base_point_num[0];
+        let all: Vec<usize> = base
+            .iter()
+            .zip(&metadata.additional_point_num[0])
+            .map(|(b, a)| b + a)
+            .collect();
+        assert_eq!(segment.levels_for(std::slice::from_ref(base)), vec![1, 1]);
+        assert_eq!(segment.levels_for(std::slice::from_ref(&all)), vec![3, 3]);
+        let more: Vec<usize> = all.iter().map(|n| n * 2).collect();
+        assert_eq!(segment.levels_for(&[more]), vec![3, 3]);
+        assert_eq!(segment.levels_for(&[vec![base[0], all[1]]]), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_adaptive_tiles() {
+        let first = cloud(500);
+        let tiles = StreamTiles::new(&Tiling::KdTree { max_points: 100 }, &first);
+        assert_eq!(tiles.count(), 8);
+        // the second frame reaches beyond the tiles of the first
+        let second = cloud(700);
+        let chunks = package(&[first, second.clone()], &tiles, &params());
+        assert_eq!(chunks.len(), 8);
+
+        let mut segment = LodSegment::new(
+            chunks
+                .iter()
+                .map(|levels| Chunk::from_bytes(&levels[0].to_bytes()).unwrap())
+                .collect(),
+        )
+        .unwrap();
+        for levels in &chunks {
+            for chunk in &levels[1..] {
+                segment.add(chunk.clone()).unwrap();
+            }
+        }
+        let metadata = segment.metadata();
+        assert_eq!(metadata.partitions, (0, 0, 0));
+        let frame = segment.frame(1).unwrap();
+        assert_eq!(frame.points.len(), second.points.len());
+        // every tile holds its points, including the ones beyond the tiles of the first frame
+        for (tile, bounds) in frame
+            .segments
+            .as_ref()
+            .unwrap()
+            .iter()
+            .zip(metadata.tiles(1))
+        {
+            assert!(tile
+                .point_indices
+                .iter()
+                .all(|&i| bounds.contains(&frame.points[i])));
+        }
+    }
+
+    #[test]
+    fn test_manifest() {
+        let tile = |min_x: f32| Bounds {
+            min_x,
+            max_x: min_x + 1.0,
+            min_y: 0.0,
+            max_y: 2.0,
+            min_z: 0.0,
+            max_z: 0.5,
+        };
+        let mpd = manifest(
+            &[vec![1000, 2000], vec![500, 800]],
+            &[tile(0.0), tile(1.0)],
+            45,
+            15,
+            30,
+        );
+        assert!(mpd.contains(r#"tileId="1" tileBounds="1 0 0 2 2 0.5""#));
+        assert!(mpd.contains(r#"<Representation id="1" dependencyId="0" bandwidth="800"/>"#));
+        assert!(mpd.contains("PT1.5S"));
+        assert_eq!(
+            chunk_name(1, 0, 15),
+            format!("tile1_level0_00015.{EXTENSION}")
+        );
+    }
+
+    #[test]
+    fn test_malformed_chunk() {
+        assert!(Chunk::from_bytes(b"VVLP").is_err());
+        assert!(Chunk::from_bytes(b"nope, not a chunk").is_err());
+        let chunk = &package(&[cloud(100)], &GRID, &params())[0][0];
+        let bytes = chunk.to_bytes();
+        assert!(Chunk::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        // a refinement level claiming u32::MAX frames
+        let mut header = b"VVLP\0\0\x01\x02".to_vec();
+        header.extend_from_slice(&u32::MAX.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        assert!(Chunk::from_bytes(&header).is_err());
+    }
+}
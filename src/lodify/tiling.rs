@@ -0,0 +1,176 @@
+//! Spatial tiling of a frame, either a uniform grid or cells split by point density.
+//!
+//! Adaptive tiles are the tight bounds of the points of every cell, so they never overlap and
+//! empty space, e.g. around a standing person, gets no tile at all.
+
+use crate::formats::bounds::Bounds;
+use crate::formats::pointxyzrgba::PointXyzRgba;
+use crate::formats::PointCloud;
+use crate::utils::get_pc_bound;
+
+/// Cells are not split below this depth, so that coincident points end the recursion.
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tiling {
+    /// uniform `x × y × z` grid over the bounds of the frame
+    Grid((usize, usize, usize)),
+    /// octree cells, split into eight until they have at most `max_points` points
+    Octree { max_points: usize },
+    /// k-d cells, split in two at the median of their longest side until they have at most `max_points` points
+    KdTree { max_points: usize },
+}
+
+impl Tiling {
+    /// The partitions of a grid. Adaptive tilings have none, their tiles are only known per frame.
+    pub fn partitions(&self) -> (usize, usize, usize) {
+        match self {
+            Tiling::Grid(partitions) => *partitions,
+            Tiling::Octree { .. } | Tiling::KdTree { .. } => (0, 0, 0),
+        }
+    }
+
+    /// The bounds of the tiles of a frame. Every point is contained in one of them.
+    pub fn tiles(&self, pc: &PointCloud<PointXyzRgba>) -> Vec<Bounds> {
+        if pc.points.is_empty() {
+            return vec![];
+        }
+        match *self {
+            Tiling::Grid(partitions) => get_pc_bound(pc).partition(partitions),
+            Tiling::Octree { max_points } => {
+                let mut tiles = vec![];
+                octree(pc.points.clone(), max_points.max(1), 0, &mut tiles);
+                tiles
+            }
+            Tiling::KdTree { max_points } => {
+                let mut tiles = vec![];
+                kd_tree(pc.points.clone(), max_points.max(1), 0, &mut tiles);
+                tiles
+            }
+        }
+    }
+}
+
+fn octree(points: Vec<PointXyzRgba>, max_points: usize, depth: usize, tiles: &mut Vec<Bounds>) {
+    let bounds = tight_bounds(&points);
+    if points.len() <= max_points || depth == MAX_DEPTH || is_point(&bounds) {
+        tiles.push(bounds);
+        return;
+    }
+    let mid_x = (bounds.min_x + bounds.max_x) / 2.0;
+    let mid_y = (bounds.min_y + bounds.max_y) / 2.0;
+    let mid_z = (bounds.min_z + bounds.max_z) / 2.0;
+    let mut children = vec![vec![]; 8];
+    for point in points {
+        let child = (point.x > mid_x) as usize * 4
+            + (point.y > mid_y) as usize * 2
+            + (point.z > mid_z) as usize;
+        children[child].push(point);
+    }
+    for child in children.into_iter().filter(|child| !child.is_empty()) {
+        octree(child, max_points, depth + 1, tiles);
+    }
+}
+
+fn kd_tree(
+    mut points: Vec<PointXyzRgba>,
+    max_points: usize,
+    depth: usize,
+    tiles: &mut Vec<Bounds>,
+) {
+    let bounds = tight_bounds(&points);
+    if points.len() <= max_points || depth == MAX_DEPTH * 3 || is_point(&bounds) {
+        tiles.push(bounds);
+        return;
+    }
+    let extents = [
+        bounds.max_x - bounds.min_x,
+        bounds.max_y - bounds.min_y,
+        bounds.max_z - bounds.min_z,
+    ];
+    let axis = (0..3)
+        .max_by(|&a, &b| extents[a].total_cmp(&extents[b]))
+        .unwrap();
+    let coordinate = |p: &PointXyzRgba| [p.x, p.y, p.z][axis];
+    points.sort_by(|a, b| coordinate(a).total_cmp(&coordinate(b)));
+    let upper = points.split_off(points.len() / 2);
+    kd_tree(points, max_points, depth + 1, tiles);
+    kd_tree(upper, max_points, depth + 1, tiles);
+}
+
+fn tight_bounds(points: &[PointXyzRgba]) -> Bounds {
+    let first = points[0];
+    points.iter().fold(
+        Bounds {
+            min_x: first.x,
+            max_x: first.x,
+            min_y: first.y,
+            max_y: first.y,
+            min_z: first.z,
+            max_z: first.z,
+        },
+        |b, p| Bounds {
+            min_x: b.min_x.min(p.x),
+            max_x: b.max_x.max(p.x),
+            min_y: b.min_y.min(p.y),
+            max_y: b.max_y.max(p.y),
+            min_z: b.min_z.min(p.z),
+            max_z: b.max_z.max(p.z),
+        },
+    )
+}
+
+fn is_point(bounds: &Bounds) -> bool {
+    bounds.min_x == bounds.max_x && bounds.min_y == bounds.max_y && bounds.min_z == bounds.max_z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::pointxyzrgba::fixtures::{cloud, point};
+
+    /// a tall and thin column of points
+    fn column() -> PointCloud<PointXyzRgba> {
+        cloud((0..1000).map(|i| point((i % 5) as f32 * 0.01, (i / 5) as f32 * 0.01, 0.0)))
+    }
+
+    fn count(pc: &PointCloud<PointXyzRgba>, tiles: &[Bounds]) -> Vec<usize> {
+        let mut counts = vec![0; tiles.len()];
+        for point in &pc.points {
+            let tile = tiles.iter().position(|b| b.contains(point)).unwrap();
+            counts[tile] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_adaptive_tiles() {
+        let pc = column();
+        for tiling in [
+            Tiling::Octree { max_points: 100 },
+            Tiling::KdTree { max_points: 100 },
+        ] {
+            let tiles = tiling.tiles(&pc);
+            let counts = count(&pc, &tiles);
+            assert!(counts.iter().all(|&n| n > 0 && n <= 100), "{tiling:?}");
+            assert_eq!(counts.iter().sum::<usize>(), 1000);
+            assert_eq!(tiling.partitions(), (0, 0, 0));
+        }
+        // the k-d tree halves the column until the tiles are small enough
+        assert_eq!(Tiling::KdTree { max_points: 100 }.tiles(&pc).len(), 16);
+
+        // a grid has empty tiles
+        let grid = Tiling::Grid((2, 2, 2));
+        let tiles = grid.tiles(&pc);
+        assert_eq!(tiles.len(), 8);
+        assert!(count(&pc, &tiles).contains(&0));
+        assert!(grid.tiles(&PointCloud::new(0, vec![])).is_empty());
+    }
+
+    #[test]
+    fn test_coincident_points() {
+        let pc = cloud(vec![column().points[0]; 50]);
+        let tiles = Tiling::Octree { max_points: 10 }.tiles(&pc);
+        assert_eq!(tiles.len(), 1);
+    }
+}
@@ -0,0 +1,154 @@
+//! Bjøntegaard delta metrics (VCEG-M33) between two rate-distortion curves.
+//!
+//! Each curve is a list of `(rate, psnr)` points. The curves are interpolated with a polynomial
+//! of degree up to 3 in the log-rate domain and compared over the range where they overlap.
+
+/// Polynomial fitted around `centre`, coefficients from the constant term up.
+struct Polynomial {
+    centre: f64,
+    coefficients: Vec<f64>,
+}
+
+impl Polynomial {
+    /// Least squares fit, by solving the normal equations. The abscissas are centred first to keep
+    /// them well conditioned.
+    fn fit(x: &[f64], y: &[f64], degree: usize) -> Option<Self> {
+        let centre = x.iter().sum::<f64>() / x.len() as f64;
+        let n = degree + 1;
+        // augmented matrix of the normal equations
+        let mut m = vec![vec![0.0; n + 1]; n];
+        for (xi, yi) in x.iter().zip(y) {
+            let xi = xi - centre;
+            for (row, equation) in m.iter_mut().enumerate() {
+                for (col, a) in equation.iter_mut().take(n).enumerate() {
+                    *a += xi.powi((row + col) as i32);
+                }
+                equation[n] += yi * xi.powi(row as i32);
+            }
+        }
+        // Gaussian elimination with partial pivoting
+        for col in 0..n {
+            let pivot = (col..n).max_by(|a, b| m[*a][col].abs().total_cmp(&m[*b][col].abs()))?;
+            if m[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            m.swap(col, pivot);
+            let pivot_row = m[col].clone();
+            for (row, equation) in m.iter_mut().enumerate() {
+                if row != col {
+                    let factor = equation[col] / pivot_row[col];
+                    for (a, p) in equation.iter_mut().zip(&pivot_row).skip(col) {
+                        *a -= factor * p;
+                    }
+                }
+            }
+        }
+        let coefficients = (0..n).map(|i| m[i][n] / m[i][i]).collect();
+        Some(Polynomial {
+            centre,
+            coefficients,
+        })
+    }
+
+    /// Integral of the polynomial between `from` and `to`.
+    fn integrate(&self, from: f64, to: f64) -> f64 {
+        let antiderivative = |x: f64| {
+            let x = x - self.centre;
+            self.coefficients
+                .iter()
+                .enumerate()
+                .map(|(i, c)| c * x.powi(i as i32 + 1) / (i + 1) as f64)
+                .sum::<f64>()
+        };
+        antiderivative(to) - antiderivative(from)
+    }
+}
+
+/// Average difference between `y = f(x)` of the test and the anchor curve, over their common `x` range.
+fn average_difference(anchor: &[(f64, f64)], test: &[(f64, f64)]) -> Option<f64> {
+    let fit = |curve: &[(f64, f64)]| {
+        let (x, y): (Vec<f64>, Vec<f64>) = curve.iter().copied().unzip();
+        Polynomial::fit(&x, &y, (curve.len() - 1).min(3))
+    };
+    let range = |curve: &[(f64, f64)]| {
+        curve.iter().fold((f64::MAX, f64::MIN), |(lo, hi), (x, _)| {
+            (lo.min(*x), hi.max(*x))
+        })
+    };
+    let (anchor_lo, anchor_hi) = range(anchor);
+    let (test_lo, test_hi) = range(test);
+    let (lo, hi) = (anchor_lo.max(test_lo), anchor_hi.min(test_hi));
+    if hi <= lo {
+        return None;
+    }
+    let anchor_integral = fit(anchor)?.integrate(lo, hi);
+    let test_integral = fit(test)?.integrate(lo, hi);
+    Some((test_integral - anchor_integral) / (hi - lo))
+}
+
+/// Keeps the points that can be compared: positive rate and finite PSNR, as `(log10 rate, psnr)`.
+fn usable(curve: &[(f64, f64)]) -> Option<Vec<(f64, f64)>> {
+    let points: Vec<(f64, f64)> = curve
+        .iter()
+        .filter(|(rate, psnr)| *rate > 0.0 && psnr.is_finite())
+        .map(|(rate, psnr)| (rate.log10(), *psnr))
+        .collect();
+    (points.len() >= 2).then_some(points)
+}
+
+/// Average rate difference of `test` against `anchor` at equal quality, in percent.
+/// Negative values mean that `test` needs fewer bits.
+pub fn bd_rate(anchor: &[(f64, f64)], test: &[(f64, f64)]) -> Option<f64> {
+    let swap = |curve: Vec<(f64, f64)>| curve.into_iter().map(|(r, p)| (p, r)).collect::<Vec<_>>();
+    let anchor = swap(usable(anchor)?);
+    let test = swap(usable(test)?);
+    let difference = average_difference(&anchor, &test)?;
+    Some((10f64.powf(difference) - 1.0) * 100.0)
+}
+
+/// Average PSNR difference of `test` against `anchor` at equal rate, in dB.
+pub fn bd_psnr(anchor: &[(f64, f64)], test: &[(f64, f64)]) -> Option<f64> {
+    average_difference(&usable(anchor)?, &usable(test)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve() -> Vec<(f64, f64)> {
+        vec![(100.0, 30.0), (200.0, 34.0), (400.0, 37.0), (800.0, 39.0)]
+    }
+
+    #[test]
+    fn test_identical_curves() {
+        assert!(bd_rate(&curve(), &curve()).unwrap().abs() < 1e-6);
+        assert!(bd_psnr(&curve(), &curve()).unwrap().abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_half_rate() {
+        let test: Vec<_> = curve().iter().map(|(r, p)| (r / 2.0, *p)).collect();
+        assert!((bd_rate(&curve(), &test).unwrap() + 50.0).abs() < 1e-6);
+        assert!(bd_psnr(&curve(), &test).unwrap() > 0.0);
+    }
+
+    #[test]
+    fn test_better_quality() {
+        let test: Vec<_> = curve().iter().map(|(r, p)| (*r, p + 1.0)).collect();
+        assert!((bd_psnr(&curve(), &test).unwrap() - 1.0).abs() < 1e-6);
+        assert!(bd_rate(&curve(), &test).unwrap() < 0.0);
+    }
+
+    #[test]
+    fn test_degenerate_curves() {
+        // two points are fitted with a line
+        let short = vec![(100.0, 30.0), (400.0, 36.0)];
+        assert!(bd_psnr(&short, &short).unwrap().abs() < 1e-6);
+        // lossless points are ignored
+        let lossless = vec![(100.0, 30.0), (200.0, f64::INFINITY)];
+        assert!(bd_rate(&curve(), &lossless).is_none());
+        // no overlap
+        let far: Vec<_> = curve().iter().map(|(r, p)| (r * 1000.0, *p)).collect();
+        assert!(bd_psnr(&curve(), &far).is_none());
+    }
+}
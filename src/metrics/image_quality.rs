@@ -0,0 +1,294 @@
+//! Image quality metrics, for comparing rendered views of an original and a reconstructed cloud.
+//!
+//! PSNR is computed over the RGB channels. SSIM and MS-SSIM follow Wang et al. and are computed
+//! on the luma with an 11x11 Gaussian window (sigma 1.5), MS-SSIM over up to five scales.
+
+use super::Metrics;
+use image::RgbaImage;
+
+const WINDOW: usize = 11;
+const SIGMA: f64 = 1.5;
+const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ImageMetric {
+    Psnr,
+    Ssim,
+    MsSsim,
+    All,
+}
+
+/// PSNR over the RGB channels, infinite for identical images
+pub fn image_psnr(original: &RgbaImage, reconstructed: &RgbaImage) -> f64 {
+    assert_eq!(original.dimensions(), reconstructed.dimensions());
+    let squared_error: f64 = original
+        .pixels()
+        .zip(reconstructed.pixels())
+        .map(|(a, b)| {
+            (0..3)
+                .map(|c| (a.0[c] as f64 - b.0[c] as f64).powi(2))
+                .sum::<f64>()
+        })
+        .sum();
+    let mse = squared_error / (original.width() as f64 * original.height() as f64 * 3.0);
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+pub fn ssim(original: &RgbaImage, reconstructed: &RgbaImage) -> f64 {
+    assert_eq!(original.dimensions(), reconstructed.dimensions());
+    ssim_cs(&Plane::luma(original), &Plane::luma(reconstructed)).0
+}
+
+pub fn ms_ssim(original: &RgbaImage, reconstructed: &RgbaImage) -> f64 {
+    assert_eq!(original.dimensions(), reconstructed.dimensions());
+    let mut a = Plane::luma(original);
+    let mut b = Plane::luma(reconstructed);
+    // stop before the window no longer fits
+    let mut scales = 1;
+    while scales < MS_SSIM_WEIGHTS.len() && a.width.min(a.height) >> scales >= WINDOW {
+        scales += 1;
+    }
+    let weights = &MS_SSIM_WEIGHTS[..scales];
+    let total: f64 = weights.iter().sum();
+
+    let mut result = 1.0;
+    for (scale, weight) in weights.iter().enumerate() {
+        let (ssim, cs) = ssim_cs(&a, &b);
+        let value = if scale + 1 == scales { ssim } else { cs };
+        result *= value.max(0.0).powf(weight / total);
+        a = a.downsample();
+        b = b.downsample();
+    }
+    result
+}
+
+/// Compare the views of a rig rendered from the original and the reconstructed cloud.
+/// Every metric is reported per view, e.g. `ssim_view_03`, and averaged over the views, e.g. `ssim`.
+pub fn compare_views(
+    originals: &[RgbaImage],
+    reconstructed: &[RgbaImage],
+    metrics: &[ImageMetric],
+) -> Metrics {
+    assert_eq!(originals.len(), reconstructed.len());
+    let has_all = metrics.contains(&ImageMetric::All);
+    let width = originals.len().saturating_sub(1).to_string().len();
+    let mut report = Metrics::new();
+    let metric_functions: [(ImageMetric, &str, fn(&RgbaImage, &RgbaImage) -> f64); 3] = [
+        (ImageMetric::Psnr, "image_psnr", image_psnr),
+        (ImageMetric::Ssim, "ssim", ssim),
+        (ImageMetric::MsSsim, "ms_ssim", ms_ssim),
+    ];
+    for (metric, name, function) in metric_functions {
+        if !has_all && !metrics.contains(&metric) {
+            continue;
+        }
+        let values = originals
+            .iter()
+            .zip(reconstructed)
+            .map(|(a, b)| function(a, b))
+            .collect::<Vec<_>>();
+        for (view, value) in values.iter().enumerate() {
+            report.insert(
+                format!("{name}_view_{view:0>width$}"),
+                format!("{value:.5}"),
+            );
+        }
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        report.insert(name.to_string(), format!("{mean:.5}"));
+    }
+    report
+}
+
+struct Plane {
+    width: usize,
+    height: usize,
+    data: Vec<f64>,
+}
+
+impl Plane {
+    /// BT.601 luma, as used by the reference SSIM implementation
+    fn luma(image: &RgbaImage) -> Self {
+        Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            data: image
+                .pixels()
+                .map(|p| 0.299 * p.0[0] as f64 + 0.587 * p.0[1] as f64 + 0.114 * p.0[2] as f64)
+                .collect(),
+        }
+    }
+
+    fn map(&self, other: &Plane, f: impl Fn(f64, f64) -> f64) -> Plane {
+        Plane {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .zip(&other.data)
+                .map(|(&a, &b)| f(a, b))
+                .collect(),
+        }
+    }
+
+    /// Gaussian weighted means over every position the window fits in.
+    /// A plane smaller than the window has a single mean with uniform weights.
+    fn local_means(&self) -> Plane {
+        if self.width < WINDOW || self.height < WINDOW {
+            let mean = self.data.iter().sum::<f64>() / self.data.len().max(1) as f64;
+            return Plane {
+                width: 1,
+                height: 1,
+                data: vec![mean],
+            };
+        }
+        let kernel = gaussian_kernel();
+        let width = self.width - WINDOW + 1;
+        let height = self.height - WINDOW + 1;
+        // the kernel is separable, filter the rows and then the columns
+        let mut rows = vec![0.0; width * self.height];
+        for y in 0..self.height {
+            let row = &self.data[y * self.width..(y + 1) * self.width];
+            for x in 0..width {
+                rows[y * width + x] = kernel.iter().zip(&row[x..]).map(|(k, v)| k * v).sum();
+            }
+        }
+        let mut data = vec![0.0; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                data[y * width + x] = kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(i, k)| k * rows[(y + i) * width + x])
+                    .sum();
+            }
+        }
+        Plane {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Average 2x2 blocks, dropping an odd last row or column
+    fn downsample(&self) -> Plane {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        if self.width < 2 || self.height < 2 {
+            return Plane {
+                width: self.width,
+                height: self.height,
+                data: self.data.clone(),
+            };
+        }
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let at = |dx: usize, dy: usize| self.data[(2 * y + dy) * self.width + 2 * x + dx];
+                data.push((at(0, 0) + at(1, 0) + at(0, 1) + at(1, 1)) / 4.0);
+            }
+        }
+        Plane {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+fn gaussian_kernel() -> [f64; WINDOW] {
+    let mut kernel = [0.0; WINDOW];
+    let centre = (WINDOW / 2) as f64;
+    for (i, k) in kernel.iter_mut().enumerate() {
+        *k = (-(i as f64 - centre).powi(2) / (2.0 * SIGMA * SIGMA)).exp();
+    }
+    let sum: f64 = kernel.iter().sum();
+    kernel.map(|k| k / sum)
+}
+
+/// Mean SSIM and mean contrast-structure term
+fn ssim_cs(a: &Plane, b: &Plane) -> (f64, f64) {
+    let mu_a = a.local_means();
+    let mu_b = b.local_means();
+    let aa = a.map(a, |x, y| x * y).local_means();
+    let bb = b.map(b, |x, y| x * y).local_means();
+    let ab = a.map(b, |x, y| x * y).local_means();
+
+    let n = mu_a.data.len() as f64;
+    let (mut ssim, mut cs) = (0.0, 0.0);
+    for i in 0..mu_a.data.len() {
+        let (ma, mb) = (mu_a.data[i], mu_b.data[i]);
+        let var_a = aa.data[i] - ma * ma;
+        let var_b = bb.data[i] - mb * mb;
+        let cov = ab.data[i] - ma * mb;
+        let contrast_structure = (2.0 * cov + C2) / (var_a + var_b + C2);
+        let luminance = (2.0 * ma * mb + C1) / (ma * ma + mb * mb + C1);
+        ssim += luminance * contrast_structure;
+        cs += contrast_structure;
+    }
+    (ssim / n, cs / n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn noise(width: u32, height: u32, amplitude: u8) -> RgbaImage {
+        // a deterministic pattern, no random number generator needed
+        RgbaImage::from_fn(width, height, |x, y| {
+            let v = (((x * 7 + y * 13) % 17) * amplitude as u32 / 16) as u8;
+            Rgba([100 + v, 120 + v, 80 + v, 255])
+        })
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let image = noise(64, 48, 16);
+        assert_eq!(image_psnr(&image, &image), f64::INFINITY);
+        assert!((ssim(&image, &image) - 1.0).abs() < 1e-9);
+        assert!((ms_ssim(&image, &image) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_constant_images() {
+        let black = RgbaImage::from_pixel(32, 32, Rgba([0, 0, 0, 255]));
+        let grey = RgbaImage::from_pixel(32, 32, Rgba([10, 10, 10, 255]));
+        // mse of 100
+        assert!((image_psnr(&black, &grey) - 28.13080).abs() < 1e-4);
+        // only the luminance term differs
+        let expected = C1 / (100.0 + C1);
+        assert!((ssim(&black, &grey) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_distortion_lowers_scores() {
+        let original = noise(128, 128, 16);
+        let flat = noise(128, 128, 0);
+        let slight = RgbaImage::from_fn(128, 128, |x, y| {
+            let mut p = *original.get_pixel(x, y);
+            if (x + y) % 5 == 0 {
+                p.0[0] = p.0[0].saturating_add(8);
+            }
+            p
+        });
+        assert!(ssim(&original, &slight) > ssim(&original, &flat));
+        assert!(ms_ssim(&original, &slight) > ms_ssim(&original, &flat));
+        assert!(image_psnr(&original, &slight) > image_psnr(&original, &flat));
+        assert!(ssim(&original, &flat) < 1.0);
+
+        let report = compare_views(
+            &[original.clone(), original.clone()],
+            &[original, flat],
+            &[ImageMetric::Ssim],
+        );
+        let names = report
+            .metrics()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["ssim", "ssim_view_0", "ssim_view_1"]);
+    }
+}
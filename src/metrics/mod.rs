@@ -1,8 +1,11 @@
 mod acd;
+mod bd;
 mod cd;
 mod cd_psnr;
 mod hd;
+mod image_quality;
 mod lc_psnr;
+mod mpeg_psnr;
 mod psnr;
 mod vqoe;
 
@@ -16,10 +19,13 @@ use crate::formats::{pointxyzrgba::PointXyzRgba, PointCloud};
 use kiddo::KdTree;
 
 use self::acd::Acd;
+pub use self::bd::{bd_psnr, bd_rate};
 use self::cd::Cd;
 use self::cd_psnr::CdPsnr;
 use self::hd::Hd;
+pub use self::image_quality::{compare_views, image_psnr, ms_ssim, ssim, ImageMetric};
 use self::lc_psnr::LcPsnr;
+pub use self::mpeg_psnr::{calculate_distortion, Distortion};
 use self::psnr::Psnr;
 use self::vqoe::VQoE;
 
@@ -31,6 +37,8 @@ pub enum SupoportedMetrics {
     Hd,
     LcPsnr,
     VQoe,
+    /// D1, D2 and colour PSNR
+    MpegPsnr,
     All,
 }
 
@@ -44,6 +52,7 @@ impl FromStr for SupoportedMetrics {
             "hd" => Ok(SupoportedMetrics::Hd),
             "lc-psnr" => Ok(SupoportedMetrics::LcPsnr),
             "v-qoe" => Ok(SupoportedMetrics::VQoe),
+            "mpeg-psnr" => Ok(SupoportedMetrics::MpegPsnr),
             "all" => Ok(SupoportedMetrics::All),
             _ => Err(format!("{} is not a valid metric", s)),
         }
@@ -100,6 +109,29 @@ impl Metrics {
     }
 }
 
+/// D1, D2 and colour PSNR of a reconstruction, see [`Distortion`].
+pub fn distortion(
+    original: &PointCloud<PointXyzRgba>,
+    reconstructed: &PointCloud<PointXyzRgba>,
+    peak: Option<f64>,
+) -> Distortion {
+    let build_tree = |pc: &PointCloud<PointXyzRgba>| {
+        let mut tree = KdTree::new();
+        for (i, pt) in pc.points.iter().enumerate() {
+            tree.add(&[pt.x, pt.y, pt.z], i)
+                .expect("Failed to add to tree");
+        }
+        tree
+    };
+    calculate_distortion(
+        &original.points,
+        &build_tree(original),
+        &reconstructed.points,
+        &build_tree(reconstructed),
+        peak,
+    )
+}
+
 pub fn calculate_metrics(
     original: &PointCloud<PointXyzRgba>,
     reconstructed: &PointCloud<PointXyzRgba>,
@@ -209,6 +241,25 @@ pub fn calculate_metrics(
         metrics_report.insert("vqoe".to_string(), format!("{:.5}", vqoe));
     }
 
+    if has_all || metrics.contains(&SupoportedMetrics::MpegPsnr) {
+        let distortion = calculate_distortion(
+            &original.points,
+            &original_tree,
+            &reconstructed.points,
+            &reconstructed_tree,
+            None,
+        );
+        for (name, value) in [
+            ("d1_psnr", distortion.d1_psnr),
+            ("d2_psnr", distortion.d2_psnr),
+            ("y_psnr", distortion.y_psnr),
+            ("u_psnr", distortion.u_psnr),
+            ("v_psnr", distortion.v_psnr),
+        ] {
+            metrics_report.insert(name.to_string(), format!("{:.5}", value));
+        }
+    }
+
     Psnr::calculate_metric(
         &original.points,
         &original_tree,
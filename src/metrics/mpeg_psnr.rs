@@ -0,0 +1,251 @@
+use kiddo::{distance::squared_euclidean, KdTree};
+use nalgebra::{Matrix3, Vector3};
+use rayon::prelude::*;
+
+use crate::formats::pointxyzrgba::PointXyzRgba;
+
+/// Neighbours used to estimate the normals of the original for D2.
+const NORMAL_NEIGHBOURS: usize = 12;
+
+/// Objective distortion of a reconstruction, as defined by the MPEG PCC common test conditions.
+///
+/// Every measure is symmetric: it is computed from the original to the reconstruction and back,
+/// and the worse of the two is kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Distortion {
+    /// point-to-point geometry PSNR
+    pub d1_psnr: f64,
+    /// point-to-plane geometry PSNR, against normals estimated on the original
+    pub d2_psnr: f64,
+    /// colour PSNR of the BT.709 luma and chroma channels
+    pub y_psnr: f64,
+    pub u_psnr: f64,
+    pub v_psnr: f64,
+}
+
+/// Squared errors accumulated from the points of `from` to their nearest neighbour in `to`.
+#[derive(Default)]
+struct Errors {
+    d1: f64,
+    d2: f64,
+    yuv: [f64; 3],
+}
+
+impl std::ops::Add for Errors {
+    type Output = Errors;
+
+    fn add(self, other: Errors) -> Errors {
+        Errors {
+            d1: self.d1 + other.d1,
+            d2: self.d2 + other.d2,
+            yuv: [0, 1, 2].map(|i| self.yuv[i] + other.yuv[i]),
+        }
+    }
+}
+
+fn to_yuv(p: &PointXyzRgba) -> [f64; 3] {
+    let (r, g, b) = (p.r as f64, p.g as f64, p.b as f64);
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    [y, (b - y) / 1.8556 + 128.0, (r - y) / 1.5748 + 128.0]
+}
+
+fn psnr(mse: f64, peak_energy: f64) -> f64 {
+    if mse <= 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (peak_energy / mse).log10()
+    }
+}
+
+/// Unit normal of every point, from the principal axes of its neighbourhood.
+fn estimate_normals(points: &[PointXyzRgba], tree: &KdTree<f32, usize, 3>) -> Vec<Vector3<f64>> {
+    points
+        .par_iter()
+        .map(|pt| {
+            let neighbours = tree
+                .nearest(&[pt.x, pt.y, pt.z], NORMAL_NEIGHBOURS, &squared_euclidean)
+                .unwrap_or_default();
+            let positions: Vec<Vector3<f64>> = neighbours
+                .iter()
+                .map(|(_, idx)| {
+                    let p = &points[**idx];
+                    Vector3::new(p.x as f64, p.y as f64, p.z as f64)
+                })
+                .collect();
+            if positions.len() < 3 {
+                return Vector3::zeros();
+            }
+            let mean = positions.iter().sum::<Vector3<f64>>() / positions.len() as f64;
+            let covariance = positions
+                .iter()
+                .map(|p| (p - mean) * (p - mean).transpose())
+                .sum::<Matrix3<f64>>();
+            let eigen = covariance.symmetric_eigen();
+            let min_index = eigen.eigenvalues.imin();
+            eigen.eigenvectors.column(min_index).normalize()
+        })
+        .collect()
+}
+
+/// Errors from every point of `from` to its nearest neighbour in `to`.
+/// `normals_of_from` tells whether `normals` belong to `from` or to `to`.
+fn errors(
+    from: &[PointXyzRgba],
+    to: &[PointXyzRgba],
+    to_tree: &KdTree<f32, usize, 3>,
+    normals: &[Vector3<f64>],
+    normals_of_from: bool,
+) -> Errors {
+    from.par_iter()
+        .enumerate()
+        .map(|(i, pt)| {
+            let Ok(nearest) = to_tree.nearest(&[pt.x, pt.y, pt.z], 1, &squared_euclidean) else {
+                return Errors::default();
+            };
+            let Some((_, idx)) = nearest.first() else {
+                return Errors::default();
+            };
+            let other = &to[**idx];
+            let diff = Vector3::new(
+                (other.x - pt.x) as f64,
+                (other.y - pt.y) as f64,
+                (other.z - pt.z) as f64,
+            );
+            let normal = if normals_of_from {
+                normals[i]
+            } else {
+                normals[**idx]
+            };
+            let (a, b) = (to_yuv(pt), to_yuv(other));
+            Errors {
+                d1: diff.norm_squared(),
+                d2: diff.dot(&normal).powi(2),
+                yuv: [0, 1, 2].map(|c| (a[c] - b[c]).powi(2)),
+            }
+        })
+        .reduce(Errors::default, |a, b| a + b)
+}
+
+/// Computes D1, D2 and colour PSNR.
+///
+/// `peak` is the signal peak of the geometry PSNRs, e.g. 1023 for 10 bit voxelised content.
+/// It defaults to the largest side of the bounding box of the original.
+pub fn calculate_distortion(
+    original: &[PointXyzRgba],
+    original_tree: &KdTree<f32, usize, 3>,
+    reconstructed: &[PointXyzRgba],
+    reconstructed_tree: &KdTree<f32, usize, 3>,
+    peak: Option<f64>,
+) -> Distortion {
+    let peak = peak.unwrap_or_else(|| {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for p in original {
+            for (axis, v) in [p.x, p.y, p.z].into_iter().enumerate() {
+                min[axis] = min[axis].min(v);
+                max[axis] = max[axis].max(v);
+            }
+        }
+        (0..3)
+            .map(|axis| (max[axis] - min[axis]) as f64)
+            .fold(0.0, f64::max)
+    });
+    if original.is_empty() || reconstructed.is_empty() {
+        return Distortion {
+            d1_psnr: 0.0,
+            d2_psnr: 0.0,
+            y_psnr: 0.0,
+            u_psnr: 0.0,
+            v_psnr: 0.0,
+        };
+    }
+
+    let normals = estimate_normals(original, original_tree);
+    let forward = errors(original, reconstructed, reconstructed_tree, &normals, true);
+    let backward = errors(reconstructed, original, original_tree, &normals, false);
+    let mse = |forward: f64, forward_n: usize, backward: f64, backward_n: usize| {
+        (forward / forward_n as f64).max(backward / backward_n as f64)
+    };
+    let (n, m) = (original.len(), reconstructed.len());
+    let geometry_peak = 3.0 * peak * peak;
+    Distortion {
+        d1_psnr: psnr(mse(forward.d1, n, backward.d1, m), geometry_peak),
+        d2_psnr: psnr(mse(forward.d2, n, backward.d2, m), geometry_peak),
+        y_psnr: psnr(mse(forward.yuv[0], n, backward.yuv[0], m), 255.0 * 255.0),
+        u_psnr: psnr(mse(forward.yuv[1], n, backward.yuv[1], m), 255.0 * 255.0),
+        v_psnr: psnr(mse(forward.yuv[2], n, backward.yuv[2], m), 255.0 * 255.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::pointxyzrgba::fixtures::point;
+
+    fn tree(points: &[PointXyzRgba]) -> KdTree<f32, usize, 3> {
+        let mut tree = KdTree::new();
+        for (i, pt) in points.iter().enumerate() {
+            tree.add(&[pt.x, pt.y, pt.z], i).unwrap();
+        }
+        tree
+    }
+
+    fn plane(offset: f32, colour: u8) -> Vec<PointXyzRgba> {
+        (0..400)
+            .map(|i| PointXyzRgba {
+                r: colour,
+                g: colour,
+                b: colour,
+                ..point((i % 20) as f32, (i / 20) as f32, offset)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_identical() {
+        let original = plane(0.0, 100);
+        let distortion = calculate_distortion(
+            &original,
+            &tree(&original),
+            &original,
+            &tree(&original),
+            None,
+        );
+        assert!(distortion.d1_psnr.is_infinite());
+        assert!(distortion.y_psnr.is_infinite());
+    }
+
+    #[test]
+    fn test_shifted_plane() {
+        let original = plane(0.0, 100);
+        let shifted = plane(0.5, 110);
+        let distortion = calculate_distortion(
+            &original,
+            &tree(&original),
+            &shifted,
+            &tree(&shifted),
+            Some(19.0),
+        );
+        // every point is 0.5 away along the normal, so D1 and D2 agree
+        let expected = 10.0 * (3.0 * 19.0f64 * 19.0 / 0.25).log10();
+        assert!((distortion.d1_psnr - expected).abs() < 1e-6);
+        assert!((distortion.d2_psnr - expected).abs() < 1e-3);
+        let expected = 10.0 * (255.0f64 * 255.0 / 100.0).log10();
+        assert!((distortion.y_psnr - expected).abs() < 1e-6);
+        assert!(distortion.u_psnr > 100.0);
+
+        // a shift within the plane is not penalised by D2
+        let mut sliding = plane(0.0, 100);
+        for p in &mut sliding {
+            p.x += 0.5;
+        }
+        let distortion = calculate_distortion(
+            &original,
+            &tree(&original),
+            &sliding,
+            &tree(&sliding),
+            Some(19.0),
+        );
+        assert!(distortion.d2_psnr > distortion.d1_psnr + 20.0);
+    }
+}
@@ -94,6 +94,10 @@ impl ExecutorBuilder {
             || cmd.as_str() == "info"
             || cmd.as_str() == "dash"
             || cmd.as_str() == "extend"
+            || cmd.as_str() == "serve"
+            || cmd.as_str() == "decode"
+            || cmd.as_str() == "rd"
+            || cmd.as_str() == "qoe"
             || has_help
         {
         } else {
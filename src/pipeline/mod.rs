@@ -17,9 +17,10 @@ use self::{
     executor::ExecutorBuilder,
     subcommands::extension::SubcommandObject,
     subcommands::{
-        convert, dash, downsample, extension, info, lodify, metrics, normal_estimation, read,
-        render, upsample, write, Convert, Dash, Downsampler, Extension, Info, Lodifier,
-        MetricsCalculator, NormalEstimation, Read, Render, Subcommand, Upsampler, Write,
+        convert, dash, decode, downsample, encode, extension, image_metrics, info, lodify, metrics,
+        normal_estimation, qoe, rd, read, render, serve, upsample, write, Convert, Dash, Decode,
+        Downsampler, Encode, Extension, ImageMetricsCalculator, Info, Lodifier, MetricsCalculator,
+        NormalEstimation, QoeCalculator, Rd, Read, Render, Serve, Subcommand, Upsampler, Write,
     },
 };
 
@@ -31,6 +32,7 @@ fn subcommand(s: &str) -> Option<SubcommandCreator> {
         "render" => Some(Box::from(Render::from_args)),
         "read" => Some(Box::from(Read::from_args)),
         "metrics" => Some(Box::from(MetricsCalculator::from_args)),
+        "image-metrics" => Some(Box::from(ImageMetricsCalculator::from_args)),
         "downsample" => Some(Box::from(Downsampler::from_args)),
         "upsample" => Some(Box::from(Upsampler::from_args)),
         "convert" => Some(Box::from(Convert::from_args)),
@@ -40,6 +42,11 @@ fn subcommand(s: &str) -> Option<SubcommandCreator> {
         "info" => Some(Box::from(Info::from_args)),
         "extend" => Some(Box::from(Extension::from_args)),
         "lodify" => Some(Box::from(Lodifier::from_args)),
+        "serve" => Some(Box::from(Serve::from_args)),
+        "encode" => Some(Box::from(Encode::from_args)),
+        "decode" => Some(Box::from(Decode::from_args)),
+        "rd" => Some(Box::from(Rd::from_args)),
+        "qoe" => Some(Box::from(QoeCalculator::from_args)),
         _ => None,
     }
 }
@@ -50,7 +57,14 @@ pub enum PipelineMessage {
     IndexedPointCloudNormal(PointCloud<PointXyzRgbaNormal>, u32),
     IndexedPointCloudWithName(PointCloud<PointXyzRgba>, u32, String, bool),
     // PointCloud(PointCloud<PointXyzRgba>),
-    MetaData(Bounds, Vec<usize>, Vec<usize>, (usize, usize, usize)),
+    /// bounds, tile bounds, base points per tile, points of every additional level per tile and grid partitions of a frame
+    MetaData(
+        Bounds,
+        Vec<Bounds>,
+        Vec<usize>,
+        Vec<Vec<usize>>,
+        (usize, usize, usize),
+    ),
     Metrics(Metrics),
     // Pipeline message used by vv extend, contains the object that
     // subcommand wants to pass to children or subsequennt subcommand
@@ -210,6 +224,8 @@ enum VVSubCommand {
     Render(render::Args),
     #[clap(name = "metrics")]
     Metrics(metrics::Args),
+    #[clap(name = "image-metrics")]
+    ImageMetrics(image_metrics::Args),
     #[clap(name = "downsample")]
     Downsample(downsample::Args),
     #[clap(name = "upsample")]
@@ -224,6 +240,16 @@ enum VVSubCommand {
     Extend(extension::Args),
     #[clap(name = "normal")]
     NormalEstimation(normal_estimation::Args),
+    #[clap(name = "serve")]
+    Serve(serve::Args),
+    #[clap(name = "encode")]
+    Encode(encode::Args),
+    #[clap(name = "decode")]
+    Decode(decode::Args),
+    #[clap(name = "rd")]
+    Rd(rd::Args),
+    #[clap(name = "qoe")]
+    Qoe(qoe::Args),
 }
 
 fn display_main_help_msg() {
@@ -240,10 +266,16 @@ mod pipeline_mod_test {
         assert!(Pipeline::if_at_least_one_command("write"));
         assert!(Pipeline::if_at_least_one_command("render"));
         assert!(Pipeline::if_at_least_one_command("metrics"));
+        assert!(Pipeline::if_at_least_one_command("image-metrics"));
         assert!(Pipeline::if_at_least_one_command("downsample"));
         assert!(Pipeline::if_at_least_one_command("upsample"));
         assert!(Pipeline::if_at_least_one_command("lodify"));
         assert!(Pipeline::if_at_least_one_command("convert"));
+        assert!(Pipeline::if_at_least_one_command("serve"));
+        assert!(Pipeline::if_at_least_one_command("encode"));
+        assert!(Pipeline::if_at_least_one_command("decode"));
+        assert!(Pipeline::if_at_least_one_command("rd"));
+        assert!(Pipeline::if_at_least_one_command("qoe"));
         assert!(!Pipeline::if_at_least_one_command("not_a_command"));
     }
 }
@@ -1,282 +1,439 @@
 use super::Subcommand;
 use crate::pipeline::channel::Channel;
 use crate::pipeline::PipelineMessage;
+use anyhow::{anyhow, bail, Context, Result};
 use clap::Parser;
-use regex::Regex;
-use std::path::PathBuf;
+use serde::Deserialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
-use crate::pipeline::subcommands::read::FileType;
-use crate::utils::{find_all_files, read_file_to_point_cloud};
-use std::str::FromStr;
-
-use crate::abr::quetra::Quetra;
-use crate::abr::RateAdapter;
-
-#[derive(Debug, Copy, Clone, Eq, PartialEq, clap::ValueEnum)]
-enum DashAlgo {
-    Naive,
-    Quetra,
-}
-
-impl ToString for DashAlgo {
-    fn to_string(&self) -> String {
-        match self {
-            DashAlgo::Naive => "naive".to_string(),
-            DashAlgo::Quetra => "quetra".to_string(),
-        }
-    }
-}
-
-impl FromStr for DashAlgo {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "naive" => Ok(DashAlgo::Naive),
-            "quetra" => Ok(DashAlgo::Quetra),
-            _ => Err("unknown algorithm".to_string()),
-        }
-    }
-}
+use crate::abr::{DecodeTimeAware, RateAdapter};
+use crate::dash::parser::MPDParser;
+use crate::dash::ThroughputPrediction;
+use crate::formats::PointCloud;
+use crate::render::wgpu::camera::CameraPosition;
+use crate::simulation::NetworkTrace;
+use crate::utils::{
+    expand_directory, get_cosines, get_tile_cosines, predict_quality, read_file_to_point_cloud,
+};
+use crate::vvplay_async_prefetch::enums::{AbrType, ThroughputPredictionType};
 
 #[derive(Parser)]
 #[clap(
-    about = "Dash will simulate a varying network conditions, it reads in one of our supported file formats. \nFiles can be of the type .pcd .ply. \nThe path can be a file path or a directory path contains these files.",
-    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] <FILES>... +output=plys", "dash")
+    about = "Dash will simulate a varying network conditions. \nIt discovers the representations from an MPD or a JSON manifest, \nadapts the quality with the same ABR algorithms as vvplay_async and outputs the selected frames.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] <INPUT> <NETWORK_PATH> +output=plys", "dash")
 )]
 pub struct Args {
-    /// input directory with different quality of point clouds
-    input_path: PathBuf,
-    /// path to network settings
+    /// MPD (.mpd) or JSON manifest describing the representations. Segments must be local files.
+    input: PathBuf,
+    /// path to network trace in Kbps, one sample per line. Every segment download takes the next sample.
     network_path: PathBuf,
-    #[clap(short, long, default_value = "naive")]
-    algorithm: DashAlgo,
+    #[clap(long = "abr", value_enum, default_value_t = AbrType::Quetra)]
+    abr_type: AbrType,
+    #[clap(long = "tp", value_enum, default_value_t = ThroughputPredictionType::Last)]
+    throughput_prediction_type: ThroughputPredictionType,
+    /// Alpha for throughput prediction. Only used for EMA, GAEMA, and LPEMA
+    #[clap(long, default_value_t = 0.1)]
+    throughput_alpha: f64,
+    /// buffer capacity in seconds
+    #[clap(short, long, default_value_t = 11)]
+    buffer_capacity: u64,
+    /// Set this flag if each view is encoded separately, i.e. multiview. Only supported for MPDs.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    multiview: bool,
     #[clap(short, long)]
-    /// read previous n files after sorting lexicalgraphically
+    /// only simulate the first n segments
     num: Option<usize>,
-    #[clap(short = 't', long, value_enum, default_value_t = FileType::All)]
-    filetype: FileType,
+    /// write the adaptation decision of every segment to this CSV file
+    #[clap(long)]
+    decision_log: Option<PathBuf>,
 }
 
-pub struct Dash {
-    args: Args,
+/// A JSON manifest, for content that has no MPD. Every file in a representation's directory is one frame,
+/// in lexicographical order.
+///
+/// ```json
+/// {
+///     "fps": 30,
+///     "representations": [
+///         { "path": "R01", "bandwidth": 4641000 },
+///         { "path": "R02" }
+///     ]
+/// }
+/// ```
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default = "default_fps")]
+    fps: f64,
+    representations: Vec<ManifestRepresentation>,
 }
 
-impl Dash {
-    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
-        Box::from(Dash {
-            args: Args::parse_from(args),
+#[derive(Deserialize)]
+struct ManifestRepresentation {
+    /// directory with the frames, relative to the manifest
+    path: PathBuf,
+    /// in bits per second. If not given, it is estimated from the file sizes.
+    bandwidth: Option<u64>,
+}
+
+fn default_fps() -> f64 {
+    30.0
+}
+
+/// Where the representations of the simulated content come from.
+enum Source {
+    Mpd {
+        parser: MPDParser,
+        /// number of views if multiview
+        views: Option<u8>,
+    },
+    Manifest {
+        fps: f64,
+        /// files of every representation, one per frame
+        frames: Vec<Vec<PathBuf>>,
+        bitrates: Vec<u64>,
+    },
+}
+
+impl Source {
+    fn load(path: &Path, multiview: bool) -> Result<Self> {
+        let content =
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        if path.extension().and_then(|ext| ext.to_str()) == Some("mpd") {
+            let mut parser = MPDParser::new(&content)?;
+            parser.set_manifest_url(path.to_str().ok_or_else(|| anyhow!("invalid MPD path"))?);
+            let views = if multiview {
+                let views = parser.views(0, 0)?;
+                if views == 0 {
+                    bail!("multiview needs AdaptationSets with a viewId");
+                }
+                Some(views)
+            } else {
+                None
+            };
+            return Ok(Source::Mpd { parser, views });
+        }
+        if multiview {
+            bail!("multiview is only supported for MPDs");
+        }
+
+        let manifest: Manifest = serde_json::from_str(&content)
+            .with_context(|| format!("parsing manifest {}", path.display()))?;
+        let root = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut frames = vec![];
+        let mut bitrates = vec![];
+        for representation in &manifest.representations {
+            let dir = root.join(&representation.path);
+            if !dir.is_dir() {
+                bail!("representation directory {} does not exist", dir.display());
+            }
+            let mut files = expand_directory(&dir);
+            files.sort();
+            let bitrate = match representation.bandwidth {
+                Some(bandwidth) => bandwidth,
+                None => {
+                    let total_size: u64 = files
+                        .iter()
+                        .filter_map(|f| f.metadata().ok())
+                        .map(|m| m.len())
+                        .sum();
+                    (total_size as f64 * 8.0 * manifest.fps / files.len().max(1) as f64) as u64
+                }
+            };
+            frames.push(files);
+            bitrates.push(bitrate);
+        }
+        if frames.is_empty() {
+            bail!("manifest has no representations");
+        }
+        Ok(Source::Manifest {
+            fps: manifest.fps,
+            frames,
+            bitrates,
         })
     }
 
-    fn prepare_bandwidth(&self) -> Vec<f32> {
-        // reading network conditions
-        let network_content = std::fs::read_to_string(self.args.network_path.clone())
-            .expect("could not read network file");
-        // using f32 for bandwidth in KB/s
-        let mut bandwidth: Vec<f32> = Vec::new();
-        for line in network_content.lines() {
-            bandwidth.push(line.parse().unwrap());
+    fn fps(&self) -> f64 {
+        match self {
+            Source::Mpd { parser, .. } => parser.fps(),
+            Source::Manifest { fps, .. } => *fps,
         }
-        bandwidth
     }
 
-    fn main_process(&self) -> Vec<PathBuf> {
-        // adapt from vvdash.rs
-        let bandwidth = self.prepare_bandwidth();
-
-        let mut starting_frame_int: usize = 0;
-        let mut _frame_increment_int: usize = 0;
-        let mut count: usize = 0;
-        let mut total_frames: usize = 0;
-        let extension = "pcd";
+    /// number of frames per segment
+    fn segment_frames(&self) -> Result<u64> {
+        match self {
+            Source::Mpd { parser, .. } => parser.segment_frames(),
+            Source::Manifest { .. } => Ok(1),
+        }
+    }
 
-        let mut input_folder_r01 = self.args.input_path.clone();
-        input_folder_r01.push(format!("{}", "R01"));
-        let mut input_folder_r02 = self.args.input_path.clone();
-        input_folder_r02.push(format!("{}", "R02"));
-        let mut input_folder_r03 = self.args.input_path.clone();
-        input_folder_r03.push(format!("{}", "R03"));
-        let mut input_folder_r04 = self.args.input_path.clone();
-        input_folder_r04.push(format!("{}", "R04"));
-        let mut input_folder_r05 = self.args.input_path.clone();
-        input_folder_r05.push(format!("{}", "R05"));
-        // let mut input_folder: ReadDir;
-        let mut input_folder_pathbuf: &PathBuf;
+    fn total_frames(&self) -> u64 {
+        match self {
+            Source::Mpd { parser, .. } => parser.total_frames() as u64,
+            Source::Manifest { frames, .. } => {
+                frames.iter().map(|f| f.len()).min().unwrap_or_default() as u64
+            }
+        }
+    }
 
-        // longdress format: r1_longdress_dec_0000.ply
-        let mut entries = find_all_files(vec![input_folder_r05.clone().into_os_string()].as_ref());
-        entries.sort();
-        let re = Regex::new(r"(.{2})_(.{9})_(.{3})_(\d{4}).pcd").unwrap();
-        let first_entry_filename = entries[0].as_path().to_str().unwrap();
-        let first_entry_filename_short = &first_entry_filename
-            [(input_folder_r05.as_path().to_str().unwrap().chars().count() + 1)..]; // + 1 for the slash /
-        assert!(re.is_match(first_entry_filename_short)); // panics if file name not a match, able to input regex as CLI params?
+    fn views(&self) -> Vec<Option<u8>> {
+        match self {
+            Source::Mpd {
+                views: Some(views), ..
+            } => (0..*views).map(Some).collect(),
+            _ => vec![None],
+        }
+    }
 
-        // S25C2AIR05_F30_rec_0536.pcd -> [R05] [F30] [0536] information needed for decoding are retrieved from file name
-        for cap in re.captures_iter(first_entry_filename_short) {
-            let starting_frame = &cap[4].to_owned();
-            // frame_count is 'F30', substring
-            _frame_increment_int = 1;
-            starting_frame_int = starting_frame.parse().unwrap();
-            total_frames = entries.len() * _frame_increment_int;
+    /// available bitrates of the segment starting at `frame`, for every view
+    fn bitrates(&self, frame: u64) -> Result<Vec<Vec<u64>>> {
+        match self {
+            Source::Mpd { parser, .. } => self
+                .views()
+                .into_iter()
+                .map(|view| parser.available_bitrates(0, frame, view))
+                .collect(),
+            Source::Manifest { bitrates, .. } => Ok(vec![bitrates.clone()]),
         }
+    }
 
-        let available_bitrates = vec![vec![4641, 7975, 14050, 25974, 46778]];
-        let start_no = starting_frame_int;
+    /// cosines from the camera to every view, per tile if the MPD gives the bounds of the views
+    fn cosines(&self, frame: u64, camera: CameraPosition) -> Vec<f32> {
+        match self {
+            Source::Mpd {
+                parser,
+                views: Some(views),
+            } => match parser.view_bounds(0, frame, *views) {
+                Ok(Some(tiles)) => get_tile_cosines(camera, &tiles),
+                _ if *views == 6 => get_cosines(camera),
+                _ => vec![1.0; *views as usize],
+            },
+            _ => get_cosines(camera),
+        }
+    }
 
-        let mut in_frame_name_buf = Vec::new();
-        match self.args.algorithm {
-            DashAlgo::Naive => {
-                while count < total_frames {
-                    let rate_prefix: &str;
-                    // buffer-based approach used for rate adaptation, appropriate lower and higher reservoir
-                    // needed in order to avoid overflow and underflow
-                    let mut bandwidth_buf: f32 = 0.0;
-                    // for i in count..count + frame_increment_int {}
-                    bandwidth_buf += bandwidth[count / 30];
+    /// predicted quality of every representation, as used by the multiview algorithms
+    fn qualities(&self) -> Vec<f32> {
+        match self {
+            Source::Mpd { parser, .. } => parser
+                .get_qp()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|qp| match qp {
+                    (Some(geo_qp), Some(attr_qp)) => predict_quality(geo_qp as f32, attr_qp as f32),
+                    _ => 0.0,
+                })
+                .collect(),
+            Source::Manifest { bitrates, .. } => (1..=bitrates.len()).map(|q| q as f32).collect(),
+        }
+    }
 
-                    // for simulation purposes, use the .bin file sizes as benchmark for values (naive algo)
-                    // values used for longdress, R01 to R05
-                    if bandwidth_buf < available_bitrates[0][0] as f32 {
-                        input_folder_pathbuf = &input_folder_r01;
-                        rate_prefix = "r1";
-                    } else if bandwidth_buf < available_bitrates[0][1] as f32 {
-                        input_folder_pathbuf = &input_folder_r02;
-                        rate_prefix = "r2";
-                    } else if bandwidth_buf < available_bitrates[0][2] as f32 {
-                        input_folder_pathbuf = &input_folder_r03;
-                        rate_prefix = "r3";
-                    } else if bandwidth_buf < available_bitrates[0][3] as f32 {
-                        input_folder_pathbuf = &input_folder_r04;
-                        rate_prefix = "r4";
-                    } else {
-                        input_folder_pathbuf = &input_folder_r05;
-                        rate_prefix = "r5";
+    /// files of the segment starting at `frame`, for every view
+    fn files(&self, frame: u64, quality: &[usize]) -> Result<Vec<PathBuf>> {
+        match self {
+            Source::Mpd { parser, .. } => self
+                .views()
+                .into_iter()
+                .enumerate()
+                .map(|(i, view)| {
+                    let q = quality[i.min(quality.len() - 1)];
+                    let (url, _) = parser.get_info(0, q as u8, frame, view)?;
+                    if url.contains("://") {
+                        bail!("only local segments are supported, got {}", url);
                     }
+                    Ok(PathBuf::from(url))
+                })
+                .collect(),
+            Source::Manifest { frames, .. } => frames
+                .get(quality[0])
+                .and_then(|files| files.get(frame as usize))
+                .map(|file| vec![file.clone()])
+                .ok_or_else(|| anyhow!("no frame {} in representation {}", frame, quality[0])),
+        }
+    }
+}
 
-                    // longdress format: r1_longdress_dec_0000.ply
-                    for i in count..count + 30 {
-                        let in_frame_name = format!(
-                            "{}_longdress_dec_{}.{}",
-                            rate_prefix,
-                            format!("{:0>4}", i + start_no),
-                            extension
-                        );
+/// The adaptation decision for one segment.
+struct Decision {
+    segment: usize,
+    frame: u64,
+    quality: Vec<usize>,
+    /// total bitrate of the selected representations in bps
+    bitrate: u64,
+    /// throughput predicted before the download, in bps
+    predicted_throughput: f64,
+    /// throughput of the simulated network during the download, in bps
+    throughput: f64,
+    download_time: f64,
+    /// buffer level in seconds after the download
+    buffer: f64,
+    stall: f64,
+}
 
-                        // let out_frame_name = format!("out_{}_{}.{}", format!("{:0>4}", i), quality, extension);
-                        let mut input_frame = input_folder_pathbuf.clone();
-                        input_frame.push(in_frame_name);
-                        in_frame_name_buf.push(input_frame);
-                    }
-                    count += 30;
-                }
-                in_frame_name_buf
-            }
-            DashAlgo::Quetra => {
-                let mut buffer_status: Vec<u64> = Vec::new();
-                let mut quality_selected: Vec<u64> = Vec::new();
-                // buffer capacity set to 10 seconds, fps 30
-                let quetra = Quetra::new(10, 30.0);
+/// Simulates downloading segments over the network trace, with the same ABR and throughput prediction as the player.
+struct Simulation {
+    abr: DecodeTimeAware,
+    throughput_predictor: Box<dyn ThroughputPrediction>,
+    network: NetworkTrace,
+    fps: f64,
+    segment_duration: f64,
+    /// in seconds
+    buffer_capacity: f64,
+    /// in seconds
+    buffer: f64,
+}
 
-                let mut buffer_occupancy = 0;
-                let mut network_throughput;
+impl Simulation {
+    fn step(
+        &mut self,
+        segment: usize,
+        frame: u64,
+        available_bitrates: &[Vec<u64>],
+        cosines: &[f32],
+    ) -> Decision {
+        // the player starts with a guess of 1Mbps as well
+        let predicted_throughput = self.throughput_predictor.predict().unwrap_or(1_000_000.0);
+        let quality = self.abr.select_quality(
+            (self.buffer * self.fps) as u64,
+            predicted_throughput,
+            available_bitrates,
+            cosines,
+        );
+        let bitrate: u64 = available_bitrates
+            .iter()
+            .enumerate()
+            .map(|(view, bitrates)| bitrates[quality[view.min(quality.len() - 1)]])
+            .sum();
 
-                let cosines = vec![];
+        // network trace is given in Kbps
+        let throughput = self.network.next().max(1.0) * 1024.0;
+        let download_time = bitrate as f64 * self.segment_duration / throughput;
+        let stall = (download_time - self.buffer).max(0.0);
+        self.buffer = ((self.buffer - download_time).max(0.0) + self.segment_duration)
+            .min(self.buffer_capacity.max(self.segment_duration));
+        self.throughput_predictor.add(throughput);
 
-                while count < total_frames {
-                    let rate_prefix: &str;
-                    network_throughput = (bandwidth[count]) as f64;
-                    let quality = quetra.select_quality(
-                        buffer_occupancy,
-                        network_throughput,
-                        &available_bitrates,
-                        &cosines,
-                    );
-                    // dbg!(network_throughput, quality[0]);
+        Decision {
+            segment,
+            frame,
+            quality,
+            bitrate,
+            predicted_throughput,
+            throughput,
+            download_time,
+            buffer: self.buffer,
+            stall,
+        }
+    }
+}
 
-                    // fill buffer based on the downloaded segment duration
-                    let download_bitrate = available_bitrates[0][quality[0]] as f64;
-                    let no_of_frames: usize = (network_throughput / download_bitrate) as usize;
-                    buffer_occupancy = (no_of_frames) as u64;
-                    buffer_status.push(buffer_occupancy);
+pub struct Dash {
+    args: Args,
+}
 
-                    if quality[0] == 0 {
-                        input_folder_pathbuf = &input_folder_r01;
-                        rate_prefix = "r1";
-                        quality_selected.push(1);
-                    } else if quality[0] == 1 {
-                        input_folder_pathbuf = &input_folder_r02;
-                        rate_prefix = "r2";
-                        quality_selected.push(2);
-                    } else if quality[0] == 2 {
-                        input_folder_pathbuf = &input_folder_r03;
-                        rate_prefix = "r3";
-                        quality_selected.push(3);
-                    } else if quality[0] == 3 {
-                        input_folder_pathbuf = &input_folder_r04;
-                        rate_prefix = "r4";
-                        quality_selected.push(4);
-                    } else {
-                        input_folder_pathbuf = &input_folder_r05;
-                        rate_prefix = "r5";
-                        quality_selected.push(5);
-                    }
+impl Dash {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        Box::from(Dash {
+            args: Args::parse_from(args),
+        })
+    }
 
-                    // longdress format: r1_longdress_dec_0000.ply
-                    let in_frame_name = format!(
-                        "{}_longdress_dec_{}.{}",
-                        rate_prefix,
-                        format!("{:0>4}", count + start_no),
-                        extension
-                    );
+    fn simulate(&self, channel: &Channel) -> Result<()> {
+        let source = Source::load(&self.args.input, self.args.multiview)?;
+        let fps = source.fps();
+        let segment_frames = source.segment_frames()?;
+        let buffer_capacity = self.args.buffer_capacity * fps as u64;
+        let mut simulation = Simulation {
+            abr: self.args.abr_type.build(
+                buffer_capacity,
+                fps as f32,
+                source.views().len(),
+                source.qualities(),
+            ),
+            throughput_predictor: self
+                .args
+                .throughput_prediction_type
+                .build(self.args.throughput_alpha),
+            network: NetworkTrace::new(&self.args.network_path),
+            fps,
+            segment_duration: segment_frames as f64 / fps,
+            buffer_capacity: self.args.buffer_capacity as f64,
+            buffer: 0.0,
+        };
+        // the camera of the player when it is not moved
+        let camera = CameraPosition {
+            position: cgmath::Point3::new(0.0, 0.0, 1.5),
+            yaw: cgmath::Deg(-90.0).into(),
+            pitch: cgmath::Deg(0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+        };
 
-                    let mut input_frame = input_folder_pathbuf.clone();
-                    input_frame.push(&in_frame_name);
-                    count += 1;
-                    in_frame_name_buf.push(input_frame);
-                }
+        let mut log = match self.args.decision_log.as_ref() {
+            Some(path) => {
+                let mut file = std::io::BufWriter::new(
+                    std::fs::File::create(path)
+                        .with_context(|| format!("creating {}", path.display()))?,
+                );
+                writeln!(file, "segment,frame,quality,bitrate,predicted_throughput,throughput,download_time,buffer,stall")?;
+                Some(file)
+            }
+            None => None,
+        };
+
+        let segments = (0..source.total_frames())
+            .step_by(segment_frames as usize)
+            .take(self.args.num.unwrap_or(usize::MAX));
+        for (i, frame) in segments.enumerate() {
+            let available_bitrates = source.bitrates(frame)?;
+            let cosines = source.cosines(frame, camera);
+            let decision = simulation.step(i, frame, &available_bitrates, &cosines);
+            if let Some(log) = log.as_mut() {
+                let quality = decision
+                    .quality
+                    .iter()
+                    .map(|q| q.to_string())
+                    .collect::<Vec<_>>()
+                    .join(";");
+                writeln!(
+                    log,
+                    "{},{},{},{},{:.0},{:.0},{:.4},{:.4},{:.4}",
+                    decision.segment,
+                    decision.frame,
+                    quality,
+                    decision.bitrate,
+                    decision.predicted_throughput,
+                    decision.throughput,
+                    decision.download_time,
+                    decision.buffer,
+                    decision.stall
+                )?;
+            }
 
-                in_frame_name_buf
+            // the views are merged into a single point cloud
+            let mut points = vec![];
+            for file in source.files(frame, &decision.quality)? {
+                let pc = read_file_to_point_cloud(&file)
+                    .ok_or_else(|| anyhow!("failed to read {}", file.display()))?;
+                points.extend(pc.points);
             }
+            channel.send(PipelineMessage::IndexedPointCloud(
+                PointCloud::new(points.len(), points),
+                i as u32,
+            ));
         }
+        if let Some(mut log) = log {
+            log.flush()?;
+        }
+        Ok(())
     }
 }
 
 impl Subcommand for Dash {
     fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
         if messages.is_empty() {
-            let mut in_frame_name_buf = self.main_process();
-            if let Some(num) = self.args.num {
-                if num < in_frame_name_buf.len() {
-                    in_frame_name_buf = in_frame_name_buf.into_iter().take(num).collect();
-                }
-            }
-
-            for (i, file) in in_frame_name_buf.iter().enumerate() {
-                match &self.args.filetype {
-                    FileType::All => {}
-                    FileType::Pcd => {
-                        if file.extension().and_then(|ext| ext.to_str()) != Some("pcd") {
-                            continue;
-                        }
-                    }
-                    FileType::Ply => {
-                        if file.extension().and_then(|ext| ext.to_str()) != Some("ply") {
-                            continue;
-                        }
-                    }
-                    FileType::Bin => {
-                        if file.extension().and_then(|ext| ext.to_str()) != Some("bin") {
-                            continue;
-                        }
-                    }
-                }
-
-                let point_cloud = read_file_to_point_cloud(file);
-                if let Some(pc) = point_cloud {
-                    channel.send(PipelineMessage::IndexedPointCloud(pc, i as u32));
-                }
+            if let Err(e) = self.simulate(channel) {
+                eprintln!("Error: {e:#}");
             }
             channel.send(PipelineMessage::End);
         } else {
@@ -286,3 +443,32 @@ impl Subcommand for Dash {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulation_buffer() {
+        let mut simulation = Simulation {
+            abr: AbrType::Quetra.build(30, 30.0, 1, vec![]),
+            throughput_predictor: ThroughputPredictionType::Last.build(0.1),
+            network: NetworkTrace::from_samples(vec![100.0]),
+            fps: 30.0,
+            segment_duration: 1.0,
+            buffer_capacity: 2.0,
+            buffer: 0.0,
+        };
+        let bitrates = vec![vec![51200, 1_000_000]];
+        // 1Mbps is predicted at first, but only 100Kbps is available
+        let first = simulation.step(0, 0, &bitrates, &[]);
+        assert_eq!(first.bitrate, bitrates[0][first.quality[0]]);
+        assert_eq!(first.throughput, 102400.0);
+        assert_eq!(first.stall, first.download_time);
+        // the next decision is based on the measured throughput
+        let second = simulation.step(1, 30, &bitrates, &[]);
+        assert_eq!(second.predicted_throughput, 102400.0);
+        assert!((second.download_time - second.bitrate as f64 / 102400.0).abs() < 1e-9);
+        assert!(second.buffer <= 2.0);
+    }
+}
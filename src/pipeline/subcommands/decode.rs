@@ -0,0 +1,72 @@
+use clap::Parser;
+use std::ffi::OsString;
+
+use super::encode::CodecArgs;
+use super::Subcommand;
+use crate::codec::{decode_segment, Bitstream};
+use crate::pipeline::channel::Channel;
+use crate::pipeline::PipelineMessage;
+use crate::utils::find_all_files;
+
+#[derive(Parser)]
+#[clap(
+    about = "Decodes the files written by vv encode, .vvo and .vvs by default. \nThe path can be a file path or a directory path contains these files.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] <FILES>... +output=plys", "decode")
+)]
+pub struct Args {
+    /// Files, glob patterns, directories
+    files: Vec<OsString>,
+
+    #[clap(flatten)]
+    codec: CodecArgs,
+
+    #[clap(short, long)]
+    /// decode the first n files after sorting lexicographically
+    num: Option<usize>,
+}
+
+pub struct Decode {
+    args: Args,
+}
+
+impl Decode {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        Box::from(Decode {
+            args: Args::parse_from(args),
+        })
+    }
+}
+
+impl Subcommand for Decode {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+        if messages.is_empty() {
+            let mut files = find_all_files(&self.args.files);
+            files.retain(|file| self.args.codec.is_bitstream(file));
+            files.sort();
+            if let Some(num) = self.args.num {
+                files.truncate(num);
+            }
+
+            // a file holds several frames if it is a sequence
+            let decoder = self.args.codec.decoder();
+            let mut i = 0;
+            for file in &files {
+                let mut frames = decode_segment(decoder.clone(), vec![Bitstream::from_file(file)]);
+                while let Some(frame) = frames.blocking_recv() {
+                    match frame {
+                        Ok(frame) => {
+                            channel.send(PipelineMessage::IndexedPointCloud(frame.pc, i));
+                            i += 1;
+                        }
+                        Err(e) => eprintln!("{e:#}"),
+                    }
+                }
+            }
+            channel.send(PipelineMessage::End);
+        } else {
+            for message in messages {
+                channel.send(message);
+            }
+        }
+    }
+}
@@ -48,7 +48,7 @@ impl Subcommand for Downsampler {
                 | PipelineMessage::IndexedPointCloudWithTriangleFaces(_, _, _)
                 | PipelineMessage::IndexedPointCloudNormal(_, _)
                 | PipelineMessage::IndexedPointCloudWithName(_, _, _, _)
-                | PipelineMessage::MetaData(_, _, _, _)
+                | PipelineMessage::MetaData(_, _, _, _, _)
                 | PipelineMessage::DummyForIncrement => {}
                 PipelineMessage::End => {
                     channel.send(message);
@@ -0,0 +1,533 @@
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::Subcommand;
+use crate::codec::decoder::{DracoDecoder, ExternalDecoder, OctreeDecoder};
+use crate::codec::encoder::{DracoEncoder, ExternalEncoder, OctreeEncoder};
+use crate::codec::octree::{self, OctreeParams, MAX_DEPTH, MAX_QP};
+use crate::codec::{decode_blocking, sequence, Bitstream, Decoder, Encoder};
+use crate::dash::parser::MPDParser;
+use crate::formats::pointxyzrgba::PointXyzRgba;
+use crate::formats::PointCloud;
+use crate::pipeline::channel::Channel;
+use crate::pipeline::PipelineMessage;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// the native octree codec
+    Octree,
+    /// draco_encoder and draco_decoder
+    Draco,
+    /// any codec, run through --encode-cmd and --decode-cmd
+    External,
+}
+
+/// Codec options shared by `vv encode` and `vv decode`.
+#[derive(clap::Args)]
+pub struct CodecArgs {
+    #[clap(long, value_enum, default_value_t = Codec::Octree)]
+    codec: Codec,
+    /// path to the draco_decoder binary (only for draco)
+    #[clap(long, default_value = "draco_decoder")]
+    decoder_path: PathBuf,
+    /// decode command of the external codec, e.g. "my_decoder -i {input} -o {output}".
+    /// {output} must be written as ply
+    #[clap(long, required_if_eq("codec", "external"))]
+    decode_cmd: Option<String>,
+    /// file extension of the bitstreams of the external codec
+    #[clap(long, default_value = "bin")]
+    extension: String,
+}
+
+impl CodecArgs {
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// File extension of the bitstreams.
+    pub fn extension(&self) -> &str {
+        match self.codec {
+            Codec::Octree => octree::EXTENSION,
+            Codec::Draco => "drc",
+            Codec::External => &self.extension,
+        }
+    }
+
+    /// Whether `path` is a bitstream of the codec. The octree codec also writes sequences.
+    pub fn is_bitstream(&self, path: &Path) -> bool {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        extension == Some(self.extension())
+            || (self.codec == Codec::Octree && extension == Some(sequence::EXTENSION))
+    }
+
+    pub fn decoder(&self) -> Arc<dyn Decoder> {
+        match self.codec {
+            Codec::Octree => Arc::new(OctreeDecoder),
+            Codec::Draco => Arc::new(DracoDecoder::new(&self.decoder_path)),
+            Codec::External => Arc::new(ExternalDecoder::new(
+                self.decode_cmd
+                    .as_ref()
+                    .expect("clap requires --decode-cmd for the external codec"),
+            )),
+        }
+    }
+}
+
+/// Encoder options shared by `vv encode` and `vv rd`.
+#[derive(clap::Args)]
+pub struct EncoderArgs {
+    /// path to the draco_encoder binary (only for draco)
+    #[clap(long, default_value = "draco_encoder")]
+    encoder_path: PathBuf,
+    /// encode command of the external codec, e.g. "my_encoder -q {geometry_qp} -i {input} -o {output}".
+    /// {input} is a ply file
+    #[clap(long, required_if_eq("codec", "external"))]
+    encode_cmd: Option<String>,
+}
+
+impl EncoderArgs {
+    pub fn encoder(&self, codec: &CodecArgs, params: &EncoderParams) -> Box<dyn Encoder> {
+        match codec.codec {
+            Codec::Octree => Box::new(OctreeEncoder::new(params.octree)),
+            Codec::Draco => {
+                let mut encoder = DracoEncoder::new(&self.encoder_path);
+                if let Some(bits) = params.quantization_bits {
+                    encoder.set_quantization_bits(bits);
+                }
+                if let Some(level) = params.compression_level {
+                    encoder.set_compression_level(level);
+                }
+                Box::new(encoder)
+            }
+            Codec::External => {
+                let mut encoder = ExternalEncoder::new(
+                    self.encode_cmd
+                        .as_ref()
+                        .expect("clap requires --encode-cmd for the external codec"),
+                    codec.extension(),
+                );
+                for (name, value) in &params.vars {
+                    encoder.set_var(name, value);
+                }
+                Box::new(encoder)
+            }
+        }
+    }
+}
+
+/// One configuration of an encoder, e.g. one point of a rate-distortion sweep.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EncoderParams {
+    pub octree: OctreeParams,
+    pub quantization_bits: Option<u8>,
+    pub compression_level: Option<u8>,
+    /// variables of the external command templates
+    pub vars: Vec<(String, String)>,
+}
+
+impl FromStr for EncoderParams {
+    type Err = String;
+
+    /// Parses `name=value` pairs separated by commas, e.g. `geometry_qp=10,attribute_qp=16`.
+    /// `depth`, `geometry_qp`, `attribute_qp`, `quantization_bits` and `compression_level` set the
+    /// parameters of the built-in encoders. Every pair is also a variable of the command templates.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut params = EncoderParams::default();
+        for pair in s.split(',').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair
+                .split_once('=')
+                .ok_or(format!("expected name=value, got {pair}"))?;
+            let (name, value) = (name.trim(), value.trim());
+            let number = || {
+                value
+                    .parse::<u8>()
+                    .map_err(|e| format!("invalid {name} {value}: {e}"))
+            };
+            match name {
+                "depth" => params.octree.depth = number()?,
+                "geometry_qp" => params.octree.geometry_qp = number()?,
+                "attribute_qp" => params.octree.attribute_qp = number()?,
+                "quantization_bits" => params.quantization_bits = Some(number()?),
+                "compression_level" => params.compression_level = Some(number()?),
+                _ => {}
+            }
+            params.vars.push((name.to_string(), value.to_string()));
+        }
+        Ok(params)
+    }
+}
+
+impl std::fmt::Display for EncoderParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pairs: Vec<String> = self
+            .vars
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect();
+        write!(f, "{}", pairs.join(","))
+    }
+}
+
+#[derive(Parser)]
+#[clap(
+    about = "Encodes point clouds into files, with the native octree codec, draco or an external command.\nForwards the decoded point clouds, so that the coding distortion can be measured downstream.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] <output_dir> +input=plys", "encode")
+)]
+pub struct Args {
+    /// output directory for the encoded frames
+    output_dir: PathBuf,
+    #[clap(flatten)]
+    codec: CodecArgs,
+    /// precision of the voxel grid, in bits per axis (only for octree)
+    #[clap(long, default_value_t = 10, value_parser = clap::value_parser!(u8).range(1..=MAX_DEPTH as i64))]
+    depth: u8,
+    /// geometry QP. 4 keeps the voxel grid, every +6 doubles the voxel size.
+    /// Passed to the external codec as {geometry_qp}
+    #[clap(short, long, default_value_t = 4, value_parser = clap::value_parser!(u8).range(0..=MAX_QP as i64))]
+    geometry_qp: u8,
+    /// colour QP. 4 and below is lossless, every +6 doubles the quantisation step.
+    /// Passed to the external codec as {attribute_qp}
+    #[clap(short, long, default_value_t = 4, value_parser = clap::value_parser!(u8).range(0..=MAX_QP as i64))]
+    attribute_qp: u8,
+    #[clap(flatten)]
+    encoder: EncoderArgs,
+    /// quantisation bits of the positions (only for draco)
+    #[clap(long)]
+    quantization_bits: Option<u8>,
+    /// compression level between 0 and 10 (only for draco)
+    #[clap(long, value_parser = clap::value_parser!(u8).range(0..=10))]
+    compression_level: Option<u8>,
+    /// frames per group of pictures. Every GOP is encoded into one .vvs file, with the frames
+    /// after the first predicted from the previous one (only for octree)
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    gop: Option<u32>,
+    /// MPD whose segment duration sets the GOP, so that every .vvs file is one DASH segment.
+    /// The segments are written to the URLs of --representation, relative to <output_dir>
+    /// (only for octree)
+    #[clap(long, conflicts_with = "gop")]
+    mpd: Option<PathBuf>,
+    /// id of the representation of the first object of --mpd the segments belong to
+    #[clap(long, default_value_t = 0, requires = "mpd")]
+    representation: u8,
+    /// number of the first .vvs file (only for --gop)
+    #[clap(long, default_value_t = 1, conflicts_with = "mpd")]
+    start_number: u32,
+    #[clap(long, default_value_t = 5)]
+    name_length: usize,
+}
+
+pub struct Encode {
+    args: Args,
+    encoder: Box<dyn Encoder>,
+    /// frames per sequence, if the frames are encoded as sequences
+    gop: Option<u32>,
+    octree: OctreeParams,
+    /// names the sequences after the segments of the MPD
+    mpd: Option<MPDParser>,
+    /// frames of the sequence being collected
+    pending: Vec<(PointCloud<PointXyzRgba>, u32)>,
+}
+
+impl Encode {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        match Encode::new(Args::parse_from(args)) {
+            Ok(encode) => Box::new(encode),
+            Err(e) => {
+                eprintln!("Error: {e:#}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn new(args: Args) -> Result<Self> {
+        if (args.gop.is_some() || args.mpd.is_some()) && args.codec.codec() != Codec::Octree {
+            bail!("--gop and --mpd are only supported by the octree codec");
+        }
+        std::fs::create_dir_all(&args.output_dir).with_context(|| {
+            format!(
+                "failed to create the output directory {}",
+                args.output_dir.display()
+            )
+        })?;
+        let params = EncoderParams {
+            octree: OctreeParams {
+                depth: args.depth,
+                geometry_qp: args.geometry_qp,
+                attribute_qp: args.attribute_qp,
+            },
+            quantization_bits: args.quantization_bits,
+            compression_level: args.compression_level,
+            vars: vec![
+                ("geometry_qp".to_string(), args.geometry_qp.to_string()),
+                ("attribute_qp".to_string(), args.attribute_qp.to_string()),
+            ],
+        };
+        let encoder = args.encoder.encoder(&args.codec, &params);
+        let mpd = match args.mpd.as_ref() {
+            Some(path) => {
+                let xml = std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                let parser = MPDParser::new(&xml).with_context(|| path.display().to_string())?;
+                parser
+                    .get_info(0, args.representation, 0, None)
+                    .with_context(|| {
+                        format!(
+                            "no representation {} in {}",
+                            args.representation,
+                            path.display()
+                        )
+                    })?;
+                Some(parser)
+            }
+            None => None,
+        };
+        let gop = match (args.gop, mpd.as_ref()) {
+            (Some(gop), _) => Some(gop),
+            (None, Some(mpd)) => Some(
+                mpd.segment_frames()
+                    .context("the MPD has no segment duration")? as u32,
+            ),
+            (None, None) => None,
+        };
+        Ok(Encode {
+            args,
+            encoder,
+            gop,
+            octree: params.octree,
+            mpd,
+            pending: vec![],
+        })
+    }
+
+    /// Encodes a frame to `<output_dir>/<i>.<extension>` and returns the decoded frame.
+    fn encode(
+        &mut self,
+        pc: &PointCloud<PointXyzRgba>,
+        i: u32,
+    ) -> Option<PointCloud<PointXyzRgba>> {
+        let file_name = format!(
+            "{:0width$}.{}",
+            i,
+            self.encoder.extension(),
+            width = self.args.name_length
+        );
+        let output_file = Path::new(&self.args.output_dir).join(file_name);
+        let data = match self.encoder.encode(pc) {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("Failed to encode frame {i}\n{e}");
+                return None;
+            }
+        };
+        if let Err(e) = std::fs::write(&output_file, &data) {
+            eprintln!("Failed to write {:?}\n{e}", output_file);
+            return None;
+        }
+
+        let bitstream = Bitstream::from_bytes(&output_file, data);
+        match decode_blocking(self.args.codec.decoder(), vec![bitstream]) {
+            Ok(frames) => frames.into_iter().next(),
+            Err(e) => {
+                eprintln!("Failed to decode {:?}\n{e}", output_file);
+                None
+            }
+        }
+    }
+
+    /// Encodes a frame on its own or, with a GOP, collects it into the current sequence.
+    fn push(&mut self, pc: PointCloud<PointXyzRgba>, i: u32, channel: &Channel) {
+        let Some(gop) = self.gop else {
+            if let Some(decoded) = self.encode(&pc, i) {
+                channel.send(PipelineMessage::IndexedPointCloud(decoded, i));
+            }
+            return;
+        };
+        if self
+            .pending
+            .first()
+            .is_some_and(|(_, first)| first / gop != i / gop)
+        {
+            self.encode_sequence(channel);
+        }
+        self.pending.push((pc, i));
+        if (i + 1) % gop == 0 {
+            self.encode_sequence(channel);
+        }
+    }
+
+    /// Path of the sequence starting at frame `first`: the URL of its segment in the MPD, or
+    /// `<output_dir>/<n>.vvs`, where `n` counts the GOPs from `--start-number`.
+    fn sequence_path(&self, first: u32, gop: u32) -> anyhow::Result<PathBuf> {
+        let Some(mpd) = &self.mpd else {
+            let file_name = format!(
+                "{:0width$}.{}",
+                first / gop + self.args.start_number,
+                sequence::EXTENSION,
+                width = self.args.name_length
+            );
+            return Ok(self.args.output_dir.join(file_name));
+        };
+        let (url, _) = mpd.get_info(0, self.args.representation, first as u64, None)?;
+        if url.contains("://") || url.starts_with('/') {
+            anyhow::bail!("segment URL {url} is not relative to the MPD");
+        }
+        Ok(self.args.output_dir.join(url))
+    }
+
+    /// Encodes the collected frames to [`Self::sequence_path`] and sends the decoded frames.
+    fn encode_sequence(&mut self, channel: &Channel) {
+        let (frames, indices): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.pending).into_iter().unzip();
+        let (Some(gop), Some(&first)) = (self.gop, indices.first()) else {
+            return;
+        };
+        let output_file = match self.sequence_path(first, gop) {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!("No segment for frame {first}\n{e:#}");
+                return;
+            }
+        };
+        let data = match sequence::encode(&frames, &self.octree) {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("Failed to encode {:?}\n{e}", output_file);
+                return;
+            }
+        };
+        if let Some(parent) = output_file.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                eprintln!("Failed to create {:?}\n{e}", parent);
+                return;
+            }
+        }
+        if let Err(e) = std::fs::write(&output_file, &data) {
+            eprintln!("Failed to write {:?}\n{e}", output_file);
+            return;
+        }
+
+        let bitstream = Bitstream::from_bytes(&output_file, data);
+        match decode_blocking(Arc::new(OctreeDecoder), vec![bitstream]) {
+            Ok(decoded) => {
+                for (pc, i) in decoded.into_iter().zip(indices) {
+                    channel.send(PipelineMessage::IndexedPointCloud(pc, i));
+                }
+            }
+            Err(e) => eprintln!("Failed to decode {:?}\n{e}", output_file),
+        }
+    }
+}
+
+impl Subcommand for Encode {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+        for message in messages {
+            match message {
+                PipelineMessage::IndexedPointCloud(pc, i) => self.push(pc, i, channel),
+                PipelineMessage::SubcommandMessage(subcommand_object, i) => {
+                    self.push(subcommand_object.get_content().clone(), i, channel)
+                }
+                PipelineMessage::Metrics(_)
+                | PipelineMessage::IndexedPointCloudWithTriangleFaces(_, _, _)
+                | PipelineMessage::IndexedPointCloudNormal(_, _)
+                | PipelineMessage::IndexedPointCloudWithName(_, _, _, _)
+                | PipelineMessage::MetaData(_, _, _, _, _)
+                | PipelineMessage::DummyForIncrement => {}
+                PipelineMessage::End => {
+                    self.encode_sequence(channel);
+                    channel.send(message);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = dir.path().to_str().unwrap();
+        let error = |args: &[&str]| {
+            let args = Args::parse_from([&["encode", output_dir][..], args].concat());
+            format!("{:#}", Encode::new(args).err().unwrap())
+        };
+        assert_eq!(
+            error(&["--codec", "draco", "--gop", "2"]),
+            "--gop and --mpd are only supported by the octree codec"
+        );
+        let missing = dir.path().join("missing.mpd");
+        assert!(error(&["--mpd", missing.to_str().unwrap()]).starts_with("failed to read"));
+    }
+
+    #[test]
+    fn test_encode_mpd_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        // 3 frames per segment, numbered by their first frame from 1051
+        let mpd = r#"<?xml version="1.0" encoding="UTF-8"?>
+<MPD type="static">
+    <Period id="0" duration="PT1S">
+        <AdaptationSet id="0" srcObjectId="0" frameRate="30">
+            <SegmentTemplate media="$RepresentationID$/seg_$Number%05d$.vvs" duration="3" timescale="30" startNumber="1051"></SegmentTemplate>
+            <Representation id="0" bandwidth="24000"></Representation>
+            <Representation id="1" bandwidth="48000"></Representation>
+        </AdaptationSet>
+    </Period>
+</MPD>"#;
+        let mpd_path = dir.path().join("test.mpd");
+        std::fs::write(&mpd_path, mpd).unwrap();
+        let output_dir = dir.path().to_str().unwrap().to_string();
+        let mut encode = Encode::from_args(
+            [
+                "encode",
+                &output_dir,
+                "--mpd",
+                mpd_path.to_str().unwrap(),
+                "--representation",
+                "1",
+            ]
+            .map(String::from)
+            .to_vec(),
+        );
+
+        let (progress_tx, _progress_rx) = crossbeam_channel::unbounded();
+        let mut channel = Channel::new(progress_tx);
+        let decoded = channel.subscribe();
+        let frame = |i: u32| {
+            let points: Vec<PointXyzRgba> = (0..10)
+                .map(|j| PointXyzRgba {
+                    x: j as f32,
+                    y: i as f32,
+                    z: 0.0,
+                    r: 255,
+                    g: 0,
+                    b: 0,
+                    a: 255,
+                })
+                .collect();
+            PipelineMessage::IndexedPointCloud(PointCloud::new(points.len(), points), i)
+        };
+        let mut messages: Vec<_> = (0..7).map(frame).collect();
+        messages.push(PipelineMessage::End);
+        std::thread::spawn(move || while decoded.recv().is_ok() {});
+        encode.handle(messages, &channel);
+
+        let parser = MPDParser::new(mpd).unwrap();
+        for (first, frames) in [(0, 3), (3, 3), (6, 1)] {
+            let (url, _) = parser.get_info(0, 1, first, None).unwrap();
+            let path = dir.path().join(&url);
+            let data = std::fs::read(&path).unwrap_or_else(|_| panic!("{url} was not written"));
+            let decoded = decode_blocking(
+                Arc::new(OctreeDecoder),
+                vec![Bitstream::from_bytes(&path, data)],
+            )
+            .unwrap();
+            assert_eq!(decoded.len(), frames);
+        }
+        assert_eq!(parser.get_info(0, 1, 3, None).unwrap().0, "1/seg_01054.vvs");
+    }
+}
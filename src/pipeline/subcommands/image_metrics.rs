@@ -0,0 +1,142 @@
+use clap::Parser;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use crate::{
+    formats::{pointxyzrgba::PointXyzRgba, PointCloud},
+    metrics::{compare_views, ImageMetric},
+    pipeline::{channel::Channel, PipelineMessage},
+    render::rig::{Rig, RigLayout},
+    render::wgpu::png::{PngWriter, RenderBackend, RenderFormat},
+    render::wgpu::splat::SplatArgs,
+};
+
+use super::Subcommand;
+
+#[derive(Parser)]
+#[clap(
+    about = "Calculates image metrics on views rendered from two input streams.\nFirst input stream is the original.\nSecond is the reconstructed.\nBoth are rendered from the same rig of views, normalized by the first original frame.\nThen uses write command to write the metrics into a text file.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] +input=original,reconstructure +output=metrics", "image-metrics")
+)]
+pub struct Args {
+    #[clap(short, long, num_args = 1.., value_delimiter = ',', default_value = "all")]
+    metrics: Vec<ImageMetric>,
+    /// Layout of the generated views looking at the centre of the first frame
+    #[clap(long, value_enum, default_value_t = RigLayout::Cube, conflicts_with = "rig_file")]
+    rig: RigLayout,
+    /// Views to render from instead of a generated rig, one `x,y,z,pitch,yaw` line per view
+    #[clap(long)]
+    rig_file: Option<PathBuf>,
+    /// Number of views of the sphere rig
+    #[clap(long, default_value_t = 16)]
+    rig_views: usize,
+    /// Distance of the generated views to the centre
+    #[clap(long, default_value_t = 1.8)]
+    rig_distance: f32,
+    #[clap(long, default_value_t = 1024)]
+    width: u32,
+    #[clap(long, default_value_t = 1024)]
+    height: u32,
+    #[clap(long, default_value = "rgb(255,255,255)")]
+    bg_color: OsString,
+    /// Renderer to draw the views with, `cpu` gives the same images on every machine
+    #[clap(long, value_enum, default_value_t = RenderBackend::Cpu)]
+    backend: RenderBackend,
+    #[clap(flatten)]
+    splat: SplatArgs,
+}
+
+pub struct ImageMetricsCalculator<'a> {
+    writer: PngWriter<'a>,
+    rig: Rig,
+    metrics: Vec<ImageMetric>,
+    // nothing is saved, the writer only needs a directory to be created with
+    _dir: tempfile::TempDir,
+}
+
+impl<'a> ImageMetricsCalculator<'a> {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let args: Args = Args::parse_from(args);
+        let rig = match &args.rig_file {
+            Some(path) => Rig::from_file(path).unwrap_or_else(|e| panic!("{e:#}")),
+            None => Rig::generate(args.rig, args.rig_views, args.rig_distance),
+        };
+        let dir = tempfile::tempdir().expect("Failed to create a temporary directory");
+        // the camera is moved to every view of the rig before rendering
+        let mut writer = PngWriter::new(
+            dir.path().as_os_str().to_owned(),
+            0.0,
+            0.0,
+            1.8,
+            cgmath::Deg(-90.0).into(),
+            cgmath::Rad(0.0),
+            args.width,
+            args.height,
+            args.bg_color.to_str().unwrap(),
+            RenderFormat::Png,
+            args.backend,
+        );
+        writer.set_splat(args.splat.splat());
+        Box::new(ImageMetricsCalculator {
+            writer,
+            rig,
+            metrics: args.metrics,
+            _dir: dir,
+        })
+    }
+}
+
+impl ImageMetricsCalculator<'_> {
+    fn compare(
+        &mut self,
+        original: &PointCloud<PointXyzRgba>,
+        reconstructed: &PointCloud<PointXyzRgba>,
+        channel: &Channel,
+    ) {
+        // the same writer renders both, so they share the normalization of the first original frame
+        let originals = self.writer.render_views(original, &self.rig);
+        let reconstructed = self.writer.render_views(reconstructed, &self.rig);
+        let metrics = compare_views(&originals, &reconstructed, &self.metrics);
+        channel.send(PipelineMessage::Metrics(metrics));
+    }
+}
+
+impl Subcommand for ImageMetricsCalculator<'_> {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+        let mut messages_iter = messages.into_iter();
+        let message_one = messages_iter
+            .next()
+            .expect("Expecting two input streams for image metrics");
+        let message_two = messages_iter
+            .next()
+            .expect("Expecting two input streams for image metrics");
+
+        // If either one or both is from SubcommandMessage, ImageMetricsCalculator still able to handle
+        match (&message_one, &message_two) {
+            (
+                PipelineMessage::IndexedPointCloud(original, _),
+                PipelineMessage::IndexedPointCloud(reconstructed, _),
+            ) => self.compare(original, reconstructed, channel),
+            (
+                PipelineMessage::SubcommandMessage(subcommand_object, _),
+                PipelineMessage::IndexedPointCloud(reconstructed, _),
+            ) => self.compare(subcommand_object.get_content(), reconstructed, channel),
+            (
+                PipelineMessage::IndexedPointCloud(original, _),
+                PipelineMessage::SubcommandMessage(subcommand_object, _),
+            ) => self.compare(original, subcommand_object.get_content(), channel),
+            (
+                PipelineMessage::SubcommandMessage(subcommand_object_original, _),
+                PipelineMessage::SubcommandMessage(subcommand_object_reconstructed, _),
+            ) => self.compare(
+                subcommand_object_original.get_content(),
+                subcommand_object_reconstructed.get_content(),
+                channel,
+            ),
+            (PipelineMessage::End, _) | (_, PipelineMessage::End) => {
+                channel.send(PipelineMessage::End);
+            }
+            (_, _) => {}
+        }
+    }
+}
@@ -1,13 +1,27 @@
 use clap::Parser;
+use std::path::PathBuf;
 
 use crate::{
-    lodify::lodify::lodify,
+    formats::{bounds::Bounds, pointxyzrgba::PointXyzRgba, PointCloud},
+    lodify::{
+        lodify::{additional_dir, lodify, LevelTargets},
+        progressive::{chunk_name, manifest, package, LodParams, StreamTiles},
+        tiling::Tiling,
+    },
     pipeline::{channel::Channel, PipelineMessage},
     utils::get_pc_bound,
 };
 
 use super::Subcommand;
 
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum TilingMethod {
+    /// the x, y and z partitions of the bounds of every frame
+    Grid,
+    Octree,
+    KdTree,
+}
+
 /// Partition and LODifies a pointcloud into pointclouds with different resolutions.
 #[derive(Parser)]
 pub struct Args {
@@ -24,6 +38,19 @@ pub struct Args {
         help = "Set the proportion of points of the base point cloud. Should lie between 0 and 100."
     )]
     base_proportion: usize,
+    /// How frames are cut into tiles. Octree and k-d tiles are split by point density.
+    #[clap(long, value_enum, default_value_t = TilingMethod::Grid)]
+    tiling: TilingMethod,
+    /// Maximum number of points of an octree or k-d tile
+    #[clap(long, default_value = "10000")]
+    max_points_per_tile: usize,
+    /// Percentages of the points of every level but the last, which has all of them, e.g. 10,25,50.
+    /// Replaces --base-proportion, which gives a base and a single additional level.
+    #[clap(long, value_delimiter = ',', conflicts_with = "spacings")]
+    proportions: Option<Vec<usize>>,
+    /// Target spacings between the points of every level but the last, coarsest first, e.g. 0.02,0.01,0.005
+    #[clap(long, value_delimiter = ',')]
+    spacings: Option<Vec<f32>>,
     #[clap(
         short = 't',
         long = "threshold",
@@ -31,21 +58,152 @@ pub struct Args {
         default_value = "10"
     )]
     points_per_voxel_threshold: usize,
+    /// Also writes a progressive stream to this directory: one chunk per tile, level and segment, and manifest.mpd.
+    /// Every tile is an adaptation set, so octree and k-d tiles are cut once, from the first frame.
+    #[clap(long)]
+    progressive: Option<PathBuf>,
+    /// number of frames per segment of the progressive stream
+    #[clap(long, default_value = "30")]
+    segment_frames: usize,
+    /// frame rate of the progressive stream
+    #[clap(long, default_value = "30")]
+    fps: u32,
 }
 
 pub struct Lodifier {
-    partitions: (usize, usize, usize),
-    base_proportion: usize,
+    tiling: Tiling,
+    targets: LevelTargets,
     points_per_voxel_threshold: usize,
+    packager: Option<Packager>,
+}
+
+/// Packages the frames into the progressive stream, see [`crate::lodify::progressive`].
+struct Packager {
+    output_dir: PathBuf,
+    tiling: Tiling,
+    /// cut from the first frame
+    tiles: Option<StreamTiles>,
+    params: LodParams,
+    segment_frames: usize,
+    fps: u32,
+    /// frames of the segment being packaged
+    frames: Vec<PointCloud<PointXyzRgba>>,
+    /// number of frames packaged so far
+    total_frames: usize,
+    /// bytes written for every level of every tile
+    bytes: Vec<Vec<u64>>,
+    /// bounds of every tile over the frames packaged so far
+    tile_bounds: Vec<Bounds>,
+}
+
+impl Packager {
+    fn push(&mut self, pc: PointCloud<PointXyzRgba>) {
+        self.frames.push(pc);
+        if self.frames.len() == self.segment_frames {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.frames.is_empty() {
+            return;
+        }
+        let number = self.total_frames as u64;
+        let tiles = self
+            .tiles
+            .get_or_insert_with(|| StreamTiles::new(&self.tiling, &self.frames[0]));
+        for (tile, chunks) in package(&self.frames, tiles, &self.params)
+            .into_iter()
+            .enumerate()
+        {
+            let index = chunks[0].index.as_ref().unwrap();
+            let bounds = index
+                .tile_bounds
+                .iter()
+                .skip(1)
+                .fold(index.tile_bounds[0].clone(), |union, bounds| {
+                    union.union(bounds)
+                });
+            if self.bytes.len() <= tile {
+                self.bytes.push(vec![0; chunks.len()]);
+                self.tile_bounds.push(bounds);
+            } else {
+                self.tile_bounds[tile] = self.tile_bounds[tile].union(&bounds);
+            }
+            for (level, chunk) in chunks.into_iter().enumerate() {
+                let data = chunk.to_bytes();
+                self.bytes[tile][level] += data.len() as u64;
+                std::fs::write(self.output_dir.join(chunk_name(tile, level, number)), data)
+                    .expect("Failed to write chunk");
+            }
+        }
+        self.total_frames += self.frames.len();
+        self.frames.clear();
+    }
+
+    fn finish(&mut self) {
+        self.flush();
+        let seconds = self.total_frames.max(1) as f64 / self.fps as f64;
+        let bandwidths: Vec<Vec<u64>> = self
+            .bytes
+            .iter()
+            .map(|levels| {
+                levels
+                    .iter()
+                    .map(|&bytes| (bytes as f64 * 8.0 / seconds) as u64)
+                    .collect()
+            })
+            .collect();
+        let mpd = manifest(
+            &bandwidths,
+            &self.tile_bounds,
+            self.total_frames as u64,
+            self.segment_frames as u64,
+            self.fps,
+        );
+        std::fs::write(self.output_dir.join("manifest.mpd"), mpd)
+            .expect("Failed to write manifest");
+    }
 }
 
 impl Lodifier {
     pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
         let args: Args = Args::parse_from(args);
+        let partitions = (args.x_partition, args.y_partition, args.z_partition);
+        let max_points = args.max_points_per_tile;
+        let tiling = match args.tiling {
+            TilingMethod::Grid => Tiling::Grid(partitions),
+            TilingMethod::Octree => Tiling::Octree { max_points },
+            TilingMethod::KdTree => Tiling::KdTree { max_points },
+        };
+        let targets = match (args.proportions, args.spacings) {
+            (_, Some(spacings)) => LevelTargets::Spacings(spacings),
+            (Some(proportions), None) => LevelTargets::Proportions(proportions),
+            (None, None) => LevelTargets::Proportions(vec![args.base_proportion]),
+        };
+        let packager = args.progressive.map(|output_dir| {
+            std::fs::create_dir_all(&output_dir).expect("Failed to create output directory");
+            Packager {
+                output_dir,
+                tiling,
+                tiles: None,
+                params: LodParams {
+                    targets: targets.clone(),
+                    points_per_voxel_threshold: args.points_per_voxel_threshold,
+                },
+                segment_frames: args.segment_frames.max(1),
+                fps: args.fps.max(1),
+                frames: vec![],
+                total_frames: 0,
+                bytes: vec![],
+                tile_bounds: vec![],
+            }
+        });
         Box::new(Lodifier {
-            partitions: (args.x_partition, args.y_partition, args.z_partition),
-            base_proportion: args.base_proportion,
+            tiling,
+            targets,
             points_per_voxel_threshold: args.points_per_voxel_threshold,
+            packager,
         })
     }
 }
@@ -55,46 +213,56 @@ impl Subcommand for Lodifier {
         for message in messages {
             match message {
                 PipelineMessage::IndexedPointCloud(pc, i) => {
-                    let (base_pc, pc_by_segment, base_point_nums, additional_point_nums) = lodify(
+                    let lodified = lodify(
                         &pc,
-                        self.partitions,
-                        self.base_proportion,
+                        &self.tiling,
+                        &self.targets,
                         self.points_per_voxel_threshold,
                     );
 
                     channel.send(PipelineMessage::IndexedPointCloudWithName(
-                        base_pc.clone(),
+                        lodified.base,
                         i,
                         "base".to_string(),
                         true,
                     ));
 
-                    for (segment, pc) in pc_by_segment.into_iter().enumerate() {
-                        channel.send(PipelineMessage::IndexedPointCloudWithName(
-                            pc,
-                            i,
-                            format!("{}", segment),
-                            false, // don't need headers for additional point clouds
-                        ));
+                    for (level, pc_by_tile) in lodified.levels.into_iter().enumerate() {
+                        for (tile, pc) in pc_by_tile.into_iter().enumerate() {
+                            channel.send(PipelineMessage::IndexedPointCloudWithName(
+                                pc,
+                                i,
+                                additional_dir(tile, level + 1),
+                                false, // don't need headers for additional point clouds
+                            ));
+                        }
                     }
 
                     let bound = get_pc_bound(&pc);
 
                     channel.send(PipelineMessage::MetaData(
                         bound,
-                        base_point_nums,
-                        additional_point_nums,
-                        self.partitions,
+                        lodified.tiles,
+                        lodified.base_point_nums,
+                        lodified.level_point_nums,
+                        self.tiling.partitions(),
                     ));
+
+                    if let Some(packager) = self.packager.as_mut() {
+                        packager.push(pc);
+                    }
                 }
                 PipelineMessage::Metrics(_)
                 | PipelineMessage::IndexedPointCloudWithName(_, _, _, _)
                 | PipelineMessage::IndexedPointCloudNormal(_, _)
                 | PipelineMessage::IndexedPointCloudWithTriangleFaces(_, _, _)
-                | PipelineMessage::MetaData(_, _, _, _)
+                | PipelineMessage::MetaData(_, _, _, _, _)
                 | PipelineMessage::DummyForIncrement => {}
                 PipelineMessage::SubcommandMessage(_, _) => {}
                 PipelineMessage::End => {
+                    if let Some(packager) = self.packager.as_mut() {
+                        packager.finish();
+                    }
                     channel.send(message);
                 }
             };
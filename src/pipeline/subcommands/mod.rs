@@ -1,26 +1,38 @@
 pub mod convert;
 pub mod dash;
+pub mod decode;
 pub mod downsample;
+pub mod encode;
 pub mod extension;
+pub mod image_metrics;
 pub mod info;
 pub mod lodify;
 pub mod metrics;
 pub mod normal_estimation;
+pub mod qoe;
+pub mod rd;
 pub mod read;
 pub mod render;
+pub mod serve;
 pub mod upsample;
 pub mod write;
 
 pub use convert::Convert;
 pub use dash::Dash;
+pub use decode::Decode;
 pub use downsample::Downsampler;
+pub use encode::Encode;
 pub use extension::Extension;
+pub use image_metrics::ImageMetricsCalculator;
 pub use info::Info;
 pub use lodify::Lodifier;
 pub use metrics::MetricsCalculator;
 pub use normal_estimation::NormalEstimation;
+pub use qoe::QoeCalculator;
+pub use rd::Rd;
 pub use read::Read;
 pub use render::Render;
+pub use serve::Serve;
 pub use upsample::Upsampler;
 pub use write::Write;
 
@@ -195,7 +195,7 @@ impl Subcommand for NormalEstimation {
                 | PipelineMessage::IndexedPointCloudNormal(_, _)
                 | PipelineMessage::IndexedPointCloudWithTriangleFaces(_, _, _)
                 | PipelineMessage::IndexedPointCloudWithName(_, _, _, _)
-                | PipelineMessage::MetaData(_, _, _, _)
+                | PipelineMessage::MetaData(_, _, _, _, _)
                 | PipelineMessage::DummyForIncrement => {}
                 PipelineMessage::End => {
                     channel.send(message);
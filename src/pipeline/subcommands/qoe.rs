@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use std::io::Write;
+use std::path::PathBuf;
+
+use super::Subcommand;
+use crate::pipeline::channel::Channel;
+use crate::pipeline::PipelineMessage;
+use crate::vvplay_async_prefetch::session_log::{Qoe, SessionLog};
+
+#[derive(Parser)]
+#[clap(
+    about = "Computes the startup delay, rebuffer ratio, average quality and switch frequency of sessions logged by `vvplay_async --session-log`.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] <LOGS>...", "qoe")
+)]
+pub struct Args {
+    /// session logs written by vvplay_async
+    #[clap(required = true)]
+    logs: Vec<PathBuf>,
+
+    /// also write the metrics of every session to this JSON file
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct QoeReport {
+    pub log: String,
+    #[serde(flatten)]
+    pub qoe: Qoe,
+}
+
+pub fn write_csv<W: Write>(reports: &[QoeReport], writer: &mut W) -> std::io::Result<()> {
+    writeln!(
+        writer,
+        "log,startup_delay,duration,frames,stalls,rebuffer_time,rebuffer_ratio,average_quality,average_bitrate,switches,switch_frequency"
+    )?;
+    for r in reports {
+        let q = &r.qoe;
+        writeln!(
+            writer,
+            "\"{}\",{},{:.3},{},{},{:.3},{:.5},{:.3},{:.0},{},{:.3}",
+            r.log,
+            q.startup_delay.map_or(String::new(), |d| format!("{d:.3}")),
+            q.duration,
+            q.frames,
+            q.stalls,
+            q.rebuffer_time,
+            q.rebuffer_ratio,
+            q.average_quality,
+            q.average_bitrate,
+            q.switches,
+            q.switch_frequency
+        )?;
+    }
+    Ok(())
+}
+
+pub struct QoeCalculator {
+    args: Args,
+}
+
+impl QoeCalculator {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        Box::from(QoeCalculator {
+            args: Args::parse_from(args),
+        })
+    }
+
+    fn run(&self) -> Result<Vec<QoeReport>> {
+        let reports = self
+            .args
+            .logs
+            .iter()
+            .map(|path| {
+                Ok(QoeReport {
+                    log: path.to_string_lossy().to_string(),
+                    qoe: Qoe::from_records(&SessionLog::read(path)?),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(output) = &self.args.output {
+            serde_json::to_writer_pretty(
+                std::fs::File::create(output)
+                    .with_context(|| format!("failed to create {}", output.display()))?,
+                &reports,
+            )?;
+        }
+        Ok(reports)
+    }
+}
+
+impl Subcommand for QoeCalculator {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+        if messages.is_empty() {
+            match self.run() {
+                Ok(reports) => _ = write_csv(&reports, &mut std::io::stdout()),
+                Err(e) => eprintln!("Failed to compute the QoE: {e:#}"),
+            }
+            channel.send(PipelineMessage::End);
+        } else {
+            for message in messages {
+                channel.send(message);
+            }
+        }
+    }
+}
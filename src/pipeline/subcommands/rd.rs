@@ -0,0 +1,435 @@
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use super::encode::{CodecArgs, EncoderArgs, EncoderParams};
+use super::Subcommand;
+use crate::codec::{decode_blocking, Bitstream, Encoder};
+use crate::metrics::{bd_psnr, bd_rate, distortion};
+use crate::pipeline::channel::Channel;
+use crate::pipeline::PipelineMessage;
+use crate::utils::{find_all_files, read_file_to_point_cloud};
+
+#[derive(Parser)]
+#[clap(
+    about = "Sweeps encoder configurations over a sequence and reports the bits per point and D1, D2 and colour PSNR.\nComputes the BD-rate and BD-PSNR against an earlier report.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] <FILES>... --point <POINT>...", "rd")
+)]
+pub struct Args {
+    /// Files, glob patterns, directories of the sequence
+    files: Vec<OsString>,
+
+    #[clap(short, long)]
+    /// use the first n frames after sorting lexicographically
+    num: Option<usize>,
+
+    #[clap(flatten)]
+    codec: CodecArgs,
+
+    #[clap(flatten)]
+    encoder: EncoderArgs,
+
+    /// encoder configurations as name=value pairs, e.g. --point geometry_qp=10,attribute_qp=16.
+    /// Repeat for every point of the curve
+    #[clap(short, long = "point", required = true)]
+    points: Vec<EncoderParams>,
+
+    /// signal peak of the geometry PSNRs, e.g. 1023 for 10 bit voxelised content.
+    /// Defaults to the largest side of the bounding box of every frame
+    #[clap(long)]
+    peak: Option<f64>,
+
+    /// directory for the bitstreams and the reports, rd.csv and rd.json
+    #[clap(short, long, default_value = "rd")]
+    output_dir: PathBuf,
+
+    /// earlier rd.json to compute the Bjøntegaard deltas against
+    #[clap(long)]
+    anchor: Option<PathBuf>,
+}
+
+/// Non-finite PSNRs, i.e. lossless points, are written as null since JSON has no infinity.
+mod psnr_or_null {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            serializer.serialize_f64(*value)
+        } else {
+            serializer.serialize_none()
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::INFINITY))
+    }
+}
+
+/// Averages the PSNRs of the frames as the PSNR of their mean MSE, as the MPEG common test conditions do,
+/// so that lossless frames lower the error instead of making the average infinite.
+/// The MSEs are normalised by the peak of their frame, which is exact when `--peak` is given.
+fn mean_psnr(psnrs: impl ExactSizeIterator<Item = f64>) -> f64 {
+    let frames = psnrs.len() as f64;
+    let mse: f64 = psnrs.map(|psnr| 10f64.powf(-psnr / 10.0)).sum::<f64>() / frames;
+    -10.0 * mse.log10()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RdPoint {
+    /// encoder configuration, as given on the command line
+    pub params: String,
+    pub frames: usize,
+    /// number of points of the original frames
+    pub points: usize,
+    pub bits: u64,
+    /// bits per point of the original
+    pub bpp: f64,
+    /// PSNRs in dB of the MSE averaged over the frames, see [`mean_psnr`].
+    /// Infinite only when every frame is lossless
+    #[serde(with = "psnr_or_null")]
+    pub d1_psnr: f64,
+    #[serde(with = "psnr_or_null")]
+    pub d2_psnr: f64,
+    #[serde(with = "psnr_or_null")]
+    pub y_psnr: f64,
+    #[serde(with = "psnr_or_null")]
+    pub u_psnr: f64,
+    #[serde(with = "psnr_or_null")]
+    pub v_psnr: f64,
+    /// total encoding and decoding time in seconds
+    pub encode_time: f64,
+    pub decode_time: f64,
+}
+
+/// Bjøntegaard deltas of a report against its anchor, `None` when the curves do not overlap.
+/// Rates are in percent, PSNRs in dB.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BdReport {
+    pub anchor: String,
+    pub d1_bd_rate: Option<f64>,
+    pub d1_bd_psnr: Option<f64>,
+    pub d2_bd_rate: Option<f64>,
+    pub d2_bd_psnr: Option<f64>,
+    pub y_bd_rate: Option<f64>,
+    pub y_bd_psnr: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RdReport {
+    pub codec: String,
+    pub frames: Vec<String>,
+    pub points: Vec<RdPoint>,
+    pub bd: Option<BdReport>,
+}
+
+impl BdReport {
+    pub fn new(anchor_name: &str, anchor: &[RdPoint], test: &[RdPoint]) -> Self {
+        let curve = |points: &[RdPoint], psnr: fn(&RdPoint) -> f64| {
+            points.iter().map(|p| (p.bpp, psnr(p))).collect::<Vec<_>>()
+        };
+        let deltas = |psnr: fn(&RdPoint) -> f64| {
+            let (anchor, test) = (curve(anchor, psnr), curve(test, psnr));
+            (bd_rate(&anchor, &test), bd_psnr(&anchor, &test))
+        };
+        let (d1_bd_rate, d1_bd_psnr) = deltas(|p| p.d1_psnr);
+        let (d2_bd_rate, d2_bd_psnr) = deltas(|p| p.d2_psnr);
+        let (y_bd_rate, y_bd_psnr) = deltas(|p| p.y_psnr);
+        BdReport {
+            anchor: anchor_name.to_string(),
+            d1_bd_rate,
+            d1_bd_psnr,
+            d2_bd_rate,
+            d2_bd_psnr,
+            y_bd_rate,
+            y_bd_psnr,
+        }
+    }
+}
+
+impl RdReport {
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writeln!(
+            writer,
+            "params,frames,points,bits,bpp,d1_psnr,d2_psnr,y_psnr,u_psnr,v_psnr,encode_time,decode_time"
+        )?;
+        for p in &self.points {
+            writeln!(
+                writer,
+                "\"{}\",{},{},{},{:.5},{:.5},{:.5},{:.5},{:.5},{:.5},{:.3},{:.3}",
+                p.params,
+                p.frames,
+                p.points,
+                p.bits,
+                p.bpp,
+                p.d1_psnr,
+                p.d2_psnr,
+                p.y_psnr,
+                p.u_psnr,
+                p.v_psnr,
+                p.encode_time,
+                p.decode_time
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Rd {
+    args: Args,
+}
+
+impl Rd {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        Box::from(Rd {
+            args: Args::parse_from(args),
+        })
+    }
+
+    fn run(&self) -> Result<RdReport> {
+        let mut files = find_all_files(&self.args.files);
+        files.sort();
+        if let Some(num) = self.args.num {
+            files.truncate(num);
+        }
+        if files.is_empty() {
+            return Err(anyhow!("no frames found"));
+        }
+
+        let encoders: Vec<Box<dyn Encoder>> = self
+            .args
+            .points
+            .iter()
+            .map(|params| self.args.encoder.encoder(&self.args.codec, params))
+            .collect();
+        let mut results: Vec<RdPoint> = self
+            .args
+            .points
+            .iter()
+            .map(|params| RdPoint {
+                params: params.to_string(),
+                frames: 0,
+                points: 0,
+                bits: 0,
+                bpp: 0.0,
+                d1_psnr: 0.0,
+                d2_psnr: 0.0,
+                y_psnr: 0.0,
+                u_psnr: 0.0,
+                v_psnr: 0.0,
+                encode_time: 0.0,
+                decode_time: 0.0,
+            })
+            .collect();
+
+        // distortion of every frame, by point
+        let mut psnrs = vec![vec![]; results.len()];
+        let decoder = self.args.codec.decoder();
+        for (frame, file) in files.iter().enumerate() {
+            let original = read_file_to_point_cloud(file)
+                .ok_or_else(|| anyhow!("failed to read {}", file.display()))?;
+            for (i, (encoder, result)) in encoders.iter().zip(&mut results).enumerate() {
+                let now = Instant::now();
+                let bitstream = encoder
+                    .encode(&original)
+                    .with_context(|| format!("failed to encode frame {frame}"))?;
+                result.encode_time += now.elapsed().as_secs_f64();
+
+                let dir = self.args.output_dir.join(i.to_string());
+                std::fs::create_dir_all(&dir)?;
+                let path = dir.join(format!("{:05}.{}", frame, encoder.extension()));
+                std::fs::write(&path, &bitstream)?;
+
+                let now = Instant::now();
+                let reconstructed = decode_blocking(
+                    decoder.clone(),
+                    vec![Bitstream::from_bytes(&path, bitstream.clone())],
+                )
+                .with_context(|| format!("failed to decode {}", path.display()))?
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("failed to decode {}", path.display()))?;
+                result.decode_time += now.elapsed().as_secs_f64();
+
+                let d = distortion(&original, &reconstructed, self.args.peak);
+                result.frames += 1;
+                result.points += original.number_of_points;
+                result.bits += bitstream.len() as u64 * 8;
+                psnrs[i].push(d);
+            }
+        }
+
+        for (result, psnrs) in results.iter_mut().zip(&psnrs) {
+            result.bpp = result.bits as f64 / result.points.max(1) as f64;
+            result.d1_psnr = mean_psnr(psnrs.iter().map(|d| d.d1_psnr));
+            result.d2_psnr = mean_psnr(psnrs.iter().map(|d| d.d2_psnr));
+            result.y_psnr = mean_psnr(psnrs.iter().map(|d| d.y_psnr));
+            result.u_psnr = mean_psnr(psnrs.iter().map(|d| d.u_psnr));
+            result.v_psnr = mean_psnr(psnrs.iter().map(|d| d.v_psnr));
+        }
+
+        let bd = match &self.args.anchor {
+            Some(path) => {
+                let anchor: RdReport = serde_json::from_reader(
+                    std::fs::File::open(path)
+                        .with_context(|| format!("failed to open {}", path.display()))?,
+                )?;
+                Some(BdReport::new(
+                    &path.to_string_lossy(),
+                    &anchor.points,
+                    &results,
+                ))
+            }
+            None => None,
+        };
+
+        Ok(RdReport {
+            codec: format!("{:?}", self.args.codec.codec()).to_lowercase(),
+            frames: files
+                .iter()
+                .map(|file| file.to_string_lossy().to_string())
+                .collect(),
+            points: results,
+            bd,
+        })
+    }
+
+    fn write_report(&self, report: &RdReport) -> Result<()> {
+        let output_dir = Path::new(&self.args.output_dir);
+        std::fs::create_dir_all(output_dir)?;
+        report.write_csv(&mut std::fs::File::create(output_dir.join("rd.csv"))?)?;
+        serde_json::to_writer_pretty(std::fs::File::create(output_dir.join("rd.json"))?, report)?;
+        Ok(())
+    }
+}
+
+impl Subcommand for Rd {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+        if messages.is_empty() {
+            match self.run() {
+                Ok(report) => {
+                    _ = report.write_csv(&mut std::io::stdout());
+                    if let Some(bd) = &report.bd {
+                        println!("Against {}:", bd.anchor);
+                        for (name, rate, psnr) in [
+                            ("D1", bd.d1_bd_rate, bd.d1_bd_psnr),
+                            ("D2", bd.d2_bd_rate, bd.d2_bd_psnr),
+                            ("Y", bd.y_bd_rate, bd.y_bd_psnr),
+                        ] {
+                            let format = |v: Option<f64>, unit: &str| {
+                                v.map_or("n/a".to_string(), |v| format!("{v:.3}{unit}"))
+                            };
+                            println!(
+                                "  {name}: BD-rate {}, BD-PSNR {}",
+                                format(rate, "%"),
+                                format(psnr, " dB")
+                            );
+                        }
+                    }
+                    if let Err(e) = self.write_report(&report) {
+                        eprintln!("Failed to write the report: {e:#}");
+                    }
+                }
+                Err(e) => eprintln!("Rate-distortion sweep failed: {e:#}"),
+            }
+            channel.send(PipelineMessage::End);
+        } else {
+            for message in messages {
+                channel.send(message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(params: &str, bpp: f64, psnr: f64) -> RdPoint {
+        RdPoint {
+            params: params.to_string(),
+            frames: 1,
+            points: 100,
+            bits: (bpp * 100.0) as u64,
+            bpp,
+            d1_psnr: psnr,
+            d2_psnr: psnr + 4.0,
+            y_psnr: f64::INFINITY,
+            u_psnr: 40.0,
+            v_psnr: 40.0,
+            encode_time: 0.1,
+            decode_time: 0.1,
+        }
+    }
+
+    #[test]
+    fn test_report_roundtrip() {
+        let anchor: Vec<RdPoint> = [(1.0, 50.0), (2.0, 55.0), (4.0, 60.0), (8.0, 63.0)]
+            .iter()
+            .map(|(bpp, psnr)| point("geometry_qp=4,attribute_qp=4", *bpp, *psnr))
+            .collect();
+        let test: Vec<RdPoint> = anchor
+            .iter()
+            .map(|p| point(&p.params, p.bpp * 0.8, p.d1_psnr))
+            .collect();
+        let report = RdReport {
+            codec: "octree".to_string(),
+            frames: vec!["0.ply".to_string()],
+            bd: Some(BdReport::new("anchor.json", &anchor, &test)),
+            points: test,
+        };
+        let bd = report.bd.as_ref().unwrap();
+        assert!((bd.d1_bd_rate.unwrap() + 20.0).abs() < 1e-6);
+        assert!(bd.y_bd_rate.is_none());
+
+        let json = serde_json::to_string(&report).unwrap();
+        let parsed: RdReport = serde_json::from_str(&json).unwrap();
+        assert!(parsed.points[0].y_psnr.is_infinite());
+        assert_eq!(parsed.points[0].d1_psnr, 50.0);
+
+        let mut csv = vec![];
+        report.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("\"geometry_qp=4,attribute_qp=4\",1,100,80,"));
+    }
+
+    #[test]
+    fn test_mean_psnr() {
+        assert_eq!(mean_psnr([30.0, 30.0].into_iter()), 30.0);
+        // a lossless frame halves the mean MSE
+        let psnr = mean_psnr([30.0, f64::INFINITY].into_iter());
+        assert!((psnr - 30.0 - 10.0 * 2f64.log10()).abs() < 1e-9);
+        assert!(mean_psnr([f64::INFINITY, f64::INFINITY].into_iter()).is_infinite());
+        // dominated by the worst frame, unlike the mean of the PSNRs
+        assert!(mean_psnr([20.0, 60.0].into_iter()) < 30.0);
+    }
+
+    #[test]
+    fn test_args() {
+        let args = Args::parse_from([
+            "rd",
+            "./longdress",
+            "--point",
+            "geometry_qp=10,attribute_qp=22",
+            "-p",
+            "geometry_qp=16,attribute_qp=28,q=1",
+        ]);
+        assert_eq!(args.points.len(), 2);
+        assert_eq!(args.points[0].octree.geometry_qp, 10);
+        assert_eq!(args.points[0].octree.attribute_qp, 22);
+        assert_eq!(args.points[1].vars.len(), 3);
+        assert_eq!(
+            args.points[1].to_string(),
+            "geometry_qp=16,attribute_qp=28,q=1"
+        );
+        assert!(Args::try_parse_from(["rd", "./longdress", "-p", "geometry_qp"]).is_err());
+    }
+}
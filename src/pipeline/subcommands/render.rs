@@ -1,17 +1,26 @@
 use super::Subcommand;
+use crate::formats::pointxyzrgba::PointXyzRgba;
+use crate::formats::pointxyzrgbanormal::PointXyzRgbaNormal;
+use crate::formats::PointCloud;
 use crate::pipeline::channel::Channel;
 use crate::pipeline::PipelineMessage;
-use crate::render::wgpu::png::{PngWriter, RenderFormat};
+use crate::render::camera_path::{read_keyframes, CameraPath, Interpolation};
+use crate::render::passes::{DepthFormat, RenderPass};
+use crate::render::rig::{Rig, RigLayout};
+use crate::render::video::{VideoOptions, VideoWriter};
+use crate::render::wgpu::camera::CameraPosition;
+use crate::render::wgpu::png::{PngWriter, RenderBackend, RenderFormat};
+use crate::render::wgpu::splat::SplatArgs;
+use crate::simulation::CameraTrace;
 use cgmath::num_traits::pow;
 use clap::Parser;
 use std::ffi::OsString;
-use std::path::Path;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
 
-/// Writes point clouds from the input stream into images.
+/// Writes point clouds from the input stream into images or a video.
 #[derive(Parser)]
 pub struct Args {
-    /// Directory to store output png images
+    /// Directory to store output png images or the `output.<format>` video
     output_dir: OsString,
     #[clap(short = 'x', long, default_value_t = 0.0)]
     camera_x: f32,
@@ -37,14 +46,78 @@ pub struct Args {
     verbose: bool,
     #[clap(long, default_value_t = 30.0)]
     fps: f32,
+    /// ffmpeg encoder for mp4, e.g. libx265 or mpeg4 [default: libx264]
+    #[clap(long)]
+    codec: Option<String>,
+    /// Target bitrate of mp4 in bits per second [default: chosen by the encoder]
+    #[clap(long)]
+    bitrate: Option<usize>,
+    /// Renderer to draw the images with, `cpu` needs no graphics adapter and is deterministic
+    #[clap(long, value_enum, default_value_t = RenderBackend::Gpu)]
+    backend: RenderBackend,
+    #[clap(flatten)]
+    splat: SplatArgs,
+    /// Camera trace to replay instead of the fixed camera, one `x,y,z,pitch,yaw,roll` line per frame
+    #[clap(long, conflicts_with_all = ["orbit_radius", "keyframes"])]
+    camera_trace: Option<PathBuf>,
+    /// Orbit the centroid of the first frame at this distance instead of using the fixed camera
+    #[clap(long, conflicts_with = "keyframes")]
+    orbit_radius: Option<f32>,
+    /// Elevation of the orbit in degrees
+    #[clap(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    orbit_elevation: f32,
+    /// Seconds of a full orbit at --fps
+    #[clap(long, default_value_t = 10.0)]
+    orbit_period: f32,
+    /// Keyframes to move the camera through instead of the fixed camera, one `frame,x,y,z,pitch,yaw` line per keyframe
+    #[clap(long)]
+    keyframes: Option<PathBuf>,
+    /// Interpolation of the camera position between keyframes, the orientation is always slerped
+    #[clap(long, value_enum, default_value_t = Interpolation::CatmullRom)]
+    interpolation: Interpolation,
+    /// Render every frame from a generated rig of views looking at the centre of the first frame
+    #[clap(long, value_enum, conflicts_with_all = ["rig_file", "camera_trace", "orbit_radius", "keyframes"])]
+    rig: Option<RigLayout>,
+    /// Render every frame from the views of a file, one `x,y,z,pitch,yaw` line per view
+    #[clap(long, conflicts_with_all = ["camera_trace", "orbit_radius", "keyframes"])]
+    rig_file: Option<PathBuf>,
+    /// Number of views of the sphere rig
+    #[clap(long, default_value_t = 16)]
+    rig_views: usize,
+    /// Distance of the generated views to the centre
+    #[clap(long, default_value_t = 1.8)]
+    rig_distance: f32,
+    /// Passes to write for every image, depth, normal and mask line up with the colours and need --format png
+    #[clap(long, value_enum, num_args = 1.., value_delimiter = ',', default_value = "color")]
+    passes: Vec<RenderPass>,
+    /// Format of the depth pass, written as `<name>_depth.<format>`
+    #[clap(long, value_enum, default_value_t = DepthFormat::Png16)]
+    depth_format: DepthFormat,
+    /// Multiplier of the depths stored in a 16-bit png, e.g. 1000 for millimetres of a point cloud in metres
+    #[clap(long, default_value_t = 1.0)]
+    depth_scale: f32,
+    /// Write the intrinsics and extrinsics of every image into `<name>.json` next to it
+    #[clap(long, default_value_t = false)]
+    camera_json: bool,
 }
 
 pub struct Render<'a> {
     writer: PngWriter<'a>,
+    camera_path: CameraPath,
+    /// Views to render every frame from instead of the camera path
+    rig: Option<Rig>,
+    /// Encode the frames as they arrive when rendering into videos, one per view
+    videos: Vec<VideoWriter>,
+    output_dir: PathBuf,
+    passes: Vec<RenderPass>,
+    depth_format: DepthFormat,
+    depth_scale: f32,
+    camera_json: bool,
+    /// Whether the missing normals have been reported, to warn only once
+    warned_normals: bool,
     name_length: u32,
     count: u32,
     verbose: bool,
-    fps: f32,
 }
 
 impl<'a> Render<'a> {
@@ -63,92 +136,262 @@ impl<'a> Render<'a> {
             render_format,
             verbose,
             fps,
+            codec,
+            bitrate,
+            backend,
+            splat,
+            camera_trace,
+            orbit_radius,
+            orbit_elevation,
+            orbit_period,
+            keyframes,
+            interpolation,
+            rig,
+            rig_file,
+            rig_views,
+            rig_distance,
+            passes,
+            depth_format,
+            depth_scale,
+            camera_json,
         }: Args = Args::parse_from(args);
 
-        let mut output_dir = output_dir;
-        if render_format == RenderFormat::Mp4 {
-            // check ffmpeg existence first
-            let _ffmpeg_check = Command::new("ffmpeg")
-                .arg("-version")
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .status()
-                .expect("Failed to check ffmpeg existence. Please install ffmpeg first.");
-
-            output_dir = (output_dir.into_string().unwrap() + "/.tmp_mp4").into();
-            // create the directory if it doesn't exist
-            // if exists, check if it's empty
-            // if not empty, panic
-
-            let checked_path = Path::new(&output_dir);
-            if !checked_path.exists() {
-                std::fs::create_dir_all(checked_path).expect("Failed to create output directory");
-            } else {
-                if checked_path.read_dir().unwrap().next().is_some() {
-                    panic!(
-                        "Temp png directory({}) is not empty, please backup and remove the files",
-                        output_dir.to_str().unwrap()
-                    )
-                }
-                if checked_path.is_file() {
-                    panic!(
-                        "Temp png directory({}) is a file, please rename this file",
-                        output_dir.to_str().unwrap()
-                    );
-                }
-            }
+        if render_format.is_video()
+            && (passes.iter().any(|&pass| pass != RenderPass::Color) || camera_json)
+        {
+            panic!(
+                "Depth, normal and mask passes and camera json are only written with --format png"
+            );
+        }
+        if render_format.is_video() && !passes.contains(&RenderPass::Color) {
+            panic!("Videos need the color pass");
         }
 
+        let rig = match (rig, rig_file) {
+            (Some(layout), _) => Some(Rig::generate(layout, rig_views, rig_distance)),
+            (None, Some(path)) => Some(Rig::from_file(&path).unwrap_or_else(|e| panic!("{e:#}"))),
+            (None, None) => None,
+        };
+
+        let camera_path = if let Some(path) = camera_trace {
+            CameraPath::Trace(CameraTrace::new(&path, false))
+        } else if let Some(radius) = orbit_radius {
+            CameraPath::orbit(radius, cgmath::Deg(orbit_elevation), orbit_period * fps)
+        } else if let Some(path) = keyframes {
+            let keyframes = read_keyframes(&path).unwrap_or_else(|e| panic!("{e:#}"));
+            CameraPath::keyframes(keyframes, interpolation)
+        } else {
+            CameraPath::Fixed(CameraPosition {
+                position: cgmath::Point3::new(camera_x, camera_y, camera_z),
+                yaw: cgmath::Rad(camera_yaw),
+                pitch: cgmath::Rad(camera_pitch),
+                up: cgmath::Vector3::unit_y(),
+            })
+        };
+
+        let mut writer = PngWriter::new(
+            output_dir.clone(),
+            camera_x,
+            camera_y,
+            camera_z,
+            cgmath::Rad(camera_yaw),
+            cgmath::Rad(camera_pitch),
+            width,
+            height,
+            bg_color.to_str().unwrap(),
+            render_format,
+            backend,
+        );
+        writer.set_splat(splat.splat());
+
+        let videos = if render_format.is_video() {
+            let extension = render_format.to_string();
+            let options = VideoOptions {
+                fps,
+                codec,
+                bitrate,
+            };
+            let names = match &rig {
+                Some(rig) => (0..rig.len())
+                    .map(|view| format!("output_{view}.{extension}"))
+                    .collect(),
+                None => vec![format!("output.{extension}")],
+            };
+            names
+                .into_iter()
+                .map(|name| {
+                    let path = Path::new(&output_dir).join(name);
+                    VideoWriter::new(&path, render_format, width, height, &options)
+                        .unwrap_or_else(|e| panic!("Failed to create {}: {e:#}", path.display()))
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
         Box::from(Render {
-            writer: PngWriter::new(
-                output_dir,
-                camera_x,
-                camera_y,
-                camera_z,
-                cgmath::Rad(camera_yaw),
-                cgmath::Rad(camera_pitch),
-                width,
-                height,
-                bg_color.to_str().unwrap(),
-                render_format,
-            ),
+            writer,
+            camera_path,
+            rig,
+            videos,
+            output_dir: PathBuf::from(output_dir),
+            passes,
+            depth_format,
+            depth_scale,
+            camera_json,
+            warned_normals: false,
             name_length,
             count: 0,
             verbose,
-            fps,
         })
     }
 }
 
-impl Subcommand for Render<'_> {
-    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+impl Render<'_> {
+    fn write(
+        &mut self,
+        pc: &PointCloud<PointXyzRgba>,
+        normals: Option<&[[f32; 3]]>,
+        index: u32,
+        channel: &Channel,
+    ) {
+        let poses = match &self.rig {
+            Some(rig) => rig.views().to_vec(),
+            None => vec![self.camera_path.pose(self.count as usize, pc)],
+        };
+        self.count += 1;
+
+        if !self.videos.is_empty() {
+            for (video, pose) in self.videos.iter_mut().zip(poses) {
+                self.writer.update_camera_pos(pose);
+                video
+                    .write_frame(&self.writer.render_with_normals(pc, normals))
+                    .unwrap_or_else(|e| panic!("Failed to encode frame {index}: {e:#}"));
+            }
+            return;
+        }
         let max_count = pow(10, self.name_length as usize);
+        if self.count >= max_count {
+            channel.send(PipelineMessage::End);
+            panic!("Too many files, please increase the name length by setting --name-length")
+        }
+        let padded_count = format!("{:0>width$}", index, width = self.name_length as usize);
+        let view_width = self
+            .rig
+            .as_ref()
+            .map_or(0, |rig| (rig.len() - 1).to_string().len());
+        let with_passes =
+            self.camera_json || self.passes.iter().any(|&pass| pass != RenderPass::Color);
+        for (view, pose) in poses.into_iter().enumerate() {
+            // a single view keeps the plain frame names
+            let name = match self.rig {
+                Some(_) => format!("{padded_count}_{view:0>view_width$}"),
+                None => padded_count.clone(),
+            };
+            self.writer.update_camera_pos(pose);
+            if self.passes.contains(&RenderPass::Color) {
+                let image = self.writer.render_with_normals(pc, normals);
+                self.writer.save_png(&image, &format!("{name}.png"));
+            }
+            if with_passes {
+                self.write_passes(pc, normals, &name);
+            }
+        }
+    }
+
+    /// Finish the videos once the stream ends. A video that fails to finish is removed when its
+    /// writer is dropped, like the videos left unfinished by a panic.
+    fn finish_videos(&mut self) {
+        for video in self.videos.drain(..) {
+            let path = video.path().to_path_buf();
+            match video.finish() {
+                Ok(()) if self.verbose => println!("video is saved to {}", path.display()),
+                Ok(()) => {}
+                Err(e) => eprintln!("Failed to finish {}: {e:#}", path.display()),
+            }
+        }
+    }
+
+    fn write_passes(
+        &mut self,
+        pc: &PointCloud<PointXyzRgba>,
+        normals: Option<&[[f32; 3]]>,
+        name: &str,
+    ) {
+        let passes = self.writer.render_passes(pc, normals);
+        if self.passes.contains(&RenderPass::Depth) {
+            let filename = format!("{name}_depth.{}", self.depth_format.extension());
+            passes
+                .save_depth(
+                    &self.output_dir.join(filename),
+                    self.depth_format,
+                    self.depth_scale,
+                )
+                .unwrap_or_else(|e| panic!("{e:#}"));
+        }
+        if self.passes.contains(&RenderPass::Mask) {
+            passes
+                .mask
+                .save(self.output_dir.join(format!("{name}_mask.png")))
+                .expect("Failed to save the mask");
+        }
+        if self.passes.contains(&RenderPass::Normal) {
+            match &passes.normal {
+                Some(normal) => normal
+                    .save(self.output_dir.join(format!("{name}_normal.png")))
+                    .expect("Failed to save the normals"),
+                None if !self.warned_normals => {
+                    eprintln!("The point clouds have no normals, skipping the normal pass. Estimate them with `vv normal` first");
+                    self.warned_normals = true;
+                }
+                None => {}
+            }
+        }
+        if self.camera_json {
+            passes
+                .camera
+                .save(&self.output_dir.join(format!("{name}.json")))
+                .unwrap_or_else(|e| panic!("{e:#}"));
+        }
+    }
+}
+
+/// Split a point cloud with normals into the points to render and their normals
+fn split_normals(pc: &PointCloud<PointXyzRgbaNormal>) -> (PointCloud<PointXyzRgba>, Vec<[f32; 3]>) {
+    let (points, normals): (Vec<_>, Vec<_>) = pc
+        .points
+        .iter()
+        .map(|p| {
+            let point = PointXyzRgba {
+                x: p.x,
+                y: p.y,
+                z: p.z,
+                r: p.r,
+                g: p.g,
+                b: p.b,
+                a: p.a,
+            };
+            (point, [p.nx, p.ny, p.nz])
+        })
+        .unzip();
+    (PointCloud::new(points.len(), points), normals)
+}
 
+impl Subcommand for Render<'_> {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
         for message in messages {
             match &message {
-                PipelineMessage::IndexedPointCloud(pc, i) => {
-                    let padded_count = format!("{:0>width$}", i, width = self.name_length as usize);
-                    let filename = format!("{}.png", padded_count);
-                    self.count += 1;
-                    if self.count >= max_count {
-                        channel.send(PipelineMessage::End);
-                        panic!("Too many files, please increase the name length by setting --name-length")
-                    }
-                    self.writer.write_to_png(pc, &filename);
+                PipelineMessage::IndexedPointCloud(pc, i) => self.write(pc, None, *i, channel),
+                PipelineMessage::IndexedPointCloudNormal(pc, i) => {
+                    let (pc, normals) = split_normals(pc);
+                    self.write(&pc, Some(&normals), *i, channel)
                 }
                 PipelineMessage::SubcommandMessage(subcommand_object, i) => {
                     // Only vv extend will send SubcommandMessage, other subcommand will send IndexedPointCloud to make sure the other command will
                     // continue to be compatible by receiving IndexedPointCloud
-                    let pc = subcommand_object.get_content();
-                    let padded_count = format!("{:0>width$}", i, width = self.name_length as usize);
-                    let filename = format!("{}.png", padded_count);
-                    self.count += 1;
-                    if self.count >= max_count {
-                        channel.send(PipelineMessage::End);
-                        panic!("Too many files, please increase the name length by setting --name-length")
-                    }
-                    self.writer.write_to_png(pc, &filename);
+                    self.write(subcommand_object.get_content(), None, *i, channel)
                 }
+                PipelineMessage::End => self.finish_videos(),
                 _ => {}
             }
             channel.send(message);
@@ -156,17 +399,6 @@ impl Subcommand for Render<'_> {
     }
 }
 
-impl Drop for Render<'_> {
-    fn drop(&mut self) {
-        if self.writer.render_format() == RenderFormat::Mp4 {
-            self.writer
-                .write_to_mp4(self.name_length, self.fps, self.verbose);
-        }
-        // drop writer
-        // drop(&self.writer);
-    }
-}
-
 // pub fn pc_to_png(to_png: &mut ToPng, pc: PointCloud<PointXyzRgba>, filename: &str) {
 //     if to_png.point_renderer.is_none() {
 //         to_png.point_renderer = Some(PointCloudRenderer::new(
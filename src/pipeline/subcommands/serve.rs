@@ -0,0 +1,64 @@
+use super::Subcommand;
+use crate::dash::server::DashServer;
+use crate::pipeline::channel::Channel;
+use crate::pipeline::PipelineMessage;
+use crate::simulation::NetworkTrace;
+use clap::Parser;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[clap(
+    about = "Serves a directory of DASH segments over HTTP, optionally throttled by a network trace.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] <DIR>", "serve")
+)]
+pub struct Args {
+    /// directory with the segments (and usually the MPD)
+    dir: PathBuf,
+    /// MPD to serve at /<file name>, if it is not inside the directory
+    #[clap(long)]
+    mpd: Option<PathBuf>,
+    #[clap(long, default_value = "127.0.0.1")]
+    host: String,
+    #[clap(short, long, default_value_t = 3000)]
+    port: u16,
+    /// network trace in Kbps, one sample per line. Every response is throttled to the next sample.
+    #[clap(long)]
+    network_trace: Option<PathBuf>,
+}
+
+pub struct Serve {
+    args: Args,
+}
+
+impl Serve {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        Box::from(Serve {
+            args: Args::parse_from(args),
+        })
+    }
+}
+
+impl Subcommand for Serve {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+        if messages.is_empty() {
+            let mut server = DashServer::new(&self.args.dir);
+            if let Some(mpd) = self.args.mpd.as_ref() {
+                server.set_mpd(mpd);
+            }
+            if let Some(path) = self.args.network_trace.as_ref() {
+                server.set_network_trace(NetworkTrace::new(path));
+            }
+            let addr = format!("{}:{}", self.args.host, self.args.port);
+            println!("Serving {} at http://{}/", self.args.dir.display(), addr);
+            let rt = tokio::runtime::Runtime::new().expect("created tokio runtime");
+            if let Err(e) = rt.block_on(server.serve(addr)) {
+                eprintln!("Server error: {e:#}");
+            }
+            channel.send(PipelineMessage::End);
+        } else {
+            for message in messages {
+                channel.send(message);
+            }
+        }
+    }
+}
@@ -1,12 +1,24 @@
 use clap::ArgAction;
 use clap::Parser;
+use std::path::PathBuf;
 use std::time::Instant;
 
 use crate::{
+    formats::{pointxyzrgba::PointXyzRgba, PointCloud},
     pipeline::{channel::Channel, PipelineMessage},
     reconstruct::poisson_reconstruct::reconstruct,
+    render::camera_path::CameraPath,
+    render::wgpu::{
+        antialias::AntiAlias,
+        camera::{Camera, CameraPosition, CameraState},
+        renderable::Renderable,
+    },
+    simulation::CameraTrace,
     upsample::{
-        interpolate::upsample, interpolate::upsample_grid, upsample_methods::UpsampleMethod,
+        interpolate::upsample,
+        interpolate::upsample_grid,
+        upsample_methods::UpsampleMethod,
+        view_adaptive::{upsample_view_adaptive, UpsampleThresholdArgs, UpsampleThresholds},
     },
 };
 
@@ -31,12 +43,46 @@ pub struct Args {
     colour: bool,
     #[clap(long, action=ArgAction::SetTrue)]
     faces: bool,
+    /// Camera of the view-adaptive method, as in `vv render`
+    #[clap(short = 'x', long, default_value_t = 0.0)]
+    camera_x: f32,
+    #[clap(short = 'y', long, default_value_t = 0.0)]
+    camera_y: f32,
+    #[clap(short = 'z', long, default_value_t = 1.8)]
+    camera_z: f32,
+    #[clap(long = "yaw", default_value_t = -90.0, allow_hyphen_values = true)]
+    camera_yaw: f32,
+    #[clap(long = "pitch", default_value_t = 0.0, allow_hyphen_values = true)]
+    camera_pitch: f32,
+    #[clap(long, default_value_t = 1600)]
+    width: u32,
+    #[clap(long, default_value_t = 900)]
+    height: u32,
+    /// Camera trace to upsample every frame for instead of the fixed camera, one `x,y,z,pitch,yaw,roll` line per frame
+    #[clap(long)]
+    camera_trace: Option<PathBuf>,
+    /// Regions along every axis that the view-adaptive method decides to upsample or not
+    #[clap(long, default_value_t = 6)]
+    partitions: usize,
+    #[clap(flatten)]
+    thresholds: UpsampleThresholdArgs,
 }
 
 pub struct Upsampler {
     factor: usize,
 }
 
+/// Upsamples only the regions that would show holes from the camera of every frame
+pub struct ViewAdaptiveUpsampler {
+    camera_path: CameraPath,
+    camera_state: CameraState,
+    // fixed from the first frame, like the renderers do
+    antialias: Option<AntiAlias>,
+    partitions: usize,
+    thresholds: UpsampleThresholds,
+    count: usize,
+}
+
 pub struct Reconstructer {
     screening: f64,
     density_estimation_depth: usize,
@@ -53,6 +99,30 @@ impl Upsampler {
             UpsampleMethod::Default => Box::new(Upsampler {
                 factor: args.factor,
             }),
+            UpsampleMethod::ViewAdaptive => {
+                let camera_path = match &args.camera_trace {
+                    Some(path) => CameraPath::Trace(CameraTrace::new(path, false)),
+                    None => CameraPath::Fixed(CameraPosition {
+                        position: cgmath::Point3::new(args.camera_x, args.camera_y, args.camera_z),
+                        yaw: cgmath::Rad(args.camera_yaw),
+                        pitch: cgmath::Rad(args.camera_pitch),
+                        up: cgmath::Vector3::unit_y(),
+                    }),
+                };
+                let camera = Camera::new(
+                    (args.camera_x, args.camera_y, args.camera_z),
+                    cgmath::Rad(args.camera_yaw),
+                    cgmath::Rad(args.camera_pitch),
+                );
+                Box::new(ViewAdaptiveUpsampler {
+                    camera_path,
+                    camera_state: CameraState::new(camera, args.width, args.height),
+                    antialias: None,
+                    partitions: args.partitions,
+                    thresholds: args.thresholds.thresholds(),
+                    count: 0,
+                })
+            }
             UpsampleMethod::Spsr => Box::new(Reconstructer {
                 screening: args.screening,
                 density_estimation_depth: args.density_estimation_depth,
@@ -88,7 +158,52 @@ impl Subcommand for Upsampler {
                 }
                 PipelineMessage::Metrics(_)
                 | PipelineMessage::DummyForIncrement
-                | PipelineMessage::MetaData(_, _, _, _)
+                | PipelineMessage::MetaData(_, _, _, _, _)
+                | PipelineMessage::IndexedPointCloudWithName(_, _, _, _)
+                | PipelineMessage::IndexedPointCloudWithTriangleFaces(_, _, _)
+                | PipelineMessage::IndexedPointCloudNormal(_, _) => {}
+            };
+        }
+    }
+}
+
+impl ViewAdaptiveUpsampler {
+    fn upsample(&mut self, pc: &PointCloud<PointXyzRgba>) -> PointCloud<PointXyzRgba> {
+        let pose = self.camera_path.pose(self.count, pc);
+        self.count += 1;
+        if pc.points.is_empty() {
+            return pc.clone();
+        }
+        let antialias = *self.antialias.get_or_insert_with(|| pc.antialias());
+        self.camera_state.update_camera_pos(pose);
+        upsample_view_adaptive(
+            pc,
+            &self.camera_state,
+            &antialias,
+            self.partitions,
+            &self.thresholds,
+        )
+    }
+}
+
+impl Subcommand for ViewAdaptiveUpsampler {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+        for message in messages {
+            match message {
+                PipelineMessage::IndexedPointCloud(pc, i) => {
+                    let upsampled_pc = self.upsample(&pc);
+                    channel.send(PipelineMessage::IndexedPointCloud(upsampled_pc, i));
+                }
+                PipelineMessage::SubcommandMessage(subcommand_object, i) => {
+                    let upsampled_pc = self.upsample(subcommand_object.get_content());
+                    channel.send(PipelineMessage::IndexedPointCloud(upsampled_pc, i));
+                }
+                PipelineMessage::End => {
+                    channel.send(message);
+                }
+                PipelineMessage::Metrics(_)
+                | PipelineMessage::DummyForIncrement
+                | PipelineMessage::MetaData(_, _, _, _, _)
                 | PipelineMessage::IndexedPointCloudWithName(_, _, _, _)
                 | PipelineMessage::IndexedPointCloudWithTriangleFaces(_, _, _)
                 | PipelineMessage::IndexedPointCloudNormal(_, _) => {}
@@ -130,7 +245,7 @@ impl Subcommand for Reconstructer {
                 PipelineMessage::Metrics(_)
                 | PipelineMessage::DummyForIncrement
                 | PipelineMessage::IndexedPointCloudWithName(_, _, _, _)
-                | PipelineMessage::MetaData(_, _, _, _)
+                | PipelineMessage::MetaData(_, _, _, _, _)
                 | PipelineMessage::SubcommandMessage(_, _)
                 | PipelineMessage::IndexedPointCloudWithTriangleFaces(_, _, _) => {}
                 PipelineMessage::End => {
@@ -310,8 +310,9 @@ impl Subcommand for Write {
                 }
                 PipelineMessage::MetaData(
                     bound,
+                    tiles,
                     base_point_num,
-                    additional_point_num,
+                    level_point_num,
                     partitions,
                 ) => {
                     if self.metadata.is_none() {
@@ -319,8 +320,9 @@ impl Subcommand for Write {
                     }
                     self.metadata.as_mut().unwrap().next(
                         bound.clone(),
+                        tiles.clone(),
                         base_point_num.clone(),
-                        additional_point_num.clone(),
+                        level_point_num.clone(),
                     );
                     self.metadata.as_mut().unwrap().partitions = *partitions;
                 }
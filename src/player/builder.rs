@@ -1,3 +1,4 @@
+use super::timeline::{PlaybackState, Seek};
 use crate::render::wgpu::camera::Camera;
 use std::collections::HashMap;
 use winit::dpi::PhysicalSize;
@@ -20,10 +21,18 @@ impl RenderEvent {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum EventType {
     MoveTo(usize),
+    Seek(Seek),
     Toggle,
+    SetSpeed(f32),
+    SetReverse(bool),
+    SetLooping(bool),
+    /// start or end the A-B loop at the current frame
+    SetLoopStart,
+    SetLoopEnd,
+    ClearLoop,
     Info(RenderInformation),
     Repaint,
     Shutdown,
@@ -35,6 +44,11 @@ pub struct RenderInformation {
     pub camera: Camera,
     pub current_position: usize,
     pub fps: f32,
+    pub state: PlaybackState,
+    pub speed: f32,
+    pub reverse: bool,
+    pub looping: bool,
+    pub loop_region: Option<(usize, usize)>,
 }
 
 pub trait Attachable {
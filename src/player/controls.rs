@@ -1,6 +1,7 @@
 use super::builder::{Attachable, EventType, RenderEvent, RenderInformation, Windowed};
+use super::timeline::{PlaybackState, Seek, MAX_SPEED, MIN_SPEED};
 use crate::render::wgpu::gpu::WindowGpu;
-use egui::{Button, CentralPanel, Context, FontDefinitions, Label, Slider};
+use egui::{Button, CentralPanel, Checkbox, Context, FontDefinitions, Label, Slider, TextEdit};
 use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
 use egui_winit_platform::{Platform, PlatformDescriptor};
 use epi::Frame;
@@ -80,6 +81,11 @@ impl Attachable for Controller {
             prev_slider_position: 0,
             slider_position: 0,
             slider_end: self.slider_end,
+            speed: 1.0,
+            reverse: false,
+            looping: true,
+            seek_to: String::new(),
+            seek_error: None,
             info: None,
             listeners: Vec::new(),
             display_help: false,
@@ -101,6 +107,12 @@ pub struct ControlWindow {
     prev_slider_position: usize,
     slider_position: usize,
     slider_end: usize,
+    // mirrors of the playback settings, sent to the renderer when they are changed here
+    speed: f32,
+    reverse: bool,
+    looping: bool,
+    seek_to: String,
+    seek_error: Option<String>,
     info: Option<RenderInformation>,
     listeners: Vec<WindowId>,
     display_help: bool,
@@ -110,7 +122,11 @@ pub struct ControlWindow {
 impl ControlWindow {
     fn update(&mut self, ctx: &Context, _frame: &Frame) {
         CentralPanel::default().show(ctx, |ui| {
-            if ui.add(Button::new("Play / Pause")).clicked() {
+            let play_or_pause = match self.info.map(|info| info.state) {
+                Some(PlaybackState::Play) => "Pause",
+                _ => "Play",
+            };
+            if ui.add(Button::new(play_or_pause)).clicked() {
                 self.toggle();
             };
 
@@ -120,6 +136,63 @@ impl ControlWindow {
                     .integer(),
             );
 
+            let speed = self.speed;
+            ui.add(
+                Slider::new(&mut self.speed, MIN_SPEED..=MAX_SPEED)
+                    .logarithmic(true)
+                    .text("speed"),
+            );
+            if self.speed != speed {
+                self.send(EventType::SetSpeed(self.speed));
+            }
+
+            ui.horizontal(|ui| {
+                if ui.add(Checkbox::new(&mut self.reverse, "Reverse")).changed() {
+                    self.send(EventType::SetReverse(self.reverse));
+                }
+                if ui.add(Checkbox::new(&mut self.looping, "Loop")).changed() {
+                    self.send(EventType::SetLooping(self.looping));
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui.add(Button::new("Set A")).clicked() {
+                    self.send(EventType::SetLoopStart);
+                }
+                if ui.add(Button::new("Set B")).clicked() {
+                    self.send(EventType::SetLoopEnd);
+                }
+                if ui.add(Button::new("Clear A-B")).clicked() {
+                    self.send(EventType::ClearLoop);
+                }
+                let region = match self.info.and_then(|info| info.loop_region) {
+                    Some((start, end)) => format!("A-B loop: {start} - {end}"),
+                    None => "A-B loop: off".to_string(),
+                };
+                ui.add(Label::new(region));
+            });
+
+            ui.horizontal(|ui| {
+                let response = ui.add(
+                    TextEdit::singleline(&mut self.seek_to)
+                        .hint_text("frame, 4.5s or 1:02.5")
+                        .desired_width(150.0),
+                );
+                let entered = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.add(Button::new("Seek")).clicked() || entered {
+                    match self.seek_to.parse::<Seek>() {
+                        Ok(seek) => {
+                            self.seek_error = None;
+                            self.send(EventType::Seek(seek));
+                        }
+                        Err(e) => self.seek_error = Some(e),
+                    }
+                }
+            });
+            if let Some(e) = &self.seek_error {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+
             if let Some(info) = self.info {
                 ui.add(Label::new(format!(
                     "Camera Position: PointXyz [{:.2}, {:.2}, {:.2}]", info.camera.position.x, info.camera.position.y, info.camera.position.z
@@ -182,6 +255,13 @@ impl ControlWindow {
                         ui.label("Space      Key - Toggles  Play / Pause");
                         ui.label("LeftArrow  Key - Rewinds  by 1 frame");
                         ui.label("RightArrow Key - Advances by 1 frame");
+                        ui.label("DownArrow  Key - Rewinds  by 1 second");
+                        ui.label("UpArrow    Key - Advances by 1 second");
+                        ui.label("Home / End Key - Jumps to the start / end of the A-B loop or the video");
+                        ui.label("[ / ]      Key - Halves / doubles the playback speed");
+                        ui.label("V          Key - Toggles reverse playback");
+                        ui.label("Z / X      Key - Sets the start (A) / end (B) of the loop at the current frame");
+                        ui.label("C          Key - Clears the A-B loop");
                         ui.label("L          Key - Rotates camera horizontally(around the Y axis) clockwise");
                         ui.label("J          Key - Rotates camera horizontally(around the Y axis) counterclockwise");
                         ui.label("I          Key - Rotates camera vertically(around the X axis) clockwise");
@@ -220,6 +300,9 @@ impl Windowed for ControlWindow {
                     self.info = Some(*info);
                     self.prev_slider_position = info.current_position;
                     self.slider_position = info.current_position;
+                    self.speed = info.speed;
+                    self.reverse = info.reverse;
+                    self.looping = info.looping;
                 }
                 _ => {}
             },
@@ -234,26 +317,21 @@ impl Windowed for ControlWindow {
 
 impl ControlWindow {
     fn toggle(&self) {
-        let sender = self.event_proxy.0.lock().unwrap();
-
-        for &listener in &self.listeners {
-            sender
-                .send_event(RenderEvent {
-                    window_id: listener,
-                    event_type: EventType::Toggle,
-                })
-                .unwrap();
-        }
+        self.send(EventType::Toggle);
     }
 
     fn move_to(&self, position: usize) {
+        self.send(EventType::MoveTo(position));
+    }
+
+    fn send(&self, event_type: EventType) {
         let sender = self.event_proxy.0.lock().unwrap();
 
         for &listener in &self.listeners {
             sender
                 .send_event(RenderEvent {
                     window_id: listener,
-                    event_type: EventType::MoveTo(position),
+                    event_type: event_type.clone(),
                 })
                 .unwrap();
         }
@@ -4,3 +4,5 @@ pub mod metrics_reader;
 pub mod render_manager;
 pub mod renderer;
 pub mod resolution_controller;
+pub mod scene;
+pub mod timeline;
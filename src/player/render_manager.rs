@@ -1,15 +1,21 @@
 use std::marker::PhantomData;
 use std::path::Path;
 use std::process::exit;
+use std::sync::Arc;
 
 use super::resolution_controller::ResolutionController;
+use crate::dash::fetcher::Fetcher;
+use crate::dash::lod::LodStream;
 use crate::formats::metadata::MetaData;
 use crate::formats::pointxyzrgba::PointXyzRgba;
 use crate::formats::PointCloud;
+use crate::lodify::lodify::additional_dir;
+use crate::lodify::progressive::LodSegment;
+use crate::render::wgpu::antialias::AntiAlias;
 use crate::render::wgpu::camera::CameraState;
 use crate::render::wgpu::reader::{LODFileReader, PointCloudFileReader, RenderReader};
 use crate::render::wgpu::renderable::Renderable;
-use crate::render::wgpu::upsampler::Upsampler;
+use crate::upsample::view_adaptive::{upsample_view_adaptive, visible_points, UpsampleThresholds};
 
 pub trait RenderManager<T: Renderable> {
     fn start(&mut self) -> Option<T>;
@@ -57,10 +63,14 @@ impl AdaptiveLODManager {
                 exit(1);
             };
 
-            let add_paths =
-                (0..metadata.partitions.0 * metadata.partitions.1 * metadata.partitions.2)
-                    .map(|i| format!("{}/{}", src, i))
-                    .collect::<Vec<_>>();
+            // one directory per additional level and tile, level by level
+            let segments = metadata.max_tiles();
+            let add_paths = (1..=metadata.levels())
+                .flat_map(|level| {
+                    (0..segments)
+                        .map(move |segment| format!("{}/{}", src, additional_dir(segment, level)))
+                })
+                .collect::<Vec<_>>();
 
             let add_dirs = add_paths.iter().map(|s| Path::new(s)).collect::<Vec<_>>();
 
@@ -116,11 +126,11 @@ impl AdaptiveLODManager {
 
         let metadata = self.metadata.as_ref().unwrap();
         let base_point_num = metadata.base_point_num.get(index).unwrap();
-        let bound = metadata.bounds.get(index).unwrap().clone();
+        let tiles = metadata.tiles(index);
 
         if self.camera_state.is_none() || self.resolution_controller.is_none() {
             let mut pc = self.reader.get_at(index).unwrap();
-            pc.self_segment(base_point_num, &bound.partition(metadata.partitions));
+            pc.self_segment(base_point_num, &tiles);
             return Some(pc);
         }
 
@@ -133,27 +143,33 @@ impl AdaptiveLODManager {
         self.current_index = index;
         self.additional_points_loaded = additional_num_points_desired;
 
-        let extra_point_num = metadata.additional_point_num.get(index).unwrap();
-        let to_load = self
+        // fill the levels in order, so that a prefix of them is loaded
+        let mut remaining = self
             .additional_points_loaded
             .iter()
             .enumerate()
-            .map(|(segment, &num)| (num - base_point_num[segment]).min(extra_point_num[segment]))
+            .map(|(segment, &num)| num.saturating_sub(base_point_num[segment]))
             .collect::<Vec<_>>();
-
-        let mut pc = self.reader.get_with_additional_at(index, &to_load).unwrap();
-
+        // one entry per additional directory, zero for the tiles this frame does not have
+        let mut to_load = vec![];
         let mut offsets = base_point_num.clone();
-        offsets.extend(&to_load);
-
         let mut bound_indices = (0..base_point_num.len()).collect::<Vec<_>>();
-        bound_indices.extend((0..to_load.len()).collect::<Vec<_>>());
+        for level in metadata.additional_levels(index) {
+            for segment in 0..metadata.max_tiles() {
+                let available = level.get(segment).copied().unwrap_or(0);
+                let num = remaining.get(segment).map_or(0, |&r| r.min(available));
+                to_load.push(num);
+                if num > 0 {
+                    remaining[segment] -= num;
+                    offsets.push(num);
+                    bound_indices.push(segment);
+                }
+            }
+        }
+
+        let mut pc = self.reader.get_with_additional_at(index, &to_load).unwrap();
 
-        pc.self_segment_with_bound_indices(
-            &offsets,
-            &bound_indices,
-            &bound.partition(metadata.partitions),
-        );
+        pc.self_segment_with_bound_indices(&offsets, &bound_indices, &tiles);
 
         Some(pc)
     }
@@ -218,17 +234,216 @@ impl RenderManager<PointCloud<PointXyzRgba>> for AdaptiveLODManager {
     }
 }
 
+/// Fetch of a segment, or refinement of the shown one, running on the runtime
+type SegmentTask = tokio::task::JoinHandle<anyhow::Result<LodSegment>>;
+
+/// Streams a progressive LOD MPD, see [`crate::lodify::progressive`].
+///
+/// Segments are fetched on the runtime, with as many levels per tile as the camera asks for: the next segment is
+/// prefetched while the current one is shown, and levels are added to the current segment when the camera moves
+/// closer. `get_at` only waits for a segment that was not prefetched in time, e.g. after a seek.
+pub struct StreamingLODManager {
+    runtime: tokio::runtime::Runtime,
+    stream: Arc<tokio::sync::Mutex<LodStream>>,
+    total_frames: usize,
+    segment_frames: usize,
+    camera_state: Option<CameraState>,
+    /// the segment shown and its first frame
+    segment: Option<(usize, LodSegment)>,
+    /// the prefetched segment and its first frame
+    next: Option<(usize, LodSegment)>,
+    /// the segment being fetched or refined and its first frame, one at a time
+    task: Option<(usize, SegmentTask)>,
+    _download_dir: tempfile::TempDir,
+}
+
+impl StreamingLODManager {
+    /// Streams the object with `srcObjectId` `object_id` of the mpd
+    pub fn new(mpd_url: &str, object_id: u8) -> anyhow::Result<Self> {
+        let runtime = tokio::runtime::Runtime::new()?;
+        let download_dir = tempfile::tempdir()?;
+        let fetcher = runtime.block_on(Fetcher::new(mpd_url, download_dir.path(), false))?;
+        let total_frames = fetcher.mpd_parser.total_frames();
+        let segment_frames = fetcher.mpd_parser.segment_frames()? as usize;
+        let stream = LodStream::new(fetcher, object_id)?;
+        Ok(Self {
+            runtime,
+            stream: Arc::new(tokio::sync::Mutex::new(stream)),
+            total_frames,
+            segment_frames,
+            camera_state: None,
+            segment: None,
+            next: None,
+            task: None,
+            _download_dir: download_dir,
+        })
+    }
+
+    fn spawn_fetch(&mut self, start: usize) {
+        let stream = self.stream.clone();
+        let camera_state = self.camera_state.clone();
+        let task = self.runtime.spawn(async move {
+            let mut stream = stream.lock().await;
+            stream.fetch(start as u64, camera_state.as_ref()).await
+        });
+        self.task = Some((start, task));
+    }
+
+    /// Refines a copy of the shown segment, which stays on screen until the refined one replaces it
+    fn spawn_refine(&mut self, camera_state: &CameraState) {
+        let Some((start, segment)) = self.segment.clone() else {
+            return;
+        };
+        let stream = self.stream.clone();
+        let camera_state = camera_state.clone();
+        let task = self.runtime.spawn(async move {
+            let mut segment = segment;
+            let mut stream = stream.lock().await;
+            stream
+                .refine(&mut segment, start as u64, &camera_state)
+                .await?;
+            Ok(segment)
+        });
+        self.task = Some((start, task));
+    }
+
+    /// Collects the result of the task if it is done, or `wait`s for it. Returns whether the shown segment changed.
+    fn poll(&mut self, wait: bool) -> bool {
+        let Some((start, task)) = self.task.take() else {
+            return false;
+        };
+        if !wait && !task.is_finished() {
+            self.task = Some((start, task));
+            return false;
+        }
+        match self.runtime.block_on(task) {
+            Ok(Ok(segment)) => {
+                if matches!(&self.segment, Some((first, _)) if *first == start) {
+                    self.segment = Some((start, segment));
+                    return true;
+                }
+                self.next = Some((start, segment));
+            }
+            Ok(Err(e)) => eprintln!("Failed to stream the segment of frame {start}: {e:#}"),
+            Err(e) => eprintln!("Streaming the segment of frame {start} failed: {e}"),
+        }
+        false
+    }
+
+    /// Whether the camera asks for levels of the shown segment that are not loaded yet
+    fn wants_levels(&self, camera_state: &CameraState) -> bool {
+        let (Some((_, segment)), Ok(mut stream)) = (self.segment.as_ref(), self.stream.try_lock())
+        else {
+            return false;
+        };
+        let wanted = stream.wanted_levels(segment, camera_state);
+        wanted
+            .iter()
+            .enumerate()
+            .any(|(tile, &levels)| levels.min(segment.levels(tile)) > segment.loaded_levels(tile))
+    }
+
+    /// Refines the shown segment if the camera asks for it, or else prefetches the next one
+    fn schedule(&mut self, start: usize) {
+        if self.task.is_some() {
+            return;
+        }
+        if let Some(camera_state) = self.camera_state.clone() {
+            if self.wants_levels(&camera_state) {
+                self.spawn_refine(&camera_state);
+                return;
+            }
+        }
+        let next = start + self.segment_frames;
+        if next < self.total_frames && !matches!(&self.next, Some((first, _)) if *first == next) {
+            self.spawn_fetch(next);
+        }
+    }
+
+    fn load(&mut self, index: usize) -> anyhow::Result<PointCloud<PointXyzRgba>> {
+        let start = index / self.segment_frames * self.segment_frames;
+        self.poll(false);
+        if !matches!(&self.segment, Some((first, _)) if *first == start) {
+            if !matches!(&self.next, Some((first, _)) if *first == start) {
+                // not prefetched in time, playback waits for the segment
+                if !matches!(&self.task, Some((first, _)) if *first == start) {
+                    if let Some((_, task)) = self.task.take() {
+                        task.abort();
+                    }
+                    self.spawn_fetch(start);
+                }
+                self.poll(true);
+            }
+            if matches!(&self.next, Some((first, _)) if *first == start) {
+                self.segment = self.next.take();
+            }
+        }
+        let pc = match self.segment.as_ref() {
+            Some((first, segment)) if *first == start => segment
+                .frame(index - start)
+                .ok_or_else(|| anyhow::anyhow!("frame {index} is missing from its segment"))?,
+            _ => anyhow::bail!("the segment of frame {index} could not be fetched"),
+        };
+        self.schedule(start);
+        Ok(pc)
+    }
+}
+
+impl RenderManager<PointCloud<PointXyzRgba>> for StreamingLODManager {
+    fn start(&mut self) -> Option<PointCloud<PointXyzRgba>> {
+        self.get_at(0)
+    }
+
+    fn get_at(&mut self, index: usize) -> Option<PointCloud<PointXyzRgba>> {
+        match self.load(index) {
+            Ok(pc) => Some(pc),
+            Err(e) => {
+                eprintln!("Failed to stream frame {index}: {e:#}");
+                None
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.total_frames
+    }
+
+    fn is_empty(&self) -> bool {
+        self.total_frames == 0
+    }
+
+    fn set_len(&mut self, _len: usize) {}
+
+    fn set_camera_state(&mut self, camera_state: Option<CameraState>) {
+        self.camera_state = camera_state;
+    }
+
+    fn should_redraw(&mut self, camera_state: &CameraState) -> bool {
+        // redraw once the refined segment has arrived
+        if self.poll(false) {
+            return true;
+        }
+        if self.task.is_none() && self.wants_levels(camera_state) {
+            self.spawn_refine(camera_state);
+        }
+        false
+    }
+}
+
 pub struct AdaptiveUpsamplingManager {
     reader: PointCloudFileReader,
 
     // For adaptive loading
     camera_state: Option<CameraState>,
+    // Fixed from the first frame, like the renderer does
+    antialias: Option<AntiAlias>,
 
     // As the temporary cache
     current_index: usize,
     pc: Option<PointCloud<PointXyzRgba>>,
 
     should_adaptive_upsample: bool,
+    thresholds: UpsampleThresholds,
 }
 
 impl AdaptiveUpsamplingManager {
@@ -247,11 +462,18 @@ impl AdaptiveUpsamplingManager {
             pc: None,
             reader,
             camera_state: None,
+            antialias: None,
             current_index: usize::MAX,
             should_adaptive_upsample,
+            thresholds: UpsampleThresholds::default(),
         }
     }
 
+    pub fn with_upsample_thresholds(mut self, thresholds: UpsampleThresholds) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
     pub fn len(&self) -> usize {
         self.reader.len()
     }
@@ -259,7 +481,11 @@ impl AdaptiveUpsamplingManager {
 
 impl RenderManager<PointCloud<PointXyzRgba>> for AdaptiveUpsamplingManager {
     fn start(&mut self) -> Option<PointCloud<PointXyzRgba>> {
-        self.reader.get_at(0)
+        let pc = self.reader.get_at(0)?;
+        if !pc.points.is_empty() {
+            self.antialias = Some(pc.antialias());
+        }
+        Some(pc)
     }
 
     fn get_at(&mut self, index: usize) -> Option<PointCloud<PointXyzRgba>> {
@@ -274,23 +500,21 @@ impl RenderManager<PointCloud<PointXyzRgba>> for AdaptiveUpsamplingManager {
             self.current_index = index;
         }
 
-        let camera_state = self.camera_state.as_ref().unwrap();
-
-        let mut visible_pc =
-            Upsampler::get_visible_points(self.pc.as_ref().unwrap().clone(), camera_state);
-        let mut needs_upsampling = true;
-        while needs_upsampling {
-            let upsampled_points = Upsampler::upsample(&visible_pc, camera_state, PARTITION_SIZE);
-
-            if let Some(upsampled_points) = upsampled_points {
-                self.pc.as_mut().unwrap().combine(&upsampled_points);
-                visible_pc.combine(&upsampled_points);
-            } else {
-                needs_upsampling = false;
-            }
+        let pc = self.pc.as_ref().unwrap();
+        if pc.points.is_empty() {
+            return Some(pc.clone());
         }
-
-        Some(visible_pc)
+        let camera_state = self.camera_state.as_ref().unwrap();
+        let antialias = *self.antialias.get_or_insert_with(|| pc.antialias());
+
+        let visible_pc = visible_points(pc.clone(), camera_state, &antialias);
+        Some(upsample_view_adaptive(
+            &visible_pc,
+            camera_state,
+            &antialias,
+            PARTITION_SIZE,
+            &self.thresholds,
+        ))
     }
 
     fn len(&self) -> usize {
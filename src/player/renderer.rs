@@ -1,6 +1,7 @@
 use super::builder::{Attachable, EventType, RenderEvent, RenderInformation, Windowed};
 use super::metrics_reader::MetricsReader;
 use super::render_manager::RenderManager;
+use super::timeline::{PlaybackState, Seek, Timeline};
 // use std::f16::consts::E;
 // use winit::dpi::{PhysicalPosition, PhysicalSize};
 use crate::render::wgpu::camera::{Camera, CameraState};
@@ -8,6 +9,7 @@ use crate::render::wgpu::color::parse_wgpu_color;
 use crate::render::wgpu::gpu::WindowGpu;
 use crate::render::wgpu::point_cloud_renderer::PointCloudRenderer;
 use crate::render::wgpu::renderable::Renderable;
+use crate::render::wgpu::splat::Splat;
 use log::debug;
 use std::iter;
 use std::marker::PhantomData;
@@ -21,12 +23,6 @@ use winit::event::{
 use winit::event_loop::{EventLoop, EventLoopProxy};
 use winit::window::{Window, WindowBuilder, WindowId};
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub enum PlaybackState {
-    Paused,
-    Play,
-}
-
 pub struct Renderer<T, U>
 where
     T: RenderManager<U>,
@@ -39,6 +35,7 @@ where
     metrics_reader: Option<MetricsReader>,
     _data: PhantomData<U>,
     bg_color: wgpu::Color,
+    splat: Splat,
 }
 
 impl<T, U> Renderer<T, U>
@@ -62,8 +59,15 @@ where
             metrics_reader,
             _data: PhantomData::default(),
             bg_color: parse_wgpu_color(bg_color_str).unwrap(),
+            splat: Splat::default(),
         }
     }
+
+    /// Set how every point is drawn, a single pixel by default
+    pub fn with_splat(mut self, splat: Splat) -> Self {
+        self.splat = splat;
+        self
+    }
 }
 
 impl<T, U> Attachable for Renderer<T, U>
@@ -94,6 +98,7 @@ where
             self.camera_state,
             self.metrics_reader,
             self.bg_color,
+            self.splat,
         );
         (state, window)
     }
@@ -121,9 +126,7 @@ where
     // Playback
     current_position: usize,
     fps: f32, // the average playout fps
-    time_to_advance: std::time::Duration,
-    state: PlaybackState,
-    time_since_last_update: std::time::Duration,
+    timeline: Timeline,
     reader: T,
 
     // Rendering Stats
@@ -231,7 +234,14 @@ where
                 event_type,
             }) if *window_id == window.id() => match event_type {
                 EventType::Toggle => self.toggle(),
-                EventType::MoveTo(position) => self.move_to(*position),
+                EventType::MoveTo(position) => self.seek(Seek::Frame(*position)),
+                EventType::Seek(seek) => self.seek(*seek),
+                EventType::SetSpeed(speed) => self.timeline.set_speed(*speed),
+                EventType::SetReverse(reverse) => self.timeline.set_reverse(*reverse),
+                EventType::SetLooping(looping) => self.timeline.set_looping(*looping),
+                EventType::SetLoopStart => self.set_loop_start(),
+                EventType::SetLoopEnd => self.set_loop_end(),
+                EventType::ClearLoop => self.timeline.set_loop_region(None),
                 _ => {}
             },
             _ => {}
@@ -241,7 +251,8 @@ where
     fn resize(&mut self, new_size: PhysicalSize<u32>) {
         self.gpu.resize(new_size);
         self.camera_state.resize(new_size);
-        self.pcd_renderer.resize(new_size, &self.gpu.device);
+        self.pcd_renderer
+            .resize(new_size, &self.gpu.device, &self.gpu.queue);
     }
 }
 
@@ -250,6 +261,7 @@ where
     T: RenderManager<U>,
     U: Renderable,
 {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         event_proxy: EventLoopProxy<RenderEvent>,
         gpu: WindowGpu,
@@ -258,11 +270,12 @@ where
         camera_state: CameraState,
         metrics_reader: Option<MetricsReader>,
         bg_color: wgpu::Color,
+        splat: Splat,
     ) -> Self {
         let initial_render = reader
             .start()
             .expect("There should be at least one point cloud to render!");
-        let pcd_renderer = PointCloudRenderer::new(
+        let mut pcd_renderer = PointCloudRenderer::new(
             &gpu.device,
             gpu.config.format,
             &initial_render,
@@ -270,6 +283,7 @@ where
             &camera_state,
             bg_color,
         );
+        pcd_renderer.set_splat(&gpu.queue, splat);
 
         let metrics_renderer = MetricsRenderer::new(gpu.size, &gpu.device);
 
@@ -287,9 +301,7 @@ where
 
             current_position: 0,
             fps,
-            time_to_advance: std::time::Duration::from_secs(1).div_f32(fps),
-            state: PlaybackState::Paused,
-            time_since_last_update: std::time::Duration::from_secs(0),
+            timeline: Timeline::new(reader.len(), fps),
             reader,
 
             metrics_reader,
@@ -308,18 +320,24 @@ where
     }
 
     fn toggle(&mut self) {
-        match self.state {
-            PlaybackState::Play => self.pause(),
-            PlaybackState::Paused => self.play(),
-        }
+        self.timeline.toggle();
     }
 
-    fn play(&mut self) {
-        self.state = PlaybackState::Play;
+    fn pause(&mut self) {
+        self.timeline.set_state(PlaybackState::Paused);
     }
 
-    fn pause(&mut self) {
-        self.state = PlaybackState::Paused;
+    /// Show the frame the timeline moved to, if it is another one
+    fn follow_timeline(&mut self) {
+        let position = self.timeline.position();
+        if position != self.current_position {
+            self.move_to(position);
+        }
+    }
+
+    fn seek(&mut self, seek: Seek) {
+        let position = self.timeline.seek(seek);
+        self.move_to(position);
     }
 
     fn redisplay(&mut self) {
@@ -344,22 +362,27 @@ where
         //     "time taken: {}",
         //     time_taken.max(self.time_to_advance).as_secs_f32()
         // );
-        self.fps =
-            0.9 * self.fps + 0.1 * (1.0 / time_taken.max(self.time_to_advance).as_secs_f32());
+        self.fps = 0.9 * self.fps
+            + 0.1 * (1.0 / time_taken.max(self.timeline.frame_interval()).as_secs_f32());
     }
 
-    fn back(&mut self) {
-        if self.current_position > 0 {
-            self.move_to(self.current_position - 1);
-        }
+    /// Move by whole frames while paused
+    fn step(&mut self, frames: i64) {
+        self.pause();
+        self.timeline.step(frames);
+        self.follow_timeline();
     }
 
-    fn advance(&mut self) {
-        if self.current_position == self.reader.len() - 1 {
-            self.move_to(0);
-        } else {
-            self.move_to(self.current_position + 1);
-        }
+    /// Marks the frame on screen as the start of the A-B loop
+    fn set_loop_start(&mut self) {
+        self.timeline.set_loop_start(self.current_position);
+        self.follow_timeline();
+    }
+
+    /// Marks the frame on screen as the end of the A-B loop
+    fn set_loop_end(&mut self) {
+        self.timeline.set_loop_end(self.current_position);
+        self.follow_timeline();
     }
 
     fn handle_keyboard_input(&mut self, event: &DeviceEvent) {
@@ -373,14 +396,41 @@ where
                 (VirtualKeyCode::Space, ElementState::Pressed) => {
                     self.toggle();
                 }
-                (VirtualKeyCode::Left, ElementState::Pressed) => {
-                    self.pause();
-                    self.back();
+                (VirtualKeyCode::Left, ElementState::Pressed) => self.step(-1),
+                (VirtualKeyCode::Right, ElementState::Pressed) => self.step(1),
+                // one second back or forward, at the frame rate of the content
+                (VirtualKeyCode::Down, ElementState::Pressed) => {
+                    let seconds = self.current_position as f32 / self.timeline.fps() - 1.0;
+                    self.seek(Seek::Time(seconds.max(0.0)));
+                }
+                (VirtualKeyCode::Up, ElementState::Pressed) => {
+                    let seconds = self.current_position as f32 / self.timeline.fps() + 1.0;
+                    self.seek(Seek::Time(seconds));
+                }
+                // start and end of the A-B loop, or of the sequence
+                (VirtualKeyCode::Home, ElementState::Pressed) => {
+                    let start = self.timeline.loop_region().map_or(0, |(start, _)| start);
+                    self.seek(Seek::Frame(start));
+                }
+                (VirtualKeyCode::End, ElementState::Pressed) => {
+                    let end = self
+                        .timeline
+                        .loop_region()
+                        .map_or(self.reader.len().saturating_sub(1), |(_, end)| end);
+                    self.seek(Seek::Frame(end));
+                }
+                (VirtualKeyCode::LBracket, ElementState::Pressed) => {
+                    self.timeline.set_speed(self.timeline.speed() / 2.0)
+                }
+                (VirtualKeyCode::RBracket, ElementState::Pressed) => {
+                    self.timeline.set_speed(self.timeline.speed() * 2.0)
                 }
-                (VirtualKeyCode::Right, ElementState::Pressed) => {
-                    self.pause();
-                    self.advance();
+                (VirtualKeyCode::V, ElementState::Pressed) => {
+                    self.timeline.set_reverse(!self.timeline.is_reverse())
                 }
+                (VirtualKeyCode::Z, ElementState::Pressed) => self.set_loop_start(),
+                (VirtualKeyCode::X, ElementState::Pressed) => self.set_loop_end(),
+                (VirtualKeyCode::C, ElementState::Pressed) => self.timeline.set_loop_region(None),
                 _ => {}
             }
         }
@@ -393,11 +443,10 @@ where
         self.pcd_renderer
             .update_camera(&self.gpu.queue, self.camera_state.camera_uniform);
 
-        if self.state == PlaybackState::Play {
-            self.time_since_last_update += dt;
-            if self.time_since_last_update >= self.time_to_advance {
-                self.advance();
-                self.time_since_last_update = Duration::from_secs(0);
+        if self.timeline.state() == PlaybackState::Play {
+            // the clock runs on the time between redraws, frames that took too long to load are skipped
+            if let Some(position) = self.timeline.advance(dt) {
+                self.move_to(position);
             }
         } else if self.reader.should_redraw(&self.camera_state) {
             self.redisplay();
@@ -407,6 +456,11 @@ where
             camera: self.camera_state.camera,
             current_position: self.current_position,
             fps: self.fps,
+            state: self.timeline.state(),
+            speed: self.timeline.speed(),
+            reverse: self.timeline.is_reverse(),
+            looping: self.timeline.is_looping(),
+            loop_region: self.timeline.loop_region(),
         };
 
         for listener in &self.listeners {
@@ -30,15 +30,16 @@ impl ResolutionController {
         }
     }
 
+    /// Replaces the layout the point budgets are computed for, e.g. with the one of the next segment.
+    pub fn set_metadata(&mut self, metadata: Option<MetaData>) {
+        self.metadata = metadata;
+    }
+
     pub fn get_desired_num_points(&self, index: usize, camera_state: &CameraState) -> Vec<usize> {
         let metadata = self.metadata.as_ref().unwrap();
 
         // let centroids = metadata.centroids.get(index).unwrap();
-        let bounds = metadata
-            .bounds
-            .get(index)
-            .unwrap()
-            .partition(metadata.partitions);
+        let bounds = metadata.tiles(index);
         let base_point_num = metadata.base_point_num.get(index).unwrap();
 
         let mut desired_num_points = vec![0; bounds.len()];
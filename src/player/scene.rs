@@ -0,0 +1,403 @@
+//! Several point cloud sequences composed into one scene.
+//!
+//! A scene is described by a json file listing its objects, each a local directory or an mpd with its own
+//! transform:
+//! ```json
+//! {
+//!     "objects": [
+//!         { "src": "./longdress", "translation": [-400, 0, 0] },
+//!         { "src": "http://localhost:3000/soldier.mpd", "object_id": 0, "yaw": 90, "scale": 1.2 }
+//!     ]
+//! }
+//! ```
+//!
+//! Every object is loaded by its own [`RenderManager`], so it is buffered and adapted independently.
+//! The frames of all objects are merged into one point cloud, which the renderer draws with a single depth buffer,
+//! so objects occlude each other correctly.
+
+use super::render_manager::RenderManager;
+use crate::formats::pointxyzrgba::PointXyzRgba;
+use crate::formats::PointCloud;
+use crate::render::wgpu::antialias::AntiAlias;
+use crate::render::wgpu::camera::{CameraPosition, CameraState};
+use crate::render::wgpu::renderable::Renderable;
+use anyhow::{bail, Context};
+use cgmath::{Deg, EuclideanSpace, InnerSpace, Matrix, Matrix3, Point3, Rad, Vector3};
+use std::path::Path;
+
+/// Places an object in the scene: points are scaled, turned about the vertical axis and then translated
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(default)]
+pub struct Transform {
+    /// in the units of the point clouds
+    pub translation: [f32; 3],
+    /// rotation about the y axis in degrees
+    pub yaw: f32,
+    pub scale: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: [0.0; 3],
+            yaw: 0.0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Transform {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn rotation(&self) -> Matrix3<f32> {
+        Matrix3::from_angle_y(Deg(self.yaw))
+    }
+
+    pub fn apply(&self, pc: &mut PointCloud<PointXyzRgba>) {
+        if self.is_identity() {
+            return;
+        }
+        let rotation = self.rotation() * self.scale;
+        let translation = Vector3::from(self.translation);
+        for point in pc.points.iter_mut() {
+            let p = rotation * Vector3::new(point.x, point.y, point.z) + translation;
+            point.x = p.x;
+            point.y = p.y;
+            point.z = p.z;
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SceneObject {
+    /// directory of point cloud files or url of an mpd
+    pub src: String,
+    /// `srcObjectId` of the adaptation sets of the object in the mpd
+    #[serde(default)]
+    pub object_id: u8,
+    #[serde(flatten)]
+    pub transform: Transform,
+}
+
+impl SceneObject {
+    /// An object left where it is
+    pub fn new(src: String) -> Self {
+        Self {
+            src,
+            object_id: 0,
+            transform: Transform::default(),
+        }
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.src.starts_with("http://") || self.src.starts_with("https://")
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Scene {
+    pub objects: Vec<SceneObject>,
+}
+
+impl Scene {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let scene: Scene = serde_json::from_str(&data)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        if scene.objects.is_empty() {
+            bail!("{} has no objects", path.display());
+        }
+        Ok(scene)
+    }
+}
+
+struct Object {
+    manager: Box<dyn RenderManager<PointCloud<PointXyzRgba>>>,
+    transform: Transform,
+    /// normalization of the first frame of the object, which its manager adapts with
+    antialias: Option<AntiAlias>,
+}
+
+/// Renders the objects of a scene together, shorter sequences loop.
+///
+/// A frame is only shown once every object has it, otherwise the renderer keeps the previous frame.
+#[derive(Default)]
+pub struct SceneManager {
+    objects: Vec<Object>,
+    /// normalization of the first frame of the scene, which the renderer draws with
+    antialias: Option<AntiAlias>,
+}
+
+impl SceneManager {
+    pub fn add_object<M>(&mut self, manager: M, transform: Transform)
+    where
+        M: RenderManager<PointCloud<PointXyzRgba>> + 'static,
+    {
+        self.objects.push(Object {
+            manager: Box::new(manager),
+            transform,
+            antialias: None,
+        });
+    }
+
+    /// The camera that sees the normalized points of an object like `camera_state` sees them in the scene
+    fn object_camera(&self, object: &Object, camera_state: &CameraState) -> CameraState {
+        let (Some(scene), Some(own)) = (self.antialias, object.antialias) else {
+            return camera_state.clone();
+        };
+        let transform = &object.transform;
+        let rotation = transform.rotation();
+        // normalized object points map to the normalized scene by factor * rotation * p + offset
+        let factor = transform.scale * own.scale / scene.scale;
+        let offset = (rotation * Vector3::new(own.x, own.y, own.z) * transform.scale
+            + Vector3::from(transform.translation)
+            - Vector3::new(scene.x, scene.y, scene.z))
+            / scene.scale;
+
+        let camera = &camera_state.camera;
+        let inverse = rotation.transpose();
+        let position = inverse * (camera.position.to_vec() - offset) / factor;
+        let (sin_pitch, cos_pitch) = camera.pitch.0.sin_cos();
+        let (sin_yaw, cos_yaw) = camera.yaw.0.sin_cos();
+        let direction = (inverse
+            * Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw))
+        .normalize();
+
+        let mut object_camera = camera_state.clone();
+        object_camera.update_camera_pos(CameraPosition {
+            position: Point3::from_vec(position),
+            yaw: Rad(direction.z.atan2(direction.x)),
+            pitch: Rad(direction.y.clamp(-1.0, 1.0).asin()),
+            up: camera.up,
+        });
+        object_camera
+    }
+
+    fn merge(frames: Vec<PointCloud<PointXyzRgba>>) -> Option<PointCloud<PointXyzRgba>> {
+        let mut frames = frames.into_iter();
+        let mut scene = frames.next()?;
+        for frame in frames {
+            scene.number_of_points += frame.number_of_points;
+            scene.points.extend(frame.points);
+        }
+        // the segments of the objects are not meaningful in the scene
+        scene.segments = None;
+        Some(scene)
+    }
+}
+
+impl RenderManager<PointCloud<PointXyzRgba>> for SceneManager {
+    fn start(&mut self) -> Option<PointCloud<PointXyzRgba>> {
+        let mut frames = Vec::with_capacity(self.objects.len());
+        for object in self.objects.iter_mut() {
+            let mut pc = object.manager.start()?;
+            if !pc.points.is_empty() {
+                object.antialias = Some(pc.antialias());
+            }
+            object.transform.apply(&mut pc);
+            frames.push(pc);
+        }
+        let scene = Self::merge(frames)?;
+        if !scene.points.is_empty() {
+            self.antialias = Some(scene.antialias());
+        }
+        Some(scene)
+    }
+
+    fn get_at(&mut self, index: usize) -> Option<PointCloud<PointXyzRgba>> {
+        let mut frames = Vec::with_capacity(self.objects.len());
+        for object in self.objects.iter_mut() {
+            let len = object.manager.len().max(1);
+            let mut pc = object.manager.get_at(index % len)?;
+            object.transform.apply(&mut pc);
+            frames.push(pc);
+        }
+        Self::merge(frames)
+    }
+
+    fn len(&self) -> usize {
+        self.objects
+            .iter()
+            .map(|object| object.manager.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.objects.iter().all(|object| object.manager.is_empty())
+    }
+
+    fn set_len(&mut self, len: usize) {
+        for object in self.objects.iter_mut() {
+            object.manager.set_len(len);
+        }
+    }
+
+    fn set_camera_state(&mut self, camera_state: Option<CameraState>) {
+        let cameras = self
+            .objects
+            .iter()
+            .map(|object| {
+                camera_state
+                    .as_ref()
+                    .map(|camera_state| self.object_camera(object, camera_state))
+            })
+            .collect::<Vec<_>>();
+        for (object, camera) in self.objects.iter_mut().zip(cameras) {
+            object.manager.set_camera_state(camera);
+        }
+    }
+
+    fn should_redraw(&mut self, camera_state: &CameraState) -> bool {
+        let cameras = self
+            .objects
+            .iter()
+            .map(|object| self.object_camera(object, camera_state))
+            .collect::<Vec<_>>();
+        // every object is asked, so none of them misses the camera
+        let mut redraw = false;
+        for (object, camera) in self.objects.iter_mut().zip(cameras) {
+            redraw |= object.manager.should_redraw(&camera);
+        }
+        redraw
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::pointxyzrgba::fixtures::{cloud, point};
+    use crate::render::wgpu::camera::Camera;
+    use cgmath::{Matrix4, Transform as _};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Frames {
+        frames: Vec<PointCloud<PointXyzRgba>>,
+        camera: Rc<RefCell<Option<CameraState>>>,
+    }
+
+    impl RenderManager<PointCloud<PointXyzRgba>> for Frames {
+        fn start(&mut self) -> Option<PointCloud<PointXyzRgba>> {
+            self.get_at(0)
+        }
+
+        fn get_at(&mut self, index: usize) -> Option<PointCloud<PointXyzRgba>> {
+            self.frames.get(index).cloned()
+        }
+
+        fn len(&self) -> usize {
+            self.frames.len()
+        }
+
+        fn is_empty(&self) -> bool {
+            self.frames.is_empty()
+        }
+
+        fn set_len(&mut self, _len: usize) {}
+
+        fn set_camera_state(&mut self, camera_state: Option<CameraState>) {
+            *self.camera.borrow_mut() = camera_state;
+        }
+
+        fn should_redraw(&mut self, _camera_state: &CameraState) -> bool {
+            false
+        }
+    }
+
+    fn frame(points: &[[f32; 3]]) -> PointCloud<PointXyzRgba> {
+        cloud(points.iter().map(|&[x, y, z]| point(x, y, z)))
+    }
+
+    fn project(camera_state: &CameraState, antialias: &AntiAlias, point: [f32; 3]) -> Point3<f32> {
+        let view_proj = Matrix4::from(camera_state.camera_uniform.view_proj);
+        view_proj.transform_point(Point3::from(antialias.apply_single(&point)))
+    }
+
+    #[test]
+    fn test_scene() {
+        let camera = Rc::new(RefCell::new(None));
+        let mut scene = SceneManager::default();
+        scene.add_object(
+            Frames {
+                frames: vec![
+                    frame(&[[0.0, 0.0, 0.0], [10.0, 10.0, 10.0]]),
+                    frame(&[[0.0, 0.0, 0.0]]),
+                ],
+                camera: Rc::new(RefCell::new(None)),
+            },
+            Transform::default(),
+        );
+        let transform = Transform {
+            translation: [100.0, 0.0, 0.0],
+            yaw: 90.0,
+            scale: 2.0,
+        };
+        scene.add_object(
+            Frames {
+                frames: vec![frame(&[[0.0, 0.0, 0.0], [0.0, 0.0, 5.0]])],
+                camera: camera.clone(),
+            },
+            transform,
+        );
+        assert_eq!(scene.len(), 2);
+
+        let first = scene.start().unwrap();
+        assert_eq!(first.number_of_points, 4);
+        // scaled, turned from +z to +x and translated
+        let moved = &first.points[3];
+        assert!((moved.x - 110.0).abs() < 1e-4 && moved.z.abs() < 1e-4);
+        // the shorter object loops
+        assert_eq!(scene.get_at(1).unwrap().number_of_points, 3);
+
+        // the object sees its own normalized points where the scene camera sees the transformed ones
+        let camera_state = CameraState::new(
+            Camera::new((0.3, 0.2, 1.5), Deg(-100.0), Deg(-5.0)),
+            800,
+            600,
+        );
+        scene.set_camera_state(Some(camera_state.clone()));
+        let object_camera = camera.borrow().clone().unwrap();
+        let scene_antialias = first.antialias();
+        let own_antialias = frame(&[[0.0, 0.0, 0.0], [0.0, 0.0, 5.0]]).antialias();
+        for (own, moved) in [
+            ([0.0, 0.0, 0.0], [100.0, 0.0, 0.0]),
+            ([0.0, 0.0, 5.0], [110.0, 0.0, 0.0]),
+        ] {
+            let expected = project(&camera_state, &scene_antialias, moved);
+            let actual = project(&object_camera, &own_antialias, own);
+            assert!(
+                (expected.x - actual.x).abs() < 1e-3,
+                "{expected:?} {actual:?}"
+            );
+            assert!(
+                (expected.y - actual.y).abs() < 1e-3,
+                "{expected:?} {actual:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_scene_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scene.json");
+        std::fs::write(
+            &path,
+            r#"{"objects": [{"src": "a"}, {"src": "http://host/b.mpd", "object_id": 1, "translation": [1, 2, 3], "yaw": 90}]}"#,
+        )
+        .unwrap();
+        let scene = Scene::from_file(&path).unwrap();
+        assert_eq!(scene.objects.len(), 2);
+        assert!(scene.objects[0].transform.is_identity());
+        assert!(!scene.objects[0].is_remote() && scene.objects[1].is_remote());
+        assert_eq!(scene.objects[1].object_id, 1);
+        assert_eq!(scene.objects[1].transform.translation, [1.0, 2.0, 3.0]);
+        assert_eq!(scene.objects[1].transform.scale, 1.0);
+
+        std::fs::write(&path, r#"{"objects": []}"#).unwrap();
+        assert!(Scene::from_file(&path).is_err());
+    }
+}
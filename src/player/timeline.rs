@@ -0,0 +1,307 @@
+//! Playback clock of the player.
+//!
+//! The clock runs on the time between redraws, not on how long frames take to load, so frames that are not
+//! ready in time are dropped instead of slowing the playback down.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const MIN_SPEED: f32 = 0.25;
+pub const MAX_SPEED: f32 = 4.0;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PlaybackState {
+    Paused,
+    Play,
+}
+
+/// Where to seek to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Seek {
+    Frame(usize),
+    /// seconds from the first frame
+    Time(f32),
+}
+
+impl FromStr for Seek {
+    type Err = String;
+
+    /// A frame number, e.g. `120`, or a timestamp, e.g. `4.5s`, `1:02.5` or `1:00:02`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(seconds) = s.strip_suffix('s') {
+            return seconds
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|seconds| *seconds >= 0.0)
+                .map(Seek::Time)
+                .ok_or_else(|| format!("invalid timestamp {s}"));
+        }
+        if s.contains(':') {
+            let mut seconds = 0.0;
+            for part in s.split(':') {
+                let value = part
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|value| *value >= 0.0)
+                    .ok_or_else(|| format!("invalid timestamp {s}"))?;
+                seconds = seconds * 60.0 + value;
+            }
+            return Ok(Seek::Time(seconds));
+        }
+        s.parse::<usize>()
+            .map(Seek::Frame)
+            .map_err(|_| format!("expected a frame number or a timestamp, got {s}"))
+    }
+}
+
+pub struct Timeline {
+    len: usize,
+    /// frames per second of the content at 1x
+    fps: f32,
+    speed: f32,
+    reverse: bool,
+    /// wrap around at the end, otherwise playback pauses there
+    looping: bool,
+    /// first and last frame of the A-B loop, which always loops
+    region: Option<(usize, usize)>,
+    state: PlaybackState,
+    /// position in frames, the frame shown is its integer part
+    clock: f64,
+}
+
+impl Timeline {
+    pub fn new(len: usize, fps: f32) -> Self {
+        Self {
+            len: len.max(1),
+            fps,
+            speed: 1.0,
+            reverse: false,
+            looping: true,
+            region: None,
+            state: PlaybackState::Paused,
+            clock: 0.0,
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.clock as usize
+    }
+
+    pub fn fps(&self) -> f32 {
+        self.fps
+    }
+
+    pub fn state(&self) -> PlaybackState {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: PlaybackState) {
+        self.state = state;
+    }
+
+    pub fn toggle(&mut self) {
+        self.state = match self.state {
+            PlaybackState::Play => PlaybackState::Paused,
+            PlaybackState::Paused => PlaybackState::Play,
+        };
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Clamped to [`MIN_SPEED`]..=[`MAX_SPEED`]
+    pub fn set_speed(&mut self, speed: f32) {
+        if speed.is_finite() {
+            self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        }
+    }
+
+    /// Time between frames at the current speed
+    pub fn frame_interval(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / (self.fps * self.speed))
+    }
+
+    pub fn is_reverse(&self) -> bool {
+        self.reverse
+    }
+
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.reverse = reverse;
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn loop_region(&self) -> Option<(usize, usize)> {
+        self.region
+    }
+
+    /// Loop from `frame` to the end of the current region, or to the last frame
+    pub fn set_loop_start(&mut self, frame: usize) {
+        let frame = frame.min(self.len - 1);
+        let end = self.region.map_or(self.len - 1, |(_, end)| end).max(frame);
+        self.set_loop_region(Some((frame, end)));
+    }
+
+    /// Loop from the start of the current region, or from the first frame, to `frame`
+    pub fn set_loop_end(&mut self, frame: usize) {
+        let frame = frame.min(self.len - 1);
+        let start = self.region.map_or(0, |(start, _)| start).min(frame);
+        self.set_loop_region(Some((start, frame)));
+    }
+
+    /// Playback jumps into the region if it is outside
+    pub fn set_loop_region(&mut self, region: Option<(usize, usize)>) {
+        self.region = region.map(|(a, b)| {
+            let (a, b) = (a.min(self.len - 1), b.min(self.len - 1));
+            (a.min(b), a.max(b))
+        });
+        if let Some((start, end)) = self.region {
+            if self.position() < start || self.position() > end {
+                self.clock = start as f64;
+            }
+        }
+    }
+
+    /// Frame accurate seek, clamped to the last frame
+    pub fn seek(&mut self, seek: Seek) -> usize {
+        let frame = match seek {
+            Seek::Frame(frame) => frame,
+            Seek::Time(seconds) => (seconds * self.fps).round() as usize,
+        };
+        self.clock = frame.min(self.len - 1) as f64;
+        self.position()
+    }
+
+    /// Move by whole frames, wrapping around like playback
+    pub fn step(&mut self, frames: i64) -> usize {
+        let (start, end) = self.bounds();
+        let span = (end - start + 1) as i64;
+        let position = self.position() as i64;
+        let position = if self.looping || self.region.is_some() {
+            start as i64 + (position - start as i64 + frames).rem_euclid(span)
+        } else {
+            (position + frames).clamp(start as i64, end as i64)
+        };
+        self.clock = position as f64;
+        self.position()
+    }
+
+    /// Runs the clock for `dt` if playing. Returns the frame to show when it changed.
+    /// Without looping, playback pauses on the last frame, or the first one in reverse.
+    pub fn advance(&mut self, dt: Duration) -> Option<usize> {
+        if self.state != PlaybackState::Play {
+            return None;
+        }
+        let previous = self.position();
+        let (start, end) = self.bounds();
+        let (start, end) = (start as f64, (end + 1) as f64);
+        let frames = dt.as_secs_f64() * self.fps as f64 * self.speed as f64;
+        let mut clock = if self.reverse {
+            self.clock - frames
+        } else {
+            self.clock + frames
+        };
+        if clock < start || clock >= end {
+            if self.looping || self.region.is_some() {
+                clock = start + (clock - start).rem_euclid(end - start);
+            } else {
+                clock = if self.reverse { start } else { end - 1.0 };
+                self.state = PlaybackState::Paused;
+            }
+        }
+        self.clock = clock;
+        (self.position() != previous).then_some(self.position())
+    }
+
+    fn bounds(&self) -> (usize, usize) {
+        self.region.unwrap_or((0, self.len - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(seconds: f64) -> Duration {
+        Duration::from_secs_f64(seconds)
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut timeline = Timeline::new(30, 30.0);
+        assert_eq!(timeline.advance(seconds(0.1)), None);
+        timeline.toggle();
+        assert_eq!(timeline.advance(seconds(0.1)), Some(3));
+        // slow frames are dropped, the clock keeps up with the time
+        assert_eq!(timeline.advance(seconds(0.5)), Some(18));
+        assert_eq!(timeline.advance(seconds(0.01)), None);
+        timeline.set_speed(2.0);
+        assert_eq!(timeline.advance(seconds(0.1)), Some(24));
+        // loops around
+        assert_eq!(timeline.advance(seconds(0.1)), Some(0));
+        timeline.set_speed(100.0);
+        assert_eq!(timeline.speed(), MAX_SPEED);
+        timeline.set_speed(0.0);
+        assert_eq!(timeline.speed(), MIN_SPEED);
+    }
+
+    #[test]
+    fn test_reverse_and_end() {
+        let mut timeline = Timeline::new(10, 10.0);
+        timeline.toggle();
+        timeline.set_reverse(true);
+        assert_eq!(timeline.advance(seconds(0.15)), Some(8));
+        timeline.set_looping(false);
+        assert_eq!(timeline.advance(seconds(2.0)), Some(0));
+        assert_eq!(timeline.state(), PlaybackState::Paused);
+
+        timeline.set_reverse(false);
+        timeline.toggle();
+        assert_eq!(timeline.advance(seconds(5.0)), Some(9));
+        assert_eq!(timeline.state(), PlaybackState::Paused);
+        assert_eq!(timeline.step(1), 9);
+    }
+
+    #[test]
+    fn test_loop_region() {
+        let mut timeline = Timeline::new(100, 10.0);
+        timeline.set_loop_start(20);
+        assert_eq!(timeline.loop_region(), Some((20, 99)));
+        assert_eq!(timeline.position(), 20);
+        timeline.set_loop_end(24);
+        assert_eq!(timeline.loop_region(), Some((20, 24)));
+        timeline.set_looping(false);
+        timeline.toggle();
+        // the region loops even without looping
+        assert_eq!(timeline.advance(seconds(0.65)), Some(21));
+        assert_eq!(timeline.step(-2), 24);
+        timeline.set_reverse(true);
+        assert_eq!(timeline.advance(seconds(0.35)), Some(20));
+        timeline.set_loop_region(None);
+        assert_eq!(timeline.step(1), 21);
+        // swapped and clamped
+        timeline.set_loop_region(Some((150, 40)));
+        assert_eq!(timeline.loop_region(), Some((40, 99)));
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut timeline = Timeline::new(300, 30.0);
+        assert_eq!(timeline.seek("120".parse().unwrap()), 120);
+        assert_eq!(timeline.seek("4.5s".parse().unwrap()), 135);
+        assert_eq!(timeline.seek("0:02.5".parse().unwrap()), 75);
+        assert_eq!(timeline.seek(Seek::Frame(1000)), 299);
+        assert_eq!("1:00:02".parse::<Seek>(), Ok(Seek::Time(3602.0)));
+        assert!("-1s".parse::<Seek>().is_err());
+        assert!("abc".parse::<Seek>().is_err());
+    }
+}
@@ -0,0 +1,311 @@
+//! Camera poses that change from frame to frame, for rendering moving views of a sequence.
+//!
+//! Poses are in the coordinates the renderers draw in, i.e. after the first frame has been centred
+//! and scaled into a unit cube by its [`AntiAlias`](crate::render::wgpu::antialias::AntiAlias).
+
+use crate::formats::pointxyzrgba::PointXyzRgba;
+use crate::formats::PointCloud;
+use crate::render::wgpu::camera::CameraPosition;
+use crate::render::wgpu::renderable::Renderable;
+use crate::simulation::CameraTrace;
+use anyhow::{bail, Context};
+use cgmath::{
+    Deg, EuclideanSpace, InnerSpace, Point3, Quaternion, Rad, Rotation, Rotation3, Vector3,
+};
+use std::path::Path;
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Interpolation {
+    /// straight lines between the keyframes
+    Linear,
+    /// a smooth curve through the keyframes
+    CatmullRom,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Keyframe {
+    pub frame: usize,
+    pub pose: CameraPosition,
+}
+
+pub enum CameraPath {
+    /// the same pose for every frame
+    Fixed(CameraPosition),
+    /// replays a camera trace, one line per frame, looping at the end
+    Trace(CameraTrace),
+    /// circles the centroid of the first frame, starting in front of it on the +z side
+    Orbit {
+        radius: f32,
+        elevation: Rad<f32>,
+        /// number of frames of a full circle
+        period: f32,
+        centre: Option<Point3<f32>>,
+    },
+    /// interpolates between poses, holding the first and last pose outside of them
+    Keyframes {
+        keyframes: Vec<Keyframe>,
+        interpolation: Interpolation,
+    },
+}
+
+impl CameraPath {
+    pub fn orbit<E: Into<Rad<f32>>>(radius: f32, elevation: E, period: f32) -> Self {
+        CameraPath::Orbit {
+            radius,
+            elevation: elevation.into(),
+            period,
+            centre: None,
+        }
+    }
+
+    pub fn keyframes(mut keyframes: Vec<Keyframe>, interpolation: Interpolation) -> Self {
+        assert!(!keyframes.is_empty(), "camera path needs a keyframe");
+        keyframes.sort_by_key(|keyframe| keyframe.frame);
+        CameraPath::Keyframes {
+            keyframes,
+            interpolation,
+        }
+    }
+
+    /// The pose of the camera for the `frame`-th rendered frame
+    pub fn pose(&mut self, frame: usize, pc: &PointCloud<PointXyzRgba>) -> CameraPosition {
+        match self {
+            CameraPath::Fixed(pose) => *pose,
+            CameraPath::Trace(trace) => trace.next(),
+            CameraPath::Orbit {
+                radius,
+                elevation,
+                period,
+                centre,
+            } => {
+                let centre = match centre {
+                    Some(centre) => *centre,
+                    None if pc.points.is_empty() => Point3::origin(),
+                    None => *centre.insert(centroid(pc)),
+                };
+                let angle = std::f32::consts::FRAC_PI_2
+                    + std::f32::consts::TAU * frame as f32 / period.max(1.0);
+                let (sin_elevation, cos_elevation) = elevation.0.sin_cos();
+                let offset = Vector3::new(
+                    cos_elevation * angle.cos(),
+                    sin_elevation,
+                    cos_elevation * angle.sin(),
+                );
+                look_at(centre + offset * *radius, centre)
+            }
+            CameraPath::Keyframes {
+                keyframes,
+                interpolation,
+            } => interpolate(keyframes, *interpolation, frame),
+        }
+    }
+}
+
+/// Read keyframes from a csv file with lines of `frame,x,y,z,pitch,yaw`, angles in degrees like a camera trace
+pub fn read_keyframes(path: &Path) -> anyhow::Result<Vec<Keyframe>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read keyframes from {}", path.display()))?;
+    let mut keyframes = vec![];
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let values = line.split(',').map(|s| s.trim()).collect::<Vec<_>>();
+        let [frame, x, y, z, pitch, yaw] = values[..] else {
+            bail!("line {}: expected frame,x,y,z,pitch,yaw", number + 1);
+        };
+        let parse = |s: &str| {
+            s.parse::<f32>()
+                .with_context(|| format!("line {}: invalid number {s}", number + 1))
+        };
+        keyframes.push(Keyframe {
+            frame: frame
+                .parse()
+                .with_context(|| format!("line {}: invalid frame {frame}", number + 1))?,
+            pose: CameraPosition {
+                position: Point3::new(parse(x)?, parse(y)?, parse(z)?),
+                pitch: Deg(parse(pitch)?).into(),
+                yaw: Deg(parse(yaw)?).into(),
+                up: Vector3::unit_y(),
+            },
+        });
+    }
+    if keyframes.is_empty() {
+        bail!("no keyframes in {}", path.display());
+    }
+    Ok(keyframes)
+}
+
+/// A pose at `position` looking at `target`.
+/// Looking straight down the top of the view points to -z, looking straight up it points to +z.
+pub fn look_at(position: Point3<f32>, target: Point3<f32>) -> CameraPosition {
+    let direction = target - position;
+    if direction.magnitude2() == 0.0 {
+        return CameraPosition {
+            position,
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+            up: Vector3::unit_y(),
+        };
+    }
+    let direction = direction.normalize();
+    let (yaw, pitch) = yaw_pitch(direction);
+    let up = if direction.y.abs() > 0.999 {
+        Vector3::new(0.0, 0.0, direction.y.signum())
+    } else {
+        Vector3::unit_y()
+    };
+    CameraPosition {
+        position,
+        yaw: Rad(yaw),
+        pitch: Rad(pitch),
+        up,
+    }
+}
+
+fn centroid(pc: &PointCloud<PointXyzRgba>) -> Point3<f32> {
+    let sum = pc.points.iter().fold([0.0f64; 3], |sum, p| {
+        [
+            sum[0] + p.x as f64,
+            sum[1] + p.y as f64,
+            sum[2] + p.z as f64,
+        ]
+    });
+    let n = pc.points.len() as f64;
+    let mean = [
+        (sum[0] / n) as f32,
+        (sum[1] / n) as f32,
+        (sum[2] / n) as f32,
+    ];
+    pc.antialias().apply_single(&mean).into()
+}
+
+fn interpolate(
+    keyframes: &[Keyframe],
+    interpolation: Interpolation,
+    frame: usize,
+) -> CameraPosition {
+    let last = keyframes.len() - 1;
+    let next = keyframes.partition_point(|keyframe| keyframe.frame <= frame);
+    if next == 0 {
+        return keyframes[0].pose;
+    }
+    if next > last {
+        return keyframes[last].pose;
+    }
+    let i = next - 1;
+    let (from, to) = (&keyframes[i], &keyframes[next]);
+    let t = (frame - from.frame) as f32 / (to.frame - from.frame) as f32;
+
+    let position = match interpolation {
+        Interpolation::Linear => from.pose.position + (to.pose.position - from.pose.position) * t,
+        Interpolation::CatmullRom => {
+            let p0 = keyframes[i.saturating_sub(1)].pose.position.to_vec();
+            let p1 = from.pose.position.to_vec();
+            let p2 = to.pose.position.to_vec();
+            let p3 = keyframes[(next + 1).min(last)].pose.position.to_vec();
+            let (t2, t3) = (t * t, t * t * t);
+            Point3::from_vec(
+                (p1 * 2.0
+                    + (p2 - p0) * t
+                    + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+                    + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+                    * 0.5,
+            )
+        }
+    };
+
+    let q0 = orientation(&from.pose);
+    let mut q1 = orientation(&to.pose);
+    // take the short way around
+    if q0.dot(q1) < 0.0 {
+        q1 = -q1;
+    }
+    let (yaw, pitch) = yaw_pitch(q0.slerp(q1, t).rotate_vector(Vector3::unit_x()));
+    CameraPosition {
+        position,
+        yaw: Rad(yaw),
+        pitch: Rad(pitch),
+        up: from.pose.up,
+    }
+}
+
+/// The rotation of the x axis into the view direction of a pose, see `Camera::calc_matrix`
+fn orientation(pose: &CameraPosition) -> Quaternion<f32> {
+    Quaternion::from_angle_y(-pose.yaw) * Quaternion::from_angle_z(pose.pitch)
+}
+
+fn yaw_pitch(direction: Vector3<f32>) -> (f32, f32) {
+    (
+        direction.z.atan2(direction.x),
+        direction.y.clamp(-1.0, 1.0).asin(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::pointxyzrgba::fixtures::{cloud, point};
+
+    fn direction(pose: &CameraPosition) -> Vector3<f32> {
+        let (sin_pitch, cos_pitch) = pose.pitch.0.sin_cos();
+        let (sin_yaw, cos_yaw) = pose.yaw.0.sin_cos();
+        Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw)
+    }
+
+    fn keyframe(frame: usize, x: f32, yaw: f32) -> Keyframe {
+        Keyframe {
+            frame,
+            pose: CameraPosition {
+                position: Point3::new(x, 0.0, 1.0),
+                yaw: Deg(yaw).into(),
+                pitch: Rad(0.0),
+                up: Vector3::unit_y(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_orbit() {
+        let pc = cloud(vec![point(0.0, 0.0, 0.0); 4]);
+        let mut path = CameraPath::orbit(2.0, Deg(30.0), 40.0);
+        for frame in [0, 10, 25] {
+            let pose = path.pose(frame, &pc);
+            let to_centre = Point3::origin() - pose.position;
+            assert!((to_centre.magnitude() - 2.0).abs() < 1e-5);
+            assert!((direction(&pose) - to_centre.normalize()).magnitude() < 1e-5);
+            assert!((pose.position.y - 1.0).abs() < 1e-5);
+        }
+        // the first frame is in front of the cloud, a quarter period later it is on the side
+        assert!(path.pose(0, &pc).position.z > 1.7);
+        assert!(path.pose(10, &pc).position.x < -1.7);
+    }
+
+    #[test]
+    fn test_keyframes() {
+        let keyframes = vec![
+            keyframe(10, 1.0, 170.0),
+            keyframe(0, 0.0, 90.0),
+            keyframe(20, 3.0, -170.0),
+        ];
+        let mut linear = CameraPath::keyframes(keyframes.clone(), Interpolation::Linear);
+        let mut smooth = CameraPath::keyframes(keyframes, Interpolation::CatmullRom);
+        let pc = PointCloud::new(0, vec![]);
+
+        for (frame, x) in [(0, 0.0), (10, 1.0), (20, 3.0), (30, 3.0)] {
+            assert!((linear.pose(frame, &pc).position.x - x).abs() < 1e-5);
+            assert!((smooth.pose(frame, &pc).position.x - x).abs() < 1e-5);
+        }
+        assert!((linear.pose(5, &pc).position.x - 0.5).abs() < 1e-5);
+        assert!((linear.pose(15, &pc).position.x - 2.0).abs() < 1e-5);
+        // the spline eases into the steeper segment after it
+        assert!(smooth.pose(5, &pc).position.x < 0.5);
+
+        // orientation turns through 180 degrees instead of going back through 0
+        let yaw = Deg::from(linear.pose(15, &pc).yaw).0;
+        assert!((yaw.abs() - 180.0).abs() < 1e-3, "{yaw}");
+        let yaw = Deg::from(linear.pose(5, &pc).yaw).0;
+        assert!((yaw - 130.0).abs() < 1e-3, "{yaw}");
+    }
+}
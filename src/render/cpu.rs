@@ -0,0 +1,385 @@
+//! A software point splatting renderer that needs no GPU.
+//!
+//! It uses the same camera model as [`crate::render::wgpu::camera`] and reproduces the output of
+//! `pointxyzrgba.wgsl` drawn into an `Rgba8UnormSrgb` texture: the colours are converted to linear
+//! space like the shader does and encoded back to sRGB like the texture does on write. Points are
+//! drawn in order with a `Less` depth test, so the same input always gives the same image.
+//!
+//! Splats are squares or circles facing the screen like on the gpu. With normals, splats with a
+//! radius in the units of the point cloud face along the normals instead, so they are drawn as
+//! ellipses by casting a ray through every pixel onto the plane of the splat.
+
+use crate::formats::pointxyzrgba::PointXyzRgba;
+use crate::formats::PointCloud;
+use crate::render::wgpu::antialias::AntiAlias;
+use crate::render::wgpu::camera::CameraUniform;
+use crate::render::wgpu::renderable::Renderable;
+use crate::render::wgpu::splat::{Splat, SplatShape, SplatSize};
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3, Vector4};
+use image::{Rgba, RgbaImage};
+
+/// The point drawn at a pixel
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fragment {
+    /// index of the point in the cloud
+    pub point: usize,
+    /// distance to the camera along the view axis, in rendering coordinates
+    pub distance: f32,
+}
+
+pub struct CpuRenderer {
+    width: u32,
+    height: u32,
+    splat: Splat,
+    background_color: wgpu::Color,
+    /// Fixed by the first non-empty frame, like the antialias buffer of the gpu renderer
+    antialias: Option<AntiAlias>,
+    depth: Vec<f32>,
+}
+
+impl CpuRenderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            splat: Splat::default(),
+            background_color: wgpu::Color::BLACK,
+            antialias: None,
+            depth: vec![1.0; (width * height) as usize],
+        }
+    }
+
+    /// Set the side of the square drawn for every point, in pixels. The default is 1 like the gpu renderer.
+    pub fn set_point_size(&mut self, point_size: u32) {
+        self.splat = Splat {
+            size: SplatSize::Pixels(point_size.max(1) as f32),
+            ..Splat::default()
+        };
+    }
+
+    pub fn set_splat(&mut self, splat: Splat) {
+        self.splat = splat;
+    }
+
+    /// Set the background color, given in linear space like a wgpu clear color.
+    pub fn set_background_color(&mut self, color: wgpu::Color) {
+        self.background_color = color;
+    }
+
+    /// The antialias fixed by the first non-empty frame, `None` until then
+    pub fn antialias(&self) -> Option<AntiAlias> {
+        self.antialias
+    }
+
+    /// Draw the point cloud, `normals` has one normal per point if the point cloud has them
+    pub fn render(
+        &mut self,
+        pc: &PointCloud<PointXyzRgba>,
+        normals: Option<&[[f32; 3]]>,
+        camera: &CameraUniform,
+    ) -> RgbaImage {
+        let background = Rgba([
+            encode_srgb(self.background_color.r as f32),
+            encode_srgb(self.background_color.g as f32),
+            encode_srgb(self.background_color.b as f32),
+            (self.background_color.a.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ]);
+        let fragments = self.rasterize(pc, normals, camera);
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            match fragments[(y * self.width + x) as usize] {
+                Some(fragment) => {
+                    let point = &pc.points[fragment.point];
+                    Rgba([
+                        encode_srgb(decode_srgb(point.r)),
+                        encode_srgb(decode_srgb(point.g)),
+                        encode_srgb(decode_srgb(point.b)),
+                        255,
+                    ])
+                }
+                None => background,
+            }
+        })
+    }
+
+    /// The point drawn at every pixel, row by row from the top left
+    pub fn rasterize(
+        &mut self,
+        pc: &PointCloud<PointXyzRgba>,
+        normals: Option<&[[f32; 3]]>,
+        camera: &CameraUniform,
+    ) -> Vec<Option<Fragment>> {
+        let mut fragments = vec![None; (self.width * self.height) as usize];
+        self.depth.fill(1.0);
+        if pc.points.is_empty() {
+            return fragments;
+        }
+
+        let antialias = *self.antialias.get_or_insert_with(|| pc.antialias());
+        let view_proj = Matrix4::from(camera.view_proj);
+        // rays are cast from the camera onto splats facing along the normals
+        let oriented = match (self.splat.size, normals) {
+            (SplatSize::World(radius), Some(normals)) => view_proj
+                .invert()
+                .map(|inverse| (radius / antialias.scale, normals, inverse)),
+            _ => None,
+        };
+        let eye = Vector3::new(
+            camera.view_position[0],
+            camera.view_position[1],
+            camera.view_position[2],
+        );
+        let (width, height) = (self.width as f32, self.height as f32);
+
+        for (i, point) in pc.points.iter().enumerate() {
+            let position = Vector3::from(antialias.apply_single(&[point.x, point.y, point.z]));
+            let clip = view_proj * position.extend(1.0);
+            if clip.w <= 0.0 {
+                continue;
+            }
+            let (ndc_x, ndc_y, depth) = (clip.x / clip.w, clip.y / clip.w, clip.z / clip.w);
+            if !(0.0..=1.0).contains(&depth) {
+                continue;
+            }
+            let size = self
+                .splat
+                .pixels(clip.w, antialias.scale, self.height)
+                .max(1.0);
+            // framebuffer coordinates have y pointing down
+            let centre_x = (ndc_x + 1.0) / 2.0 * width;
+            let centre_y = (1.0 - ndc_y) / 2.0 * height;
+
+            let facing_normal = oriented.and_then(|(radius, normals, inverse)| {
+                let normal = Vector3::from(normals[i]);
+                (normal.magnitude2() > 0.0).then(|| (radius, normal.normalize(), inverse))
+            });
+            if let Some((radius, normal, inverse)) = facing_normal {
+                let (tangent, bitangent) = tangents(normal);
+                // a tilted splat can reach past its size where it comes closer to the camera
+                let left = (centre_x - size).floor() as i64;
+                let top = (centre_y - size).floor() as i64;
+                let right = (centre_x + size).ceil() as i64;
+                let bottom = (centre_y + size).ceil() as i64;
+                for py in top.max(0)..bottom.min(self.height as i64) {
+                    for px in left.max(0)..right.min(self.width as i64) {
+                        let far = inverse
+                            * Vector4::new(
+                                (px as f32 + 0.5) / width * 2.0 - 1.0,
+                                1.0 - (py as f32 + 0.5) / height * 2.0,
+                                1.0,
+                                1.0,
+                            );
+                        let direction = far.truncate() / far.w - eye;
+                        let facing = direction.dot(normal);
+                        if facing.abs() < f32::EPSILON {
+                            continue;
+                        }
+                        let offset =
+                            eye + direction * ((position - eye).dot(normal) / facing) - position;
+                        let inside = match self.splat.shape {
+                            SplatShape::Circle => offset.magnitude2() <= radius * radius,
+                            SplatShape::Square => {
+                                offset.dot(tangent).abs() <= radius
+                                    && offset.dot(bitangent).abs() <= radius
+                            }
+                        };
+                        if inside {
+                            let hit = view_proj * (position + offset).extend(1.0);
+                            let fragment = Fragment {
+                                point: i,
+                                distance: hit.w,
+                            };
+                            self.plot(&mut fragments, px, py, hit.z / hit.w, fragment);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let side = size.round() as i64;
+            let offset = (side - 1) as f32 / 2.0;
+            let left = (centre_x - offset).floor() as i64;
+            let top = (centre_y - offset).floor() as i64;
+            // circles keep the pixels whose centres are within the square's inscribed circle
+            let (middle_x, middle_y) = (
+                left as f32 + side as f32 / 2.0,
+                top as f32 + side as f32 / 2.0,
+            );
+            let radius = side as f32 / 2.0;
+            for py in top.max(0)..(top + side).min(self.height as i64) {
+                for px in left.max(0)..(left + side).min(self.width as i64) {
+                    let (dx, dy) = (px as f32 + 0.5 - middle_x, py as f32 + 0.5 - middle_y);
+                    if self.splat.shape == SplatShape::Circle && dx * dx + dy * dy > radius * radius
+                    {
+                        continue;
+                    }
+                    // w of a perspective projection is the distance along the view axis
+                    let fragment = Fragment {
+                        point: i,
+                        distance: clip.w,
+                    };
+                    self.plot(&mut fragments, px, py, depth, fragment);
+                }
+            }
+        }
+        fragments
+    }
+
+    /// Keep the fragment if it passes the depth test
+    fn plot(
+        &mut self,
+        fragments: &mut [Option<Fragment>],
+        x: i64,
+        y: i64,
+        depth: f32,
+        fragment: Fragment,
+    ) {
+        let index = y as usize * self.width as usize + x as usize;
+        if (0.0..=1.0).contains(&depth) && depth < self.depth[index] {
+            self.depth[index] = depth;
+            fragments[index] = Some(fragment);
+        }
+    }
+}
+
+/// Two unit vectors spanning the plane orthogonal to `normal`
+fn tangents(normal: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let axis = if normal.y.abs() < 0.9 {
+        Vector3::unit_y()
+    } else {
+        Vector3::unit_x()
+    };
+    let tangent = normal.cross(axis).normalize();
+    (tangent, normal.cross(tangent))
+}
+
+/// sRGB to linear, as `linear_transform` in `pointxyzrgba.wgsl`
+fn decode_srgb(value: u8) -> f32 {
+    let c = value as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Linear to sRGB, as done when writing to an `Rgba8UnormSrgb` texture
+fn encode_srgb(value: f32) -> u8 {
+    let c = value.clamp(0.0, 1.0);
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::pointxyzrgba::fixtures::{cloud, point};
+    use crate::render::wgpu::camera::{Camera, CameraState};
+
+    fn camera() -> CameraUniform {
+        let camera = Camera::new((0.0, 0.0, 1.8), cgmath::Deg(-90.0), cgmath::Deg(0.0));
+        CameraState::new(camera, 65, 49).camera_uniform
+    }
+
+    #[test]
+    fn test_srgb_round_trip() {
+        for value in 0..=255 {
+            assert_eq!(encode_srgb(decode_srgb(value)), value);
+        }
+    }
+
+    #[test]
+    fn test_depth_and_point_size() {
+        // the corners fix the antialias, the two centre points overlap on screen.
+        // odd sizes put the centre of the screen in the middle of a pixel
+        let pc = cloud(
+            [
+                (-1.0, -1.0, -1.0, 10),
+                (1.0, 1.0, 1.0, 10),
+                (0.0, 0.0, -0.5, 100),
+                (0.0, 0.0, 0.5, 200),
+            ]
+            .map(|(x, y, z, r)| PointXyzRgba {
+                r,
+                ..point(x, y, z)
+            }),
+        );
+        let mut renderer = CpuRenderer::new(65, 49);
+        renderer.set_background_color(wgpu::Color::WHITE);
+
+        let image = renderer.render(&pc, None, &camera());
+        let centre = image.get_pixel(32, 24);
+        // the point closer to the camera wins regardless of the drawing order
+        assert_eq!(centre.0, [200, 0, 0, 255]);
+        assert_eq!(image.get_pixel(28, 24).0, [255, 255, 255, 255]);
+        let drawn = image.pixels().filter(|p| p.0[0] == 200).count();
+        assert_eq!(drawn, 1);
+        let fragments = renderer.rasterize(&pc, None, &camera());
+        let fragment = fragments[24 * 65 + 32].unwrap();
+        assert_eq!(fragment.point, 3);
+        assert!(fragment.distance > 0.0 && fragment.distance < 1.8);
+        assert_eq!(fragments.iter().flatten().count(), 3);
+
+        renderer.set_point_size(3);
+        let image = renderer.render(&pc, None, &camera());
+        assert_eq!(image.pixels().filter(|p| p.0[0] == 200).count(), 9);
+        // rendering is deterministic
+        assert_eq!(image, renderer.render(&pc, None, &camera()));
+    }
+
+    #[test]
+    fn test_splats() {
+        // the corners span 2 units, so the splat radius of 0.2 is 0.1 in rendering coordinates
+        let pc = cloud(
+            [
+                (-1.0, -1.0, -1.0, 10),
+                (1.0, 1.0, 1.0, 10),
+                (0.0, 0.0, 0.0, 200),
+            ]
+            .map(|(x, y, z, r)| PointXyzRgba {
+                r,
+                ..point(x, y, z)
+            }),
+        );
+        let count = |image: &RgbaImage| image.pixels().filter(|p| p.0[0] == 200).count();
+        let mut renderer = CpuRenderer::new(65, 49);
+
+        renderer.set_splat(Splat {
+            size: SplatSize::Pixels(5.0),
+            shape: SplatShape::Circle,
+            attenuation: false,
+        });
+        // a 5x5 square without its corners
+        assert_eq!(count(&renderer.render(&pc, None, &camera())), 21);
+
+        // attenuated pixel sizes are kept 1 unit in front of the camera
+        renderer.set_splat(Splat {
+            size: SplatSize::Pixels(9.0),
+            shape: SplatShape::Square,
+            attenuation: true,
+        });
+        assert_eq!(count(&renderer.render(&pc, None, &camera())), 25);
+
+        let splat = Splat {
+            size: SplatSize::World(0.2),
+            shape: SplatShape::Circle,
+            attenuation: false,
+        };
+        renderer.set_splat(splat);
+        let radius = splat.pixels(1.8, 2.0, 49) / 2.0;
+        let area = std::f32::consts::PI * radius * radius;
+        let facing = [[0.0, 0.0, 1.0]; 3];
+        let drawn = count(&renderer.render(&pc, Some(&facing), &camera())) as f32;
+        assert!((drawn - area).abs() < area * 0.2, "{drawn} {area}");
+        // the same splat seen edge on covers almost nothing
+        let edge_on = [[1.0, 0.0, 0.0]; 3];
+        assert!(count(&renderer.render(&pc, Some(&edge_on), &camera())) <= 2 * radius as usize);
+        // tilted by 60 degrees it is an ellipse of half the area
+        let tilted = [[3f32.sqrt() / 2.0, 0.0, 0.5]; 3];
+        let drawn = count(&renderer.render(&pc, Some(&tilted), &camera())) as f32;
+        assert!((drawn - area / 2.0).abs() < area * 0.2, "{drawn} {area}");
+    }
+}
@@ -1,3 +1,8 @@
+pub mod camera_path;
+pub mod cpu;
+pub mod passes;
+pub mod rig;
+pub mod video;
 pub mod wgpu;
 
 /*
@@ -0,0 +1,328 @@
+//! Depth, normal and mask passes that line up with the rendered colour images, e.g. for training
+//! depth estimation and segmentation models, and the camera parameters of every view.
+//!
+//! The passes are rasterized by the [`CpuRenderer`] with the splats of the backend that draws the
+//! colours: along the normals like the cpu backend, or facing the screen like the gpu. Depths and camera parameters are in the units of the point cloud, i.e. the
+//! normalization of the renderers is undone.
+
+use crate::formats::pointxyzrgba::PointXyzRgba;
+use crate::formats::PointCloud;
+use crate::render::cpu::CpuRenderer;
+use crate::render::wgpu::antialias::AntiAlias;
+use crate::render::wgpu::camera::{CameraState, PROJECTION_FOXY};
+use anyhow::{bail, Context};
+use byteorder::{LittleEndian, WriteBytesExt};
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Vector3};
+use image::{GrayImage, ImageBuffer, Luma, Rgb, RgbImage};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RenderPass {
+    /// the rendered colours
+    Color,
+    /// distance to the camera along the view axis
+    Depth,
+    /// normals in camera coordinates, needs point clouds with normals, e.g. from `vv normal`
+    Normal,
+    /// white where a point is drawn, black elsewhere
+    Mask,
+}
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DepthFormat {
+    /// 16-bit grayscale png of the depth times the depth scale
+    Png16,
+    /// 32-bit float portable float map
+    Pfm,
+}
+
+impl DepthFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DepthFormat::Png16 => "png",
+            DepthFormat::Pfm => "pfm",
+        }
+    }
+}
+
+/// The passes of one view
+pub struct Passes {
+    pub width: u32,
+    pub height: u32,
+    /// distance along the view axis in the units of the point cloud, row by row from the top left, 0 where no point is drawn
+    pub depth: Vec<f32>,
+    pub mask: GrayImage,
+    /// `None` if the point cloud has no normals
+    pub normal: Option<RgbImage>,
+    pub camera: CameraParameters,
+}
+
+impl Passes {
+    /// Rasterize the passes of `pc` as seen by `camera`.
+    /// `normals` has one normal per point, in the coordinates of the point cloud. With `oriented`
+    /// the splats face along the normals like the colours of the cpu backend, otherwise they face
+    /// the screen like the colours of the gpu backend.
+    pub fn render(
+        renderer: &mut CpuRenderer,
+        pc: &PointCloud<PointXyzRgba>,
+        normals: Option<&[[f32; 3]]>,
+        oriented: bool,
+        camera: &CameraState,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let splat_normals = if oriented { normals } else { None };
+        let fragments = renderer.rasterize(pc, splat_normals, &camera.camera_uniform);
+        let antialias = renderer.antialias().unwrap_or_default();
+        // only the rotation of the view matters, the normalization does not turn normals
+        let rotation = view_rotation(&camera.camera.calc_matrix());
+        let camera = CameraParameters::new(camera, width, height, &antialias);
+
+        let depth = fragments
+            .iter()
+            .map(|fragment| fragment.map_or(0.0, |f| f.distance * antialias.scale))
+            .collect();
+        let mask = GrayImage::from_fn(width, height, |x, y| {
+            match fragments[(y * width + x) as usize] {
+                Some(_) => Luma([255]),
+                None => Luma([0]),
+            }
+        });
+        let normal = normals.map(|normals| {
+            RgbImage::from_fn(width, height, |x, y| {
+                let Some(fragment) = fragments[(y * width + x) as usize] else {
+                    return Rgb([0, 0, 0]);
+                };
+                let normal = rotation * Vector3::from(normals[fragment.point]);
+                if normal.magnitude2() == 0.0 {
+                    return Rgb([0, 0, 0]);
+                }
+                let normal = normal.normalize();
+                let encode = |v: f32| ((v + 1.0) / 2.0 * 255.0).round() as u8;
+                Rgb([encode(normal.x), encode(normal.y), encode(normal.z)])
+            })
+        });
+
+        Self {
+            width,
+            height,
+            depth,
+            mask,
+            normal,
+            camera,
+        }
+    }
+
+    /// Save the depth as a 16-bit png of `depth * scale` rounded and clamped to 65535, or as a pfm of the depth
+    pub fn save_depth(&self, path: &Path, format: DepthFormat, scale: f32) -> anyhow::Result<()> {
+        match format {
+            DepthFormat::Png16 => {
+                let image =
+                    ImageBuffer::<Luma<u16>, Vec<u16>>::from_fn(self.width, self.height, |x, y| {
+                        let depth = self.depth[(y * self.width + x) as usize] * scale;
+                        Luma([depth.round().clamp(0.0, u16::MAX as f32) as u16])
+                    });
+                image
+                    .save(path)
+                    .with_context(|| format!("failed to save {}", path.display()))
+            }
+            DepthFormat::Pfm => write_pfm(path, &self.depth, self.width, self.height),
+        }
+    }
+}
+
+/// Intrinsics and extrinsics of a view, written as json next to the images
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct CameraParameters {
+    pub width: u32,
+    pub height: u32,
+    /// focal lengths in pixels
+    pub fx: f32,
+    pub fy: f32,
+    /// principal point in pixels, from the top left corner of the image
+    pub cx: f32,
+    pub cy: f32,
+    /// row-major transform from point cloud to camera coordinates, in the units of the point cloud.
+    /// Like OpenGL, the camera looks down -z with y up.
+    pub world_to_camera: [[f32; 4]; 4],
+}
+
+impl CameraParameters {
+    pub fn new(camera: &CameraState, width: u32, height: u32, antialias: &AntiAlias) -> Self {
+        let fy = height as f32 / 2.0 / (PROJECTION_FOXY / 2.0).to_radians().tan();
+        // the view maps (p - centre) / scale, scale it back up to keep the units of the point cloud
+        let view = camera.camera.calc_matrix();
+        let rotation = view_rotation(&view);
+        let centre = Vector3::new(antialias.x, antialias.y, antialias.z);
+        let translation = view.w.truncate() * antialias.scale - rotation * centre;
+        let mut world_to_camera = Matrix4::from(rotation);
+        world_to_camera.w = translation.extend(1.0);
+        Self {
+            width,
+            height,
+            fx: fy,
+            fy,
+            cx: width as f32 / 2.0,
+            cy: height as f32 / 2.0,
+            world_to_camera: world_to_camera.transpose().into(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)?;
+        Ok(())
+    }
+}
+
+fn view_rotation(view: &Matrix4<f32>) -> Matrix3<f32> {
+    Matrix3::from_cols(view.x.truncate(), view.y.truncate(), view.z.truncate())
+}
+
+/// Portable float map, little endian with the bottom row first
+fn write_pfm(path: &Path, data: &[f32], width: u32, height: u32) -> anyhow::Result<()> {
+    if data.len() != (width * height) as usize {
+        bail!("expected {} values, got {}", width * height, data.len());
+    }
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    write!(writer, "Pf\n{width} {height}\n-1.0\n")?;
+    for row in data.chunks(width as usize).rev() {
+        for &value in row {
+            writer.write_f32::<LittleEndian>(value)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::pointxyzrgba::fixtures::{cloud, point};
+    use crate::render::wgpu::camera::Camera;
+    use crate::render::wgpu::splat::{Splat, SplatShape, SplatSize};
+    use cgmath::{Point3, Transform};
+
+    #[test]
+    fn test_passes() {
+        // the corners fix the normalization, 100 units of the point cloud are 1 unit of the renderers
+        let pc = cloud([
+            point(-50.0, -50.0, -50.0),
+            point(50.0, 50.0, 50.0),
+            point(0.0, 0.0, 25.0),
+        ]);
+        let normals = [[0.0, 0.0, 1.0], [0.0, 0.0, 1.0], [0.0, 0.0, 1.0]];
+        let camera = CameraState::new(
+            Camera::new((0.0, 0.0, 1.8), cgmath::Deg(-90.0), cgmath::Deg(0.0)),
+            65,
+            49,
+        );
+        let mut renderer = CpuRenderer::new(65, 49);
+        let passes = Passes::render(&mut renderer, &pc, Some(&normals), true, &camera, 65, 49);
+        let scale = renderer.antialias().unwrap().scale;
+
+        let centre = (24 * 65 + 32) as usize;
+        // the camera is 1.8 normalized units in front of the centre
+        let expected = 1.8 * scale - 25.0;
+        assert!((passes.depth[centre] - expected).abs() < 1e-2 * scale);
+        assert_eq!(passes.mask.get_pixel(32, 24).0, [255]);
+        assert_eq!(passes.mask.get_pixel(0, 0).0, [0]);
+        assert_eq!(passes.depth[0], 0.0);
+        assert_eq!(passes.mask.pixels().filter(|p| p.0[0] == 255).count(), 3);
+        // the normal points at the camera
+        assert_eq!(passes.normal.unwrap().get_pixel(32, 24).0, [128, 128, 255]);
+
+        // the extrinsics move the point to its depth in front of the camera
+        let world_to_camera = Matrix4::from(passes.camera.world_to_camera).transpose();
+        let in_camera = world_to_camera.transform_point(Point3::new(0.0, 0.0, 25.0));
+        assert!(in_camera.x.abs() < 1e-3 && in_camera.y.abs() < 1e-3);
+        assert!((-in_camera.z - passes.depth[centre]).abs() < 1e-2);
+        assert_eq!((passes.camera.cx, passes.camera.cy), (32.5, 24.5));
+    }
+
+    #[test]
+    fn test_mask_matches_colors() {
+        let pc = cloud([
+            point(-50.0, -50.0, -50.0),
+            point(50.0, 50.0, 50.0),
+            point(0.0, 0.0, 25.0),
+            point(20.0, -10.0, 0.0),
+        ]);
+        // tilted away from the camera, so oriented splats are ellipses and screen-facing ones are not
+        let normals = [[0.8, 0.0, 0.6]; 4];
+        let camera = CameraState::new(
+            Camera::new((0.0, 0.0, 1.8), cgmath::Deg(-90.0), cgmath::Deg(0.0)),
+            65,
+            49,
+        );
+        let mut renderer = CpuRenderer::new(65, 49);
+        renderer.set_splat(Splat {
+            size: SplatSize::World(8.0),
+            shape: SplatShape::Circle,
+            attenuation: true,
+        });
+        renderer.set_background_color(wgpu::Color::WHITE);
+
+        let mut masks = vec![];
+        // the cpu backend turns the splats along the normals, the gpu backend does not
+        for (oriented, color_normals) in [(true, Some(&normals[..])), (false, None)] {
+            let colors = renderer.render(&pc, color_normals, &camera.camera_uniform);
+            let passes = Passes::render(
+                &mut renderer,
+                &pc,
+                Some(&normals),
+                oriented,
+                &camera,
+                65,
+                49,
+            );
+            let drawn = GrayImage::from_fn(65, 49, |x, y| match colors.get_pixel(x, y).0 {
+                [255, 255, 255, 255] => Luma([0]),
+                _ => Luma([255]),
+            });
+            assert_eq!(passes.mask, drawn);
+            assert!(passes.normal.is_some());
+            masks.push(passes.mask);
+        }
+        assert_ne!(masks[0], masks[1]);
+    }
+
+    #[test]
+    fn test_save_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        let pc = cloud([point(0.0, 0.0, 0.0)]);
+        let camera = CameraState::new(
+            Camera::new((0.0, 0.0, 1.8), cgmath::Deg(-90.0), cgmath::Deg(0.0)),
+            4,
+            2,
+        );
+        let mut renderer = CpuRenderer::new(4, 2);
+        let mut passes = Passes::render(&mut renderer, &pc, None, true, &camera, 4, 2);
+        assert!(passes.normal.is_none());
+        passes.depth = vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+
+        let path = dir.path().join("depth.png");
+        passes
+            .save_depth(&path, DepthFormat::Png16, 1000.0)
+            .unwrap();
+        let image = image::open(&path).unwrap().into_luma16();
+        assert_eq!(image.get_pixel(3, 1).0, [7000]);
+        assert_eq!(image.get_pixel(0, 0).0, [0]);
+
+        let path = dir.path().join("depth.pfm");
+        passes.save_depth(&path, DepthFormat::Pfm, 1000.0).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let header = b"Pf\n4 2\n-1.0\n";
+        assert!(data.starts_with(header));
+        assert_eq!(data.len(), header.len() + 8 * 4);
+        // the bottom row comes first
+        let first = f32::from_le_bytes(data[header.len()..header.len() + 4].try_into().unwrap());
+        assert_eq!(first, 4.0);
+        passes.camera.save(&dir.path().join("camera.json")).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+//! Camera rigs that view every frame from several poses at once, as done for image based quality
+//! metrics in MPEG, e.g. the six faces of a cube or views spread evenly over a sphere.
+//!
+//! Like [`CameraPath`](crate::render::camera_path::CameraPath), poses are in rendering coordinates
+//! where the first frame is centred at the origin, so generated rigs look at the origin.
+
+use crate::render::camera_path::look_at;
+use crate::render::wgpu::camera::CameraPosition;
+use anyhow::{bail, Context};
+use cgmath::{Deg, EuclideanSpace, Point3, Vector3};
+use std::path::Path;
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RigLayout {
+    /// six views along the axes, from +x, -x, +y, -y, +z and -z
+    Cube,
+    /// views on a Fibonacci sphere
+    Sphere,
+}
+
+#[derive(Debug, Clone)]
+pub struct Rig {
+    views: Vec<CameraPosition>,
+}
+
+impl Rig {
+    pub fn new(views: Vec<CameraPosition>) -> Self {
+        assert!(!views.is_empty(), "rig needs a view");
+        Self { views }
+    }
+
+    pub fn generate(layout: RigLayout, views: usize, distance: f32) -> Self {
+        match layout {
+            RigLayout::Cube => Self::cube(distance),
+            RigLayout::Sphere => Self::sphere(views, distance),
+        }
+    }
+
+    pub fn cube(distance: f32) -> Self {
+        let axes = [
+            Vector3::unit_x(),
+            -Vector3::unit_x(),
+            Vector3::unit_y(),
+            -Vector3::unit_y(),
+            Vector3::unit_z(),
+            -Vector3::unit_z(),
+        ];
+        Self::new(
+            axes.into_iter()
+                .map(|axis| look_at(Point3::from_vec(axis * distance), Point3::origin()))
+                .collect(),
+        )
+    }
+
+    /// `count` views spread evenly over a sphere, from the top to the bottom
+    pub fn sphere(count: usize, distance: f32) -> Self {
+        let count = count.max(1);
+        let golden_angle = std::f32::consts::PI * (3.0 - 5f32.sqrt());
+        Self::new(
+            (0..count)
+                .map(|i| {
+                    let y = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+                    let radius = (1.0 - y * y).sqrt();
+                    let (sin, cos) = (golden_angle * i as f32).sin_cos();
+                    let position = Vector3::new(radius * cos, y, radius * sin) * distance;
+                    look_at(Point3::from_vec(position), Point3::origin())
+                })
+                .collect(),
+        )
+    }
+
+    /// Read poses from a file in the camera trace format, one `x,y,z,pitch,yaw[,roll]` line per view with angles in degrees
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read rig from {}", path.display()))?;
+        let mut views = vec![];
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values = line
+                .split(',')
+                .map(|s| s.trim().parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("line {}: invalid number", number + 1))?;
+            let [x, y, z, pitch, yaw, ..] = values[..] else {
+                bail!("line {}: expected x,y,z,pitch,yaw", number + 1);
+            };
+            views.push(CameraPosition {
+                position: Point3::new(x, y, z),
+                pitch: Deg(pitch).into(),
+                yaw: Deg(yaw).into(),
+                up: Vector3::unit_y(),
+            });
+        }
+        if views.is_empty() {
+            bail!("no views in {}", path.display());
+        }
+        Ok(Self::new(views))
+    }
+
+    pub fn views(&self) -> &[CameraPosition] {
+        &self.views
+    }
+
+    pub fn len(&self) -> usize {
+        self.views.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.views.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    fn direction(pose: &CameraPosition) -> Vector3<f32> {
+        let (sin_pitch, cos_pitch) = pose.pitch.0.sin_cos();
+        let (sin_yaw, cos_yaw) = pose.yaw.0.sin_cos();
+        Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw)
+    }
+
+    #[test]
+    fn test_generated_rigs() {
+        for rig in [Rig::cube(2.0), Rig::sphere(16, 2.0)] {
+            for view in rig.views() {
+                let to_centre = Point3::origin() - view.position;
+                assert!((to_centre.magnitude() - 2.0).abs() < 1e-5);
+                assert!((direction(view) - to_centre.normalize()).magnitude() < 1e-3);
+                // the up vector is never the view direction
+                assert!(view.up.dot(to_centre.normalize()).abs() < 0.999);
+            }
+        }
+        assert_eq!(Rig::cube(1.0).len(), 6);
+        assert_eq!(Rig::generate(RigLayout::Sphere, 16, 1.0).len(), 16);
+
+        // sphere views are spread over both hemispheres
+        let heights = Rig::sphere(16, 1.0)
+            .views()
+            .iter()
+            .map(|view| view.position.y)
+            .collect::<Vec<_>>();
+        assert!(heights.iter().sum::<f32>().abs() < 1e-5);
+        assert!(heights.windows(2).all(|pair| pair[0] > pair[1]));
+    }
+}
@@ -0,0 +1,231 @@
+//! Motion JPEG in an AVI container: every frame is a JPEG image, so no external encoder is needed.
+//!
+//! The headers are written with placeholder sizes and patched in [`VideoEncoder::finish`], after the
+//! `idx1` index has been appended.
+
+use super::{VideoEncoder, VideoOptions};
+use anyhow::{bail, Context};
+use byteorder::{LittleEndian, WriteBytesExt};
+use image::codecs::jpeg::JpegEncoder;
+use image::{ColorType, RgbaImage};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const JPEG_QUALITY: u8 = 90;
+/// AVIF_HASINDEX in `avih` and AVIIF_KEYFRAME in `idx1`
+const HAS_INDEX: u32 = 0x10;
+const KEYFRAME: u32 = 0x10;
+
+// offsets of the fields patched when the file is finished
+const RIFF_SIZE: u64 = 4;
+const TOTAL_FRAMES: u64 = 48;
+const STREAM_LENGTH: u64 = 140;
+const MOVI_SIZE: u64 = 216;
+/// position of the `movi` fourcc, which the offsets in `idx1` are relative to
+const MOVI_START: u64 = 220;
+
+pub struct MjpegAviEncoder {
+    writer: BufWriter<File>,
+    width: u32,
+    height: u32,
+    /// offset and size of every frame chunk
+    index: Vec<(u32, u32)>,
+    movi_size: u32,
+    jpeg: Vec<u8>,
+    rgb: Vec<u8>,
+}
+
+impl MjpegAviEncoder {
+    pub fn new(
+        path: &Path,
+        width: u32,
+        height: u32,
+        options: &VideoOptions,
+    ) -> anyhow::Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        write_headers(&mut writer, width, height, options.fps)?;
+        Ok(Self {
+            writer,
+            width,
+            height,
+            index: vec![],
+            movi_size: 4,
+            jpeg: vec![],
+            rgb: Vec::with_capacity((width * height * 3) as usize),
+        })
+    }
+}
+
+impl VideoEncoder for MjpegAviEncoder {
+    fn encode(&mut self, frame: &RgbaImage) -> anyhow::Result<()> {
+        if frame.dimensions() != (self.width, self.height) {
+            bail!(
+                "frame is {:?}, expected {:?}",
+                frame.dimensions(),
+                (self.width, self.height)
+            );
+        }
+        self.rgb.clear();
+        self.rgb
+            .extend(frame.pixels().flat_map(|p| [p.0[0], p.0[1], p.0[2]]));
+        self.jpeg.clear();
+        JpegEncoder::new_with_quality(&mut self.jpeg, JPEG_QUALITY).encode(
+            &self.rgb,
+            self.width,
+            self.height,
+            ColorType::Rgb8,
+        )?;
+
+        let size = self.jpeg.len() as u32;
+        self.index.push((self.movi_size, size));
+        self.writer.write_all(b"00dc")?;
+        self.writer.write_u32::<LittleEndian>(size)?;
+        self.writer.write_all(&self.jpeg)?;
+        // chunks are word aligned
+        if size % 2 == 1 {
+            self.writer.write_u8(0)?;
+        }
+        self.movi_size += 8 + size + size % 2;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        let w = &mut self.writer;
+        w.write_all(b"idx1")?;
+        w.write_u32::<LittleEndian>(16 * self.index.len() as u32)?;
+        for &(offset, size) in &self.index {
+            w.write_all(b"00dc")?;
+            w.write_u32::<LittleEndian>(KEYFRAME)?;
+            w.write_u32::<LittleEndian>(offset)?;
+            w.write_u32::<LittleEndian>(size)?;
+        }
+        let file_size = w.stream_position()?;
+
+        let frames = self.index.len() as u32;
+        for (position, value) in [
+            (RIFF_SIZE, file_size as u32 - 8),
+            (TOTAL_FRAMES, frames),
+            (STREAM_LENGTH, frames),
+            (MOVI_SIZE, self.movi_size),
+        ] {
+            w.seek(SeekFrom::Start(position))?;
+            w.write_u32::<LittleEndian>(value)?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+}
+
+fn write_headers<W: Write>(w: &mut W, width: u32, height: u32, fps: f32) -> std::io::Result<()> {
+    let frame_size = width * height * 3;
+    w.write_all(b"RIFF")?;
+    w.write_u32::<LittleEndian>(0)?;
+    w.write_all(b"AVI ")?;
+
+    w.write_all(b"LIST")?;
+    w.write_u32::<LittleEndian>(192)?;
+    w.write_all(b"hdrl")?;
+
+    w.write_all(b"avih")?;
+    w.write_u32::<LittleEndian>(56)?;
+    w.write_u32::<LittleEndian>((1_000_000.0 / fps).round() as u32)?;
+    w.write_u32::<LittleEndian>((frame_size as f32 * fps) as u32)?;
+    w.write_u32::<LittleEndian>(0)?;
+    w.write_u32::<LittleEndian>(HAS_INDEX)?;
+    w.write_u32::<LittleEndian>(0)?; // total frames
+    w.write_u32::<LittleEndian>(0)?;
+    w.write_u32::<LittleEndian>(1)?; // streams
+    w.write_u32::<LittleEndian>(frame_size)?;
+    w.write_u32::<LittleEndian>(width)?;
+    w.write_u32::<LittleEndian>(height)?;
+    w.write_all(&[0; 16])?;
+
+    w.write_all(b"LIST")?;
+    w.write_u32::<LittleEndian>(116)?;
+    w.write_all(b"strl")?;
+
+    w.write_all(b"strh")?;
+    w.write_u32::<LittleEndian>(56)?;
+    w.write_all(b"vids")?;
+    w.write_all(b"MJPG")?;
+    w.write_u32::<LittleEndian>(0)?;
+    w.write_u16::<LittleEndian>(0)?;
+    w.write_u16::<LittleEndian>(0)?;
+    w.write_u32::<LittleEndian>(0)?;
+    w.write_u32::<LittleEndian>(1000)?; // scale
+    w.write_u32::<LittleEndian>((fps * 1000.0).round() as u32)?; // rate
+    w.write_u32::<LittleEndian>(0)?;
+    w.write_u32::<LittleEndian>(0)?; // length
+    w.write_u32::<LittleEndian>(frame_size)?;
+    w.write_i32::<LittleEndian>(-1)?;
+    w.write_u32::<LittleEndian>(0)?;
+    w.write_u16::<LittleEndian>(0)?;
+    w.write_u16::<LittleEndian>(0)?;
+    w.write_u16::<LittleEndian>(width as u16)?;
+    w.write_u16::<LittleEndian>(height as u16)?;
+
+    w.write_all(b"strf")?;
+    w.write_u32::<LittleEndian>(40)?;
+    w.write_u32::<LittleEndian>(40)?;
+    w.write_i32::<LittleEndian>(width as i32)?;
+    w.write_i32::<LittleEndian>(height as i32)?;
+    w.write_u16::<LittleEndian>(1)?;
+    w.write_u16::<LittleEndian>(24)?;
+    w.write_all(b"MJPG")?;
+    w.write_u32::<LittleEndian>(frame_size)?;
+    w.write_all(&[0; 16])?;
+
+    w.write_all(b"LIST")?;
+    w.write_u32::<LittleEndian>(0)?; // movi size
+    w.write_all(b"movi")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(data: &[u8], position: u64) -> u32 {
+        let p = position as usize;
+        u32::from_le_bytes(data[p..p + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_mjpeg_avi() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("output.avi");
+        let options = VideoOptions {
+            fps: 30.0,
+            codec: None,
+            bitrate: None,
+        };
+        let mut encoder = MjpegAviEncoder::new(&path, 16, 8, &options).unwrap();
+        for i in 0..3 {
+            let frame = RgbaImage::from_pixel(16, 8, image::Rgba([i * 80, 0, 0, 255]));
+            encoder.encode(&frame).unwrap();
+        }
+        encoder.finish().unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        assert_eq!(&data[MOVI_START as usize..MOVI_START as usize + 4], b"movi");
+        assert_eq!(u32_at(&data, RIFF_SIZE) as usize, data.len() - 8);
+        assert_eq!(u32_at(&data, TOTAL_FRAMES), 3);
+        assert_eq!(u32_at(&data, STREAM_LENGTH), 3);
+
+        // the index points at the jpeg chunks
+        let idx1 = MOVI_START as usize + u32_at(&data, MOVI_SIZE) as usize;
+        assert_eq!(&data[idx1..idx1 + 4], b"idx1");
+        assert_eq!(u32_at(&data, idx1 as u64 + 4), 3 * 16);
+        for frame in 0..3 {
+            let entry = (idx1 + 8 + frame * 16) as u64;
+            let chunk = MOVI_START as usize + u32_at(&data, entry + 8) as usize;
+            let size = u32_at(&data, entry + 12) as usize;
+            assert_eq!(&data[chunk..chunk + 4], b"00dc");
+            let jpeg = &data[chunk + 8..chunk + 8 + size];
+            let decoded = image::load_from_memory(jpeg).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (16, 8));
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Encoding through the ffmpeg libraries, which supports any codec and container they were built with.
+
+use super::{VideoEncoder, VideoOptions};
+use anyhow::{anyhow, bail, Context};
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::software::scaling;
+use ffmpeg_next::{codec, encoder, format, frame, Packet, Rational};
+use image::RgbaImage;
+use std::path::Path;
+
+const DEFAULT_CODEC: &str = "libx264";
+
+pub struct FfmpegEncoder {
+    output: format::context::Output,
+    encoder: encoder::video::Encoder,
+    scaler: scaling::Context,
+    rgba: frame::Video,
+    yuv: frame::Video,
+    stream_index: usize,
+    encoder_time_base: Rational,
+    stream_time_base: Rational,
+    frames: i64,
+}
+
+impl FfmpegEncoder {
+    pub fn new(
+        path: &Path,
+        width: u32,
+        height: u32,
+        options: &VideoOptions,
+    ) -> anyhow::Result<Self> {
+        ffmpeg_next::init().context("failed to initialise ffmpeg")?;
+        let codec_name = options.codec.as_deref().unwrap_or(DEFAULT_CODEC);
+        let codec = encoder::find_by_name(codec_name)
+            .ok_or_else(|| anyhow!("ffmpeg has no encoder named {codec_name}"))?;
+
+        let mut output = format::output(&path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        let global_header = output
+            .format()
+            .flags()
+            .contains(format::Flags::GLOBAL_HEADER);
+
+        let frame_rate = Rational::from(options.fps as f64);
+        let encoder_time_base = frame_rate.invert();
+        let mut video = codec::context::Context::new_with_codec(codec)
+            .encoder()
+            .video()?;
+        video.set_width(width);
+        video.set_height(height);
+        video.set_format(Pixel::YUV420P);
+        video.set_time_base(encoder_time_base);
+        video.set_frame_rate(Some(frame_rate));
+        if let Some(bitrate) = options.bitrate {
+            video.set_bit_rate(bitrate);
+        }
+        if global_header {
+            video.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let encoder = video
+            .open_as(codec)
+            .with_context(|| format!("failed to open {codec_name} for {width}x{height}"))?;
+
+        let mut stream = output.add_stream(codec)?;
+        stream.set_time_base(encoder_time_base);
+        stream.set_parameters(&encoder);
+        let stream_index = stream.index();
+        output.write_header()?;
+        // the muxer may change the time base of the stream when writing the header
+        let stream_time_base = output.stream(stream_index).unwrap().time_base();
+
+        let scaler = scaling::Context::get(
+            Pixel::RGBA,
+            width,
+            height,
+            Pixel::YUV420P,
+            width,
+            height,
+            scaling::Flags::BILINEAR,
+        )?;
+
+        Ok(Self {
+            output,
+            encoder,
+            scaler,
+            rgba: frame::Video::new(Pixel::RGBA, width, height),
+            yuv: frame::Video::empty(),
+            stream_index,
+            encoder_time_base,
+            stream_time_base,
+            frames: 0,
+        })
+    }
+
+    fn write_packets(&mut self) -> anyhow::Result<()> {
+        let mut packet = Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(self.stream_index);
+            packet.rescale_ts(self.encoder_time_base, self.stream_time_base);
+            packet.write_interleaved(&mut self.output)?;
+        }
+        Ok(())
+    }
+}
+
+impl VideoEncoder for FfmpegEncoder {
+    fn encode(&mut self, frame: &RgbaImage) -> anyhow::Result<()> {
+        let (width, height) = (self.rgba.width(), self.rgba.height());
+        if frame.dimensions() != (width, height) {
+            bail!(
+                "frame is {:?}, expected {:?}",
+                frame.dimensions(),
+                (width, height)
+            );
+        }
+        // rows of ffmpeg frames may be padded
+        let stride = self.rgba.stride(0);
+        let row = width as usize * 4;
+        let data = self.rgba.data_mut(0);
+        for (y, pixels) in frame.as_raw().chunks_exact(row).enumerate() {
+            data[y * stride..y * stride + row].copy_from_slice(pixels);
+        }
+
+        self.scaler.run(&self.rgba, &mut self.yuv)?;
+        self.yuv.set_pts(Some(self.frames));
+        self.frames += 1;
+        self.encoder.send_frame(&self.yuv)?;
+        self.write_packets()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.encoder.send_eof()?;
+        self.write_packets()?;
+        self.output.write_trailer()?;
+        Ok(())
+    }
+}
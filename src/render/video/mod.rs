@@ -0,0 +1,123 @@
+//! Video encoders that take rendered frames one at a time, so no images are stored in between.
+//!
+//! `y4m` and `avi` (Motion JPEG) are written in pure Rust. `mp4` goes through `ffmpeg-next` and is
+//! only available with the `ffmpeg` feature.
+
+pub mod avi;
+#[cfg(feature = "ffmpeg")]
+pub mod ffmpeg;
+pub mod y4m;
+
+use crate::render::wgpu::png::RenderFormat;
+use anyhow::bail;
+use image::RgbaImage;
+use std::path::{Path, PathBuf};
+
+/// Encoding settings. `codec` and `bitrate` are only used by ffmpeg.
+#[derive(Debug, Clone)]
+pub struct VideoOptions {
+    pub fps: f32,
+    /// ffmpeg encoder name, `libx264` by default
+    pub codec: Option<String>,
+    /// target bitrate in bits per second, the encoder default if not set
+    pub bitrate: Option<usize>,
+}
+
+pub trait VideoEncoder {
+    fn encode(&mut self, frame: &RgbaImage) -> anyhow::Result<()>;
+    /// Flush the pending frames and write the trailer of the file.
+    fn finish(&mut self) -> anyhow::Result<()>;
+}
+
+/// Writes frames into a video file. The file is removed if the writer is dropped before [`finish`] succeeds.
+///
+/// [`finish`]: #method.finish
+pub struct VideoWriter {
+    path: PathBuf,
+    encoder: Box<dyn VideoEncoder>,
+    finished: bool,
+}
+
+impl VideoWriter {
+    pub fn new(
+        path: &Path,
+        format: RenderFormat,
+        width: u32,
+        height: u32,
+        options: &VideoOptions,
+    ) -> anyhow::Result<Self> {
+        if options.fps <= 0.0 {
+            bail!("fps must be positive, got {}", options.fps);
+        }
+        let encoder: Box<dyn VideoEncoder> = match format {
+            RenderFormat::Y4m => Box::new(y4m::Y4mEncoder::new(path, width, height, options)?),
+            RenderFormat::Avi => Box::new(avi::MjpegAviEncoder::new(path, width, height, options)?),
+            #[cfg(feature = "ffmpeg")]
+            RenderFormat::Mp4 => {
+                Box::new(ffmpeg::FfmpegEncoder::new(path, width, height, options)?)
+            }
+            #[cfg(not(feature = "ffmpeg"))]
+            RenderFormat::Mp4 => {
+                bail!("mp4 output needs the ffmpeg feature, use --format y4m or avi instead")
+            }
+            RenderFormat::Png => bail!("png is not a video format"),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            encoder,
+            finished: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write_frame(&mut self, frame: &RgbaImage) -> anyhow::Result<()> {
+        self.encoder.encode(frame)
+    }
+
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        self.encoder.finish()?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for VideoWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            // an unfinished video cannot be played, do not leave it behind
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unfinished_video_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = VideoOptions {
+            fps: 30.0,
+            codec: None,
+            bitrate: None,
+        };
+        let frame = RgbaImage::new(4, 2);
+
+        let path = dir.path().join("output.y4m");
+        let mut writer = VideoWriter::new(&path, RenderFormat::Y4m, 4, 2, &options).unwrap();
+        writer.write_frame(&frame).unwrap();
+        drop(writer);
+        assert!(!path.exists());
+
+        let mut writer = VideoWriter::new(&path, RenderFormat::Y4m, 4, 2, &options).unwrap();
+        writer.write_frame(&frame).unwrap();
+        writer.finish().unwrap();
+        assert!(path.exists());
+
+        assert!(VideoWriter::new(&path, RenderFormat::Png, 4, 2, &options).is_err());
+    }
+}
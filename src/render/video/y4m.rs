@@ -0,0 +1,106 @@
+//! Uncompressed YUV4MPEG2 with full 4:4:4 chroma, readable by ffmpeg, mpv and most video tools.
+
+use super::{VideoEncoder, VideoOptions};
+use anyhow::{bail, Context};
+use image::RgbaImage;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+pub struct Y4mEncoder {
+    writer: BufWriter<File>,
+    width: u32,
+    height: u32,
+    planes: Vec<u8>,
+}
+
+impl Y4mEncoder {
+    pub fn new(
+        path: &Path,
+        width: u32,
+        height: u32,
+        options: &VideoOptions,
+    ) -> anyhow::Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        let rate = (options.fps * 1000.0).round() as u32;
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{width} H{height} F{rate}:1000 Ip A1:1 C444"
+        )?;
+        Ok(Self {
+            writer,
+            width,
+            height,
+            planes: Vec::with_capacity((width * height * 3) as usize),
+        })
+    }
+}
+
+impl VideoEncoder for Y4mEncoder {
+    fn encode(&mut self, frame: &RgbaImage) -> anyhow::Result<()> {
+        if frame.dimensions() != (self.width, self.height) {
+            bail!(
+                "frame is {:?}, expected {:?}",
+                frame.dimensions(),
+                (self.width, self.height)
+            );
+        }
+        let pixels = frame.len() / 4;
+        self.planes.clear();
+        self.planes.resize(pixels * 3, 0);
+        let (y, uv) = self.planes.split_at_mut(pixels);
+        let (u, v) = uv.split_at_mut(pixels);
+        for (i, pixel) in frame.pixels().enumerate() {
+            [y[i], u[i], v[i]] = rgb_to_ycbcr(pixel.0[0], pixel.0[1], pixel.0[2]);
+        }
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&self.planes)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// BT.601 limited range, the default colour space of y4m
+fn rgb_to_ycbcr(r: u8, g: u8, b: u8) -> [u8; 3] {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let y = 16.0 + 65.481 * r + 128.553 * g + 24.966 * b;
+    let cb = 128.0 - 37.797 * r - 74.203 * g + 112.0 * b;
+    let cr = 128.0 + 112.0 * r - 93.786 * g - 18.214 * b;
+    [y.round() as u8, cb.round() as u8, cr.round() as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_y4m() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("output.y4m");
+        let options = VideoOptions {
+            fps: 29.97,
+            codec: None,
+            bitrate: None,
+        };
+        let mut encoder = Y4mEncoder::new(&path, 3, 2, &options).unwrap();
+        let frame = RgbaImage::from_pixel(3, 2, image::Rgba([255, 255, 255, 255]));
+        encoder.encode(&frame).unwrap();
+        encoder.encode(&frame).unwrap();
+        assert!(encoder.encode(&RgbaImage::new(2, 2)).is_err());
+        encoder.finish().unwrap();
+
+        let header = "YUV4MPEG2 W3 H2 F29970:1000 Ip A1:1 C444\n";
+        let data = std::fs::read(&path).unwrap();
+        assert!(data.starts_with(header.as_bytes()));
+        assert_eq!(data.len(), header.len() + 2 * ("FRAME\n".len() + 18));
+        // white is (235, 128, 128) in limited range
+        assert_eq!(&data[header.len() + 6..header.len() + 12], &[235; 6]);
+        assert_eq!(rgb_to_ycbcr(0, 0, 0), [16, 128, 128]);
+    }
+}
@@ -8,7 +8,7 @@ use winit::event::*;
 
 const CAMERA_SPEED: f32 = 1.0;
 const CAMERA_SENSITIVITY: f32 = 0.2;
-const PROJECTION_FOXY: f32 = 45.0;
+pub const PROJECTION_FOXY: f32 = 45.0;
 const PROJECTION_ZNEAR: f32 = 0.001;
 const PROJECTION_ZFAR: f32 = 100.0;
 
@@ -6,4 +6,4 @@ pub mod png;
 pub mod point_cloud_renderer;
 pub mod reader;
 pub mod renderable;
-pub mod upsampler;
+pub mod splat;
@@ -1,8 +1,13 @@
 use crate::formats::pointxyzrgba::PointXyzRgba;
 use crate::formats::PointCloud;
+use crate::render::cpu::CpuRenderer;
+use crate::render::passes::Passes;
+use crate::render::rig::Rig;
 use crate::render::wgpu::camera::{Camera, CameraState};
 use crate::render::wgpu::color::parse_wgpu_color;
 use crate::render::wgpu::point_cloud_renderer::PointCloudRenderer;
+use crate::render::wgpu::splat::{Splat, SplatSize};
+use image::RgbaImage;
 use std::ffi::OsString;
 use std::num::NonZeroU32;
 use std::path::Path;
@@ -11,12 +16,23 @@ use wgpu::{Buffer, Device, InstanceDescriptor, Queue, Texture, TextureDescriptor
 use winit::dpi::PhysicalSize;
 
 use super::camera::CameraPosition;
-use std::process::{Command, Stdio};
 
 #[derive(clap::ValueEnum, Debug, Copy, Clone, Eq, PartialEq)]
 pub enum RenderFormat {
     Png,
+    /// encoded with ffmpeg, needs the `ffmpeg` feature
     Mp4,
+    /// uncompressed YUV 4:4:4
+    Y4m,
+    /// Motion JPEG in an AVI container
+    Avi,
+}
+
+impl RenderFormat {
+    /// Whether the frames are encoded into a single video file instead of images
+    pub fn is_video(&self) -> bool {
+        *self != RenderFormat::Png
+    }
 }
 
 impl ToString for RenderFormat {
@@ -24,6 +40,8 @@ impl ToString for RenderFormat {
         match self {
             RenderFormat::Png => "png".to_string(),
             RenderFormat::Mp4 => "mp4".to_string(),
+            RenderFormat::Y4m => "y4m".to_string(),
+            RenderFormat::Avi => "avi".to_string(),
         }
     }
 }
@@ -35,14 +53,23 @@ impl FromStr for RenderFormat {
         match s {
             "png" => Ok(RenderFormat::Png),
             "mp4" => Ok(RenderFormat::Mp4),
+            "y4m" => Ok(RenderFormat::Y4m),
+            "avi" => Ok(RenderFormat::Avi),
             _ => Err("Invalid render format".to_string()),
         }
     }
 }
 
-pub struct PngWriter<'a> {
-    output_dir: OsString,
-    size: PhysicalSize<u32>,
+/// Which renderer draws the images
+#[derive(clap::ValueEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RenderBackend {
+    /// wgpu, needs a graphics adapter
+    Gpu,
+    /// software rasterizer, gives the same images on every machine
+    Cpu,
+}
+
+struct GpuTarget<'a> {
     device: Device,
     queue: Queue,
     texture_desc: TextureDescriptor<'a>,
@@ -50,13 +77,30 @@ pub struct PngWriter<'a> {
     texture_view: TextureView,
     u32_size: u32,
     output_buffer: Buffer,
-    camera_state: CameraState,
     point_renderer: Option<PointCloudRenderer<PointCloud<PointXyzRgba>>>,
+}
+
+enum Target<'a> {
+    Gpu(GpuTarget<'a>),
+    Cpu(CpuRenderer),
+}
+
+pub struct PngWriter<'a> {
+    output_dir: OsString,
+    size: PhysicalSize<u32>,
+    target: Target<'a>,
+    camera_state: CameraState,
     background_color: Option<wgpu::Color>,
     render_format: RenderFormat,
+    splat: Splat,
+    /// Rasterizes the depth, normal and mask passes for the gpu backend
+    pass_renderer: Option<CpuRenderer>,
+    /// Whether the gpu backend has reported that it ignores the normals, to warn only once
+    warned_normals: bool,
 }
 
 impl<'a> PngWriter<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         output_dir: OsString,
         camera_x: f32,
@@ -68,19 +112,184 @@ impl<'a> PngWriter<'a> {
         height: u32,
         bg_color: &str,
         render_format: RenderFormat,
+        backend: RenderBackend,
     ) -> Self {
         let output_path = Path::new(&output_dir);
 
         std::fs::create_dir_all(output_path).expect("Failed to create output directory");
 
         let size = PhysicalSize::new(width, height);
+        let background_color = parse_wgpu_color(bg_color).ok();
+        let target = match backend {
+            RenderBackend::Gpu => Target::Gpu(GpuTarget::new(size)),
+            RenderBackend::Cpu => {
+                let mut renderer = CpuRenderer::new(size.width, size.height);
+                renderer.set_background_color(background_color.unwrap_or(wgpu::Color::BLACK));
+                Target::Cpu(renderer)
+            }
+        };
+
+        let camera = Camera::new((camera_x, camera_y, camera_z), camera_yaw, camera_pitch);
+        let camera_state = CameraState::new(camera, size.width, size.height);
+        Self {
+            output_dir,
+            size,
+            target,
+            camera_state,
+            background_color,
+            render_format,
+            splat: Splat::default(),
+            pass_renderer: None,
+            warned_normals: false,
+        }
+    }
+
+    /// Set the background color. Call this function before the first [`write_to_png`] call
+    ///
+    /// [`write_to_png`]: #method.write_to_png
+    pub fn set_background_color(&mut self, color: wgpu::Color) {
+        self.background_color = Some(color);
+        if let Target::Cpu(ref mut renderer) = self.target {
+            renderer.set_background_color(color);
+        }
+    }
+
+    /// Set how every point is drawn, a single pixel by default.
+    /// Only the cpu backend turns splats along the normals of the points.
+    pub fn set_splat(&mut self, splat: Splat) {
+        self.splat = splat;
+        match self.target {
+            Target::Cpu(ref mut renderer) => renderer.set_splat(splat),
+            Target::Gpu(GpuTarget {
+                point_renderer: Some(ref mut renderer),
+                ref queue,
+                ..
+            }) => renderer.set_splat(queue, splat),
+            Target::Gpu(_) => {}
+        }
+        if let Some(ref mut renderer) = self.pass_renderer {
+            renderer.set_splat(splat);
+        }
+    }
+
+    pub fn render_format(&self) -> RenderFormat {
+        self.render_format
+    }
+
+    /// Update the camera position
+    pub fn update_camera_pos(&mut self, pos: CameraPosition) {
+        self.camera_state.update_camera_pos(pos);
+        if let Target::Gpu(GpuTarget {
+            point_renderer: Some(ref mut renderer),
+            ref queue,
+            ..
+        }) = self.target
+        {
+            renderer.update_camera(queue, self.camera_state.camera_uniform);
+        }
+    }
+
+    /// Render a point cloud into an image
+    pub fn render(&mut self, pc: &PointCloud<PointXyzRgba>) -> RgbaImage {
+        self.render_with_normals(pc, None)
+    }
+
+    /// Render a point cloud into an image, `normals` has one normal per point of `pc`.
+    /// The gpu backend ignores the normals and warns once if its splats have a radius.
+    pub fn render_with_normals(
+        &mut self,
+        pc: &PointCloud<PointXyzRgba>,
+        normals: Option<&[[f32; 3]]>,
+    ) -> RgbaImage {
+        match self.target {
+            Target::Gpu(ref mut target) => {
+                if normals.is_some()
+                    && matches!(self.splat.size, SplatSize::World(_))
+                    && !self.warned_normals
+                {
+                    eprintln!("The gpu backend draws the splats facing the camera and ignores the normals, use --backend cpu to turn them along the normals");
+                    self.warned_normals = true;
+                }
+                target.render(
+                    pc,
+                    self.size,
+                    &self.camera_state,
+                    self.background_color,
+                    self.splat,
+                )
+            }
+            Target::Cpu(ref mut renderer) => {
+                renderer.render(pc, normals, &self.camera_state.camera_uniform)
+            }
+        }
+    }
+
+    /// Render a point cloud from every view of a rig. The camera is left at the last view.
+    pub fn render_views(&mut self, pc: &PointCloud<PointXyzRgba>, rig: &Rig) -> Vec<RgbaImage> {
+        rig.views()
+            .iter()
+            .map(|&view| {
+                self.update_camera_pos(view);
+                self.render(pc)
+            })
+            .collect()
+    }
+
+    /// Rasterize the depth, normal and mask passes from the current camera, aligned with [`render`].
+    /// `normals` has one normal per point of `pc`. The splats of the passes face the screen on the
+    /// gpu backend, like its colours, and along the normals on the cpu backend.
+    ///
+    /// [`render`]: #method.render
+    pub fn render_passes(
+        &mut self,
+        pc: &PointCloud<PointXyzRgba>,
+        normals: Option<&[[f32; 3]]>,
+    ) -> Passes {
+        let (width, height) = (self.size.width, self.size.height);
+        let splat = self.splat;
+        let (renderer, oriented) = match self.target {
+            Target::Cpu(ref mut renderer) => (renderer, true),
+            Target::Gpu(_) => (
+                self.pass_renderer.get_or_insert_with(|| {
+                    let mut renderer = CpuRenderer::new(width, height);
+                    renderer.set_splat(splat);
+                    renderer
+                }),
+                false,
+            ),
+        };
+        Passes::render(
+            renderer,
+            pc,
+            normals,
+            oriented,
+            &self.camera_state,
+            width,
+            height,
+        )
+    }
+
+    pub fn write_to_png(&mut self, pc: &PointCloud<PointXyzRgba>, filename: &str) {
+        let image = self.render(pc);
+        self.save_png(&image, filename);
+    }
+
+    /// Save a rendered image into the output directory
+    pub fn save_png(&self, image: &RgbaImage, filename: &str) {
+        let output_path = Path::new(&self.output_dir).join(Path::new(&filename));
+        image.save(output_path).unwrap();
+    }
+}
+
+impl<'a> GpuTarget<'a> {
+    fn new(size: PhysicalSize<u32>) -> Self {
         let instance = wgpu::Instance::new(InstanceDescriptor::default());
         let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
             power_preference: wgpu::PowerPreference::default(),
             compatible_surface: None,
             force_fallback_adapter: false,
         }))
-        .unwrap();
+        .expect("No graphics adapter found, use the cpu backend instead");
 
         let (device, queue) =
             pollster::block_on(adapter.request_device(&Default::default(), None)).unwrap();
@@ -113,11 +322,7 @@ impl<'a> PngWriter<'a> {
         };
         let output_buffer = device.create_buffer(&output_buffer_desc);
 
-        let camera = Camera::new((camera_x, camera_y, camera_z), camera_yaw, camera_pitch);
-        let camera_state = CameraState::new(camera, size.width, size.height);
         Self {
-            output_dir,
-            size,
             device,
             queue,
             texture_desc,
@@ -125,46 +330,29 @@ impl<'a> PngWriter<'a> {
             texture_view,
             u32_size,
             output_buffer,
-            camera_state,
             point_renderer: None,
-            // background_color: None,
-            background_color: parse_wgpu_color(bg_color).ok(),
-            // bg_color: parse_bg_color(bg_color).unwrap(),
-            // count: 0,
-            render_format,
-        }
-    }
-
-    /// Set the background color. Call this function before the first [`write_to_png`] call
-    ///
-    /// [`write_to_png`]: #method.write_to_png
-    pub fn set_background_color(&mut self, color: wgpu::Color) {
-        self.background_color = Some(color);
-    }
-
-    pub fn render_format(&self) -> RenderFormat {
-        self.render_format
-    }
-
-    /// Update the camera position
-    pub fn update_camera_pos(&mut self, pos: CameraPosition) {
-        self.camera_state.update_camera_pos(pos);
-        if let Some(ref mut renderer) = self.point_renderer {
-            renderer.update_camera(&self.queue, self.camera_state.camera_uniform);
         }
     }
 
-    pub fn write_to_png(&mut self, pc: &PointCloud<PointXyzRgba>, filename: &str) {
+    fn render(
+        &mut self,
+        pc: &PointCloud<PointXyzRgba>,
+        size: PhysicalSize<u32>,
+        camera_state: &CameraState,
+        background_color: Option<wgpu::Color>,
+        splat: Splat,
+    ) -> RgbaImage {
         if self.point_renderer.is_none() {
-            let renderer = PointCloudRenderer::new(
+            let mut renderer = PointCloudRenderer::new(
                 &self.device,
                 self.texture_desc.format,
                 pc,
-                self.size,
-                &self.camera_state,
-                self.background_color.unwrap_or(wgpu::Color::BLACK),
+                size,
+                camera_state,
+                background_color.unwrap_or(wgpu::Color::BLACK),
             );
-            self.point_renderer = Some(if let Some(color) = self.background_color {
+            renderer.set_splat(&self.queue, splat);
+            self.point_renderer = Some(if let Some(color) = background_color {
                 renderer.with_background_color(color)
             } else {
                 renderer
@@ -188,99 +376,23 @@ impl<'a> PngWriter<'a> {
                 buffer: &self.output_buffer,
                 layout: wgpu::ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: NonZeroU32::new(self.u32_size * self.size.width),
-                    rows_per_image: NonZeroU32::new(self.size.height),
+                    bytes_per_row: NonZeroU32::new(self.u32_size * size.width),
+                    rows_per_image: NonZeroU32::new(size.height),
                 },
             },
             self.texture_desc.size,
         );
 
         self.queue.submit(Some(encoder.finish()));
-        {
+        let image = {
             let buffer_slice = self.output_buffer.slice(..);
             buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
             self.device.poll(wgpu::Maintain::Wait);
 
             let data = buffer_slice.get_mapped_range();
-
-            use image::{ImageBuffer, Rgba};
-            let buffer =
-                ImageBuffer::<Rgba<u8>, _>::from_raw(self.size.width, self.size.height, data)
-                    .unwrap();
-
-            let output_path = Path::new(&self.output_dir);
-            buffer.save(output_path.join(Path::new(&filename))).unwrap();
-        }
+            RgbaImage::from_raw(size.width, size.height, data.to_vec()).unwrap()
+        };
         self.output_buffer.unmap();
-    }
-
-    pub fn write_to_mp4(&self, name_length: u32, fps: f32, verbose: bool) {
-        let img_dir_path = Path::new(&self.output_dir);
-        let mp4_save_path = img_dir_path.parent().unwrap();
-        let mut mp4_path = mp4_save_path.to_path_buf();
-        mp4_path.push("output.mp4");
-
-        PngWriter::png_to_mp4(img_dir_path, &mp4_path, name_length, fps, verbose);
-
-        // delete tmp png dir
-        std::fs::remove_dir_all(img_dir_path).unwrap();
-    }
-
-    pub fn png_to_mp4(img_dir: &Path, mp4_path: &Path, name_length: u32, fps: f32, verbose: bool) {
-        let tmp_png_dir = Path::new(img_dir);
-        // mp4 dir is parent of tmp_png_dir
-        // read all png file in tmp_png_dir, then sort them lexicographically, then write to mp4
-        let mut png_files: Vec<_> = std::fs::read_dir(tmp_png_dir)
-            .unwrap()
-            .map(|entry| entry.unwrap().path())
-            .collect();
-        png_files.sort();
-
-        // use ffmpeg to convert png to mp4
-        let cmd = Command::new("ffmpeg")
-            .arg("-y")
-            .arg("-framerate")
-            .arg(fps.to_string())
-            .arg("-i")
-            .arg(format!("{}/%0{}d.png", tmp_png_dir.display(), name_length))
-            .arg("-c:v")
-            .arg("libx264")
-            .arg("-r")
-            .arg(fps.to_string())
-            // .arg("-pix_fmt")
-            // .arg("yuv420p")
-            .arg(mp4_path)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .expect("Failed to start ffmpeg process");
-
-        if verbose {
-            println!("cmd is {:?}", cmd);
-        }
-
-        let output = cmd
-            .wait_with_output()
-            .expect("Failed to run/wait ffmpeg process");
-
-        if output.status.success() {
-            if verbose {
-                let mut output_string = String::new();
-                if !output.stdout.is_empty() {
-                    output_string
-                        .push_str(String::from_utf8_lossy(&output.stdout).to_string().as_str());
-                }
-                // ffmpeg output all of its logging data to stderr
-                if !output.stderr.is_empty() {
-                    output_string
-                        .push_str(String::from_utf8_lossy(&output.stderr).to_string().as_str());
-                }
-                println!("ffmpeg:\n{}", output_string);
-                println!("mp4 file is saved to {}", mp4_path.display());
-            }
-        } else {
-            eprintln!("ffmpeg error:\n{}", String::from_utf8_lossy(&output.stderr));
-            panic!("ffmpeg error")
-        }
+        image
     }
 }
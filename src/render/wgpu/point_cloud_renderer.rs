@@ -1,5 +1,6 @@
 use super::renderable::Renderable;
 use crate::render::wgpu::camera::{CameraState, CameraUniform};
+use crate::render::wgpu::splat::Splat;
 use std::marker::PhantomData;
 use wgpu::{
     BindGroup, Buffer, CommandEncoder, Device, LoadOp, Operations, Queue,
@@ -11,6 +12,10 @@ pub struct PointCloudRenderer<T: Renderable> {
     camera_buffer: Buffer,
     camera_bind_group: BindGroup,
     antialias_bind_group: BindGroup,
+    splat_buffer: Buffer,
+    splat_bind_group: BindGroup,
+    splat: Splat,
+    size: PhysicalSize<u32>,
     depth_texture: Texture,
     depth_view: TextureView,
     render_pipeline: RenderPipeline,
@@ -36,11 +41,18 @@ where
             camera_state.create_buffer(device);
         let (antialias_bind_group_layout, antialias_bind_group) =
             initial_render.antialias().create_buffer(device);
+        let splat = Splat::default();
+        let (splat_buffer, splat_bind_group_layout, splat_bind_group) =
+            splat.uniform(initial_size).create_buffer(device);
 
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&camera_bind_group_layout, &antialias_bind_group_layout],
+                bind_group_layouts: &[
+                    &camera_bind_group_layout,
+                    &antialias_bind_group_layout,
+                    &splat_bind_group_layout,
+                ],
                 push_constant_ranges: &[],
             });
 
@@ -55,6 +67,10 @@ where
             camera_buffer,
             camera_bind_group,
             antialias_bind_group,
+            splat_buffer,
+            splat_bind_group,
+            splat,
+            size: initial_size,
             depth_texture,
             depth_view,
             render_pipeline,
@@ -70,14 +86,31 @@ where
         self
     }
 
-    pub fn resize(&mut self, new_size: PhysicalSize<u32>, device: &Device) {
+    /// Set how every point is drawn, a single pixel by default
+    pub fn set_splat(&mut self, queue: &Queue, splat: Splat) {
+        self.splat = splat;
+        self.update_splat(queue);
+    }
+
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>, device: &Device, queue: &Queue) {
         if new_size.width > 0 && new_size.height > 0 {
             let (depth_texture, depth_view) = T::create_depth_texture(device, new_size);
             self.depth_texture = depth_texture;
             self.depth_view = depth_view;
+            // splats are sized in pixels of the viewport
+            self.size = new_size;
+            self.update_splat(queue);
         }
     }
 
+    fn update_splat(&self, queue: &Queue) {
+        queue.write_buffer(
+            &self.splat_buffer,
+            0,
+            bytemuck::cast_slice(&[self.splat.uniform(self.size)]),
+        );
+    }
+
     pub fn update_camera(&self, queue: &Queue, camera_uniform: CameraUniform) {
         queue.write_buffer(
             &self.camera_buffer,
@@ -136,7 +169,9 @@ where
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
         render_pass.set_bind_group(1, &self.antialias_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.splat_bind_group, &[]);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.draw(0..(self.num_vertices as u32), 0..1);
+        // a quad of 4 vertices for every point
+        render_pass.draw(0..4, 0..(self.num_vertices as u32));
     }
 }
@@ -2,13 +2,13 @@ use crate::formats::pointxyzrgba::PointXyzRgba;
 use crate::formats::PointCloud;
 use crate::pcd::read_pcd_file;
 use crate::utils::{read_file_to_point_cloud, read_files_to_point_cloud};
+use crate::vvplay_async_prefetch::session_log::{SessionEvent, SessionLog};
 use crate::BufMsg;
 
 // use crate::utils::read_file_to_point_cloud;
 use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::path::{Path, PathBuf};
-use std::process::exit;
 use std::sync::mpsc::Receiver;
 use tokio::sync::mpsc::UnboundedSender;
 
@@ -204,37 +204,18 @@ impl RenderReader<PointCloud<PointXyzRgba>> for PcdMemoryReader {
 
 pub struct LODFileReader {
     base_files: Vec<PathBuf>,
-    additional_files: Option<Vec<Vec<PathBuf>>>,
+    /// the additional points of a frame are in the file of these directories named as its base file
+    additional_dirs: Option<Vec<PathBuf>>,
 }
 
 impl LODFileReader {
     pub fn new(base_dir: &Path, additional_dirs: Option<Vec<&Path>>, file_type: &str) -> Self {
         let base_files = Self::from_directory(base_dir, file_type);
-
-        if additional_dirs.is_none() {
-            return Self {
-                base_files,
-                additional_files: None,
-            };
-        }
-
-        let additional_files = additional_dirs
-            .unwrap()
-            .iter()
-            .map(|dir| Self::from_directory(dir, file_type))
-            .collect::<Vec<_>>();
-
-        let len = base_files.len();
-        for reader in additional_files.iter() {
-            if reader.len() != len {
-                eprintln!("All readers must have the same length");
-                exit(1);
-            }
-        }
-
+        let additional_dirs =
+            additional_dirs.map(|dirs| dirs.iter().map(|dir| dir.to_path_buf()).collect());
         Self {
             base_files,
-            additional_files: Some(additional_files),
+            additional_dirs,
         }
     }
 
@@ -258,20 +239,26 @@ impl LODFileReader {
         files
     }
 
-    /// Get the point point cloud at the given index with additional points at the given indices.
+    /// Get the point point cloud at the given index with the first `additional_points[i]` points of the
+    /// `i`-th additional directory. Directories without any point to load are not read, so any prefix of
+    /// the levels of detail can be loaded, and a frame may have fewer tiles than there are directories.
     pub fn get_with_additional_at(
         &self,
         index: usize,
-        additional_points: &Vec<usize>,
+        additional_points: &[usize],
     ) -> Option<PointCloud<PointXyzRgba>> {
         let base_file = self.base_files.get(index)?;
-        let additional_files = self
-            .additional_files
+        let file_name = base_file.file_name()?;
+        let (additional_files, additional_points): (Vec<_>, Vec<_>) = self
+            .additional_dirs
             .as_ref()?
             .iter()
-            .map(|reader| reader.get(index).unwrap())
-            .collect::<Vec<_>>();
-        read_files_to_point_cloud(base_file, &additional_files, additional_points)
+            .zip(additional_points)
+            .filter(|(_, num)| **num > 0)
+            .map(|(dir, &num)| (dir.join(file_name), num))
+            .unzip();
+        let additional_files = additional_files.iter().collect();
+        read_files_to_point_cloud(base_file, &additional_files, &additional_points)
     }
 }
 
@@ -297,12 +284,16 @@ impl RenderReader<PointCloud<PointXyzRgba>> for LODFileReader {
 }
 
 pub struct PcdAsyncReader {
+    /// the object of the mpd the frames are requested for
+    object_id: u8,
     total_frames: u64,
     rx: Receiver<(FrameRequest, PointCloud<PointXyzRgba>)>,
     //playback cache
     cache: VecDeque<(u64, PointCloud<PointXyzRgba>)>,
     cache_size: usize,
     tx: UnboundedSender<BufMsg>,
+    /// logs every frame handed to the renderer
+    session_log: Option<SessionLog>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -330,6 +321,7 @@ impl PcdAsyncReader {
         // buffer_size: Option<u8>,rame requst id: {}, offset: {}", new_key.object_id, new_key.frame_offsei
     ) -> Self {
         Self {
+            object_id: 0,
             rx,
             tx,
             // buffer_size,
@@ -337,6 +329,27 @@ impl PcdAsyncReader {
             cache: VecDeque::new(),
             cache_size: 10, //default number of size, Use `set_cache_size` to overwrite this value
             total_frames: 30, // default number of frames. Use `set_len` to overwrite this value
+            session_log: None,
+        }
+    }
+
+    /// Request the frames of another object than the first one
+    pub fn with_object_id(mut self, object_id: u8) -> Self {
+        self.object_id = object_id;
+        self
+    }
+
+    pub fn with_session_log(mut self, session_log: Option<SessionLog>) -> Self {
+        self.session_log = session_log;
+        self
+    }
+
+    fn log_frame(&self, index: u64) {
+        if let Some(session_log) = &self.session_log {
+            session_log.log(SessionEvent::Frame {
+                object_id: self.object_id,
+                frame: index,
+            });
         }
     }
 }
@@ -360,7 +373,7 @@ impl RenderReaderCameraPos<PointCloud<PointXyzRgba>> for PcdAsyncReader {
             return (camera_pos, Some(result.1.clone()));
         }
         _ = self.tx.send(BufMsg::FrameRequest(FrameRequest {
-            object_id: 0,
+            object_id: self.object_id,
             frame_offset: index % self.total_frames,
             camera_pos,
         }));
@@ -409,11 +422,13 @@ impl RenderReader<PointCloud<PointXyzRgba>> for PcdAsyncReader {
         // Everytime a request is made, find it from the playback cache first
         if let Some(&ref result) = self.cache.iter().find(|&i| i.0 == index) {
             // Enhancement: can improve this O(n) find algorithm in future
-            return Some(result.1.clone());
+            let pc = result.1.clone();
+            self.log_frame(index);
+            return Some(pc);
         }
         // Send request to prepare for the frame
         _ = self.tx.send(BufMsg::FrameRequest(FrameRequest {
-            object_id: 0,
+            object_id: self.object_id,
             frame_offset: index % self.total_frames,
             camera_pos: Some(CameraPosition::default()),
         }));
@@ -427,6 +442,7 @@ impl RenderReader<PointCloud<PointXyzRgba>> for PcdAsyncReader {
                 index
             );
             self.cache.push_back((index, pc.clone()));
+            self.log_frame(index);
             Some(pc)
         } else {
             None
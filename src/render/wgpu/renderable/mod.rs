@@ -61,8 +61,9 @@ impl Renderable for PointCloud<PointXyzRgba> {
         wgpu::VertexBufferLayout {
             // how wide a vertex is
             array_stride: 16,
-            // whether each element of this buffer represents per-vertex or per-instance data
-            step_mode: wgpu::VertexStepMode::Vertex,
+            // whether each element of this buffer represents per-vertex or per-instance data.
+            // Every point is an instance, drawn as a quad
+            step_mode: wgpu::VertexStepMode::Instance,
             attributes: &[
                 wgpu::VertexAttribute {
                     offset: 0,
@@ -110,7 +111,7 @@ impl Renderable for PointCloud<PointXyzRgba> {
             }),
             // how to interpret our vertices when converting into triangles
             primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::PointList,
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
                 cull_mode: None,
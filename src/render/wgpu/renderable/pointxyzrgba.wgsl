@@ -14,6 +14,18 @@ struct AntiAlias {
 
 @group(1) @binding(0) var<uniform> antialias: AntiAlias;
 
+struct Splat {
+    size: f32,
+    focal_length: f32,
+    viewport: vec2<f32>,
+    // 0: pixels, 1: pixels shrinking with the distance, 2: radius in the units of the point cloud
+    mode: u32,
+    // 0: square, 1: circle
+    shape: u32,
+}
+
+@group(2) @binding(0) var<uniform> splat: Splat;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: u32,
@@ -22,6 +34,8 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec4<f32>,
+    // position in the splat, from -1 to 1
+    @location(1) corner: vec2<f32>,
 };
 
 fn linear_transform(f: f32) -> f32 {
@@ -35,6 +49,7 @@ fn linear_transform(f: f32) -> f32 {
 
 @vertex
 fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
     model: VertexInput,
 ) -> VertexOutput {
     var out: VertexOutput;
@@ -49,11 +64,28 @@ fn vs_main(
     let position = vec3<f32>(model.position[0] - antialias.x, model.position[1] - antialias.y, model.position[2] - antialias.z);
     let pos = position / antialias.scale;
     out.color = vec4<f32>(cast_red, cast_green, cast_blue, 1.0);
-    out.clip_position = camera.view_proj * vec4<f32>(pos, 1.0);
+    let centre = camera.view_proj * vec4<f32>(pos, 1.0);
+
+    // every point is a quad facing the screen drawn as a triangle strip of 4 vertices,
+    // the vertices carry no normals to turn it along
+    let corner = vec2<f32>(f32(vertex_index & 1u), f32(vertex_index >> 1u)) * 2.0 - 1.0;
+    var size = splat.size;
+    if (splat.mode == 1u) {
+        size = splat.size / centre.w;
+    } else if (splat.mode == 2u) {
+        size = 2.0 * splat.size / antialias.scale * splat.focal_length / centre.w;
+    }
+    size = max(size, 1.0);
+    let offset = corner * size / splat.viewport * centre.w;
+    out.clip_position = centre + vec4<f32>(offset, 0.0, 0.0);
+    out.corner = corner;
     return out;
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (splat.shape == 1u && dot(in.corner, in.corner) > 1.0) {
+        discard;
+    }
     return in.color;
 }
@@ -0,0 +1,154 @@
+use crate::render::wgpu::camera::PROJECTION_FOXY;
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SplatShape {
+    Square,
+    Circle,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SplatSize {
+    /// side of the square, or diameter of the circle, in pixels
+    Pixels(f32),
+    /// radius in the units of the point cloud, shrinking with the distance like the geometry
+    World(f32),
+}
+
+/// How every point is drawn
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Splat {
+    pub size: SplatSize,
+    pub shape: SplatShape,
+    /// Shrink pixel sized splats with the distance, they have their size 1 unit in front of the camera.
+    /// World sized splats are always attenuated.
+    pub attenuation: bool,
+}
+
+impl Default for Splat {
+    /// a single pixel per point
+    fn default() -> Self {
+        Self {
+            size: SplatSize::Pixels(1.0),
+            shape: SplatShape::Square,
+            attenuation: false,
+        }
+    }
+}
+
+impl Splat {
+    /// Side or diameter in pixels of a splat `distance` in front of the camera, both in rendering coordinates.
+    /// `scale` is the normalization of the renderer, see [`AntiAlias`](super::antialias::AntiAlias).
+    pub fn pixels(&self, distance: f32, scale: f32, height: u32) -> f32 {
+        match self.size {
+            SplatSize::Pixels(size) if self.attenuation => size / distance,
+            SplatSize::Pixels(size) => size,
+            SplatSize::World(radius) => 2.0 * radius / scale * focal_length(height) / distance,
+        }
+    }
+
+    pub fn uniform(&self, size: PhysicalSize<u32>) -> SplatUniform {
+        let (value, mode) = match self.size {
+            SplatSize::Pixels(size) if self.attenuation => (size, 1),
+            SplatSize::Pixels(size) => (size, 0),
+            SplatSize::World(radius) => (radius, 2),
+        };
+        SplatUniform {
+            size: value,
+            focal_length: focal_length(size.height),
+            viewport: [size.width as f32, size.height as f32],
+            mode,
+            shape: match self.shape {
+                SplatShape::Square => 0,
+                SplatShape::Circle => 1,
+            },
+        }
+    }
+}
+
+/// Splat options shared by the commands that render point clouds
+#[derive(clap::Args, Debug, Clone)]
+pub struct SplatArgs {
+    /// Side of the square, or diameter of the circle, drawn for every point in pixels
+    #[clap(long, default_value_t = 1.0)]
+    pub point_size: f32,
+    /// Radius of the splats in the units of the point cloud instead of --point-size.
+    /// With normals the cpu backend draws them as disks facing along the normals
+    #[clap(long)]
+    pub splat_radius: Option<f32>,
+    #[clap(long, value_enum, default_value_t = SplatShape::Square)]
+    pub splat_shape: SplatShape,
+    /// Shrink pixel sized splats with the distance, keeping --point-size 1 unit in front of the camera
+    #[clap(long, default_value_t = false)]
+    pub splat_attenuation: bool,
+}
+
+impl SplatArgs {
+    pub fn splat(&self) -> Splat {
+        Splat {
+            size: match self.splat_radius {
+                Some(radius) => SplatSize::World(radius),
+                None => SplatSize::Pixels(self.point_size),
+            },
+            shape: self.splat_shape,
+            attenuation: self.splat_attenuation,
+        }
+    }
+}
+
+/// Pixels per unit of rendering coordinates 1 unit in front of the camera
+pub fn focal_length(height: u32) -> f32 {
+    height as f32 / 2.0 / (PROJECTION_FOXY / 2.0).to_radians().tan()
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SplatUniform {
+    pub size: f32,
+    pub focal_length: f32,
+    pub viewport: [f32; 2],
+    /// 0 for pixels, 1 for attenuated pixels and 2 for a radius in the units of the point cloud
+    pub mode: u32,
+    /// 0 for squares and 1 for circles
+    pub shape: u32,
+}
+
+impl SplatUniform {
+    pub fn create_buffer(
+        &self,
+        device: &wgpu::Device,
+    ) -> (wgpu::Buffer, wgpu::BindGroupLayout, wgpu::BindGroup) {
+        let splat_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Splat Buffer"),
+            contents: bytemuck::cast_slice(&[*self]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let splat_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("splat_bind_group_layout"),
+            });
+
+        let splat_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &splat_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: splat_buffer.as_entire_binding(),
+            }],
+            label: Some("splat_bind_group"),
+        });
+
+        (splat_buffer, splat_bind_group_layout, splat_bind_group)
+    }
+}
@@ -136,6 +136,15 @@ impl NetworkTrace {
         }
     }
 
+    /// A trace of the given bandwidth samples in Kbps.
+    pub fn from_samples(data: Vec<f64>) -> Self {
+        assert!(!data.is_empty(), "network trace must not be empty");
+        NetworkTrace {
+            data,
+            index: RefCell::new(0),
+        }
+    }
+
     // Get the next bandwidth sample
     pub fn next(&self) -> f64 {
         let idx = *self.index.borrow();
@@ -1,7 +1,7 @@
 use std::{collections::HashSet, time::Instant};
 
 use kiddo::{distance::squared_euclidean, KdTree};
-use log::warn;
+use log::{debug, warn};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::{
@@ -99,23 +99,35 @@ pub fn contains(bound: &Bounds, point: &PointXyzRgba) -> bool {
         && point.z <= bound.max_z * ERROR_MARGIN_PERCENTAGE
 }
 
-fn partition(
+/// Splits the points into a grid of `partitions` cells over their bounds, every point in exactly one cell.
+pub(crate) fn partition(
     pc: &PointCloud<PointXyzRgba>,
     partitions: (usize, usize, usize),
 ) -> Vec<Vec<PointXyzRgba>> {
-    let pc_bound = get_pc_bound(&pc);
-    let child_bounds = pc_bound.partition(partitions);
-
-    child_bounds
-        .par_iter()
-        .map(|bound| {
-            pc.points
-                .iter()
-                .map(|point| point.clone())
-                .filter(|point| contains(bound, point))
-                .collect::<Vec<_>>()
-        })
-        .collect::<Vec<_>>()
+    let (kx, ky, kz) = (
+        partitions.0.max(1),
+        partitions.1.max(1),
+        partitions.2.max(1),
+    );
+    let mut cells = vec![vec![]; kx * ky * kz];
+    if pc.points.is_empty() {
+        return cells;
+    }
+    let bound = get_pc_bound(pc);
+    let cell = |v: f32, min: f32, max: f32, k: usize| {
+        if max > min {
+            (((v - min) / (max - min) * k as f32) as usize).min(k - 1)
+        } else {
+            0
+        }
+    };
+    for point in &pc.points {
+        let x = cell(point.x, bound.min_x, bound.max_x, kx);
+        let y = cell(point.y, bound.min_y, bound.max_y, ky);
+        let z = cell(point.z, bound.min_z, bound.max_z, kz);
+        cells[(z * ky + y) * kx + x].push(*point);
+    }
+    cells
 }
 
 fn euclidean_distance_3d(point1: &PointXyzRgba, point2: &PointXyzRgba) -> f32 {
@@ -191,11 +203,13 @@ pub fn upsample_grid(
         .filter(|vertices| !vertices.is_empty())
         .flat_map(|vertices| upsample_grid_vertices_dedup(vertices.clone()))
         .collect::<Vec<_>>();
-    println!("{:?}", start.elapsed().as_micros());
+    debug!("Upsampled in {} us", start.elapsed().as_micros());
     PointCloud::new(new_points.len(), new_points)
 }
 
-fn upsample_grid_vertices_dedup(vertices: Vec<PointXyzRgba>) -> Vec<PointXyzRgba> {
+/// Adds the middle points between every point and its 8 nearest neighbours, and between consecutive
+/// neighbours around it, skipping pairs that were already interpolated. Returns the new points followed by `vertices`.
+pub(crate) fn upsample_grid_vertices_dedup(vertices: Vec<PointXyzRgba>) -> Vec<PointXyzRgba> {
     let mut vertices = vertices;
     vertices.sort_unstable();
     let mut kd_tree = KdTree::new();
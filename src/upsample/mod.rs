@@ -1,2 +1,3 @@
 pub mod interpolate;
 pub mod upsample_methods;
+pub mod view_adaptive;
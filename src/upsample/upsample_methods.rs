@@ -4,6 +4,7 @@ use std::str::FromStr;
 pub enum UpsampleMethod {
     Spsr,
     Default,
+    ViewAdaptive,
 }
 
 impl ToString for UpsampleMethod {
@@ -11,6 +12,7 @@ impl ToString for UpsampleMethod {
         match self {
             UpsampleMethod::Spsr => "spsr",
             UpsampleMethod::Default => "default",
+            UpsampleMethod::ViewAdaptive => "view-adaptive",
         }
         .to_string()
     }
@@ -23,6 +25,7 @@ impl FromStr for UpsampleMethod {
         match s {
             "spsr" => Ok(UpsampleMethod::Spsr),
             "default" => Ok(UpsampleMethod::Default),
+            "view-adaptive" => Ok(UpsampleMethod::ViewAdaptive),
             _ => Err(format!("{} is not a valid output format", s)),
         }
     }
@@ -0,0 +1,270 @@
+use cgmath::{Matrix4, Point3, Transform};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::interpolate::{partition, upsample_grid_vertices_dedup};
+use crate::formats::{pointxyzrgba::PointXyzRgba, PointCloud};
+use crate::render::wgpu::{antialias::AntiAlias, camera::CameraState};
+
+/**
+ * This file contains the view-adaptive upsampling, which only densifies the parts of a point cloud
+ * that would show holes on the screen. It is used by the adaptive upsampling of vvplay and by
+ * `vv upsample --method view-adaptive`.
+ *
+ * Points are projected like the renderers draw them, after being centred and scaled by an antialias.
+ */
+
+/// When a region of a point cloud is considered too sparse for the screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UpsampleThresholds {
+    /// pixels searched in every direction around a drawn pixel for its neighbours
+    pub pixel_range: i32,
+    /// drawn neighbouring pixels for a drawn pixel to be covered
+    pub min_neighbours: usize,
+    /// a region is upsampled while less than this fraction of its drawn pixels are covered
+    pub min_coverage: f32,
+    /// regions with more visible points than this are never upsampled
+    pub max_points: usize,
+    /// times a region is upsampled at most
+    pub max_iterations: usize,
+}
+
+impl Default for UpsampleThresholds {
+    fn default() -> Self {
+        Self {
+            pixel_range: 1,
+            min_neighbours: 4,
+            min_coverage: 0.8,
+            max_points: 300_000,
+            max_iterations: 3,
+        }
+    }
+}
+
+/// Thresholds of the view-adaptive upsampling shared by the commands that use it
+#[derive(clap::Args, Debug, Clone)]
+pub struct UpsampleThresholdArgs {
+    /// Pixels searched in every direction around a drawn pixel for its neighbours
+    #[clap(long, default_value_t = 1)]
+    pub pixel_range: i32,
+    /// Drawn neighbouring pixels for a drawn pixel to count as covered
+    #[clap(long, default_value_t = 4)]
+    pub min_neighbours: usize,
+    /// Upsample a region while less than this fraction of its drawn pixels are covered
+    #[clap(long, default_value_t = 0.8)]
+    pub min_coverage: f32,
+    /// Never upsample a region with more visible points than this
+    #[clap(long, default_value_t = 300_000)]
+    pub max_points: usize,
+    /// Upsample a region at most this many times
+    #[clap(long, default_value_t = 3)]
+    pub max_iterations: usize,
+}
+
+impl UpsampleThresholdArgs {
+    pub fn thresholds(&self) -> UpsampleThresholds {
+        UpsampleThresholds {
+            pixel_range: self.pixel_range,
+            min_neighbours: self.min_neighbours,
+            min_coverage: self.min_coverage,
+            max_points: self.max_points,
+            max_iterations: self.max_iterations,
+        }
+    }
+}
+
+fn view_proj(camera_state: &CameraState) -> Matrix4<f32> {
+    Matrix4::from(camera_state.camera_uniform.view_proj)
+}
+
+fn normalize(point: &PointXyzRgba, antialias: &AntiAlias) -> Point3<f32> {
+    Point3::new(
+        point.x - antialias.x,
+        point.y - antialias.y,
+        point.z - antialias.z,
+    ) / antialias.scale
+}
+
+fn is_visible(point: &PointXyzRgba, view_proj: &Matrix4<f32>, antialias: &AntiAlias) -> bool {
+    let clip = view_proj * normalize(point, antialias).to_homogeneous();
+    clip.w > 0.0
+        && (clip.x / clip.w).abs() <= 1.0
+        && (clip.y / clip.w).abs() <= 1.0
+        && (clip.z / clip.w).abs() <= 1.0
+}
+
+/// Points of the point cloud inside the view frustum of the camera
+pub fn visible_points(
+    pc: PointCloud<PointXyzRgba>,
+    camera_state: &CameraState,
+    antialias: &AntiAlias,
+) -> PointCloud<PointXyzRgba> {
+    let view_proj = view_proj(camera_state);
+    let points = pc
+        .points
+        .into_par_iter()
+        .filter(|point| is_visible(point, &view_proj, antialias))
+        .collect::<Vec<_>>();
+    PointCloud::new(points.len(), points)
+}
+
+/// Fraction of the pixels drawn by the visible points that have at least `min_neighbours` drawn pixels
+/// within `pixel_range`, 1 if no point is visible.
+pub fn screen_coverage(
+    points: &[PointXyzRgba],
+    camera_state: &CameraState,
+    antialias: &AntiAlias,
+    thresholds: &UpsampleThresholds,
+) -> f32 {
+    let view_proj = view_proj(camera_state);
+    let width = camera_state.get_window_size().width as usize;
+    let height = camera_state.get_window_size().height as usize;
+    let mut filled = vec![false; (height + 1) * (width + 1)];
+    for point in points
+        .iter()
+        .filter(|point| is_visible(point, &view_proj, antialias))
+    {
+        let ndc = view_proj.transform_point(normalize(point, antialias));
+        let x = (((ndc.x + 1.0) * width as f32 / 2.0) as usize).min(width);
+        let y = (((ndc.y + 1.0) * height as f32 / 2.0) as usize).min(height);
+        filled[y * (width + 1) + x] = true;
+    }
+
+    let range = thresholds.pixel_range;
+    let (drawn, covered) = (0..filled.len())
+        .into_par_iter()
+        .filter(|&index| filled[index])
+        .map(|index| {
+            let x = (index % (width + 1)) as i32;
+            let y = (index / (width + 1)) as i32;
+            let mut neighbours = 0;
+            for x_curr in (x - range)..=(x + range) {
+                for y_curr in (y - range)..=(y + range) {
+                    if x_curr < 0
+                        || x_curr > width as i32
+                        || y_curr < 0
+                        || y_curr > height as i32
+                        || (x_curr, y_curr) == (x, y)
+                    {
+                        continue;
+                    }
+                    if filled[y_curr as usize * (width + 1) + x_curr as usize] {
+                        neighbours += 1;
+                    }
+                }
+            }
+            (1usize, (neighbours >= thresholds.min_neighbours) as usize)
+        })
+        .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+    if drawn == 0 {
+        1.0
+    } else {
+        covered as f32 / drawn as f32
+    }
+}
+
+/// Whether the points would leave holes on the screen and are few enough to be upsampled
+pub fn should_upsample(
+    points: &[PointXyzRgba],
+    camera_state: &CameraState,
+    antialias: &AntiAlias,
+    thresholds: &UpsampleThresholds,
+) -> bool {
+    let view_proj = view_proj(camera_state);
+    let visible = points
+        .iter()
+        .filter(|point| is_visible(point, &view_proj, antialias))
+        .count();
+    visible > 0
+        && visible <= thresholds.max_points
+        && screen_coverage(points, camera_state, antialias, thresholds) < thresholds.min_coverage
+}
+
+/// Splits the point cloud into `partitions`^3 regions and upsamples with the grid interpolation, up to
+/// `max_iterations` times, the regions whose visible points would leave holes on the screen.
+/// The other regions are kept as they are.
+pub fn upsample_view_adaptive(
+    pc: &PointCloud<PointXyzRgba>,
+    camera_state: &CameraState,
+    antialias: &AntiAlias,
+    partitions: usize,
+    thresholds: &UpsampleThresholds,
+) -> PointCloud<PointXyzRgba> {
+    let points = partition(pc, (partitions, partitions, partitions))
+        .into_par_iter()
+        .flat_map(|mut region| {
+            for _ in 0..thresholds.max_iterations {
+                if !should_upsample(&region, camera_state, antialias, thresholds) {
+                    break;
+                }
+                let before = region.len();
+                region = upsample_grid_vertices_dedup(region);
+                if region.len() == before {
+                    // too few points to interpolate between
+                    break;
+                }
+            }
+            region
+        })
+        .collect::<Vec<_>>();
+    PointCloud::new(points.len(), points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::pointxyzrgba::fixtures::{cloud, point};
+    use crate::render::wgpu::camera::Camera;
+    use crate::render::wgpu::renderable::Renderable;
+    use cgmath::Deg;
+
+    /// 11 x 11 points 0.1 apart on the z = 0 plane
+    fn plane() -> PointCloud<PointXyzRgba> {
+        cloud((0..11).flat_map(|i| {
+            (0..11).map(move |j| PointXyzRgba {
+                r: 255,
+                ..point(i as f32 * 0.1 - 0.5, j as f32 * 0.1 - 0.5, 0.0)
+            })
+        }))
+    }
+
+    fn camera_state(yaw: f32, width: u32, height: u32) -> CameraState {
+        CameraState::new(
+            Camera::new((0.0, 0.0, 1.8), Deg(yaw), Deg(0.0)),
+            width,
+            height,
+        )
+    }
+
+    #[test]
+    fn test_upsample_view_adaptive() {
+        let pc = plane();
+        let antialias = pc.antialias();
+        let thresholds = UpsampleThresholds {
+            max_iterations: 1,
+            ..Default::default()
+        };
+
+        // the points are tens of pixels apart on a large screen
+        let large = camera_state(-90.0, 1600, 900);
+        assert!(screen_coverage(&pc.points, &large, &antialias, &thresholds) < 0.8);
+        assert!(should_upsample(&pc.points, &large, &antialias, &thresholds));
+        let upsampled = upsample_view_adaptive(&pc, &large, &antialias, 1, &thresholds);
+        assert!(upsampled.number_of_points > pc.number_of_points);
+        assert!(pc.points.iter().all(|p| upsampled.points.contains(p)));
+
+        // and cover a handful of touching pixels on a tiny one
+        let tiny = camera_state(-90.0, 16, 9);
+        assert!(!should_upsample(&pc.points, &tiny, &antialias, &thresholds));
+        let kept = upsample_view_adaptive(&pc, &tiny, &antialias, 1, &thresholds);
+        assert_eq!(kept.number_of_points, pc.number_of_points);
+
+        // nothing is upsampled behind the camera
+        let away = camera_state(90.0, 1600, 900);
+        assert_eq!(
+            visible_points(pc.clone(), &away, &antialias).number_of_points,
+            0
+        );
+        let kept = upsample_view_adaptive(&pc, &away, &antialias, 2, &thresholds);
+        assert_eq!(kept.number_of_points, pc.number_of_points);
+    }
+}
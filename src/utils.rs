@@ -1,4 +1,5 @@
 use crate::{
+    codec::octree,
     formats::{
         bounds::Bounds, pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal,
         triangle_face::TriangleFace, PointCloud,
@@ -56,6 +57,9 @@ pub fn read_file_to_point_cloud(file: &PathBuf) -> Option<PointCloud<PointXyzRgb
             "ply" => read_ply(file),
             "pcd" => read_pcd_file(file).map(PointCloud::from).ok(),
             "bin" => read_velodyn_bin_file(file).map(PointCloud::from).ok(),
+            octree::EXTENSION => std::fs::read(file)
+                .ok()
+                .and_then(|data| octree::decode(&data).ok()),
             _ => None,
         };
         return point_cloud;
@@ -443,6 +447,32 @@ pub fn get_cosines(pos: CameraPosition) -> Vec<f32> {
     vec![left, bottom, back, right, top, front]
 }
 
+/// Get the cosines from the camera to each tile, the counterpart of [`get_cosines`] for views that cover tiles instead of the faces of a cube.
+///
+/// The cosine of a tile is the cosine of the angle between the look vector and the direction from the camera to the centre of the tile,
+/// negated so that tiles straight ahead have -1 and tiles behind the camera have 1, as faces met first have negative values.
+pub fn get_tile_cosines(pos: CameraPosition, tiles: &[Bounds]) -> Vec<f32> {
+    let (sin_pitch, cos_pitch) = pos.pitch.0.sin_cos();
+    let (sin_yaw, cos_yaw) = pos.yaw.0.sin_cos();
+    let look_vector = Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw).normalize();
+    tiles
+        .iter()
+        .map(|tile| {
+            let centre = Point3::new(
+                (tile.min_x + tile.max_x) / 2.0,
+                (tile.min_y + tile.max_y) / 2.0,
+                (tile.min_z + tile.max_z) / 2.0,
+            );
+            let direction = centre - pos.position;
+            if direction.magnitude2() == 0.0 {
+                // the camera is at the centre of the tile
+                return -1.0;
+            }
+            -direction.normalize().dot(look_vector)
+        })
+        .collect()
+}
+
 /// Predict the quality of the point cloud based on the geometry and attribute quality
 pub fn predict_quality(geo_qp: f32, attr_qp: f32) -> f32 {
     2.292_971_4 - 0.0020313 * geo_qp + 0.20795236 * attr_qp - 0.00464757 * geo_qp * geo_qp
@@ -965,6 +995,29 @@ mod tests {
         assert!((ema.predict().unwrap() - 3.004459).abs() < EPSILON);
     }
 
+    #[test]
+    fn test_tile_cosines() {
+        let tile = |z: f32| Bounds {
+            min_x: -0.5,
+            max_x: 0.5,
+            min_y: -0.5,
+            max_y: 0.5,
+            min_z: z - 0.5,
+            max_z: z + 0.5,
+        };
+        // looking down the negative z axis from z = 1.5
+        let pos = CameraPosition {
+            position: Point3::new(0.0, 0.0, 1.5),
+            yaw: cgmath::Deg(-90.0).into(),
+            pitch: cgmath::Deg(0.0).into(),
+            up: Vector3::unit_y(),
+        };
+        let cosines = get_tile_cosines(pos, &[tile(0.0), tile(3.0), tile(1.5)]);
+        assert!((cosines[0] + 1.0).abs() < 1e-4);
+        assert!((cosines[1] - 1.0).abs() < 1e-4);
+        assert_eq!(cosines[2], -1.0);
+    }
+
     // #[test]
     // fn test_gaema() {
     //     let mut gaema = GAEMA::new(0.1);
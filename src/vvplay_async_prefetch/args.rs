@@ -2,18 +2,24 @@ use clap::Parser;
 use std::ffi::OsString;
 use std::path::PathBuf;
 
+use crate::render::wgpu::splat::SplatArgs;
 use crate::vvplay_async_prefetch::enums::AbrType;
+use crate::vvplay_async_prefetch::enums::Clock;
 use crate::vvplay_async_prefetch::enums::DecoderType;
 use crate::vvplay_async_prefetch::enums::ThroughputPredictionType;
 use crate::vvplay_async_prefetch::enums::ViewportPredictionType;
 
-#[derive(Parser)]
+#[derive(Parser, Clone)]
 pub struct Args {
     /// src can be:
     ///
     /// 1. Directory with all the ply files
     /// 2. location of the mpd url (dash)
-    pub src: String,
+    #[clap(required_unless_present = "scene")]
+    pub src: Option<String>,
+    /// Plays the objects of a scene file together instead of src, each buffered and adapted on its own
+    #[clap(long, conflicts_with = "src")]
+    pub scene: Option<PathBuf>,
     #[clap(short, long, default_value_t = 30.0)]
     pub fps: f32,
     #[clap(short = 'x', long, default_value_t = 0.0)]
@@ -70,6 +76,10 @@ pub struct Args {
     /// Path to record camera trace from the player.
     #[clap(long)]
     pub record_camera_trace: Option<PathBuf>,
+    /// Path to write the session log to, a JSON line for every frame displayed, stall, quality chosen by the ABR,
+    /// buffer occupancy and throughput sample. `vv qoe` computes the QoE metrics from it.
+    #[clap(long)]
+    pub session_log: Option<PathBuf>,
     /// Enable fetcher optimizations
     ///
     /// 1. Not fetching when file has been previously downloaded.
@@ -77,4 +87,33 @@ pub struct Args {
     pub enable_fetcher_optimizations: bool,
     #[clap(long, default_value = "rgb(255,255,255)")]
     pub bg_color: OsString,
+    /// Target latency behind the live edge in seconds. Only used when src is a dynamic (live) MPD.
+    #[clap(long, default_value_t = 3.0)]
+    pub live_latency: f32,
+    /// Number of times a segment download is retried (with exponential backoff) before giving up
+    #[clap(long, default_value_t = 3)]
+    pub retries: u32,
+    /// Keep downloaded segments in a cache of at most this many MB, evicting the least recently used ones
+    #[clap(long)]
+    pub cache_size: Option<u64>,
+    /// Abandon a segment download after this many seconds and fetch the lowest quality instead
+    #[clap(long)]
+    pub abandon_after: Option<f32>,
+    #[clap(flatten)]
+    pub splat: SplatArgs,
+    /// Play without any window or gpu, e.g. on a server. The camera follows --camera-trace
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    pub headless: bool,
+    /// Clock the headless player shows the frames by
+    #[clap(long, value_enum, default_value_t = Clock::Wall)]
+    pub clock: Clock,
+    /// Number of frames the headless player plays, the length of the video by default
+    #[clap(long)]
+    pub frames: Option<usize>,
+    /// Directory the headless player renders snapshots into with the cpu renderer
+    #[clap(long)]
+    pub snapshot_dir: Option<PathBuf>,
+    /// Render a snapshot of every n-th frame
+    #[clap(long, default_value_t = 30)]
+    pub snapshot_every: usize,
 }
@@ -1,11 +1,16 @@
 use crate::dash::buffer::{Buffer, FrameStatus};
+use crate::dash::fetcher::CancellationToken;
+use crate::dash::live::LivePlayhead;
 use crate::dash::ViewportPrediction;
 use crate::formats::pointxyzrgba::PointXyzRgba;
 use crate::formats::PointCloud;
 use crate::render::wgpu::{camera::CameraPosition, reader::FrameRequest};
 use crate::vvplay_async_prefetch::camera_trace::CameraTrace;
 use crate::vvplay_async_prefetch::fetch_request::FetchRequest;
+use crate::vvplay_async_prefetch::session_log::{SessionEvent, SessionLog};
 use crate::BufMsg;
+use log::warn;
+use std::collections::HashMap;
 
 /**
  * This file contains Buffer Manager struct and related implementation
@@ -32,11 +37,19 @@ pub struct BufferManager {
     /// frame_to_answer is the frame we are pending to answer to the renderer.
     /// Note(25Mar23): it is an option because we are only dealing with 1 object_id for now.
     frame_to_answer: Option<FrameRequest>,
+    /// when the renderer started waiting for `frame_to_answer`, on the clock of the session log
+    waiting_since: Option<f64>,
     /// buffer stores all requests, it might be in fetching or decoding or ready state.
     buffer: Buffer,
     total_frames: usize,
     segment_size: u64,
     shutdown_recv: tokio::sync::watch::Receiver<bool>,
+    /// Set when playing a dynamic MPD. Frames are then requested relative to the live edge and never wrap around.
+    live: Option<LivePlayhead>,
+    /// cancellation tokens of the fetch requests that have not completed yet, by (object_id, frame_offset)
+    in_flight: HashMap<(u8, u64), CancellationToken>,
+    /// logs the buffer occupancy and the stalls of the session
+    session_log: Option<SessionLog>,
 }
 
 impl BufferManager {
@@ -54,21 +67,119 @@ impl BufferManager {
             buf_in_sx,
             buf_out_sx,
             frame_to_answer: None,
+            waiting_since: None,
             total_frames,
             segment_size: segment_size.0,
             shutdown_recv,
             // buffer size is given in seconds. however our frames are only segment_size.0 / segment_size.1 seconds long.
             // buffer: Buffer::new(buffer_size as usize),
             buffer: Buffer::new((buffer_size * segment_size.1 / segment_size.0) as usize), // from
-                                                                                           // ply_play
+            // ply_play
+            live: None,
+            in_flight: HashMap::new(),
+            session_log: None,
         }
     }
 
+    pub fn set_session_log(&mut self, session_log: SessionLog) {
+        self.session_log = Some(session_log);
+    }
+
+    /// Sends a fetch request to the fetcher, keeping its cancellation token around.
+    fn send_fetch_request(&mut self, req: FrameRequest) {
+        let fetch_req = FetchRequest::new(req, self.buffer.len());
+        if let Some(session_log) = &self.session_log {
+            session_log.log(SessionEvent::Buffer {
+                object_id: req.object_id,
+                occupancy: self.buffer.len(),
+            });
+        }
+        self.in_flight
+            .insert((req.object_id, req.frame_offset), fetch_req.cancel.clone());
+        _ = self.buf_in_sx.send(fetch_req);
+    }
+
+    /// Cancels all ongoing fetches, e.g. when the buffer is cleared after a seek.
+    fn cancel_in_flight(&mut self) {
+        for (_, cancel) in self.in_flight.drain() {
+            cancel.cancel();
+        }
+    }
+
+    /// The renderer waits for `req`, which is not ready yet.
+    fn wait_for(&mut self, req: FrameRequest) {
+        self.frame_to_answer = Some(req);
+        if let Some(session_log) = &self.session_log {
+            self.waiting_since
+                .get_or_insert_with(|| session_log.elapsed());
+        }
+    }
+
+    /// Sends a frame to the renderer, logging how long it stalled if it had to wait for it.
+    fn answer(&mut self, req: FrameRequest, pc: PointCloud<PointXyzRgba>) {
+        if let (Some(since), Some(session_log)) = (self.waiting_since.take(), &self.session_log) {
+            session_log.log(SessionEvent::Stall {
+                object_id: req.object_id,
+                frame: req.frame_offset,
+                duration: session_log.elapsed() - since,
+            });
+        }
+        _ = self.buf_out_sx.send((req, pc));
+        self.frame_to_answer = None;
+    }
+
+    /// Drops the segment starting at `req` from the buffer after its fetch failed.
+    ///
+    /// If the renderer is waiting for it, playback skips to the next segment, which is fetched unless it already is.
+    /// Otherwise the segment is requested again once the renderer reaches it.
+    async fn skip_segment(&mut self, req: FrameRequest) {
+        self.buffer.remove(req);
+        if self.frame_to_answer != Some(req) {
+            return;
+        }
+        let next = self.get_next_frame_req(&req);
+        if self.buffer.front().map(|f| f.req) != Some(next) {
+            self.buffer.clear();
+            self.cancel_in_flight();
+            self.send_fetch_request(next);
+            self.buffer.add(next);
+        }
+        let mut front = self.buffer.pop_front().unwrap();
+        match front.state {
+            FrameStatus::Fetching | FrameStatus::Decoding => {
+                self.wait_for(next);
+                self.buffer.push_front(front);
+            }
+            FrameStatus::Ready(remaining_frames, mut rx) => {
+                let pc = rx
+                    .recv()
+                    .await
+                    .expect("we should never have an empty channel");
+                self.answer(next, pc);
+                if remaining_frames > 1 {
+                    front.req.frame_offset += 1;
+                    front.state = FrameStatus::Ready(remaining_frames - 1, rx);
+                    self.buffer.push_front(front);
+                }
+            }
+        }
+    }
+
+    /// Play a live stream, following the live edge of the playhead.
+    pub fn set_live(&mut self, playhead: LivePlayhead) {
+        self.live = Some(playhead);
+    }
+
     /// Get next frame request assuming playback is continuous
     pub fn get_next_frame_req(&self, req: &FrameRequest) -> FrameRequest {
+        let frame_offset = if self.live.is_some() {
+            req.frame_offset + self.segment_size
+        } else {
+            (req.frame_offset + self.segment_size) % self.total_frames as u64
+        };
         FrameRequest {
             object_id: req.object_id,
-            frame_offset: (req.frame_offset + self.segment_size) % self.total_frames as u64,
+            frame_offset,
             camera_pos: req.camera_pos,
         }
     }
@@ -82,9 +193,7 @@ impl BufferManager {
         };
         // The frame prefetched is the next frame of the frame at the back of the buffer
         let req = self.get_next_frame_req(&last_req);
-        _ = self
-            .buf_in_sx
-            .send(FetchRequest::new(req, self.buffer.len()));
+        self.send_fetch_request(req);
         //println!("In prefetch_frame, the request is {:?}", req);
 
         self.buffer.add(req);
@@ -98,9 +207,7 @@ impl BufferManager {
     ) {
         assert!(camera_pos.is_some());
         let req = self.get_next_frame_req(&last_req);
-        _ = self
-            .buf_in_sx
-            .send(FetchRequest::new(req, self.buffer.len()));
+        self.send_fetch_request(req);
         //println!("In prefetch_frame_with_request, the request is {:?}", req);
 
         self.buffer.add(req);
@@ -159,10 +266,20 @@ impl BufferManager {
                                 renderer_req.camera_pos = viewport_predictor.predict();
                             }
 
+                            // For live streams, the renderer's frame index is relative to where we joined the stream
+                            if let Some(live) = self.live.as_mut() {
+                                match live.map(renderer_req.frame_offset) {
+                                    Some(frame_offset) => renderer_req.frame_offset = frame_offset,
+                                    None => warn!("live edge is not known yet, requesting frame {}", renderer_req.frame_offset),
+                                }
+                            }
+
                             // First, attempt to fulfill the request from the renderer.
                             // If the requested frame is not inside the buffer, we will clear the buffer.
                             if !self.buffer.is_empty() && !self.buffer.is_frame_in_buffer(renderer_req) {
                                 self.buffer.clear();
+                                // none of the frames being fetched are needed anymore
+                                self.cancel_in_flight();
                             } else if !self.buffer.is_empty() && self.buffer.is_frame_in_buffer(renderer_req)  {
                                 // If the frame requested is inside the buffer, we will pop all previous frame such that the requested frame is at front.
                                 let num_frames_to_remove = renderer_req.frame_offset - self.buffer.front().unwrap().req.frame_offset;
@@ -177,7 +294,7 @@ impl BufferManager {
                                 match front.state {
                                     FrameStatus::Fetching | FrameStatus::Decoding => {
                                         // We update frame_to_answer to indicate that we are waiting to send back this data to renderer.
-                                        self.frame_to_answer = Some(renderer_req);
+                                        self.wait_for(renderer_req);
                                         self.buffer.push_front(front);
                                     }
                                     FrameStatus::Ready(remaining_frames, mut rx) => {
@@ -192,8 +309,7 @@ impl BufferManager {
                                                     renderer_req.camera_pos
                                                 };
                                                 // send to point cloud to renderer
-                                                _ = self.buf_out_sx.send((renderer_req, pc));
-                                                self.frame_to_answer = None;
+                                                self.answer(renderer_req, pc);
                                                 front.req.frame_offset += 1;
                                                 front.state = FrameStatus::Ready(remaining_frames - 1, rx);
                                                 if remaining_frames > 1 {
@@ -216,10 +332,10 @@ impl BufferManager {
                                 }
                             } else {
                                 // If the requested frame is not inside the buffer, we send a request to the fetcher to fetch the data
-                                _ = self.buf_in_sx.send(FetchRequest::new(renderer_req, self.buffer.len()));
+                                self.send_fetch_request(renderer_req);
 
                                 // we update frame_to_answer to indicate that we are waiting to send back this data to renderer.
-                                self.frame_to_answer = Some(renderer_req);
+                                self.wait_for(renderer_req);
 
                                 // we also update next_fetch_req so that when the fetcher returns the data, we can immediately send the next request to the fetcher
                                 self.buffer.add(renderer_req);
@@ -229,6 +345,11 @@ impl BufferManager {
                             // upon receiving fetch result, immediately schedule the next fetch request
                             //println!{"---------------------------"};
                             //println!("the current buffer message is fetch done for {:?}", req);
+                            self.in_flight.remove(&(req.object_id, req.frame_offset));
+                            // the frame might have been dropped from the buffer while it was being fetched
+                            if self.buffer.get(req).is_none() {
+                                continue;
+                            }
                             self.buffer.update_state(req, FrameStatus::Decoding);
 
                             if !self.buffer.is_full() {
@@ -238,6 +359,15 @@ impl BufferManager {
                                 is_desired_buffer_level_reached = true;
                             }
                         }
+                        BufMsg::FetchFailed(req) => {
+                            self.in_flight.remove(&(req.object_id, req.frame_offset));
+                            // the frame might have been dropped from the buffer while it was being fetched
+                            if self.buffer.get(req).is_none() {
+                                continue;
+                            }
+                            warn!("Gave up fetching frame {} of object {}, skipping its segment", req.frame_offset, req.object_id);
+                            self.skip_segment(req).await;
+                        }
                         BufMsg::PointCloud((mut metadata, mut rx)) => {
                             //println!{"---------------------------"};
                             //println!("[buffer mgr] received a point cloud result {:?}", &metadata);
@@ -252,8 +382,7 @@ impl BufferManager {
                             {
                                 let pc = rx.recv().await.unwrap();
                                 // Send results to the renderer
-                                _ = self.buf_out_sx.send((self.frame_to_answer.unwrap(), pc));
-                                self.frame_to_answer = None;
+                                self.answer(self.frame_to_answer.unwrap(), pc);
                                 metadata.frame_offset += 1;
                                 remaining -= 1;
                             }
@@ -271,3 +400,69 @@ impl BufferManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::LastValue;
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_failed_skips_segment() {
+        let (to_buf_sx, to_buf_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (buf_in_sx, mut buf_in_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (buf_out_sx, buf_out_rx) = std::sync::mpsc::channel();
+        let (shutdown_send, shutdown_recv) = tokio::sync::watch::channel(false);
+        // segments of 3 frames, and room for one segment in the buffer
+        let mut buffer_manager = BufferManager::new(
+            to_buf_rx,
+            buf_in_sx,
+            buf_out_sx,
+            3,
+            30,
+            (3, 1),
+            shutdown_recv,
+        );
+        let manager = tokio::spawn(async move {
+            buffer_manager
+                .run(
+                    Box::new(LastValue::new()),
+                    CameraPosition::default(),
+                    None,
+                    None,
+                )
+                .await
+        });
+
+        let frame = |frame_offset| FrameRequest {
+            object_id: 0,
+            frame_offset,
+            camera_pos: Some(CameraPosition::default()),
+        };
+        to_buf_sx.send(BufMsg::FrameRequest(frame(0))).unwrap();
+        let req = buf_in_rx.recv().await.unwrap();
+        assert_eq!(req.frame_offset, 0);
+
+        // the fetcher gives up on the first segment, so the player moves on to the next one
+        to_buf_sx.send(BufMsg::FetchFailed(req.into())).unwrap();
+        let req = buf_in_rx.recv().await.unwrap();
+        assert_eq!(req.frame_offset, 3);
+
+        let (pc_sx, pc_rx) = tokio::sync::mpsc::unbounded_channel();
+        for _ in 0..3 {
+            pc_sx.send(PointCloud::new(0, vec![])).unwrap();
+        }
+        to_buf_sx
+            .send(BufMsg::PointCloud((req.into(), pc_rx)))
+            .unwrap();
+        let (answered, _) =
+            tokio::task::spawn_blocking(move || buf_out_rx.recv_timeout(Duration::from_secs(5)))
+                .await
+                .unwrap()
+                .expect("playback stalled on the failed segment");
+        assert_eq!(answered, frame(3));
+
+        shutdown_send.send(true).unwrap();
+        manager.await.unwrap();
+    }
+}
@@ -1,3 +1,12 @@
+use crate::abr::quetra::{Quetra, QuetraMultiview};
+use crate::abr::{DecodeTimeAware, RateAdapter, MCKP};
+use crate::codec::decoder::{DracoDecoder, NoopDecoder, OctreeDecoder};
+use crate::codec::Decoder;
+use crate::dash::ThroughputPrediction;
+use crate::utils::{ExponentialMovingAverage, LastValue, SimpleRunningAverage, GAEMA, LPEMA};
+use std::path::PathBuf;
+use std::sync::Arc;
+
 /**
  * This file contains all the enums that is used by vvplay_async_prefetch.rs
  */
@@ -8,6 +17,24 @@ pub enum DecoderType {
     Noop,
     Draco,
     Tmc2rs,
+    Octree,
+}
+
+impl DecoderType {
+    /// Creates the decoder. `decoder_path` is the draco_decoder binary, only used by Draco.
+    pub fn build(self, decoder_path: Option<PathBuf>) -> Arc<dyn Decoder> {
+        match self {
+            DecoderType::Noop => Arc::new(NoopDecoder),
+            DecoderType::Draco => Arc::new(DracoDecoder::new(
+                decoder_path.expect("must provide decoder path for Draco"),
+            )),
+            DecoderType::Octree => Arc::new(OctreeDecoder),
+            #[cfg(feature = "with-tmc2-rs-decoder")]
+            DecoderType::Tmc2rs => Arc::new(crate::codec::decoder::Tmc2rsDecoder),
+            #[cfg(not(feature = "with-tmc2-rs-decoder"))]
+            DecoderType::Tmc2rs => Arc::new(NoopDecoder),
+        }
+    }
 }
 
 #[derive(clap::ValueEnum, Clone, Copy)]
@@ -17,6 +44,34 @@ pub enum AbrType {
     Mckp,
 }
 
+impl AbrType {
+    /// Creates the rate adapter, wrapped in [`DecodeTimeAware`]. Used by both the player and `vv dash`,
+    /// so that they adapt identically.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer_capacity` - buffer capacity in frames
+    /// * `fps` - frames per second of the content
+    /// * `views` - number of views of the content. Only used by the multiview algorithms.
+    /// * `qualities` - predicted quality of each representation. Only used by the multiview algorithms.
+    pub fn build(
+        self,
+        buffer_capacity: u64,
+        fps: f32,
+        views: usize,
+        qualities: Vec<f32>,
+    ) -> DecodeTimeAware {
+        let inner: Box<dyn RateAdapter> = match self {
+            AbrType::Quetra => Box::new(Quetra::new(buffer_capacity, fps)),
+            AbrType::Mckp => Box::new(MCKP::new(views, qualities)),
+            AbrType::QuetraMultiview => {
+                Box::new(QuetraMultiview::new(buffer_capacity, fps, views, qualities))
+            }
+        };
+        DecodeTimeAware::new(inner, fps)
+    }
+}
+
 #[derive(clap::ValueEnum, Clone, Copy)]
 pub enum ThroughputPredictionType {
     /// Last throughput
@@ -31,8 +86,33 @@ pub enum ThroughputPredictionType {
     Lpema,
 }
 
+impl ThroughputPredictionType {
+    /// Creates the throughput predictor. `alpha` is only used by EMA, GAEMA and LPEMA.
+    pub fn build(self, alpha: f64) -> Box<dyn ThroughputPrediction> {
+        match self {
+            ThroughputPredictionType::Last => Box::new(LastValue::new()),
+            ThroughputPredictionType::Avg => Box::new(SimpleRunningAverage::<f64, 3>::new()),
+            ThroughputPredictionType::Ema => Box::new(ExponentialMovingAverage::new(alpha)),
+            ThroughputPredictionType::Gaema => Box::new(GAEMA::new(alpha)),
+            ThroughputPredictionType::Lpema => Box::new(LPEMA::new(alpha)),
+        }
+    }
+}
+
 #[derive(clap::ValueEnum, Clone, Copy)]
 pub enum ViewportPredictionType {
     /// Last viewport
     Last,
 }
+
+/// Clock of the headless player
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Clock {
+    /// Frames are shown at the target fps of the wall clock. Playback stalls until a frame that is not ready
+    /// arrives, then skips the frames whose time has passed
+    Wall,
+    /// Every frame is shown in turn as soon as it is ready, one frame interval of the clock each.
+    /// Waiting for a frame that is not ready moves the clock by the time waited.
+    /// The session log is stamped with this clock
+    Virtual,
+}
@@ -0,0 +1,306 @@
+use crate::abr::{DecodeTimeAware, RateAdapter};
+use crate::codec::{decode_segment, Decoder};
+use crate::dash::fetcher::{FetchError, FetchResult, Fetcher};
+use crate::dash::live::LiveEdge;
+use crate::dash::ThroughputPrediction;
+use crate::vvplay_async_prefetch::fetch_request::FetchRequest;
+use crate::vvplay_async_prefetch::network_trace::NetworkTrace;
+use crate::vvplay_async_prefetch::session_log::{SessionEvent, SessionLog};
+use crate::{BufMsg, PCMetadata};
+use log::{debug, info, trace, warn};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::watch;
+
+/**
+ * This file contains the fetch and decode loops of vvplay_async, between the buffer manager and the network
+ */
+
+/// Quality of a segment and the decode time of one of its frames, reported by the decoder to the ABR
+pub type DecodeTimeReport = (Vec<usize>, Duration);
+
+/// Downloads the segments the buffer manager asks for, choosing their quality with the ABR.
+///
+/// Downloaded segments are passed on to the decoder, see [`decode_loop`], and the buffer manager is told that the
+/// fetch is done, or that it failed once the fetcher has exhausted its retries.
+pub struct FetchLoop {
+    fetcher: Fetcher,
+    abr: DecodeTimeAware,
+    throughput_predictor: Box<dyn ThroughputPrediction>,
+    multiview: bool,
+    /// abandon a download that takes longer than this and fall back to the lowest quality
+    abandon_after: Option<Duration>,
+    /// simulated network throughput in Kbps, one sample per request
+    network_trace: Option<NetworkTrace>,
+    session_log: Option<SessionLog>,
+    /// kept up to date while playing a dynamic MPD
+    live_edge: Option<LiveEdge>,
+}
+
+impl FetchLoop {
+    pub fn new(
+        fetcher: Fetcher,
+        abr: DecodeTimeAware,
+        throughput_predictor: Box<dyn ThroughputPrediction>,
+    ) -> Self {
+        FetchLoop {
+            fetcher,
+            abr,
+            throughput_predictor,
+            multiview: false,
+            abandon_after: None,
+            network_trace: None,
+            session_log: None,
+            live_edge: None,
+        }
+    }
+
+    pub fn with_multiview(mut self, multiview: bool) -> Self {
+        self.multiview = multiview;
+        self
+    }
+
+    pub fn with_abandon_after(mut self, abandon_after: Option<Duration>) -> Self {
+        self.abandon_after = abandon_after;
+        self
+    }
+
+    pub fn with_network_trace(mut self, network_trace: Option<NetworkTrace>) -> Self {
+        self.network_trace = network_trace;
+        self
+    }
+
+    pub fn with_session_log(mut self, session_log: Option<SessionLog>) -> Self {
+        self.session_log = session_log;
+        self
+    }
+
+    pub fn with_live_edge(mut self, live_edge: Option<LiveEdge>) -> Self {
+        self.live_edge = live_edge;
+        self
+    }
+
+    /// Runs until shutdown or until the buffer manager hangs up.
+    pub async fn run(
+        mut self,
+        mut buf_in_rx: UnboundedReceiver<FetchRequest>,
+        to_buf_sx: UnboundedSender<BufMsg>,
+        in_dec_sx: UnboundedSender<(FetchRequest, FetchResult)>,
+        mut decode_time_rx: UnboundedReceiver<DecodeTimeReport>,
+        mut shutdown_recv: watch::Receiver<bool>,
+    ) {
+        let mut refresh_interval = tokio::time::interval(Duration::from_millis(100));
+        loop {
+            tokio::select! {
+                _ = shutdown_recv.changed() => {
+                    trace!("[fetcher] shutdown signal received");
+                    break;
+                },
+                _ = refresh_interval.tick(), if self.live_edge.is_some() => {
+                    if let Err(e) = self.fetcher.refresh_mpd_if_needed().await {
+                        warn!("Failed to refresh MPD: {:#}", e);
+                    }
+                    self.live_edge.as_ref().unwrap().set(self.fetcher.live_edge());
+                },
+                Some((quality, decode_time)) = decode_time_rx.recv() => {
+                    self.abr.report_decode_time(&quality, decode_time);
+                },
+                Some(req) = buf_in_rx.recv() => self.fetch(req, &to_buf_sx, &in_dec_sx).await,
+                else => break,
+            }
+        }
+    }
+
+    async fn fetch(
+        &mut self,
+        req: FetchRequest,
+        to_buf_sx: &UnboundedSender<BufMsg>,
+        in_dec_sx: &UnboundedSender<(FetchRequest, FetchResult)>,
+    ) {
+        let camera_pos = req.camera_pos.expect("camera position is always provided");
+
+        // We start with a guess of 1Mbps network throughput.
+        let network_throughput = match &self.network_trace {
+            Some(trace) => trace.next() * 1024.0,
+            None => self.throughput_predictor.predict().unwrap_or(1_000_000.0),
+        };
+        let simulated_throughput = self.network_trace.as_ref().map(|_| network_throughput);
+
+        let available_bitrates = if self.multiview {
+            self.fetcher
+                .all_available_bitrates(req.object_id, req.frame_offset)
+        } else {
+            self.fetcher
+                .available_bitrates(req.object_id, req.frame_offset, None)
+                .map(|bitrates| vec![bitrates])
+        };
+        let available_bitrates = match available_bitrates {
+            Ok(available_bitrates) => available_bitrates,
+            Err(e) => {
+                warn!("Cannot fetch frame {}: {:#}", req.frame_offset, e);
+                _ = to_buf_sx.send(BufMsg::FetchFailed(req.into()));
+                return;
+            }
+        };
+
+        let cosines = self
+            .fetcher
+            .view_cosines(req.object_id, req.frame_offset, camera_pos);
+
+        let mut quality = self.abr.select_quality(
+            req.buffer_occupancy as u64,
+            network_throughput,
+            &available_bitrates,
+            &cosines,
+        );
+        info!(
+            "buffer_occupancy: {}, network: {}, cosines: {:?}",
+            req.buffer_occupancy, network_throughput, &cosines
+        );
+
+        // Retries are handled by the fetcher. Here we only fall back to the lowest quality
+        // when the download was abandoned for taking too long.
+        let mut deadline = self.abandon_after;
+        loop {
+            trace!("[fetcher] trying request {:?}", &req);
+
+            let p = self
+                .fetcher
+                .download_cancellable(
+                    req.object_id,
+                    req.frame_offset,
+                    &quality,
+                    self.multiview,
+                    simulated_throughput,
+                    &req.cancel,
+                    deadline,
+                )
+                .await;
+
+            match p {
+                Ok(res) => {
+                    // update throughput prediction
+                    if res.throughput > 0.0 {
+                        self.throughput_predictor.add(res.throughput);
+                    }
+                    if let Some(session_log) = &self.session_log {
+                        if res.throughput > 0.0 {
+                            session_log.log(SessionEvent::Throughput {
+                                object_id: req.object_id,
+                                throughput: res.throughput,
+                            });
+                        }
+                        let bitrate: u64 = res
+                            .quality
+                            .iter()
+                            .zip(&available_bitrates)
+                            .filter_map(|(q, bitrates)| bitrates.get(*q))
+                            .sum();
+                        session_log.log(SessionEvent::Quality {
+                            object_id: req.object_id,
+                            frame: req.frame_offset,
+                            quality: res.quality.clone(),
+                            bitrate,
+                        });
+                    }
+                    // send the response to the decoder
+                    _ = in_dec_sx.send((req.clone(), res.clone()));
+                    // let buffer know that we are done fetching
+                    _ = to_buf_sx.send(BufMsg::FetchDone((req.into(), res)));
+                    return;
+                }
+                Err(e) => match e.downcast_ref::<FetchError>() {
+                    Some(FetchError::Cancelled) => {
+                        debug!("[fetcher] request for frame {} cancelled", req.frame_offset);
+                        return;
+                    }
+                    Some(FetchError::Abandoned {
+                        received_bits,
+                        elapsed,
+                    }) => {
+                        warn!("{}, falling back to the lowest quality", e);
+                        let throughput = *received_bits as f64 / elapsed.as_secs_f64();
+                        if throughput > 0.0 {
+                            self.throughput_predictor.add(throughput);
+                            if let Some(session_log) = &self.session_log {
+                                session_log.log(SessionEvent::Throughput {
+                                    object_id: req.object_id,
+                                    throughput,
+                                });
+                            }
+                        }
+                        quality = vec![0; quality.len()];
+                        deadline = None;
+                    }
+                    // the fetcher has already retried with backoff, trying again
+                    // would only spin on a permanent error such as a 404
+                    None => {
+                        warn!("Error downloading frame {}: {:#}", req.frame_offset, e);
+                        _ = to_buf_sx.send(BufMsg::FetchFailed(req.into()));
+                        return;
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Decodes the segments passed on by the fetch loop, views concurrently, and hands the frames to the buffer manager.
+/// The decode time of every frame is reported back to the ABR. Segments of a representation with another frame
+/// rate than the presentation are converted to the presentation frame rate.
+pub async fn decode_loop(
+    decoder: Arc<dyn Decoder>,
+    mut in_dec_rx: UnboundedReceiver<(FetchRequest, FetchResult)>,
+    to_buf_sx: UnboundedSender<BufMsg>,
+    decode_time_sx: UnboundedSender<DecodeTimeReport>,
+    mut shutdown_recv: watch::Receiver<bool>,
+) {
+    loop {
+        tokio::select! {
+            _ = shutdown_recv.changed() => {
+                trace!("[decoder] shutdown signal received");
+                break;
+            },
+            Some((req, res)) = in_dec_rx.recv() => {
+                debug!("got fetch result {:?}", req);
+                // views are decoded concurrently, straight from the downloaded bytes
+                let mut frames = decode_segment(decoder.clone(), res.bitstreams());
+                // Everytime a segment is fetched, an unbounded channel is created
+                let (output_sx, output_rx) = tokio::sync::mpsc::unbounded_channel();
+                // Send BufMsg to inform the buffer that the PointCloud is ready
+                _ = to_buf_sx.send(BufMsg::PointCloud((
+                    PCMetadata {
+                        frame_offset: req.frame_offset,
+                        object_id: req.object_id,
+                    },
+                    output_rx,
+                )));
+                // frames of the representation and presentation frames handed on so far
+                let (mut decoded, mut presented) = (0, 0);
+                while let Some(frame) = frames.recv().await {
+                    match frame {
+                        Ok(frame) => {
+                            _ = decode_time_sx.send((res.quality.clone(), frame.decode_time));
+                            match res.frame_rate {
+                                None => {
+                                    _ = output_sx.send(frame.pc);
+                                }
+                                // repeated or dropped to fill the presentation frames it covers
+                                Some(conversion) => {
+                                    while conversion.representation_frame(presented) == decoded {
+                                        _ = output_sx.send(frame.pc.clone());
+                                        presented += 1;
+                                    }
+                                }
+                            }
+                            decoded += 1;
+                        }
+                        Err(e) => warn!("Frame {}: {:#}", req.frame_offset, e),
+                    }
+                }
+            }
+            else => break,
+        }
+    }
+}
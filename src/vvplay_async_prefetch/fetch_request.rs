@@ -1,3 +1,4 @@
+use crate::dash::fetcher::CancellationToken;
 use crate::render::wgpu::camera::CameraPosition;
 use crate::render::wgpu::reader::FrameRequest;
 use crate::PCMetadata;
@@ -6,7 +7,7 @@ use crate::PCMetadata;
  * This file contains all FetchRequest struct and related implementation
  */
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct FetchRequest {
     pub object_id: u8,
     // pub quality: u8,
@@ -17,6 +18,8 @@ pub struct FetchRequest {
     /// The camera position when the frame was requested.
     pub camera_pos: Option<CameraPosition>,
     pub buffer_occupancy: usize,
+    /// Cancelled by the buffer manager once the frame is no longer needed.
+    pub cancel: CancellationToken,
 }
 
 impl FetchRequest {
@@ -26,6 +29,7 @@ impl FetchRequest {
             frame_offset: req.frame_offset,
             camera_pos: req.camera_pos,
             buffer_occupancy,
+            cancel: CancellationToken::new(),
         }
     }
 }
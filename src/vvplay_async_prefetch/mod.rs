@@ -2,5 +2,7 @@ pub mod args;
 pub mod buffer_manager;
 pub mod camera_trace;
 pub mod enums;
+pub mod fetch_loop;
 pub mod fetch_request;
 pub mod network_trace;
+pub mod session_log;
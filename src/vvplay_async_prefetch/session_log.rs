@@ -0,0 +1,409 @@
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/**
+ * This file contains the session log of vvplay_async and the QoE metrics computed from it
+ */
+
+/// What happened during playback, one JSON object per line of the session log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SessionEvent {
+    /// A frame was handed to the renderer to be displayed
+    Frame { object_id: u8, frame: u64 },
+    /// The requested frame was not ready, playback stood still for `duration` seconds until it was
+    Stall {
+        object_id: u8,
+        frame: u64,
+        duration: f64,
+    },
+    /// Representation of every view of a downloaded segment and their total bitrate in bits per second
+    Quality {
+        object_id: u8,
+        frame: u64,
+        quality: Vec<usize>,
+        bitrate: u64,
+    },
+    /// Number of segments in the buffer when the next one is requested
+    Buffer { object_id: u8, occupancy: usize },
+    /// Throughput of a download in bits per second
+    Throughput { object_id: u8, throughput: f64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    /// seconds since the start of the session
+    pub t: f64,
+    #[serde(flatten)]
+    pub event: SessionEvent,
+}
+
+/// Time the records of a session log are stamped with
+#[derive(Clone)]
+enum SessionClock {
+    /// the wall clock, since the start of the session
+    Wall(Instant),
+    /// moved by [`SessionLog::advance`], e.g. one frame interval per frame shown,
+    /// and by the wall clock while [`SessionLog::run_while`] waits, e.g. for a frame that is not ready
+    Virtual(Arc<Mutex<VirtualClock>>),
+}
+
+#[derive(Default)]
+struct VirtualClock {
+    now: Duration,
+    /// set while the clock follows the wall clock
+    running_since: Option<Instant>,
+}
+
+impl VirtualClock {
+    fn now(&self) -> Duration {
+        self.now
+            + self
+                .running_since
+                .map_or(Duration::ZERO, |since| since.elapsed())
+    }
+}
+
+/// Writes the session log as JSON lines. Clones share the file and the clock of the session,
+/// so the fetcher, the buffer manager and the reader of every object can log to the same timeline.
+#[derive(Clone)]
+pub struct SessionLog {
+    clock: SessionClock,
+    // lines are flushed as they are written, the player might exit without dropping it
+    writer: Arc<Mutex<LineWriter<File>>>,
+}
+
+impl SessionLog {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create session log {}", path.display()))?;
+        Ok(Self {
+            clock: SessionClock::Wall(Instant::now()),
+            writer: Arc::new(Mutex::new(LineWriter::new(file))),
+        })
+    }
+
+    /// Stamps the records with a virtual clock that starts at 0 and only moves with `advance` and `run_while`,
+    /// so that the timeline does not depend on how fast the machine renders. Clones made before do not share it.
+    pub fn with_virtual_clock(mut self) -> Self {
+        self.clock = SessionClock::Virtual(Arc::default());
+        self
+    }
+
+    /// Moves the virtual clock forward, the wall clock moves on its own
+    pub fn advance(&self, dt: Duration) {
+        if let SessionClock::Virtual(clock) = &self.clock {
+            clock.lock().unwrap().now += dt;
+        }
+    }
+
+    /// Runs `f` with the virtual clock following the wall clock, so that the time spent waiting in `f`,
+    /// e.g. for a frame to be downloaded and decoded, shows up in the session as a stall.
+    pub fn run_while<T>(&self, f: impl FnOnce() -> T) -> T {
+        let SessionClock::Virtual(clock) = &self.clock else {
+            return f();
+        };
+        clock.lock().unwrap().running_since = Some(Instant::now());
+        let result = f();
+        let mut clock = clock.lock().unwrap();
+        clock.now = clock.now();
+        clock.running_since = None;
+        result
+    }
+
+    /// Seconds since the start of the session, on the clock of the session
+    pub fn elapsed(&self) -> f64 {
+        match &self.clock {
+            SessionClock::Wall(start) => start.elapsed().as_secs_f64(),
+            SessionClock::Virtual(clock) => clock.lock().unwrap().now().as_secs_f64(),
+        }
+    }
+
+    pub fn log(&self, event: SessionEvent) {
+        let record = SessionRecord {
+            t: self.elapsed(),
+            event,
+        };
+        let mut writer = self.writer.lock().unwrap();
+        let written = serde_json::to_writer(&mut *writer, &record)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| writeln!(writer).map_err(anyhow::Error::from));
+        if let Err(e) = written {
+            warn!("Failed to write the session log: {:#}", e);
+        }
+    }
+
+    /// Reads back the records of a session log
+    pub fn read(path: &Path) -> Result<Vec<SessionRecord>> {
+        let file = File::open(path)
+            .with_context(|| format!("failed to open session log {}", path.display()))?;
+        let mut records = vec![];
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            records.push(
+                serde_json::from_str(&line)
+                    .with_context(|| format!("{}:{}: invalid record", path.display(), i + 1))?,
+            );
+        }
+        Ok(records)
+    }
+}
+
+/// QoE metrics of a session. Times are in seconds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Qoe {
+    /// time until the first frame was displayed, `None` if no frame was
+    pub startup_delay: Option<f64>,
+    /// from the first to the last frame displayed, stalls included
+    pub duration: f64,
+    pub frames: usize,
+    /// stalls after the first frame
+    pub stalls: usize,
+    pub rebuffer_time: f64,
+    /// rebuffer time over duration
+    pub rebuffer_ratio: f64,
+    /// representation index averaged over the views of every downloaded segment
+    pub average_quality: f64,
+    /// bits per second averaged over the downloaded segments
+    pub average_bitrate: f64,
+    /// changes of the representation of any view between consecutive segments of an object
+    pub switches: usize,
+    /// switches per minute of duration
+    pub switch_frequency: f64,
+}
+
+impl Qoe {
+    pub fn from_records(records: &[SessionRecord]) -> Self {
+        let mut qoe = Qoe::default();
+        let frame_times: Vec<f64> = records
+            .iter()
+            .filter(|r| matches!(r.event, SessionEvent::Frame { .. }))
+            .map(|r| r.t)
+            .collect();
+        qoe.frames = frame_times.len();
+        let (first, last) = match (frame_times.first(), frame_times.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return qoe,
+        };
+        qoe.startup_delay = Some(first);
+        qoe.duration = last - first;
+
+        // objects of a scene stall together, so overlapping stalls are only counted once
+        let mut stalls: Vec<(f64, f64)> = records
+            .iter()
+            .filter_map(|r| match r.event {
+                SessionEvent::Stall { duration, .. } => Some(((r.t - duration).max(first), r.t)),
+                _ => None,
+            })
+            .filter(|(start, end)| end > start)
+            .collect();
+        stalls.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut merged: Vec<(f64, f64)> = vec![];
+        for (start, end) in stalls {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        qoe.stalls = merged.len();
+        qoe.rebuffer_time = merged.iter().map(|(start, end)| end - start).sum();
+        if qoe.duration > 0.0 {
+            qoe.rebuffer_ratio = (qoe.rebuffer_time / qoe.duration).min(1.0);
+        }
+
+        let mut segments = 0;
+        let mut previous: HashMap<u8, &Vec<usize>> = HashMap::new();
+        for record in records {
+            if let SessionEvent::Quality {
+                object_id,
+                quality,
+                bitrate,
+                ..
+            } = &record.event
+            {
+                segments += 1;
+                if !quality.is_empty() {
+                    qoe.average_quality +=
+                        quality.iter().sum::<usize>() as f64 / quality.len() as f64;
+                }
+                qoe.average_bitrate += *bitrate as f64;
+                if previous
+                    .insert(*object_id, quality)
+                    .is_some_and(|p| p != quality)
+                {
+                    qoe.switches += 1;
+                }
+            }
+        }
+        if segments > 0 {
+            qoe.average_quality /= segments as f64;
+            qoe.average_bitrate /= segments as f64;
+        }
+        if qoe.duration > 0.0 {
+            qoe.switch_frequency = qoe.switches as f64 * 60.0 / qoe.duration;
+        }
+        qoe
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(t: f64, event: SessionEvent) -> SessionRecord {
+        SessionRecord { t, event }
+    }
+
+    fn quality(t: f64, object_id: u8, quality: Vec<usize>, bitrate: u64) -> SessionRecord {
+        record(
+            t,
+            SessionEvent::Quality {
+                object_id,
+                frame: 0,
+                quality,
+                bitrate,
+            },
+        )
+    }
+
+    fn stall(t: f64, object_id: u8, duration: f64) -> SessionRecord {
+        record(
+            t,
+            SessionEvent::Stall {
+                object_id,
+                frame: 0,
+                duration,
+            },
+        )
+    }
+
+    fn frame(t: f64, frame: u64) -> SessionRecord {
+        record(
+            t,
+            SessionEvent::Frame {
+                object_id: 0,
+                frame,
+            },
+        )
+    }
+
+    #[test]
+    fn test_qoe() {
+        let records = vec![
+            quality(0.5, 0, vec![0, 1], 1000),
+            quality(0.6, 1, vec![2], 3000),
+            // startup, not a rebuffer
+            stall(2.0, 0, 1.5),
+            frame(2.0, 0),
+            quality(3.0, 0, vec![0, 1], 1000),
+            quality(4.0, 0, vec![1, 1], 2000),
+            // the two objects stall at the same time
+            stall(6.0, 0, 1.0),
+            stall(6.5, 1, 1.0),
+            stall(9.0, 1, 0.5),
+            quality(10.0, 1, vec![1], 2000),
+            frame(14.0, 360),
+        ];
+        let qoe = Qoe::from_records(&records);
+        assert_eq!(qoe.startup_delay, Some(2.0));
+        assert_eq!(qoe.duration, 12.0);
+        assert_eq!(qoe.frames, 2);
+        assert_eq!(qoe.stalls, 2);
+        assert!((qoe.rebuffer_time - 2.0).abs() < 1e-9);
+        assert!((qoe.rebuffer_ratio - 2.0 / 12.0).abs() < 1e-9);
+        assert!((qoe.average_quality - (0.5 + 2.0 + 0.5 + 1.0 + 1.0) / 5.0).abs() < 1e-9);
+        assert_eq!(qoe.average_bitrate, 1800.0);
+        assert_eq!(qoe.switches, 2);
+        assert_eq!(qoe.switch_frequency, 10.0);
+
+        assert_eq!(Qoe::from_records(&records[..3]).startup_delay, None);
+    }
+
+    #[test]
+    fn test_session_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let log = SessionLog::create(&path).unwrap();
+        log.log(SessionEvent::Buffer {
+            object_id: 0,
+            occupancy: 3,
+        });
+        log.clone().log(SessionEvent::Quality {
+            object_id: 1,
+            frame: 30,
+            quality: vec![2, 0],
+            bitrate: 5000,
+        });
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content
+            .lines()
+            .next()
+            .unwrap()
+            .contains(r#""event":"buffer","object_id":0,"occupancy":3"#));
+
+        let records = SessionLog::read(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].t <= records[1].t);
+        assert_eq!(
+            records[1].event,
+            SessionEvent::Quality {
+                object_id: 1,
+                frame: 30,
+                quality: vec![2, 0],
+                bitrate: 5000,
+            }
+        );
+    }
+
+    #[test]
+    fn test_virtual_clock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let log = SessionLog::create(&path).unwrap().with_virtual_clock();
+        let reader = log.clone();
+        reader.log(SessionEvent::Frame {
+            object_id: 0,
+            frame: 0,
+        });
+        std::thread::sleep(Duration::from_millis(20));
+        log.advance(Duration::from_millis(100));
+        reader.log(SessionEvent::Frame {
+            object_id: 0,
+            frame: 1,
+        });
+        let t: Vec<f64> = SessionLog::read(&path)
+            .unwrap()
+            .iter()
+            .map(|r| r.t)
+            .collect();
+        assert_eq!(t, [0.0, 0.1]);
+    }
+
+    #[test]
+    fn test_virtual_clock_run_while() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let log = SessionLog::create(&path).unwrap().with_virtual_clock();
+        log.advance(Duration::from_secs(1));
+        let waited = log.run_while(|| {
+            std::thread::sleep(Duration::from_millis(50));
+            log.elapsed()
+        });
+        assert!(waited >= 1.05);
+        let after = log.elapsed();
+        assert!(after >= waited);
+        // the clock stands still again
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(log.elapsed(), after);
+    }
+}
@@ -0,0 +1,254 @@
+//! End-to-end tests that stream the fixture MPDs in `test_files/dash/fixtures` from the in-process
+//! DASH server, through the fetch and decode loops of vvplay_async and the buffer manager, to a stand-in
+//! for the renderer.
+
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::unbounded_channel;
+use vivotk::codec::decoder::NoopDecoder;
+use vivotk::dash::fetcher::Fetcher;
+use vivotk::dash::parser::MPDParser;
+use vivotk::dash::server::DashServer;
+use vivotk::render::wgpu::camera::CameraPosition;
+use vivotk::render::wgpu::reader::FrameRequest;
+use vivotk::simulation::NetworkTrace;
+use vivotk::utils::LastValue;
+use vivotk::vvplay_async_prefetch::buffer_manager::BufferManager;
+use vivotk::vvplay_async_prefetch::enums::AbrType;
+use vivotk::vvplay_async_prefetch::fetch_loop::{decode_loop, FetchLoop};
+use vivotk::vvplay_async_prefetch::fetch_request::FetchRequest;
+use vivotk::vvplay_async_prefetch::session_log::{Qoe, SessionEvent, SessionLog};
+use vivotk::BufMsg;
+
+const FIXTURES: &str = "test_files/dash/fixtures";
+
+/// Number of points in a segment of the given bitrate, so that segment sizes roughly match the MPD.
+fn points_for_bitrate(bitrate: u64, fps: f64) -> usize {
+    // each point takes about 24 bytes in ASCII ply
+    ((bitrate as f64 / fps / 8.0 / 24.0).ceil() as usize).max(1)
+}
+
+fn write_ply(path: &Path, num_points: usize) {
+    let mut ply = format!(
+        "ply\nformat ascii 1.0\nelement vertex {num_points}\nproperty float x\nproperty float y\nproperty float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\nend_header\n"
+    );
+    for i in 0..num_points {
+        ply += &format!("{} 0.25 0.125 255 0 0\n", i as f32 / num_points as f32);
+    }
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, ply).unwrap();
+}
+
+/// Copies the fixture MPD into `dir` and generates every segment it references.
+/// Returns the possible number of points per view, by representation.
+fn prepare_content(dir: &Path, mpd_name: &str, views: Option<u8>) -> Vec<usize> {
+    let mpd_path = dir.join(mpd_name);
+    std::fs::copy(Path::new(FIXTURES).join(mpd_name), &mpd_path).unwrap();
+    let mut parser = MPDParser::new(&std::fs::read_to_string(&mpd_path).unwrap()).unwrap();
+    let base = "http://fixture/";
+    parser.set_manifest_url(&format!("{base}{mpd_name}"));
+
+    let (duration, timescale) = parser.segment_duration().unwrap();
+    let segment_frames = duration * parser.fps() as u64 / timescale;
+    let views: Vec<Option<u8>> = match views {
+        Some(views) => (0..views).map(Some).collect(),
+        None => vec![None],
+    };
+    let bitrates = parser.available_bitrates(0, 0, views[0]).unwrap();
+    for view in &views {
+        for (repr, bitrate) in bitrates.iter().enumerate() {
+            for frame in (0..parser.total_frames() as u64).step_by(segment_frames as usize) {
+                let (url, _) = parser.get_info(0, repr as u8, frame, *view).unwrap();
+                let path = dir.join(url.strip_prefix(base).unwrap());
+                write_ply(&path, points_for_bitrate(*bitrate, parser.fps()));
+            }
+        }
+    }
+    bitrates
+        .iter()
+        .map(|bitrate| points_for_bitrate(*bitrate, parser.fps()))
+        .collect()
+}
+
+struct Playback {
+    /// (frame offset, number of points) of every frame delivered to the renderer
+    frames: Vec<(u64, usize)>,
+    /// quality selected for every segment that was downloaded
+    decisions: Vec<Vec<usize>>,
+    total_frames: usize,
+}
+
+/// Plays the fixture from start to end with the given ABR algorithm, through the fetch and decode loops of
+/// vvplay_async.
+async fn stream(mpd_name: &str, abr_type: AbrType, views: Option<u8>) -> (Playback, Vec<usize>) {
+    let content = tempfile::tempdir().unwrap();
+    let points_per_view = prepare_content(content.path(), mpd_name, views);
+    let mut server = DashServer::new(content.path());
+    server.set_network_trace(NetworkTrace::from_samples(vec![400.0, 100.0, 200.0, 800.0]));
+    let server = server.bind("127.0.0.1:0").await.unwrap();
+
+    let download_dir = tempfile::tempdir().unwrap();
+    let fetcher = Fetcher::new(&server.url(mpd_name), download_dir.path(), false)
+        .await
+        .unwrap();
+    let total_frames = fetcher.mpd_parser.total_frames();
+    let segment_duration = fetcher.mpd_parser.segment_duration().unwrap();
+    let fps = fetcher.mpd_parser.fps() as f32;
+    let view_count = fetcher.views(0, 0);
+    let abr = abr_type.build(fps as u64, fps, view_count, vec![1.0, 2.0]);
+    let session_log_path = download_dir.path().join("session.jsonl");
+    let session_log = SessionLog::create(&session_log_path).unwrap();
+
+    let (buf_in_sx, buf_in_rx) = unbounded_channel::<FetchRequest>();
+    let (in_dec_sx, in_dec_rx) = unbounded_channel();
+    let (to_buf_sx, to_buf_rx) = unbounded_channel();
+    let (decode_time_sx, decode_time_rx) = unbounded_channel();
+    let (buf_out_sx, buf_out_rx) = std::sync::mpsc::channel();
+    let (shutdown_sx, shutdown_rx) = tokio::sync::watch::channel(false);
+
+    let fetcher_task = tokio::spawn(
+        FetchLoop::new(fetcher, abr, Box::new(LastValue::<f64>::new()))
+            .with_multiview(views.is_some())
+            .with_session_log(Some(session_log))
+            .run(
+                buf_in_rx,
+                to_buf_sx.clone(),
+                in_dec_sx,
+                decode_time_rx,
+                shutdown_rx.clone(),
+            ),
+    );
+    let decoder_task = tokio::spawn(decode_loop(
+        Arc::new(NoopDecoder),
+        in_dec_rx,
+        to_buf_sx.clone(),
+        decode_time_sx,
+        shutdown_rx.clone(),
+    ));
+
+    let mut buffer = BufferManager::new(
+        to_buf_rx,
+        buf_in_sx,
+        buf_out_sx,
+        1,
+        total_frames,
+        segment_duration,
+        shutdown_rx,
+    );
+    let buffer_task = tokio::spawn(async move {
+        buffer
+            .run(
+                Box::new(LastValue::<CameraPosition>::new()),
+                CameraPosition::default(),
+                None,
+                None,
+            )
+            .await
+    });
+
+    // renderer
+    let frames = tokio::task::spawn_blocking(move || {
+        (0..total_frames as u64)
+            .map(|frame_offset| {
+                _ = to_buf_sx.send(BufMsg::FrameRequest(FrameRequest {
+                    object_id: 0,
+                    frame_offset,
+                    camera_pos: Some(CameraPosition::default()),
+                }));
+                let (req, pc) = buf_out_rx
+                    .recv_timeout(Duration::from_secs(10))
+                    .unwrap_or_else(|_| panic!("frame {frame_offset} was not delivered"));
+                (req.frame_offset, pc.number_of_points)
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap();
+
+    shutdown_sx.send(true).unwrap();
+    buffer_task.await.unwrap();
+    fetcher_task.await.unwrap();
+    decoder_task.await.unwrap();
+    let decisions = SessionLog::read(&session_log_path)
+        .unwrap()
+        .into_iter()
+        .filter_map(|record| match record.event {
+            SessionEvent::Quality { quality, .. } => Some(quality),
+            _ => None,
+        })
+        .collect();
+    (
+        Playback {
+            frames,
+            decisions,
+            total_frames,
+        },
+        points_per_view,
+    )
+}
+
+fn check_playback(playback: &Playback, points_per_view: &[usize], views: usize) {
+    assert!(playback.total_frames > 0);
+    assert_eq!(playback.frames.len(), playback.total_frames);
+    for (i, (frame_offset, num_points)) in playback.frames.iter().enumerate() {
+        assert_eq!(*frame_offset, i as u64);
+        let min = points_per_view[0] * views;
+        let max = points_per_view.last().unwrap() * views;
+        assert!(
+            (min..=max).contains(num_points),
+            "frame {frame_offset} has {num_points} points"
+        );
+    }
+    assert!(playback.decisions.len() >= playback.total_frames);
+    for quality in &playback.decisions {
+        assert!(quality.iter().all(|q| *q < points_per_view.len()));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_stream_quetra() {
+    let (playback, points_per_view) = stream("single.mpd", AbrType::Quetra, None).await;
+    check_playback(&playback, &points_per_view, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_stream_quetra_multiview() {
+    let (playback, points_per_view) =
+        stream("multiview.mpd", AbrType::QuetraMultiview, Some(6)).await;
+    check_playback(&playback, &points_per_view, 6);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_stream_mckp() {
+    let (playback, points_per_view) = stream("multiview.mpd", AbrType::Mckp, Some(6)).await;
+    check_playback(&playback, &points_per_view, 6);
+}
+
+/// On the virtual clock, the headless player logs the time it waits for a throttled server as stalls.
+#[tokio::test(flavor = "multi_thread")]
+async fn test_headless_virtual_clock_stalls() {
+    let content = tempfile::tempdir().unwrap();
+    prepare_content(content.path(), "single.mpd", None);
+    let mut server = DashServer::new(content.path());
+    // tens of milliseconds per segment
+    server.set_network_trace(NetworkTrace::from_samples(vec![50.0]));
+    let server = server.bind("127.0.0.1:0").await.unwrap();
+
+    let session_log = content.path().join("session.jsonl");
+    let status = tokio::process::Command::new(env!("CARGO_BIN_EXE_vvplay_async"))
+        .arg(server.url("single.mpd"))
+        .args(["--headless", "--clock", "virtual", "--session-log"])
+        .arg(&session_log)
+        .stdout(Stdio::null())
+        .status()
+        .await
+        .unwrap();
+    assert!(status.success());
+
+    let qoe = Qoe::from_records(&SessionLog::read(&session_log).unwrap());
+    assert_eq!(qoe.frames, 20);
+    assert!(qoe.startup_delay.unwrap() > 0.0);
+    assert!(qoe.rebuffer_ratio > 0.0, "{qoe:?}");
+}